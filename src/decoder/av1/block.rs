@@ -0,0 +1,895 @@
+use super::{frame::Stage, symbol::SyntaxCategory, BitStream, Decoder, NumPlanes};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SubSize {
+    #[default]
+    Block4x4 = 0,
+    Block4x8 = 1,
+    Block8x4 = 2,
+    Block8x8 = 3,
+    Block8x16 = 4,
+    Block16x8 = 5,
+    Block16x16 = 6,
+    Block16x32 = 7,
+    Block32x16 = 8,
+    Block32x32 = 9,
+    Block32x64 = 10,
+    Block64x32 = 11,
+    Block64x64 = 12,
+    Block64x128 = 13,
+    Block128x64 = 14,
+    Block128x128 = 15,
+    Block4x16 = 16,
+    Block16x4 = 17,
+    Block8x32 = 18,
+    Block32x8 = 19,
+    Block16x64 = 20,
+    Block64x16 = 21,
+}
+
+impl SubSize {
+    pub fn new(val: u64) -> SubSize {
+        match val {
+            0 => SubSize::Block4x4,
+            1 => SubSize::Block4x8,
+            2 => SubSize::Block8x4,
+            3 => SubSize::Block8x8,
+            4 => SubSize::Block8x16,
+            5 => SubSize::Block16x8,
+            6 => SubSize::Block16x16,
+            7 => SubSize::Block16x32,
+            8 => SubSize::Block32x16,
+            9 => SubSize::Block32x32,
+            10 => SubSize::Block32x64,
+            11 => SubSize::Block64x32,
+            12 => SubSize::Block64x64,
+            13 => SubSize::Block64x128,
+            14 => SubSize::Block128x64,
+            15 => SubSize::Block128x128,
+            16 => SubSize::Block4x16,
+            17 => SubSize::Block16x4,
+            18 => SubSize::Block8x32,
+            19 => SubSize::Block32x8,
+            20 => SubSize::Block16x64,
+            21 => SubSize::Block64x16,
+            _ => panic!("invalid value for SubSize: {val}"),
+        }
+    }
+
+    pub fn width(self) -> usize {
+        Decoder::NUM_4X4_BLOCKS_WIDE[self as usize] * 4
+    }
+
+    pub fn height(self) -> usize {
+        Decoder::NUM_4X4_BLOCKS_HIGH[self as usize] * 4
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum YMode {
+    #[default]
+    Dc = 0,
+    V = 1,
+    H = 2,
+    D45 = 3,
+    D135 = 4,
+    D113 = 5,
+    D157 = 6,
+    D203 = 7,
+    D67 = 8,
+    Smooth = 9,
+    SmoothV = 10,
+    SmoothH = 11,
+    Paeth = 12,
+    NearestMv = 13,
+    NearMv = 14,
+    GlobalMv = 15,
+    NewMv = 16,
+    NearestNearestMv = 17,
+    NearNearMv = 18,
+    NearestNewMv = 19,
+    NewNearestMv = 20,
+    NearNewMv = 21,
+    NewNearMv = 22,
+    GlobalGlobalMv = 23,
+    NewNewMv = 24,
+}
+
+impl YMode {
+    pub fn new(val: u64) -> YMode {
+        match val {
+            0 => YMode::Dc,
+            1 => YMode::V,
+            2 => YMode::H,
+            3 => YMode::D45,
+            4 => YMode::D135,
+            5 => YMode::D113,
+            6 => YMode::D157,
+            7 => YMode::D203,
+            8 => YMode::D67,
+            9 => YMode::Smooth,
+            10 => YMode::SmoothV,
+            11 => YMode::SmoothH,
+            12 => YMode::Paeth,
+            13 => YMode::NearestMv,
+            14 => YMode::NearMv,
+            15 => YMode::GlobalMv,
+            16 => YMode::NewMv,
+            17 => YMode::NearestNearestMv,
+            18 => YMode::NearNearMv,
+            19 => YMode::NearestNewMv,
+            20 => YMode::NewNearestMv,
+            21 => YMode::NearNewMv,
+            22 => YMode::NewNearMv,
+            23 => YMode::GlobalGlobalMv,
+            24 => YMode::NewNewMv,
+            _ => panic!("invalid value for YMode: {val}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UvMode {
+    #[default]
+    Dc = 0,
+    V = 1,
+    H = 2,
+    D45 = 3,
+    D135 = 4,
+    D113 = 5,
+    D157 = 6,
+    D203 = 7,
+    D67 = 8,
+    Smooth = 9,
+    SmoothV = 10,
+    SmoothH = 11,
+    Paeth = 12,
+    Cfl = 13,
+}
+
+impl UvMode {
+    pub fn new(val: u64) -> UvMode {
+        match val {
+            0 => UvMode::Dc,
+            1 => UvMode::V,
+            2 => UvMode::H,
+            3 => UvMode::D45,
+            4 => UvMode::D135,
+            5 => UvMode::D113,
+            6 => UvMode::D157,
+            7 => UvMode::D203,
+            8 => UvMode::D67,
+            9 => UvMode::Smooth,
+            10 => UvMode::SmoothV,
+            11 => UvMode::SmoothH,
+            12 => UvMode::Paeth,
+            13 => UvMode::Cfl,
+            _ => panic!("invalid value for UvMode: {val}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TxSize {
+    #[default]
+    Tx4x4 = 0,
+    Tx8x8 = 1,
+    Tx16x16 = 2,
+    Tx32x32 = 3,
+    Tx64x64 = 4,
+    Tx4x8 = 5,
+    Tx8x4 = 6,
+    Tx8x16 = 7,
+    Tx16x8 = 8,
+    Tx16x32 = 9,
+    Tx32x16 = 10,
+    Tx32x64 = 11,
+    Tx64x32 = 12,
+    Tx4x16 = 13,
+    Tx16x4 = 14,
+    Tx8x32 = 15,
+    Tx32x8 = 16,
+    Tx16x64 = 17,
+    Tx64x16 = 18,
+}
+
+impl TxSize {
+    const TX_WIDTH: [usize; 19] = [
+        4, 8, 16, 32, 64, 4, 8, 8, 16, 16, 32, 32, 64, 4, 16, 8, 32, 16, 64,
+    ];
+    const TX_HEIGHT: [usize; 19] = [
+        4, 8, 16, 32, 64, 8, 4, 16, 8, 32, 16, 64, 32, 16, 4, 32, 8, 64, 16,
+    ];
+
+    pub fn new(val: u64) -> TxSize {
+        match val {
+            0 => TxSize::Tx4x4,
+            1 => TxSize::Tx8x8,
+            2 => TxSize::Tx16x16,
+            3 => TxSize::Tx32x32,
+            4 => TxSize::Tx64x64,
+            5 => TxSize::Tx4x8,
+            6 => TxSize::Tx8x4,
+            7 => TxSize::Tx8x16,
+            8 => TxSize::Tx16x8,
+            9 => TxSize::Tx16x32,
+            10 => TxSize::Tx32x16,
+            11 => TxSize::Tx32x64,
+            12 => TxSize::Tx64x32,
+            13 => TxSize::Tx4x16,
+            14 => TxSize::Tx16x4,
+            15 => TxSize::Tx8x32,
+            16 => TxSize::Tx32x8,
+            17 => TxSize::Tx16x64,
+            18 => TxSize::Tx64x16,
+            _ => panic!("invalid value for TxSize: {val}"),
+        }
+    }
+
+    pub fn width(self) -> usize {
+        TxSize::TX_WIDTH[self as usize]
+    }
+
+    pub fn height(self) -> usize {
+        TxSize::TX_HEIGHT[self as usize]
+    }
+
+    pub fn square(side: usize) -> TxSize {
+        match side {
+            4 => TxSize::Tx4x4,
+            8 => TxSize::Tx8x8,
+            16 => TxSize::Tx16x16,
+            32 => TxSize::Tx32x32,
+            64 => TxSize::Tx64x64,
+            _ => panic!("invalid side for a square TxSize: {side}"),
+        }
+    }
+
+    /// Tx_Size_Sqr, the largest square that fits.
+    pub fn sqr(self) -> TxSize {
+        TxSize::square(self.width().min(self.height()))
+    }
+
+    /// Tx_Size_Sqr_Up, the smallest square that covers it.
+    pub fn sqr_up(self) -> TxSize {
+        TxSize::square(self.width().max(self.height()))
+    }
+
+    /// The transform size with the given dimensions.
+    pub fn find(width: usize, height: usize) -> TxSize {
+        (0..19)
+            .map(TxSize::new)
+            .find(|tx_sz| tx_sz.width() == width && tx_sz.height() == height)
+            .unwrap_or_else(|| panic!("invalid dimensions for a TxSize: {width}x{height}"))
+    }
+
+    /// Split_Tx_Size, the next smaller size when a transform is split.
+    pub fn split(self) -> TxSize {
+        match self {
+            TxSize::Tx4x4 | TxSize::Tx8x8 | TxSize::Tx4x8 | TxSize::Tx8x4 => TxSize::Tx4x4,
+            TxSize::Tx16x16 | TxSize::Tx8x16 | TxSize::Tx16x8 => TxSize::Tx8x8,
+            TxSize::Tx32x32 | TxSize::Tx16x32 | TxSize::Tx32x16 => TxSize::Tx16x16,
+            TxSize::Tx64x64 | TxSize::Tx32x64 | TxSize::Tx64x32 => TxSize::Tx32x32,
+            TxSize::Tx4x16 => TxSize::Tx4x8,
+            TxSize::Tx16x4 => TxSize::Tx8x4,
+            TxSize::Tx8x32 => TxSize::Tx8x16,
+            TxSize::Tx32x8 => TxSize::Tx16x8,
+            TxSize::Tx16x64 => TxSize::Tx16x32,
+            TxSize::Tx64x16 => TxSize::Tx32x16,
+        }
+    }
+
+    /// Adjusted_Tx_Size, the size of the coefficients actually coded: only the top left
+    /// 32x32 of transforms with a side of 64.
+    pub fn adjusted(self) -> TxSize {
+        TxSize::find(self.width().min(32), self.height().min(32))
+    }
+}
+
+/// The vertical transform comes first: ADST_DCT is an ADST on the columns and a DCT on the
+/// rows. V_ and H_ types apply the named transform in one direction and the identity in
+/// the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TxType {
+    #[default]
+    DctDct = 0,
+    AdstDct = 1,
+    DctAdst = 2,
+    AdstAdst = 3,
+    FlipadstDct = 4,
+    DctFlipadst = 5,
+    FlipadstFlipadst = 6,
+    AdstFlipadst = 7,
+    FlipadstAdst = 8,
+    Idtx = 9,
+    VDct = 10,
+    HDct = 11,
+    VAdst = 12,
+    HAdst = 13,
+    VFlipadst = 14,
+    HFlipadst = 15,
+}
+
+impl TxType {
+    pub const COUNT: usize = 16;
+
+    pub fn new(val: u64) -> TxType {
+        match val {
+            0 => TxType::DctDct,
+            1 => TxType::AdstDct,
+            2 => TxType::DctAdst,
+            3 => TxType::AdstAdst,
+            4 => TxType::FlipadstDct,
+            5 => TxType::DctFlipadst,
+            6 => TxType::FlipadstFlipadst,
+            7 => TxType::AdstFlipadst,
+            8 => TxType::FlipadstAdst,
+            9 => TxType::Idtx,
+            10 => TxType::VDct,
+            11 => TxType::HDct,
+            12 => TxType::VAdst,
+            13 => TxType::HAdst,
+            14 => TxType::VFlipadst,
+            15 => TxType::HFlipadst,
+            _ => panic!("invalid value for TxType: {val}"),
+        }
+    }
+
+    /// Whether the transform is one-dimensional, which changes how the coefficients are
+    /// scanned and their contexts.
+    pub fn class(self) -> TxClass {
+        match self {
+            TxType::VDct | TxType::VAdst | TxType::VFlipadst => TxClass::Vert,
+            TxType::HDct | TxType::HAdst | TxType::HFlipadst => TxClass::Horiz,
+            _ => TxClass::TwoD,
+        }
+    }
+
+    /// The name used by the specification, e.g. ADST_FLIPADST.
+    pub fn name(self) -> &'static str {
+        match self {
+            TxType::DctDct => "DCT_DCT",
+            TxType::AdstDct => "ADST_DCT",
+            TxType::DctAdst => "DCT_ADST",
+            TxType::AdstAdst => "ADST_ADST",
+            TxType::FlipadstDct => "FLIPADST_DCT",
+            TxType::DctFlipadst => "DCT_FLIPADST",
+            TxType::FlipadstFlipadst => "FLIPADST_FLIPADST",
+            TxType::AdstFlipadst => "ADST_FLIPADST",
+            TxType::FlipadstAdst => "FLIPADST_ADST",
+            TxType::Idtx => "IDTX",
+            TxType::VDct => "V_DCT",
+            TxType::HDct => "H_DCT",
+            TxType::VAdst => "V_ADST",
+            TxType::HAdst => "H_ADST",
+            TxType::VFlipadst => "V_FLIPADST",
+            TxType::HFlipadst => "H_FLIPADST",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxClass {
+    TwoD = 0,
+    Horiz = 1,
+    Vert = 2,
+}
+
+/// The transform types a block may choose from, depending on its size, whether it is
+/// inter coded and reduced_tx_set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxSet {
+    DctOnly,
+    Intra1,
+    Intra2,
+    Inter1,
+    Inter2,
+    Inter3,
+}
+
+impl TxSet {
+    pub fn contains(self, tx_type: TxType) -> bool {
+        use TxType::*;
+        match self {
+            TxSet::DctOnly => tx_type == DctDct,
+            TxSet::Intra1 => matches!(
+                tx_type,
+                DctDct | AdstDct | DctAdst | AdstAdst | Idtx | VDct | HDct
+            ),
+            TxSet::Intra2 => matches!(tx_type, DctDct | AdstDct | DctAdst | AdstAdst | Idtx),
+            TxSet::Inter1 => true,
+            TxSet::Inter2 => !matches!(tx_type, VAdst | HAdst | VFlipadst | HFlipadst),
+            TxSet::Inter3 => matches!(tx_type, DctDct | Idtx),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InterpFilter {
+    #[default]
+    EightTap = 0,
+    EightTapSmooth = 1,
+    EightTapSharp = 2,
+    Bilinear = 3,
+    Switchable = 4,
+}
+
+impl InterpFilter {
+    pub fn new(val: u64) -> InterpFilter {
+        match val {
+            0 => InterpFilter::EightTap,
+            1 => InterpFilter::EightTapSmooth,
+            2 => InterpFilter::EightTapSharp,
+            3 => InterpFilter::Bilinear,
+            4 => InterpFilter::Switchable,
+            _ => panic!("invalid value for InterpFilter: {val}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MotionMode {
+    #[default]
+    Simple = 0,
+    Obmc = 1,
+    LocalWarp = 2,
+}
+
+impl MotionMode {
+    pub fn new(val: u64) -> MotionMode {
+        match val {
+            0 => MotionMode::Simple,
+            1 => MotionMode::Obmc,
+            2 => MotionMode::LocalWarp,
+            _ => panic!("invalid value for MotionMode: {val}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompoundType {
+    Wedge = 0,
+    Diffwtd = 1,
+    #[default]
+    Average = 2,
+    Intra = 3,
+    Distance = 4,
+}
+
+impl CompoundType {
+    pub fn new(val: u64) -> CompoundType {
+        match val {
+            0 => CompoundType::Wedge,
+            1 => CompoundType::Diffwtd,
+            2 => CompoundType::Average,
+            3 => CompoundType::Intra,
+            4 => CompoundType::Distance,
+            _ => panic!("invalid value for CompoundType: {val}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InterIntraMode {
+    #[default]
+    Dc = 0,
+    V = 1,
+    H = 2,
+    Smooth = 3,
+}
+
+impl InterIntraMode {
+    pub fn new(val: u64) -> InterIntraMode {
+        match val {
+            0 => InterIntraMode::Dc,
+            1 => InterIntraMode::V,
+            2 => InterIntraMode::H,
+            3 => InterIntraMode::Smooth,
+            _ => panic!("invalid value for InterIntraMode: {val}"),
+        }
+    }
+}
+
+/// Per-block syntax values kept around after decoding so they can be inspected.
+#[derive(Debug, Clone, Default)]
+pub struct BlockInfo {
+    pub mi_row: u64,
+    pub mi_col: u64,
+    pub mi_size: SubSize,
+    /// The luma intra mode, or the inter mode of inter blocks.
+    pub y_mode: YMode,
+    pub uv_mode: UvMode,
+    pub palette_size_y: u64,
+    pub palette_size_uv: u64,
+    pub palette_colors_y: Vec<u16>,
+    pub palette_colors_u: Vec<u16>,
+    pub palette_colors_v: Vec<u16>,
+    pub is_inter: bool,
+    pub use_intrabc: bool,
+    pub ref_frame: [i64; 2],
+    pub mv: [[i64; 2]; 2],
+    pub motion_mode: MotionMode,
+    /// Only meaningful for compound and inter-intra blocks.
+    pub compound_type: CompoundType,
+    pub interintra: bool,
+    pub segment_id: u64,
+    /// The quantizer index after delta q and segmentation.
+    pub qindex: u64,
+    /// The loop filter levels after delta lf, segmentation and the reference and mode
+    /// deltas: luma vertical edges, luma horizontal edges, U and V.
+    pub filter_levels: [i64; 4],
+    /// The bits the block took to code, indexed by SyntaxCategory.
+    pub bits: [f64; SyntaxCategory::COUNT],
+}
+
+impl Decoder {
+    pub const BLOCK_SIZES: usize = 22;
+    pub const NUM_4X4_BLOCKS_WIDE: [usize; Decoder::BLOCK_SIZES] = [
+        1, 1, 2, 2, 2, 4, 4, 4, 8, 8, 8, 16, 16, 16, 32, 32, 1, 4, 2, 8, 4, 16,
+    ];
+    pub const NUM_4X4_BLOCKS_HIGH: [usize; Decoder::BLOCK_SIZES] = [
+        1, 2, 1, 2, 4, 2, 4, 8, 4, 8, 16, 8, 16, 32, 16, 32, 4, 1, 8, 2, 16, 4,
+    ];
+    pub const MI_WIDTH_LOG2: [u64; Decoder::BLOCK_SIZES] = [
+        0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4, 5, 5, 0, 2, 1, 3, 2, 4,
+    ];
+    pub const MI_HEIGHT_LOG2: [u64; Decoder::BLOCK_SIZES] = [
+        0, 1, 0, 1, 2, 1, 2, 3, 2, 3, 4, 3, 4, 5, 4, 5, 2, 0, 3, 1, 4, 2,
+    ];
+    pub const MI_SIZE: u64 = 4;
+    pub const MAX_TX_SIZE_RECT: [TxSize; Decoder::BLOCK_SIZES] = [
+        TxSize::Tx4x4,
+        TxSize::Tx4x8,
+        TxSize::Tx8x4,
+        TxSize::Tx8x8,
+        TxSize::Tx8x16,
+        TxSize::Tx16x8,
+        TxSize::Tx16x16,
+        TxSize::Tx16x32,
+        TxSize::Tx32x16,
+        TxSize::Tx32x32,
+        TxSize::Tx32x64,
+        TxSize::Tx64x32,
+        TxSize::Tx64x64,
+        TxSize::Tx64x64,
+        TxSize::Tx64x64,
+        TxSize::Tx64x64,
+        TxSize::Tx4x16,
+        TxSize::Tx16x4,
+        TxSize::Tx8x32,
+        TxSize::Tx32x8,
+        TxSize::Tx16x64,
+        TxSize::Tx64x16,
+    ];
+
+    /// Allocates the frame-sized arrays once the frame size is known. CurrFrame is padded to
+    /// whole 128x128 superblocks, because predictions of blocks crossing the right or bottom
+    /// edge are written in full.
+    pub(super) fn init_block_info(&mut self) {
+        let rows = self.mi_rows as usize;
+        let cols = self.mi_cols as usize;
+
+        self.block_infos = Vec::new();
+        self.palette_sizes = vec![vec![vec![0; cols]; rows]; 2];
+        self.palette_colors = vec![vec![vec![Vec::new(); cols]; rows]; 2];
+        self.mi_sizes = vec![vec![SubSize::default(); cols]; rows];
+        self.y_modes = vec![vec![YMode::default(); cols]; rows];
+        self.uv_modes = vec![vec![UvMode::default(); cols]; rows];
+        self.ref_frames = vec![vec![[Decoder::INTRA_FRAME, Decoder::NONE]; cols]; rows];
+        self.ref_frames_written = vec![vec![false; cols]; rows];
+        self.mvs = vec![vec![[[0; 2]; 2]; cols]; rows];
+        self.is_inters = vec![vec![false; cols]; rows];
+        self.interp_filters = vec![vec![[InterpFilter::default(); 2]; cols]; rows];
+        self.comp_group_idxs = vec![vec![0; cols]; rows];
+        self.skips = vec![vec![false; cols]; rows];
+        self.skip_modes = vec![vec![false; cols]; rows];
+        self.inter_tx_sizes = vec![vec![TxSize::default(); cols]; rows];
+        self.cdef_idx = vec![vec![-1; cols]; rows];
+        self.cdef_directions = vec![vec![None; cols.div_ceil(2)]; rows.div_ceil(2)];
+        self.cdef_variances = vec![vec![0; cols.div_ceil(2)]; rows.div_ceil(2)];
+        self.segment_ids = vec![vec![0; cols]; rows];
+        self.delta_lfs = vec![vec![[0; Decoder::FRAME_LF_COUNT]; cols]; rows];
+        self.loopfilter_tx_sizes = vec![vec![vec![TxSize::default(); cols]; rows]; 3];
+        self.tx_types = vec![vec![TxType::default(); cols]; rows];
+        self.compound_idxs = vec![vec![0; cols]; rows];
+
+        let height = ((rows + 31) & !31) * Decoder::MI_SIZE as usize;
+        let width = ((cols + 31) & !31) * Decoder::MI_SIZE as usize;
+        let color_config = &self.sequence_header.color_config;
+        let chroma_height = height >> color_config.subsampling_y as usize;
+        let chroma_width = width >> color_config.subsampling_x as usize;
+        self.curr_frame = vec![vec![vec![0; width]; height]];
+        for _ in 1..self.num_planes as usize {
+            self.curr_frame
+                .push(vec![vec![0; chroma_width]; chroma_height]);
+        }
+
+        self.residual_frame =
+            if self.stages.contains(&Stage::Prediction) || self.stages.contains(&Stage::Residual) {
+                self.curr_frame
+                    .iter()
+                    .map(|plane| vec![vec![0; plane[0].len()]; plane.len()])
+                    .collect()
+            } else {
+                Vec::new()
+            };
+    }
+
+    /// The last step of reconstruction: adds the residual of a transform block to the
+    /// prediction in CurrFrame, keeping it in residual_frame when a stage needs it.
+    pub(super) fn add_residual(&mut self, plane: usize, x: usize, y: usize, residual: &[Vec<i32>]) {
+        let max = (1 << self.bit_depth as i32) - 1;
+        let keep = !self.residual_frame.is_empty();
+        for (i, row) in residual.iter().enumerate() {
+            for (j, &r) in row.iter().enumerate() {
+                let sample = &mut self.curr_frame[plane][y + i][x + j];
+                *sample = (*sample as i32 + r).clamp(0, max) as u16;
+                if keep {
+                    self.residual_frame[plane][y + i][x + j] = r;
+                }
+            }
+        }
+    }
+
+    /// The set of transform types the current block can use for a transform of size tx_sz.
+    pub(super) fn get_tx_set(&self, tx_sz: TxSize) -> TxSet {
+        let tx_sz_sqr = tx_sz.sqr();
+        let tx_sz_sqr_up = tx_sz.sqr_up();
+        if tx_sz_sqr_up.width() > 32 {
+            return TxSet::DctOnly;
+        }
+
+        if self.is_inter {
+            if self.uh.reduced_tx_set || tx_sz_sqr_up == TxSize::Tx32x32 {
+                TxSet::Inter3
+            } else if tx_sz_sqr == TxSize::Tx16x16 {
+                TxSet::Inter2
+            } else {
+                TxSet::Inter1
+            }
+        } else if tx_sz_sqr_up == TxSize::Tx32x32 {
+            TxSet::DctOnly
+        } else if self.uh.reduced_tx_set || tx_sz_sqr == TxSize::Tx16x16 {
+            TxSet::Intra2
+        } else {
+            TxSet::Intra1
+        }
+    }
+
+    /// The transform size used by the given plane. Chroma uses the largest transform that
+    /// fits, but never one with a side of 64.
+    pub(super) fn get_tx_size(&self, plane: usize, tx_sz: TxSize) -> TxSize {
+        if plane == 0 {
+            return tx_sz;
+        }
+
+        let uv_tx =
+            Decoder::MAX_TX_SIZE_RECT[self.get_plane_residual_size(self.mi_size, plane) as usize];
+        if uv_tx.width() == 64 || uv_tx.height() == 64 {
+            if uv_tx.width() == 16 {
+                TxSize::Tx16x32
+            } else if uv_tx.height() == 16 {
+                TxSize::Tx32x16
+            } else {
+                TxSize::Tx32x32
+            }
+        } else {
+            uv_tx
+        }
+    }
+
+    /// Chroma is coded with the last of the blocks that share a chroma block, when a
+    /// subsampled dimension of the block is a single 4x4.
+    pub(super) fn compute_has_chroma(&mut self) {
+        let color_config = &self.sequence_header.color_config;
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+
+        let skipped_y = bh4 == 1 && color_config.subsampling_y && self.mi_row & 1 == 0;
+        let skipped_x = bw4 == 1 && color_config.subsampling_x && self.mi_col & 1 == 0;
+        self.has_chroma = !skipped_y && !skipped_x && matches!(self.num_planes, NumPlanes::Three);
+    }
+
+    /// Decodes the mode info and residual of one block and reconstructs it.
+    pub(super) fn decode_block(&mut self, b: &mut BitStream, r: u64, c: u64, sub_size: SubSize) {
+        self.mi_row = r;
+        self.mi_col = c;
+        self.mi_size = sub_size;
+        self.compute_has_chroma();
+
+        let (r, c) = (r as i64, c as i64);
+        self.avail_u = self.is_inside(r - 1, c);
+        self.avail_l = self.is_inside(r, c - 1);
+        self.avail_u_chroma = self.avail_u;
+        self.avail_l_chroma = self.avail_l;
+        if self.has_chroma {
+            let color_config = &self.sequence_header.color_config;
+            let (subsampling_x, subsampling_y) =
+                (color_config.subsampling_x, color_config.subsampling_y);
+            if subsampling_y && Decoder::NUM_4X4_BLOCKS_HIGH[sub_size as usize] == 1 {
+                self.avail_u_chroma = self.is_inside(r - 2, c);
+            }
+            if subsampling_x && Decoder::NUM_4X4_BLOCKS_WIDE[sub_size as usize] == 1 {
+                self.avail_l_chroma = self.is_inside(r, c - 2);
+            }
+        } else {
+            self.avail_u_chroma = false;
+            self.avail_l_chroma = false;
+        }
+
+        self.mode_info(b);
+        self.palette_tokens(b);
+        self.read_block_tx_size(b);
+        if self.skip {
+            self.reset_block_context();
+        }
+
+        self.store_mode_info();
+        self.compute_prediction();
+        self.residual(b);
+        self.record_block_info();
+    }
+
+    /// Skipped blocks have no coefficients, which later blocks see as zero levels.
+    fn reset_block_context(&mut self) {
+        let color_config = &self.sequence_header.color_config;
+        let (subsampling_x, subsampling_y) = (
+            color_config.subsampling_x as usize,
+            color_config.subsampling_y as usize,
+        );
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+        let mi_row = self.mi_row as usize;
+        let mi_col = self.mi_col as usize;
+
+        for plane in 0..1 + 2 * self.has_chroma as usize {
+            let (sub_x, sub_y) = if plane > 0 {
+                (subsampling_x, subsampling_y)
+            } else {
+                (0, 0)
+            };
+
+            for i in mi_col >> sub_x..(mi_col + bw4) >> sub_x {
+                self.above_level_context[plane][i] = 0;
+                self.above_dc_context[plane][i] = 0;
+            }
+            for i in mi_row >> sub_y..(mi_row + bh4) >> sub_y {
+                self.left_level_context[plane][i] = 0;
+                self.left_dc_context[plane][i] = 0;
+            }
+        }
+    }
+
+    /// Marks which 4x4 blocks around the superblock at (r, c) count as decoded for the
+    /// above right and below left availability of intra prediction. `block_decoded` is
+    /// offset by one, so index 0 is the row above and the column left of the superblock.
+    pub(super) fn clear_block_decoded_flags(&mut self, r: u64, c: u64, sb_size4: usize) {
+        let color_config = &self.sequence_header.color_config;
+        let (subsampling_x, subsampling_y) = (
+            color_config.subsampling_x as usize,
+            color_config.subsampling_y as usize,
+        );
+        let num_planes = self.num_planes as usize;
+        self.block_decoded = vec![vec![vec![false; sb_size4 + 2]; sb_size4 + 2]; num_planes];
+
+        for plane in 0..num_planes {
+            let (sub_x, sub_y) = if plane > 0 {
+                (subsampling_x, subsampling_y)
+            } else {
+                (0, 0)
+            };
+            let sb_width4 = (self.mi_col_end as usize - c as usize) >> sub_x;
+            let sb_height4 = (self.mi_row_end as usize - r as usize) >> sub_y;
+
+            for (y, row) in self.block_decoded[plane]
+                .iter_mut()
+                .enumerate()
+                .take((sb_size4 >> sub_y) + 2)
+            {
+                for (x, decoded) in row.iter_mut().enumerate().take((sb_size4 >> sub_x) + 2) {
+                    // y and x are one more than the spec's.
+                    *decoded = (y == 0 && x <= sb_width4) || (x == 0 && y <= sb_height4);
+                }
+            }
+
+            self.block_decoded[plane][(sb_size4 >> sub_y) + 1][0] = false;
+        }
+    }
+
+    /// Predicts inter blocks. Intra blocks are predicted one transform block at a time in
+    /// transform_block(), as each depends on the reconstruction of the previous ones.
+    fn compute_prediction(&mut self) {
+        if self.is_inter {
+            self.predict_inter_block();
+        }
+    }
+
+    /// Visits the mode info positions of the current block that are inside the frame.
+    pub(super) fn block_positions(&self) -> Vec<(usize, usize)> {
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as u64;
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as u64;
+
+        let mut positions = Vec::new();
+        for y in 0..bh4 {
+            for x in 0..bw4 {
+                let row = self.mi_row + y;
+                let col = self.mi_col + x;
+                if row < self.mi_rows && col < self.mi_cols {
+                    positions.push((row as usize, col as usize));
+                }
+            }
+        }
+
+        positions
+    }
+
+    /// Stores the mode info of the current block. This happens before prediction, because
+    /// the prediction of chroma for small blocks looks at the modes of the current block.
+    pub(super) fn store_mode_info(&mut self) {
+        let is_compound = self.ref_frame[1] > Decoder::INTRA_FRAME;
+
+        for (row, col) in self.block_positions() {
+            self.y_modes[row][col] = self.y_mode;
+            self.uv_modes[row][col] = self.uv_mode;
+            self.skip_modes[row][col] = self.skip_mode;
+            self.ref_frames[row][col] = self.ref_frame;
+            self.ref_frames_written[row][col] = true;
+
+            if self.is_inter {
+                for ref_list in 0..1 + is_compound as usize {
+                    self.mvs[row][col][ref_list] = self.mv[ref_list];
+                }
+
+                self.interp_filters[row][col] = self.interp_filter;
+                self.comp_group_idxs[row][col] = self.comp_group_idx;
+                self.compound_idxs[row][col] = self.compound_idx;
+            }
+        }
+    }
+
+    /// Stores the state of the block that has just been decoded, both for the context
+    /// derivation of later blocks and in `block_infos`.
+    pub(super) fn record_block_info(&mut self) {
+        for (row, col) in self.block_positions() {
+            self.is_inters[row][col] = self.is_inter;
+            self.mi_sizes[row][col] = self.mi_size;
+            self.palette_sizes[0][row][col] = self.palette_size_y;
+            self.palette_sizes[1][row][col] = self.palette_size_uv;
+            self.palette_colors[0][row][col] = self.palette_colors_y.clone();
+            self.palette_colors[1][row][col] = self.palette_colors_u.clone();
+            self.skips[row][col] = self.skip;
+            self.segment_ids[row][col] = self.segment_id;
+            self.delta_lfs[row][col].copy_from_slice(&self.delta_lf);
+        }
+
+        self.block_infos.push(BlockInfo {
+            mi_row: self.mi_row,
+            mi_col: self.mi_col,
+            mi_size: self.mi_size,
+            y_mode: self.y_mode,
+            uv_mode: self.uv_mode,
+            palette_size_y: self.palette_size_y,
+            palette_size_uv: self.palette_size_uv,
+            palette_colors_y: self.palette_colors_y.clone(),
+            palette_colors_u: self.palette_colors_u.clone(),
+            palette_colors_v: self.palette_colors_v.clone(),
+            is_inter: self.is_inter,
+            use_intrabc: self.use_intrabc,
+            ref_frame: self.ref_frame,
+            mv: self.mv,
+            motion_mode: self.motion_mode,
+            compound_type: self.compound_type,
+            interintra: self.interintra,
+            segment_id: self.segment_id,
+            qindex: self.get_qindex(
+                false,
+                self.segment_id as usize,
+                self.uh.segmentation_enabled,
+                self.uh.delta_q_present,
+                self.uh.quantization_params.base_q_idx,
+            ),
+            filter_levels: self.block_filter_levels(),
+            bits: self.symbol.take_bits(),
+        });
+    }
+}
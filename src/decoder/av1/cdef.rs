@@ -0,0 +1,325 @@
+use super::{
+    block::SubSize,
+    symbol::{floor_log2, SyntaxCategory},
+    BitStream, Decoder, NumPlanes,
+};
+
+// https://aomediacodec.github.io/av1-spec/#cdef-process
+
+impl Decoder {
+    pub const MI_SIZE_LOG2: i64 = 2;
+    const CDEF_SIZE4: usize = 16;
+    const CDEF_UV_DIR: [[[usize; 8]; 2]; 2] = [
+        [[0, 1, 2, 3, 4, 5, 6, 7], [1, 2, 2, 2, 3, 4, 6, 0]],
+        [[7, 0, 2, 4, 5, 6, 6, 6], [0, 1, 2, 3, 4, 5, 6, 7]],
+    ];
+    const DIV_TABLE: [i64; 9] = [0, 840, 420, 280, 210, 168, 140, 120, 105];
+    const CDEF_PRI_TAPS: [[i64; 2]; 2] = [[4, 2], [3, 3]];
+    const CDEF_SEC_TAPS: [[i64; 2]; 2] = [[2, 1], [2, 1]];
+    /// Cdef_Directions[dir][k] is the (row, col) offset of the k-th tap along a direction.
+    const CDEF_DIRECTIONS: [[[i64; 2]; 2]; 8] = [
+        [[-1, 1], [-2, 2]],
+        [[0, 1], [-1, 2]],
+        [[0, 1], [0, 2]],
+        [[0, 1], [1, 2]],
+        [[1, 1], [2, 2]],
+        [[1, 0], [2, 1]],
+        [[1, 0], [2, 0]],
+        [[1, 0], [2, -1]],
+    ];
+
+    /// Marks the 64x64 blocks of a superblock as not having a cdef_idx yet.
+    pub(super) fn clear_cdef(&mut self, r: usize, c: usize) {
+        self.cdef_idx[r][c] = -1;
+
+        if self.sequence_header.use_128x128_superblock {
+            let size = Decoder::CDEF_SIZE4;
+            for (row, col) in [(r, c + size), (r + size, c), (r + size, c + size)] {
+                if row < self.mi_rows as usize && col < self.mi_cols as usize {
+                    self.cdef_idx[row][col] = -1;
+                }
+            }
+        }
+    }
+
+    /// Reads the cdef_idx of the 64x64 block(s) covered by the current block, the first
+    /// time a non-skipped block in them is seen.
+    pub(super) fn read_cdef(&mut self, b: &mut BitStream) {
+        if self.skip
+            || self.coded_lossless
+            || !self.sequence_header.enable_cdef
+            || self.uh.allow_intrabc
+        {
+            return;
+        }
+
+        let cdef_mask4 = !(Decoder::CDEF_SIZE4 - 1);
+        let r = self.mi_row as usize & cdef_mask4;
+        let c = self.mi_col as usize & cdef_mask4;
+
+        if self.cdef_idx[r][c] == -1 {
+            let category = self.symbol.set_category(SyntaxCategory::Filter);
+            self.cdef_idx[r][c] = self.symbol.read_literal(b, self.uh.cdef_params.cdef_bits) as i64;
+            self.symbol.set_category(category);
+
+            let w4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+            let h4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+            for y in (r..r + h4).step_by(Decoder::CDEF_SIZE4) {
+                for x in (c..c + w4).step_by(Decoder::CDEF_SIZE4) {
+                    if y < self.mi_rows as usize && x < self.mi_cols as usize {
+                        self.cdef_idx[y][x] = self.cdef_idx[r][c];
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies CDEF to every 8x8 block of the frame, writing the result to cdef_frame.
+    pub(super) fn cdef_frame(&mut self) {
+        self.cdef_frame = self.curr_frame.clone();
+
+        let step4 = Decoder::NUM_4X4_BLOCKS_WIDE[SubSize::Block8x8 as usize];
+        let cdef_mask4 = !(Decoder::CDEF_SIZE4 - 1);
+        for r in (0..self.mi_rows as usize).step_by(step4) {
+            for c in (0..self.mi_cols as usize).step_by(step4) {
+                let idx = self.cdef_idx[r & cdef_mask4][c & cdef_mask4];
+                self.cdef_block(r, c, idx);
+            }
+        }
+    }
+
+    fn cdef_block(&mut self, r: usize, c: usize, idx: i64) {
+        if idx == -1 {
+            return;
+        }
+
+        let skip = self.skips[r][c]
+            && self.skips[r + 1][c]
+            && self.skips[r][c + 1]
+            && self.skips[r + 1][c + 1];
+        if skip {
+            return;
+        }
+
+        let idx = idx as usize;
+        let coeff_shift = self.bit_depth as i64 - 8;
+        let cdef_params = &self.uh.cdef_params;
+
+        let (y_dir, var) = self.cdef_direction(r, c);
+        self.cdef_directions[r >> 1][c >> 1] = Some(y_dir);
+        self.cdef_variances[r >> 1][c >> 1] = var;
+
+        let pri_str = (cdef_params.cdef_y_pri_strength[idx] as i64) << coeff_shift;
+        let sec_str = (cdef_params.cdef_y_sec_strength[idx] as i64) << coeff_shift;
+        let dir = if pri_str == 0 { 0 } else { y_dir };
+        let var_str = if (var >> 6) != 0 {
+            (floor_log2((var >> 6) as u64) as i64).min(12)
+        } else {
+            0
+        };
+        let pri_str = if var != 0 {
+            (pri_str * (4 + var_str) + 8) >> 4
+        } else {
+            0
+        };
+        let damping = self.cdef_damping as i64 + coeff_shift;
+        self.cdef_filter(0, r, c, pri_str, sec_str, damping, dir);
+
+        if matches!(self.num_planes, NumPlanes::One) {
+            return;
+        }
+
+        let cdef_params = &self.uh.cdef_params;
+        let color_config = &self.sequence_header.color_config;
+        let pri_str = (cdef_params.cdef_uv_pri_strength[idx] as i64) << coeff_shift;
+        let sec_str = (cdef_params.cdef_uv_sec_strength[idx] as i64) << coeff_shift;
+        let dir = if pri_str == 0 {
+            0
+        } else {
+            Decoder::CDEF_UV_DIR[color_config.subsampling_x as usize]
+                [color_config.subsampling_y as usize][y_dir]
+        };
+        let damping = self.cdef_damping as i64 + coeff_shift - 1;
+        self.cdef_filter(1, r, c, pri_str, sec_str, damping, dir);
+        self.cdef_filter(2, r, c, pri_str, sec_str, damping, dir);
+    }
+
+    /// Finds the dominant direction of the 8x8 luma block at (r, c), returning it with
+    /// the directional contrast used to scale the primary strength.
+    pub fn cdef_direction(&self, r: usize, c: usize) -> (usize, i64) {
+        let mut cost = [0i64; 8];
+        let mut partial = [[0i64; 15]; 8];
+
+        let x0 = c * Decoder::MI_SIZE as usize;
+        let y0 = r * Decoder::MI_SIZE as usize;
+        let shift = self.bit_depth as i64 - 8;
+
+        for i in 0..8 {
+            for j in 0..8 {
+                let x = (self.curr_frame[0][y0 + i][x0 + j] as i64 >> shift) - 128;
+                partial[0][i + j] += x;
+                partial[1][i + j / 2] += x;
+                partial[2][i] += x;
+                partial[3][3 + i - j / 2] += x;
+                partial[4][7 + i - j] += x;
+                partial[5][3 - i / 2 + j] += x;
+                partial[6][j] += x;
+                partial[7][i / 2 + j] += x;
+            }
+        }
+
+        cost[2] = partial[2][..8].iter().map(|p| p * p).sum::<i64>() * Decoder::DIV_TABLE[8];
+        cost[6] = partial[6][..8].iter().map(|p| p * p).sum::<i64>() * Decoder::DIV_TABLE[8];
+
+        for i in 0..7 {
+            cost[0] += (partial[0][i] * partial[0][i] + partial[0][14 - i] * partial[0][14 - i])
+                * Decoder::DIV_TABLE[i + 1];
+            cost[4] += (partial[4][i] * partial[4][i] + partial[4][14 - i] * partial[4][14 - i])
+                * Decoder::DIV_TABLE[i + 1];
+        }
+        cost[0] += partial[0][7] * partial[0][7] * Decoder::DIV_TABLE[8];
+        cost[4] += partial[4][7] * partial[4][7] * Decoder::DIV_TABLE[8];
+
+        for i in (1..8).step_by(2) {
+            for j in 0..5 {
+                cost[i] += partial[i][3 + j] * partial[i][3 + j];
+            }
+            cost[i] *= Decoder::DIV_TABLE[8];
+
+            for j in 0..3 {
+                cost[i] += (partial[i][j] * partial[i][j]
+                    + partial[i][10 - j] * partial[i][10 - j])
+                    * Decoder::DIV_TABLE[2 * j + 2];
+            }
+        }
+
+        let mut best_cost = 0;
+        let mut y_dir = 0;
+        for (i, &cost) in cost.iter().enumerate() {
+            if cost > best_cost {
+                best_cost = cost;
+                y_dir = i;
+            }
+        }
+
+        let var = (best_cost - cost[(y_dir + 4) & 7]) >> 10;
+
+        (y_dir, var)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn cdef_filter(
+        &mut self,
+        plane: usize,
+        r: usize,
+        c: usize,
+        pri_str: i64,
+        sec_str: i64,
+        damping: i64,
+        dir: usize,
+    ) {
+        let coeff_shift = self.bit_depth as i64 - 8;
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane == 0 {
+            (0, 0)
+        } else {
+            (
+                color_config.subsampling_x as usize,
+                color_config.subsampling_y as usize,
+            )
+        };
+
+        let x0 = (c * Decoder::MI_SIZE as usize) >> sub_x;
+        let y0 = (r * Decoder::MI_SIZE as usize) >> sub_y;
+        let w = 8 >> sub_x;
+        let h = 8 >> sub_y;
+
+        let pri_taps = Decoder::CDEF_PRI_TAPS[((pri_str >> coeff_shift) & 1) as usize];
+        let sec_taps = Decoder::CDEF_SEC_TAPS[((pri_str >> coeff_shift) & 1) as usize];
+
+        for i in 0..h {
+            for j in 0..w {
+                let x = self.curr_frame[plane][y0 + i][x0 + j] as i64;
+                let mut sum = 0;
+                let mut max = x;
+                let mut min = x;
+
+                for k in 0..2 {
+                    for sign in [-1, 1] {
+                        let offset = Decoder::CDEF_DIRECTIONS[dir][k];
+                        if let Some(p) = self.cdef_get_at(
+                            plane,
+                            (x0, y0),
+                            i as i64 + sign * offset[0],
+                            j as i64 + sign * offset[1],
+                            (sub_x, sub_y),
+                        ) {
+                            sum += pri_taps[k] * Decoder::constrain(p - x, pri_str, damping);
+                            max = max.max(p);
+                            min = min.min(p);
+                        }
+
+                        for dir_off in [-2, 2] {
+                            let offset =
+                                Decoder::CDEF_DIRECTIONS[(dir as i64 + dir_off) as usize & 7][k];
+                            if let Some(s) = self.cdef_get_at(
+                                plane,
+                                (x0, y0),
+                                i as i64 + sign * offset[0],
+                                j as i64 + sign * offset[1],
+                                (sub_x, sub_y),
+                            ) {
+                                sum += sec_taps[k] * Decoder::constrain(s - x, sec_str, damping);
+                                max = max.max(s);
+                                min = min.min(s);
+                            }
+                        }
+                    }
+                }
+
+                let filtered = x + ((8 + sum - (sum < 0) as i64) >> 4);
+                self.cdef_frame[plane][y0 + i][x0 + j] = filtered.clamp(min, max) as u16;
+            }
+        }
+    }
+
+    /// The sample at offset (i, j) from (x0, y0), or None if it lies outside the frame.
+    fn cdef_get_at(
+        &self,
+        plane: usize,
+        (x0, y0): (usize, usize),
+        i: i64,
+        j: i64,
+        (sub_x, sub_y): (usize, usize),
+    ) -> Option<i64> {
+        let y = y0 as i64 + i;
+        let x = x0 as i64 + j;
+
+        let candidate_r = (y << sub_y) >> Decoder::MI_SIZE_LOG2;
+        let candidate_c = (x << sub_x) >> Decoder::MI_SIZE_LOG2;
+
+        if self.is_inside_filter_region(candidate_r, candidate_c) {
+            Some(self.curr_frame[plane][y as usize][x as usize] as i64)
+        } else {
+            None
+        }
+    }
+
+    fn is_inside_filter_region(&self, candidate_r: i64, candidate_c: i64) -> bool {
+        (0..self.mi_cols as i64).contains(&candidate_c)
+            && (0..self.mi_rows as i64).contains(&candidate_r)
+    }
+
+    fn constrain(diff: i64, threshold: i64, damping: i64) -> i64 {
+        if threshold == 0 {
+            return 0;
+        }
+
+        let damping_adj = (damping - floor_log2(threshold as u64) as i64).max(0);
+        let val = diff
+            .abs()
+            .min((threshold - (diff.abs() >> damping_adj)).max(0));
+
+        diff.signum() * val
+    }
+}
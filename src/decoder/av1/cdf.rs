@@ -0,0 +1,405 @@
+// https://aomediacodec.github.io/av1-spec/#initialization-process-for-symbol-decoder
+
+/// The adaptive cumulative distribution functions used by the symbol decoder.
+///
+/// Every CDF holds N cumulative probabilities followed by an adaptation counter. The
+/// default values are loaded by `init_non_coeff_cdfs()` and `init_coeff_cdfs()`.
+#[derive(Debug, Default, Clone)]
+pub struct Cdfs {
+    /// [above_ctx][left_ctx]
+    pub intra_frame_y_mode: Vec<Vec<Vec<u16>>>,
+    /// [size_group]
+    pub y_mode: Vec<Vec<u16>>,
+    /// [y_mode]
+    pub uv_mode_cfl_not_allowed: Vec<Vec<u16>>,
+    /// [y_mode]
+    pub uv_mode_cfl_allowed: Vec<Vec<u16>>,
+    /// [mode - V_PRED]
+    pub angle_delta: Vec<Vec<u16>>,
+    /// [ctx]
+    pub partition_w8: Vec<Vec<u16>>,
+    /// [ctx]
+    pub partition_w16: Vec<Vec<u16>>,
+    /// [ctx]
+    pub partition_w32: Vec<Vec<u16>>,
+    /// [ctx]
+    pub partition_w64: Vec<Vec<u16>>,
+    /// [ctx]
+    pub partition_w128: Vec<Vec<u16>>,
+    /// [ctx]
+    pub segment_id: Vec<Vec<u16>>,
+    /// [ctx]
+    pub segment_id_predicted: Vec<Vec<u16>>,
+    /// [max_tx_depth - 1][ctx]
+    pub tx_size: Vec<Vec<Vec<u16>>>,
+    /// [ctx]
+    pub txfm_split: Vec<Vec<u16>>,
+    pub filter_intra_mode: Vec<u16>,
+    /// [mi_size]
+    pub filter_intra: Vec<Vec<u16>>,
+    pub delta_q: Vec<u16>,
+    pub delta_lf: Vec<u16>,
+    /// [i]
+    pub delta_lf_multi: Vec<Vec<u16>>,
+    /// [tx_sz_sqr][intra_dir]
+    pub intra_tx_type_set1: Vec<Vec<Vec<u16>>>,
+    /// [tx_sz_sqr][intra_dir]
+    pub intra_tx_type_set2: Vec<Vec<Vec<u16>>>,
+    /// [tx_sz_sqr]
+    pub inter_tx_type_set1: Vec<Vec<u16>>,
+    /// [tx_sz_sqr]
+    pub inter_tx_type_set2: Vec<Vec<u16>>,
+    /// [tx_sz_sqr]
+    pub inter_tx_type_set3: Vec<Vec<u16>>,
+    pub cfl_sign: Vec<u16>,
+    /// [ctx]
+    pub cfl_alpha: Vec<Vec<u16>>,
+    /// [ctx]
+    pub skip_mode: Vec<Vec<u16>>,
+    /// [ctx]
+    pub skip: Vec<Vec<u16>>,
+    /// [ctx]
+    pub is_inter: Vec<Vec<u16>>,
+    /// [ctx]
+    pub comp_mode: Vec<Vec<u16>>,
+    /// [ctx]
+    pub comp_ref_type: Vec<Vec<u16>>,
+    /// [ctx][n]
+    pub uni_comp_ref: Vec<Vec<Vec<u16>>>,
+    /// [ctx][n]
+    pub comp_ref: Vec<Vec<Vec<u16>>>,
+    /// [ctx][n]
+    pub comp_bwd_ref: Vec<Vec<Vec<u16>>>,
+    /// [ctx][n]
+    pub single_ref: Vec<Vec<Vec<u16>>>,
+    /// [ctx]
+    pub compound_mode: Vec<Vec<u16>>,
+    /// [ctx]
+    pub new_mv: Vec<Vec<u16>>,
+    /// [ctx]
+    pub zero_mv: Vec<Vec<u16>>,
+    /// [ctx]
+    pub ref_mv: Vec<Vec<u16>>,
+    /// [ctx]
+    pub drl_mode: Vec<Vec<u16>>,
+    /// [bsize_ctx][ctx]
+    pub palette_y_mode: Vec<Vec<Vec<u16>>>,
+    /// [ctx]
+    pub palette_uv_mode: Vec<Vec<u16>>,
+    /// [bsize_ctx]
+    pub palette_y_size: Vec<Vec<u16>>,
+    /// [bsize_ctx]
+    pub palette_uv_size: Vec<Vec<u16>>,
+    /// [palette_size - 2][ctx]
+    pub palette_y_color: Vec<Vec<Vec<u16>>>,
+    /// [palette_size - 2][ctx]
+    pub palette_uv_color: Vec<Vec<Vec<u16>>>,
+    pub intrabc: Vec<u16>,
+    /// [ctx]
+    pub interp_filter: Vec<Vec<u16>>,
+    /// [mi_size]
+    pub use_obmc: Vec<Vec<u16>>,
+    /// [mi_size]
+    pub motion_mode: Vec<Vec<u16>>,
+    /// [size_group - 1]
+    pub interintra: Vec<Vec<u16>>,
+    /// [size_group - 1]
+    pub interintra_mode: Vec<Vec<u16>>,
+    /// [mi_size]
+    pub wedge_interintra: Vec<Vec<u16>>,
+    /// [mi_size]
+    pub wedge_index: Vec<Vec<u16>>,
+    /// [ctx]
+    pub comp_group_idx: Vec<Vec<u16>>,
+    /// [ctx]
+    pub compound_idx: Vec<Vec<u16>>,
+    /// [mi_size]
+    pub compound_type: Vec<Vec<u16>>,
+    pub use_wiener: Vec<u16>,
+    pub use_sgrproj: Vec<u16>,
+    pub restoration_type: Vec<u16>,
+    /// [mv_ctx]
+    pub mv_joint: Vec<Vec<u16>>,
+    /// [mv_ctx][comp]
+    pub mv_sign: Vec<Vec<Vec<u16>>>,
+    /// [mv_ctx][comp]
+    pub mv_class: Vec<Vec<Vec<u16>>>,
+    /// [mv_ctx][comp]
+    pub mv_class0_bit: Vec<Vec<Vec<u16>>>,
+    /// [mv_ctx][comp][mv_class0_bit]
+    pub mv_class0_fr: Vec<Vec<Vec<Vec<u16>>>>,
+    /// [mv_ctx][comp]
+    pub mv_class0_hp: Vec<Vec<Vec<u16>>>,
+    /// [mv_ctx][comp][i]
+    pub mv_bit: Vec<Vec<Vec<Vec<u16>>>>,
+    /// [mv_ctx][comp]
+    pub mv_fr: Vec<Vec<Vec<u16>>>,
+    /// [mv_ctx][comp]
+    pub mv_hp: Vec<Vec<Vec<u16>>>,
+    /// [tx_sz_ctx][ctx]
+    pub txb_skip: Vec<Vec<Vec<u16>>>,
+    /// [eob_multi_size][ptype][ctx], with eob_multi_size 0 for 16 coefficients up to 6
+    /// for 1024.
+    pub eob_pt: Vec<Vec<Vec<Vec<u16>>>>,
+    /// [tx_sz_ctx][ptype][ctx]
+    pub eob_extra: Vec<Vec<Vec<Vec<u16>>>>,
+    /// [ptype][ctx]
+    pub dc_sign: Vec<Vec<Vec<u16>>>,
+    /// [tx_sz_ctx][ptype][ctx]
+    pub coeff_base_eob: Vec<Vec<Vec<Vec<u16>>>>,
+    /// [tx_sz_ctx][ptype][ctx]
+    pub coeff_base: Vec<Vec<Vec<Vec<u16>>>>,
+    /// [tx_sz_ctx][ptype][ctx]
+    pub coeff_br: Vec<Vec<Vec<Vec<u16>>>>,
+}
+
+/// Turns one of the default tables into the nested Vecs that Cdfs holds.
+trait ToCdfs {
+    type Output;
+
+    fn to_cdfs(&self) -> Self::Output;
+}
+
+impl<const N: usize> ToCdfs for [u16; N] {
+    type Output = Vec<u16>;
+
+    fn to_cdfs(&self) -> Vec<u16> {
+        self.to_vec()
+    }
+}
+
+impl<T: ToCdfs, const N: usize> ToCdfs for [T; N] {
+    type Output = Vec<T::Output>;
+
+    fn to_cdfs(&self) -> Self::Output {
+        self.iter().map(ToCdfs::to_cdfs).collect()
+    }
+}
+
+/// A CDF or a nested array of them.
+trait CdfArray {
+    fn clear_counters(&mut self);
+}
+
+impl CdfArray for Vec<u16> {
+    fn clear_counters(&mut self) {
+        if let Some(counter) = self.last_mut() {
+            *counter = 0;
+        }
+    }
+}
+
+impl<T: CdfArray> CdfArray for Vec<T> {
+    fn clear_counters(&mut self) {
+        self.iter_mut().for_each(CdfArray::clear_counters);
+    }
+}
+
+impl Cdfs {
+    const MV_CONTEXTS: usize = 2;
+
+    /// init_non_coeff_cdfs()
+    pub fn init_non_coeff_cdfs(&mut self) {
+        self.intra_frame_y_mode = Cdfs::DEFAULT_INTRA_FRAME_Y_MODE_CDF.to_cdfs();
+        self.y_mode = Cdfs::DEFAULT_Y_MODE_CDF.to_cdfs();
+        self.uv_mode_cfl_not_allowed = Cdfs::DEFAULT_UV_MODE_CFL_NOT_ALLOWED_CDF.to_cdfs();
+        self.uv_mode_cfl_allowed = Cdfs::DEFAULT_UV_MODE_CFL_ALLOWED_CDF.to_cdfs();
+        self.angle_delta = Cdfs::DEFAULT_ANGLE_DELTA_CDF.to_cdfs();
+        self.partition_w8 = Cdfs::DEFAULT_PARTITION_W8_CDF.to_cdfs();
+        self.partition_w16 = Cdfs::DEFAULT_PARTITION_W16_CDF.to_cdfs();
+        self.partition_w32 = Cdfs::DEFAULT_PARTITION_W32_CDF.to_cdfs();
+        self.partition_w64 = Cdfs::DEFAULT_PARTITION_W64_CDF.to_cdfs();
+        self.partition_w128 = Cdfs::DEFAULT_PARTITION_W128_CDF.to_cdfs();
+        self.segment_id = Cdfs::DEFAULT_SEGMENT_ID_CDF.to_cdfs();
+        self.segment_id_predicted = Cdfs::DEFAULT_SEGMENT_ID_PREDICTED_CDF.to_cdfs();
+        self.tx_size = vec![
+            Cdfs::DEFAULT_TX_8X8_CDF.to_cdfs(),
+            Cdfs::DEFAULT_TX_16X16_CDF.to_cdfs(),
+            Cdfs::DEFAULT_TX_32X32_CDF.to_cdfs(),
+            Cdfs::DEFAULT_TX_64X64_CDF.to_cdfs(),
+        ];
+        self.txfm_split = Cdfs::DEFAULT_TXFM_SPLIT_CDF.to_cdfs();
+        self.filter_intra_mode = Cdfs::DEFAULT_FILTER_INTRA_MODE_CDF.to_cdfs();
+        self.filter_intra = Cdfs::DEFAULT_FILTER_INTRA_CDF.to_cdfs();
+        self.delta_q = Cdfs::DEFAULT_DELTA_Q_CDF.to_cdfs();
+        self.delta_lf = Cdfs::DEFAULT_DELTA_LF_CDF.to_cdfs();
+        self.delta_lf_multi = Cdfs::DEFAULT_DELTA_LF_MULTI_CDF.to_cdfs();
+        self.intra_tx_type_set1 = Cdfs::DEFAULT_INTRA_TX_TYPE_SET1_CDF.to_cdfs();
+        self.intra_tx_type_set2 = Cdfs::DEFAULT_INTRA_TX_TYPE_SET2_CDF.to_cdfs();
+        self.inter_tx_type_set1 = Cdfs::DEFAULT_INTER_TX_TYPE_SET1_CDF.to_cdfs();
+        self.inter_tx_type_set2 = Cdfs::DEFAULT_INTER_TX_TYPE_SET2_CDF.to_cdfs();
+        self.inter_tx_type_set3 = Cdfs::DEFAULT_INTER_TX_TYPE_SET3_CDF.to_cdfs();
+        self.cfl_sign = Cdfs::DEFAULT_CFL_SIGN_CDF.to_cdfs();
+        self.cfl_alpha = Cdfs::DEFAULT_CFL_ALPHA_CDF.to_cdfs();
+        self.skip_mode = Cdfs::DEFAULT_SKIP_MODE_CDF.to_cdfs();
+        self.skip = Cdfs::DEFAULT_SKIP_CDF.to_cdfs();
+        self.is_inter = Cdfs::DEFAULT_IS_INTER_CDF.to_cdfs();
+        self.comp_mode = Cdfs::DEFAULT_COMP_MODE_CDF.to_cdfs();
+        self.comp_ref_type = Cdfs::DEFAULT_COMP_REF_TYPE_CDF.to_cdfs();
+        self.uni_comp_ref = Cdfs::DEFAULT_UNI_COMP_REF_CDF.to_cdfs();
+        self.comp_ref = Cdfs::DEFAULT_COMP_REF_CDF.to_cdfs();
+        self.comp_bwd_ref = Cdfs::DEFAULT_COMP_BWD_REF_CDF.to_cdfs();
+        self.single_ref = Cdfs::DEFAULT_SINGLE_REF_CDF.to_cdfs();
+        self.compound_mode = Cdfs::DEFAULT_COMPOUND_MODE_CDF.to_cdfs();
+        self.new_mv = Cdfs::DEFAULT_NEW_MV_CDF.to_cdfs();
+        self.zero_mv = Cdfs::DEFAULT_ZERO_MV_CDF.to_cdfs();
+        self.ref_mv = Cdfs::DEFAULT_REF_MV_CDF.to_cdfs();
+        self.drl_mode = Cdfs::DEFAULT_DRL_MODE_CDF.to_cdfs();
+        self.palette_y_mode = Cdfs::DEFAULT_PALETTE_Y_MODE_CDF.to_cdfs();
+        self.palette_uv_mode = Cdfs::DEFAULT_PALETTE_UV_MODE_CDF.to_cdfs();
+        self.palette_y_size = Cdfs::DEFAULT_PALETTE_Y_SIZE_CDF.to_cdfs();
+        self.palette_uv_size = Cdfs::DEFAULT_PALETTE_UV_SIZE_CDF.to_cdfs();
+        self.palette_y_color = vec![
+            Cdfs::DEFAULT_PALETTE_SIZE_2_Y_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_3_Y_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_4_Y_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_5_Y_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_6_Y_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_7_Y_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_8_Y_COLOR_CDF.to_cdfs(),
+        ];
+        self.palette_uv_color = vec![
+            Cdfs::DEFAULT_PALETTE_SIZE_2_UV_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_3_UV_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_4_UV_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_5_UV_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_6_UV_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_7_UV_COLOR_CDF.to_cdfs(),
+            Cdfs::DEFAULT_PALETTE_SIZE_8_UV_COLOR_CDF.to_cdfs(),
+        ];
+        self.intrabc = Cdfs::DEFAULT_INTRABC_CDF.to_cdfs();
+        self.interp_filter = Cdfs::DEFAULT_INTERP_FILTER_CDF.to_cdfs();
+        self.use_obmc = Cdfs::DEFAULT_USE_OBMC_CDF.to_cdfs();
+        self.motion_mode = Cdfs::DEFAULT_MOTION_MODE_CDF.to_cdfs();
+        self.interintra = Cdfs::DEFAULT_INTER_INTRA_CDF.to_cdfs();
+        self.interintra_mode = Cdfs::DEFAULT_INTER_INTRA_MODE_CDF.to_cdfs();
+        self.wedge_interintra = Cdfs::DEFAULT_WEDGE_INTER_INTRA_CDF.to_cdfs();
+        self.wedge_index = Cdfs::DEFAULT_WEDGE_INDEX_CDF.to_cdfs();
+        self.comp_group_idx = Cdfs::DEFAULT_COMP_GROUP_IDX_CDF.to_cdfs();
+        self.compound_idx = Cdfs::DEFAULT_COMPOUND_IDX_CDF.to_cdfs();
+        self.compound_type = Cdfs::DEFAULT_COMPOUND_TYPE_CDF.to_cdfs();
+        self.use_wiener = Cdfs::DEFAULT_USE_WIENER_CDF.to_cdfs();
+        self.use_sgrproj = Cdfs::DEFAULT_USE_SGRPROJ_CDF.to_cdfs();
+        self.restoration_type = Cdfs::DEFAULT_RESTORATION_TYPE_CDF.to_cdfs();
+
+        // Both motion vector contexts and both components start out the same.
+        let per_comp = |cdf: Vec<u16>| vec![vec![cdf; 2]; Cdfs::MV_CONTEXTS];
+        self.mv_joint = vec![Cdfs::DEFAULT_MV_JOINT_CDF.to_cdfs(); Cdfs::MV_CONTEXTS];
+        self.mv_sign = per_comp(Cdfs::DEFAULT_MV_SIGN_CDF.to_cdfs());
+        self.mv_class = per_comp(Cdfs::DEFAULT_MV_CLASS_CDF.to_cdfs());
+        self.mv_class0_bit = per_comp(Cdfs::DEFAULT_MV_CLASS0_BIT_CDF.to_cdfs());
+        self.mv_class0_fr =
+            vec![vec![Cdfs::DEFAULT_MV_CLASS0_FR_CDF.to_cdfs(); 2]; Cdfs::MV_CONTEXTS];
+        self.mv_class0_hp = per_comp(Cdfs::DEFAULT_MV_CLASS0_HP_CDF.to_cdfs());
+        self.mv_bit = vec![vec![Cdfs::DEFAULT_MV_BIT_CDF.to_cdfs(); 2]; Cdfs::MV_CONTEXTS];
+        self.mv_fr = per_comp(Cdfs::DEFAULT_MV_FR_CDF.to_cdfs());
+        self.mv_hp = per_comp(Cdfs::DEFAULT_MV_HP_CDF.to_cdfs());
+    }
+
+    /// init_coeff_cdfs(). The defaults are picked by how coarse the quantizer is.
+    pub fn init_coeff_cdfs(&mut self, base_q_idx: u64) {
+        let idx = match base_q_idx {
+            0..=20 => 0,
+            21..=60 => 1,
+            61..=120 => 2,
+            _ => 3,
+        };
+
+        self.txb_skip = Cdfs::DEFAULT_TXB_SKIP_CDF[idx].to_cdfs();
+        self.eob_pt = vec![
+            Cdfs::DEFAULT_EOB_PT_16_CDF[idx].to_cdfs(),
+            Cdfs::DEFAULT_EOB_PT_32_CDF[idx].to_cdfs(),
+            Cdfs::DEFAULT_EOB_PT_64_CDF[idx].to_cdfs(),
+            Cdfs::DEFAULT_EOB_PT_128_CDF[idx].to_cdfs(),
+            Cdfs::DEFAULT_EOB_PT_256_CDF[idx].to_cdfs(),
+            Cdfs::DEFAULT_EOB_PT_512_CDF[idx].to_cdfs(),
+            Cdfs::DEFAULT_EOB_PT_1024_CDF[idx].to_cdfs(),
+        ];
+        self.eob_extra = Cdfs::DEFAULT_EOB_EXTRA_CDF[idx].to_cdfs();
+        self.dc_sign = Cdfs::DEFAULT_DC_SIGN_CDF[idx].to_cdfs();
+        self.coeff_base_eob = Cdfs::DEFAULT_COEFF_BASE_EOB_CDF[idx].to_cdfs();
+        self.coeff_base = Cdfs::DEFAULT_COEFF_BASE_CDF[idx].to_cdfs();
+        self.coeff_br = Cdfs::DEFAULT_COEFF_BR_CDF[idx].to_cdfs();
+    }
+
+    /// Resets the adaptation counter of every CDF, as happens when the CDFs at the end of a
+    /// tile are saved for the next frame.
+    pub fn clear_counters(&mut self) {
+        self.intra_frame_y_mode.clear_counters();
+        self.y_mode.clear_counters();
+        self.uv_mode_cfl_not_allowed.clear_counters();
+        self.uv_mode_cfl_allowed.clear_counters();
+        self.angle_delta.clear_counters();
+        self.partition_w8.clear_counters();
+        self.partition_w16.clear_counters();
+        self.partition_w32.clear_counters();
+        self.partition_w64.clear_counters();
+        self.partition_w128.clear_counters();
+        self.segment_id.clear_counters();
+        self.segment_id_predicted.clear_counters();
+        self.tx_size.clear_counters();
+        self.txfm_split.clear_counters();
+        self.filter_intra_mode.clear_counters();
+        self.filter_intra.clear_counters();
+        self.delta_q.clear_counters();
+        self.delta_lf.clear_counters();
+        self.delta_lf_multi.clear_counters();
+        self.intra_tx_type_set1.clear_counters();
+        self.intra_tx_type_set2.clear_counters();
+        self.inter_tx_type_set1.clear_counters();
+        self.inter_tx_type_set2.clear_counters();
+        self.inter_tx_type_set3.clear_counters();
+        self.cfl_sign.clear_counters();
+        self.cfl_alpha.clear_counters();
+        self.skip_mode.clear_counters();
+        self.skip.clear_counters();
+        self.is_inter.clear_counters();
+        self.comp_mode.clear_counters();
+        self.comp_ref_type.clear_counters();
+        self.uni_comp_ref.clear_counters();
+        self.comp_ref.clear_counters();
+        self.comp_bwd_ref.clear_counters();
+        self.single_ref.clear_counters();
+        self.compound_mode.clear_counters();
+        self.new_mv.clear_counters();
+        self.zero_mv.clear_counters();
+        self.ref_mv.clear_counters();
+        self.drl_mode.clear_counters();
+        self.palette_y_mode.clear_counters();
+        self.palette_uv_mode.clear_counters();
+        self.palette_y_size.clear_counters();
+        self.palette_uv_size.clear_counters();
+        self.palette_y_color.clear_counters();
+        self.palette_uv_color.clear_counters();
+        self.intrabc.clear_counters();
+        self.interp_filter.clear_counters();
+        self.use_obmc.clear_counters();
+        self.motion_mode.clear_counters();
+        self.interintra.clear_counters();
+        self.interintra_mode.clear_counters();
+        self.wedge_interintra.clear_counters();
+        self.wedge_index.clear_counters();
+        self.comp_group_idx.clear_counters();
+        self.compound_idx.clear_counters();
+        self.compound_type.clear_counters();
+        self.use_wiener.clear_counters();
+        self.use_sgrproj.clear_counters();
+        self.restoration_type.clear_counters();
+        self.mv_joint.clear_counters();
+        self.mv_sign.clear_counters();
+        self.mv_class.clear_counters();
+        self.mv_class0_bit.clear_counters();
+        self.mv_class0_fr.clear_counters();
+        self.mv_class0_hp.clear_counters();
+        self.mv_bit.clear_counters();
+        self.mv_fr.clear_counters();
+        self.mv_hp.clear_counters();
+        self.txb_skip.clear_counters();
+        self.eob_pt.clear_counters();
+        self.eob_extra.clear_counters();
+        self.dc_sign.clear_counters();
+        self.coeff_base_eob.clear_counters();
+        self.coeff_base.clear_counters();
+        self.coeff_br.clear_counters();
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt::Display;
+
+// Colour code points shared by AV1, HEVC and friends.
+// https://www.itu.int/rec/T-REC-H.273
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorPrimaries {
+    Bt709,
+    #[default]
+    Unspecified,
+    Bt470M,
+    Bt470BG,
+    Bt601,
+    Smpte240,
+    GenericFilm,
+    Bt2020,
+    Xyz,
+    Smpte431,
+    Smpte432,
+    Ebu3213,
+    Reserved(u8),
+}
+
+impl ColorPrimaries {
+    pub fn new(val: u64) -> ColorPrimaries {
+        match val {
+            1 => ColorPrimaries::Bt709,
+            2 => ColorPrimaries::Unspecified,
+            4 => ColorPrimaries::Bt470M,
+            5 => ColorPrimaries::Bt470BG,
+            6 => ColorPrimaries::Bt601,
+            7 => ColorPrimaries::Smpte240,
+            8 => ColorPrimaries::GenericFilm,
+            9 => ColorPrimaries::Bt2020,
+            10 => ColorPrimaries::Xyz,
+            11 => ColorPrimaries::Smpte431,
+            12 => ColorPrimaries::Smpte432,
+            22 => ColorPrimaries::Ebu3213,
+            0 | 3 | 13..=21 | 23..=255 => ColorPrimaries::Reserved(val as u8),
+            _ => panic!("invalid value for ColorPrimaries: {val}"),
+        }
+    }
+
+    pub fn value(self) -> u8 {
+        match self {
+            ColorPrimaries::Bt709 => 1,
+            ColorPrimaries::Unspecified => 2,
+            ColorPrimaries::Bt470M => 4,
+            ColorPrimaries::Bt470BG => 5,
+            ColorPrimaries::Bt601 => 6,
+            ColorPrimaries::Smpte240 => 7,
+            ColorPrimaries::GenericFilm => 8,
+            ColorPrimaries::Bt2020 => 9,
+            ColorPrimaries::Xyz => 10,
+            ColorPrimaries::Smpte431 => 11,
+            ColorPrimaries::Smpte432 => 12,
+            ColorPrimaries::Ebu3213 => 22,
+            ColorPrimaries::Reserved(val) => val,
+        }
+    }
+}
+
+impl Display for ColorPrimaries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorPrimaries::Bt709 => write!(f, "BT.709"),
+            ColorPrimaries::Unspecified => write!(f, "unspecified"),
+            ColorPrimaries::Bt470M => write!(f, "BT.470 System M"),
+            ColorPrimaries::Bt470BG => write!(f, "BT.470 System B, G"),
+            ColorPrimaries::Bt601 => write!(f, "BT.601"),
+            ColorPrimaries::Smpte240 => write!(f, "SMPTE 240M"),
+            ColorPrimaries::GenericFilm => write!(f, "generic film"),
+            ColorPrimaries::Bt2020 => write!(f, "BT.2020, BT.2100"),
+            ColorPrimaries::Xyz => write!(f, "SMPTE ST 428 (CIE 1931 XYZ)"),
+            ColorPrimaries::Smpte431 => write!(f, "SMPTE RP 431-2 (DCI-P3)"),
+            ColorPrimaries::Smpte432 => write!(f, "SMPTE EG 432-1 (Display P3)"),
+            ColorPrimaries::Ebu3213 => write!(f, "EBU Tech. 3213-E"),
+            ColorPrimaries::Reserved(val) => write!(f, "reserved ({val})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransferCharacteristics {
+    Bt709,
+    #[default]
+    Unspecified,
+    Bt470M,
+    Bt470BG,
+    Bt601,
+    Smpte240,
+    Linear,
+    Log100,
+    Log100Sqrt10,
+    Iec61966,
+    Bt1361,
+    Srgb,
+    Bt2020TenBit,
+    Bt2020TwelveBit,
+    /// Perceptual quantizer, SMPTE ST 2084.
+    Smpte2084,
+    Smpte428,
+    /// Hybrid log-gamma, ARIB STD-B67.
+    Hlg,
+    Reserved(u8),
+}
+
+impl TransferCharacteristics {
+    pub fn new(val: u64) -> TransferCharacteristics {
+        match val {
+            1 => TransferCharacteristics::Bt709,
+            2 => TransferCharacteristics::Unspecified,
+            4 => TransferCharacteristics::Bt470M,
+            5 => TransferCharacteristics::Bt470BG,
+            6 => TransferCharacteristics::Bt601,
+            7 => TransferCharacteristics::Smpte240,
+            8 => TransferCharacteristics::Linear,
+            9 => TransferCharacteristics::Log100,
+            10 => TransferCharacteristics::Log100Sqrt10,
+            11 => TransferCharacteristics::Iec61966,
+            12 => TransferCharacteristics::Bt1361,
+            13 => TransferCharacteristics::Srgb,
+            14 => TransferCharacteristics::Bt2020TenBit,
+            15 => TransferCharacteristics::Bt2020TwelveBit,
+            16 => TransferCharacteristics::Smpte2084,
+            17 => TransferCharacteristics::Smpte428,
+            18 => TransferCharacteristics::Hlg,
+            0 | 3 | 19..=255 => TransferCharacteristics::Reserved(val as u8),
+            _ => panic!("invalid value for TransferCharacteristics: {val}"),
+        }
+    }
+
+    pub fn value(self) -> u8 {
+        match self {
+            TransferCharacteristics::Bt709 => 1,
+            TransferCharacteristics::Unspecified => 2,
+            TransferCharacteristics::Bt470M => 4,
+            TransferCharacteristics::Bt470BG => 5,
+            TransferCharacteristics::Bt601 => 6,
+            TransferCharacteristics::Smpte240 => 7,
+            TransferCharacteristics::Linear => 8,
+            TransferCharacteristics::Log100 => 9,
+            TransferCharacteristics::Log100Sqrt10 => 10,
+            TransferCharacteristics::Iec61966 => 11,
+            TransferCharacteristics::Bt1361 => 12,
+            TransferCharacteristics::Srgb => 13,
+            TransferCharacteristics::Bt2020TenBit => 14,
+            TransferCharacteristics::Bt2020TwelveBit => 15,
+            TransferCharacteristics::Smpte2084 => 16,
+            TransferCharacteristics::Smpte428 => 17,
+            TransferCharacteristics::Hlg => 18,
+            TransferCharacteristics::Reserved(val) => val,
+        }
+    }
+}
+
+impl Display for TransferCharacteristics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferCharacteristics::Bt709 => write!(f, "BT.709"),
+            TransferCharacteristics::Unspecified => write!(f, "unspecified"),
+            TransferCharacteristics::Bt470M => write!(f, "BT.470 System M"),
+            TransferCharacteristics::Bt470BG => write!(f, "BT.470 System B, G"),
+            TransferCharacteristics::Bt601 => write!(f, "BT.601"),
+            TransferCharacteristics::Smpte240 => write!(f, "SMPTE 240M"),
+            TransferCharacteristics::Linear => write!(f, "linear"),
+            TransferCharacteristics::Log100 => write!(f, "logarithmic (100:1)"),
+            TransferCharacteristics::Log100Sqrt10 => write!(f, "logarithmic (100 * sqrt(10):1)"),
+            TransferCharacteristics::Iec61966 => write!(f, "IEC 61966-2-4"),
+            TransferCharacteristics::Bt1361 => write!(f, "BT.1361"),
+            TransferCharacteristics::Srgb => write!(f, "sRGB"),
+            TransferCharacteristics::Bt2020TenBit => write!(f, "BT.2020 10-bit"),
+            TransferCharacteristics::Bt2020TwelveBit => write!(f, "BT.2020 12-bit"),
+            TransferCharacteristics::Smpte2084 => write!(f, "SMPTE ST 2084 (PQ)"),
+            TransferCharacteristics::Smpte428 => write!(f, "SMPTE ST 428"),
+            TransferCharacteristics::Hlg => write!(f, "ARIB STD-B67 (HLG)"),
+            TransferCharacteristics::Reserved(val) => write!(f, "reserved ({val})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatrixCoefficients {
+    /// GBR, the samples are stored without any transform.
+    Identity,
+    Bt709,
+    #[default]
+    Unspecified,
+    Fcc,
+    Bt470BG,
+    Bt601,
+    Smpte240,
+    SmpteYcgco,
+    /// BT.2020 non-constant luminance.
+    Bt2020Ncl,
+    /// BT.2020 constant luminance.
+    Bt2020Cl,
+    Smpte2085,
+    ChromatNcl,
+    ChromatCl,
+    Ictcp,
+    Reserved(u8),
+}
+
+impl MatrixCoefficients {
+    pub fn new(val: u64) -> MatrixCoefficients {
+        match val {
+            0 => MatrixCoefficients::Identity,
+            1 => MatrixCoefficients::Bt709,
+            2 => MatrixCoefficients::Unspecified,
+            4 => MatrixCoefficients::Fcc,
+            5 => MatrixCoefficients::Bt470BG,
+            6 => MatrixCoefficients::Bt601,
+            7 => MatrixCoefficients::Smpte240,
+            8 => MatrixCoefficients::SmpteYcgco,
+            9 => MatrixCoefficients::Bt2020Ncl,
+            10 => MatrixCoefficients::Bt2020Cl,
+            11 => MatrixCoefficients::Smpte2085,
+            12 => MatrixCoefficients::ChromatNcl,
+            13 => MatrixCoefficients::ChromatCl,
+            14 => MatrixCoefficients::Ictcp,
+            3 | 15..=255 => MatrixCoefficients::Reserved(val as u8),
+            _ => panic!("invalid value for MatrixCoefficients: {val}"),
+        }
+    }
+
+    pub fn value(self) -> u8 {
+        match self {
+            MatrixCoefficients::Identity => 0,
+            MatrixCoefficients::Bt709 => 1,
+            MatrixCoefficients::Unspecified => 2,
+            MatrixCoefficients::Fcc => 4,
+            MatrixCoefficients::Bt470BG => 5,
+            MatrixCoefficients::Bt601 => 6,
+            MatrixCoefficients::Smpte240 => 7,
+            MatrixCoefficients::SmpteYcgco => 8,
+            MatrixCoefficients::Bt2020Ncl => 9,
+            MatrixCoefficients::Bt2020Cl => 10,
+            MatrixCoefficients::Smpte2085 => 11,
+            MatrixCoefficients::ChromatNcl => 12,
+            MatrixCoefficients::ChromatCl => 13,
+            MatrixCoefficients::Ictcp => 14,
+            MatrixCoefficients::Reserved(val) => val,
+        }
+    }
+}
+
+impl Display for MatrixCoefficients {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatrixCoefficients::Identity => write!(f, "identity (GBR)"),
+            MatrixCoefficients::Bt709 => write!(f, "BT.709"),
+            MatrixCoefficients::Unspecified => write!(f, "unspecified"),
+            MatrixCoefficients::Fcc => write!(f, "US FCC 73.628"),
+            MatrixCoefficients::Bt470BG => write!(f, "BT.470 System B, G"),
+            MatrixCoefficients::Bt601 => write!(f, "BT.601"),
+            MatrixCoefficients::Smpte240 => write!(f, "SMPTE 240M"),
+            MatrixCoefficients::SmpteYcgco => write!(f, "YCgCo"),
+            MatrixCoefficients::Bt2020Ncl => write!(f, "BT.2020 non-constant luminance"),
+            MatrixCoefficients::Bt2020Cl => write!(f, "BT.2020 constant luminance"),
+            MatrixCoefficients::Smpte2085 => write!(f, "SMPTE ST 2085"),
+            MatrixCoefficients::ChromatNcl => {
+                write!(f, "chromaticity-derived non-constant luminance")
+            }
+            MatrixCoefficients::ChromatCl => write!(f, "chromaticity-derived constant luminance"),
+            MatrixCoefficients::Ictcp => write!(f, "ICtCp"),
+            MatrixCoefficients::Reserved(val) => write!(f, "reserved ({val})"),
+        }
+    }
+}
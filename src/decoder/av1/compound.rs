@@ -0,0 +1,508 @@
+use super::{
+    block::{CompoundType, InterIntraMode, SubSize},
+    round2, BitStream, Decoder,
+};
+
+// https://aomediacodec.github.io/av1-spec/#mask-blend-process
+
+const WEDGE_HORIZONTAL: usize = 0;
+const WEDGE_VERTICAL: usize = 1;
+const WEDGE_OBLIQUE27: usize = 2;
+const WEDGE_OBLIQUE63: usize = 3;
+const WEDGE_OBLIQUE117: usize = 4;
+const WEDGE_OBLIQUE153: usize = 5;
+
+impl Decoder {
+    const MASK_MASTER_SIZE: usize = 64;
+    const WEDGE_TYPES: usize = 16;
+    const MAX_SB_SIZE: usize = 128;
+    pub const MAX_FRAME_DISTANCE: i64 = 31;
+
+    pub const SIZE_GROUP: [usize; Decoder::BLOCK_SIZES] = [
+        0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 0, 0, 1, 1, 2, 2,
+    ];
+    const WEDGE_BITS: [u64; Decoder::BLOCK_SIZES] = [
+        0, 0, 0, 4, 4, 4, 4, 4, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 4, 4, 0, 0,
+    ];
+
+    const WEDGE_MASTER_OBLIQUE_ODD: [i64; 64] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2,
+        6, 18, 37, 53, 60, 63, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+        64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+    ];
+    const WEDGE_MASTER_OBLIQUE_EVEN: [i64; 64] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 4,
+        11, 27, 46, 58, 62, 63, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+        64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+    ];
+    const WEDGE_MASTER_VERTICAL: [i64; 64] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2,
+        7, 21, 43, 57, 62, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+        64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+    ];
+
+    /// Wedge_Codebook[block_shape][wedge_index] = (direction, xoff, yoff), with the block
+    /// shapes taller than wide, wider than tall and square.
+    const WEDGE_CODEBOOK: [[(usize, i64, i64); 16]; 3] = [
+        [
+            (WEDGE_OBLIQUE27, 4, 4),
+            (WEDGE_OBLIQUE63, 4, 4),
+            (WEDGE_OBLIQUE117, 4, 4),
+            (WEDGE_OBLIQUE153, 4, 4),
+            (WEDGE_HORIZONTAL, 4, 2),
+            (WEDGE_HORIZONTAL, 4, 4),
+            (WEDGE_HORIZONTAL, 4, 6),
+            (WEDGE_VERTICAL, 4, 4),
+            (WEDGE_OBLIQUE27, 4, 2),
+            (WEDGE_OBLIQUE27, 4, 6),
+            (WEDGE_OBLIQUE153, 4, 2),
+            (WEDGE_OBLIQUE153, 4, 6),
+            (WEDGE_OBLIQUE63, 2, 4),
+            (WEDGE_OBLIQUE63, 6, 4),
+            (WEDGE_OBLIQUE117, 2, 4),
+            (WEDGE_OBLIQUE117, 6, 4),
+        ],
+        [
+            (WEDGE_OBLIQUE27, 4, 4),
+            (WEDGE_OBLIQUE63, 4, 4),
+            (WEDGE_OBLIQUE117, 4, 4),
+            (WEDGE_OBLIQUE153, 4, 4),
+            (WEDGE_VERTICAL, 2, 4),
+            (WEDGE_VERTICAL, 4, 4),
+            (WEDGE_VERTICAL, 6, 4),
+            (WEDGE_HORIZONTAL, 4, 4),
+            (WEDGE_OBLIQUE27, 4, 2),
+            (WEDGE_OBLIQUE27, 4, 6),
+            (WEDGE_OBLIQUE153, 4, 2),
+            (WEDGE_OBLIQUE153, 4, 6),
+            (WEDGE_OBLIQUE63, 2, 4),
+            (WEDGE_OBLIQUE63, 6, 4),
+            (WEDGE_OBLIQUE117, 2, 4),
+            (WEDGE_OBLIQUE117, 6, 4),
+        ],
+        [
+            (WEDGE_OBLIQUE27, 4, 4),
+            (WEDGE_OBLIQUE63, 4, 4),
+            (WEDGE_OBLIQUE117, 4, 4),
+            (WEDGE_OBLIQUE153, 4, 4),
+            (WEDGE_HORIZONTAL, 4, 2),
+            (WEDGE_HORIZONTAL, 4, 6),
+            (WEDGE_VERTICAL, 2, 4),
+            (WEDGE_VERTICAL, 6, 4),
+            (WEDGE_OBLIQUE27, 4, 2),
+            (WEDGE_OBLIQUE27, 4, 6),
+            (WEDGE_OBLIQUE153, 4, 2),
+            (WEDGE_OBLIQUE153, 4, 6),
+            (WEDGE_OBLIQUE63, 2, 4),
+            (WEDGE_OBLIQUE63, 6, 4),
+            (WEDGE_OBLIQUE117, 2, 4),
+            (WEDGE_OBLIQUE117, 6, 4),
+        ],
+    ];
+
+    const II_WEIGHTS_1D: [i64; Decoder::MAX_SB_SIZE] = [
+        60, 58, 56, 54, 52, 50, 48, 47, 45, 44, 42, 41, 39, 38, 37, 35, 34, 33, 32, 31, 30, 29, 28,
+        27, 26, 25, 24, 23, 22, 22, 21, 20, 19, 19, 18, 18, 17, 16, 16, 15, 15, 14, 14, 13, 13, 12,
+        12, 12, 11, 11, 10, 10, 10, 9, 9, 9, 8, 8, 8, 8, 7, 7, 7, 7, 6, 6, 6, 6, 6, 5, 5, 5, 5, 5,
+        4, 4, 4, 4, 4, 4, 4, 4, 3, 3, 3, 3, 3, 3, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+        2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    ];
+
+    const QUANT_DIST_WEIGHT: [[i64; 2]; 4] =
+        [[2, 3], [2, 5], [2, 7], [1, Decoder::MAX_FRAME_DISTANCE]];
+    const QUANT_DIST_LOOKUP: [[i64; 2]; 4] = [[9, 7], [11, 5], [12, 4], [13, 3]];
+
+    /// The inter-intra part of inter_block_mode_info().
+    pub(super) fn read_inter_intra(&mut self, b: &mut BitStream, is_compound: bool) {
+        self.interintra = false;
+
+        if self.skip_mode
+            || !self.sequence_header.enable_interintra_compound
+            || is_compound
+            || self.mi_size < SubSize::Block8x8
+            || self.mi_size > SubSize::Block32x32
+        {
+            return;
+        }
+
+        let ctx = Decoder::SIZE_GROUP[self.mi_size as usize] - 1;
+        self.interintra = self.symbol.read_symbol(b, &mut self.cdf.interintra[ctx]) != 0;
+        if !self.interintra {
+            return;
+        }
+
+        self.interintra_mode = InterIntraMode::new(
+            self.symbol
+                .read_symbol(b, &mut self.cdf.interintra_mode[ctx]) as u64,
+        );
+        self.ref_frame[1] = Decoder::INTRA_FRAME;
+        self.angle_delta_y = 0;
+        self.angle_delta_uv = 0;
+        self.use_filter_intra = false;
+
+        let mi_size = self.mi_size as usize;
+        self.wedge_interintra = self
+            .symbol
+            .read_symbol(b, &mut self.cdf.wedge_interintra[mi_size])
+            != 0;
+        if self.wedge_interintra {
+            self.wedge_index = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.wedge_index[mi_size]);
+            self.wedge_sign = 0;
+        }
+    }
+
+    /// read_compound_type(). Inter-intra blocks reuse compound_type to pick their mask.
+    pub(super) fn read_compound_type(&mut self, b: &mut BitStream, is_compound: bool) {
+        self.comp_group_idx = 0;
+        self.compound_idx = 1;
+
+        if self.skip_mode {
+            self.compound_type = CompoundType::Average;
+            return;
+        }
+
+        if !is_compound {
+            self.compound_type = if !self.interintra {
+                CompoundType::Average
+            } else if self.wedge_interintra {
+                CompoundType::Wedge
+            } else {
+                CompoundType::Intra
+            };
+            return;
+        }
+
+        let mi_size = self.mi_size as usize;
+        if self.sequence_header.enable_masked_compound {
+            let ctx = self.comp_group_idx_ctx();
+            self.comp_group_idx =
+                self.symbol
+                    .read_symbol(b, &mut self.cdf.comp_group_idx[ctx]) as u64;
+        }
+
+        self.compound_type = if self.comp_group_idx == 0 {
+            if self.sequence_header.enable_jnt_comp {
+                let ctx = self.compound_idx_ctx();
+                self.compound_idx =
+                    self.symbol.read_symbol(b, &mut self.cdf.compound_idx[ctx]) as u64;
+                if self.compound_idx != 0 {
+                    CompoundType::Average
+                } else {
+                    CompoundType::Distance
+                }
+            } else {
+                CompoundType::Average
+            }
+        } else if Decoder::WEDGE_BITS[mi_size] == 0 {
+            CompoundType::Diffwtd
+        } else {
+            CompoundType::new(
+                self.symbol
+                    .read_symbol(b, &mut self.cdf.compound_type[mi_size]) as u64,
+            )
+        };
+
+        if self.compound_type == CompoundType::Wedge {
+            self.wedge_index = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.wedge_index[mi_size]);
+            self.wedge_sign = self.symbol.read_literal(b, 1) as usize;
+        } else if self.compound_type == CompoundType::Diffwtd {
+            self.mask_type = self.symbol.read_literal(b, 1) != 0;
+        }
+    }
+
+    /// Compound neighbours contribute their comp_group_idx, single reference neighbours
+    /// 3 if they use ALTREF_FRAME.
+    fn comp_group_idx_ctx(&self) -> usize {
+        let mut ctx = 0;
+
+        for (avail, row, col) in self.above_and_left() {
+            if !avail {
+                continue;
+            }
+
+            if self.ref_frames[row][col][1] > Decoder::INTRA_FRAME {
+                ctx += self.comp_group_idxs[row][col] as usize;
+            } else if self.ref_frames[row][col][0] == Decoder::ALTREF_FRAME as i64 {
+                ctx += 3;
+            }
+        }
+
+        ctx.min(5)
+    }
+
+    fn compound_idx_ctx(&self) -> usize {
+        let fwd = self
+            .get_relative_dist(
+                self.order_hints[self.ref_frame[0] as usize],
+                self.order_hint,
+            )
+            .abs();
+        let bck = self
+            .get_relative_dist(
+                self.order_hints[self.ref_frame[1] as usize],
+                self.order_hint,
+            )
+            .abs();
+        let mut ctx = if fwd == bck { 3 } else { 0 };
+
+        for (avail, row, col) in self.above_and_left() {
+            if !avail {
+                continue;
+            }
+
+            if self.ref_frames[row][col][1] > Decoder::INTRA_FRAME {
+                ctx += self.compound_idxs[row][col] as usize;
+            } else if self.ref_frames[row][col][0] == Decoder::ALTREF_FRAME as i64 {
+                ctx += 1;
+            }
+        }
+
+        ctx
+    }
+
+    fn above_and_left(&self) -> [(bool, usize, usize); 2] {
+        let mi_row = self.mi_row as usize;
+        let mi_col = self.mi_col as usize;
+
+        [
+            (self.avail_u, mi_row.wrapping_sub(1), mi_col),
+            (self.avail_l, mi_row, mi_col.wrapping_sub(1)),
+        ]
+    }
+
+    /// Builds WedgeMasks[bsize][flip_sign][wedge][i][j] from the three master masks. The
+    /// sign is flipped where needed so that wedge_sign 0 always weights the top left more.
+    #[allow(clippy::needless_range_loop)]
+    fn initialise_wedge_mask_table(&mut self) {
+        let size = Decoder::MASK_MASTER_SIZE;
+        let mut master_mask = vec![vec![vec![0i64; size]; size]; 6];
+
+        for j in 0..size {
+            let mut shift = (size / 4) as i64;
+            for i in (0..size).step_by(2) {
+                let clamp = |v: i64| v.clamp(0, size as i64 - 1) as usize;
+                master_mask[WEDGE_OBLIQUE63][i][j] =
+                    Decoder::WEDGE_MASTER_OBLIQUE_EVEN[clamp(j as i64 - shift)];
+                shift -= 1;
+                master_mask[WEDGE_OBLIQUE63][i + 1][j] =
+                    Decoder::WEDGE_MASTER_OBLIQUE_ODD[clamp(j as i64 - shift)];
+                master_mask[WEDGE_VERTICAL][i][j] = Decoder::WEDGE_MASTER_VERTICAL[j];
+                master_mask[WEDGE_VERTICAL][i + 1][j] = Decoder::WEDGE_MASTER_VERTICAL[j];
+            }
+        }
+
+        for i in 0..size {
+            for j in 0..size {
+                let msk = master_mask[WEDGE_OBLIQUE63][i][j];
+                master_mask[WEDGE_OBLIQUE27][j][i] = msk;
+                master_mask[WEDGE_OBLIQUE117][i][size - 1 - j] = 64 - msk;
+                master_mask[WEDGE_OBLIQUE153][size - 1 - j][i] = 64 - msk;
+                master_mask[WEDGE_HORIZONTAL][j][i] = master_mask[WEDGE_VERTICAL][i][j];
+            }
+        }
+
+        self.wedge_masks = vec![Vec::new(); Decoder::BLOCK_SIZES];
+        for bsize in SubSize::Block8x8 as usize..Decoder::BLOCK_SIZES {
+            if Decoder::WEDGE_BITS[bsize] == 0 {
+                continue;
+            }
+
+            let sub_size = SubSize::new(bsize as u64);
+            let w = sub_size.width();
+            let h = sub_size.height();
+            let mut masks = vec![vec![vec![vec![0; w]; h]; Decoder::WEDGE_TYPES]; 2];
+
+            for wedge in 0..Decoder::WEDGE_TYPES {
+                let (dir, xoff, yoff) =
+                    Decoder::WEDGE_CODEBOOK[Decoder::block_shape(sub_size)][wedge];
+                let xoff = (size / 2) as i64 - ((xoff * w as i64) >> 3);
+                let yoff = (size / 2) as i64 - ((yoff * h as i64) >> 3);
+                let (xoff, yoff) = (xoff as usize, yoff as usize);
+
+                let mut sum = 0;
+                for i in 0..w {
+                    sum += master_mask[dir][yoff][xoff + i];
+                }
+
+                for i in 1..h {
+                    sum += master_mask[dir][yoff + i][xoff];
+                }
+
+                let avg = (sum + (w + h - 1) as i64 / 2) / (w + h - 1) as i64;
+                let flip_sign = (avg < 32) as usize;
+
+                for i in 0..h {
+                    for j in 0..w {
+                        let m = master_mask[dir][yoff + i][xoff + j];
+                        masks[flip_sign][wedge][i][j] = m;
+                        masks[1 - flip_sign][wedge][i][j] = 64 - m;
+                    }
+                }
+            }
+
+            self.wedge_masks[bsize] = masks;
+        }
+    }
+
+    fn block_shape(bsize: SubSize) -> usize {
+        let w4 = Decoder::NUM_4X4_BLOCKS_WIDE[bsize as usize];
+        let h4 = Decoder::NUM_4X4_BLOCKS_HIGH[bsize as usize];
+
+        if h4 > w4 {
+            0
+        } else if h4 < w4 {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Computes Mask for the current block. Wedge and difference weighted masks are only
+    /// computed for luma; chroma subsamples them in mask_blend().
+    pub(super) fn compound_mask(
+        &mut self,
+        plane: usize,
+        preds: &[Vec<Vec<i64>>],
+        w: usize,
+        h: usize,
+    ) {
+        match self.compound_type {
+            CompoundType::Wedge if plane == 0 => {
+                if self.wedge_masks.is_empty() {
+                    self.initialise_wedge_mask_table();
+                }
+
+                self.mask = self.wedge_masks[self.mi_size as usize][self.wedge_sign]
+                    [self.wedge_index]
+                    .clone();
+            }
+            CompoundType::Intra => {
+                let size_scale = Decoder::MAX_SB_SIZE / h.max(w);
+                self.mask = vec![vec![32; w]; h];
+
+                for (i, row) in self.mask.iter_mut().enumerate() {
+                    for (j, m) in row.iter_mut().enumerate() {
+                        *m = match self.interintra_mode {
+                            InterIntraMode::V => Decoder::II_WEIGHTS_1D[i * size_scale],
+                            InterIntraMode::H => Decoder::II_WEIGHTS_1D[j * size_scale],
+                            InterIntraMode::Smooth => Decoder::II_WEIGHTS_1D[i.min(j) * size_scale],
+                            InterIntraMode::Dc => 32,
+                        };
+                    }
+                }
+            }
+            CompoundType::Diffwtd if plane == 0 => {
+                let shift = (self.bit_depth as i64 - 8) + self.inter_post_round;
+                self.mask = vec![vec![0; w]; h];
+
+                for (i, row) in self.mask.iter_mut().enumerate() {
+                    for (j, m) in row.iter_mut().enumerate() {
+                        let diff = round2((preds[0][i][j] - preds[1][i][j]).abs(), shift);
+                        let v = (38 + diff / 16).clamp(0, 64);
+                        *m = if self.mask_type { 64 - v } else { v };
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Blends two inter predictions, or an inter prediction with the intra prediction already
+    /// in CurrFrame, with Mask.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn mask_blend(
+        &mut self,
+        preds: &[Vec<Vec<i64>>],
+        plane: usize,
+        dst_x: usize,
+        dst_y: usize,
+        w: usize,
+        h: usize,
+    ) {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (color_config.subsampling_x, color_config.subsampling_y)
+        } else {
+            (false, false)
+        };
+        let is_inter_intra = self.ref_frame[1] == Decoder::INTRA_FRAME;
+
+        for y in 0..h {
+            for x in 0..w {
+                let mask = &self.mask;
+                let m = if (!sub_x && !sub_y) || (is_inter_intra && !self.wedge_interintra) {
+                    mask[y][x]
+                } else if sub_x && !sub_y {
+                    round2(mask[y][2 * x] + mask[y][2 * x + 1], 1)
+                } else if !sub_x && sub_y {
+                    round2(mask[2 * y][x] + mask[2 * y + 1][x], 1)
+                } else {
+                    round2(
+                        mask[2 * y][2 * x]
+                            + mask[2 * y][2 * x + 1]
+                            + mask[2 * y + 1][2 * x]
+                            + mask[2 * y + 1][2 * x + 1],
+                        2,
+                    )
+                };
+
+                let value = if is_inter_intra {
+                    let pred0 = self
+                        .clip1(round2(preds[0][y][x], self.inter_post_round).max(0) as u64)
+                        as i64;
+                    let pred1 = self.curr_frame[plane][y + dst_y][x + dst_x] as i64;
+                    round2(m * pred1 + (64 - m) * pred0, 6) as u16
+                } else {
+                    let pred0 = preds[0][y][x];
+                    let pred1 = preds[1][y][x];
+                    self.clip1(
+                        round2(m * pred0 + (64 - m) * pred1, 6 + self.inter_post_round).max(0)
+                            as u64,
+                    )
+                };
+
+                self.curr_frame[plane][y + dst_y][x + dst_x] = value;
+            }
+        }
+    }
+
+    /// Sets FwdWeight and BckWeight from the distances to both references. The closer
+    /// reference gets the larger weight.
+    pub(super) fn distance_weights(&mut self, cand_row: usize, cand_col: usize) {
+        let mut dist = [0; 2];
+        for (ref_list, d) in dist.iter_mut().enumerate() {
+            let h = self.order_hints[self.ref_frames[cand_row][cand_col][ref_list] as usize];
+            *d = self
+                .get_relative_dist(h, self.order_hint)
+                .abs()
+                .clamp(0, Decoder::MAX_FRAME_DISTANCE);
+        }
+
+        let d0 = dist[1];
+        let d1 = dist[0];
+        let order = (d0 <= d1) as usize;
+
+        let i = if d0 == 0 || d1 == 0 {
+            3
+        } else {
+            (0..3)
+                .find(|&i| {
+                    let c0 = Decoder::QUANT_DIST_WEIGHT[i][order];
+                    let c1 = Decoder::QUANT_DIST_WEIGHT[i][1 - order];
+                    if order == 1 {
+                        d0 * c0 > d1 * c1
+                    } else {
+                        d0 * c0 < d1 * c1
+                    }
+                })
+                .unwrap_or(3)
+        };
+
+        self.fwd_weight = Decoder::QUANT_DIST_LOOKUP[i][order];
+        self.bck_weight = Decoder::QUANT_DIST_LOOKUP[i][1 - order];
+    }
+}
@@ -0,0 +1,471 @@
+use super::{block::YMode, round2, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#loop-filter-process
+
+/// The strength of the filter applied to one edge.
+#[derive(Debug, Clone, Copy)]
+struct FilterStrength {
+    lvl: i64,
+    limit: i64,
+    blimit: i64,
+    thresh: i64,
+}
+
+/// The result of the filter mask process for one position along an edge.
+#[derive(Debug, Clone, Copy)]
+struct FilterMasks {
+    hev_mask: bool,
+    filter_mask: bool,
+    flat_mask: bool,
+    flat_mask2: bool,
+}
+
+impl Decoder {
+    pub const MAX_LOOP_FILTER: i64 = 63;
+    const SEG_LVL_ALT_LF_Y_V: usize = 1;
+
+    /// Deblocks the vertical (pass 0) or horizontal (pass 1) edges of every plane. The
+    /// planes are independent, so running pass 0 for the whole frame before pass 1 gives
+    /// the same result as filtering one plane after the other.
+    pub(super) fn loop_filter_frame(&mut self, pass: usize) {
+        let loop_filter_level = self.uh.loop_filter_params.loop_filter_level;
+        if loop_filter_level[0] == 0 && loop_filter_level[1] == 0 {
+            return;
+        }
+
+        for plane in 0..self.num_planes as usize {
+            if plane == 0 || loop_filter_level[1 + plane] != 0 {
+                self.loop_filter_plane_pass(plane, pass);
+            }
+        }
+    }
+
+    /// Filters every vertical (pass 0) or horizontal (pass 1) edge of one plane, so the
+    /// intermediate frame can be inspected between the two directions.
+    pub fn loop_filter_plane_pass(&mut self, plane: usize, pass: usize) {
+        let color_config = &self.sequence_header.color_config;
+        let (row_step, col_step) = if plane == 0 {
+            (1, 1)
+        } else {
+            (
+                1 << color_config.subsampling_y as usize,
+                1 << color_config.subsampling_x as usize,
+            )
+        };
+
+        for row in (0..self.mi_rows as usize).step_by(row_step) {
+            for col in (0..self.mi_cols as usize).step_by(col_step) {
+                self.edge_loop_filter(plane, pass, row, col);
+            }
+        }
+    }
+
+    fn edge_loop_filter(&mut self, plane: usize, pass: usize, row: usize, col: usize) {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane == 0 {
+            (0, 0)
+        } else {
+            (
+                color_config.subsampling_x as usize,
+                color_config.subsampling_y as usize,
+            )
+        };
+        let (dx, dy) = if pass == 0 { (1, 0) } else { (0, 1) };
+
+        let x = col * Decoder::MI_SIZE as usize;
+        let y = row * Decoder::MI_SIZE as usize;
+        let row = row | sub_y;
+        let col = col | sub_x;
+
+        let on_screen = x < self.frame_width as usize
+            && y < self.frame_height as usize
+            && !(x == 0 && pass == 0)
+            && !(y == 0 && pass == 1);
+        if !on_screen {
+            return;
+        }
+
+        let x_p = x >> sub_x;
+        let y_p = y >> sub_y;
+        let prev_row = row - (dy << sub_y);
+        let prev_col = col - (dx << sub_x);
+
+        let tx_sz = self.loopfilter_tx_sizes[plane][row >> sub_y][col >> sub_x];
+        let plane_size = self.get_plane_residual_size(self.mi_sizes[row][col], plane);
+        let skip = self.skips[row][col];
+        let is_intra = self.ref_frames[row][col][0] <= Decoder::INTRA_FRAME;
+        let prev_tx_sz = self.loopfilter_tx_sizes[plane][prev_row >> sub_y][prev_col >> sub_x];
+
+        let (is_block_edge, is_tx_edge) = if pass == 0 {
+            (
+                x_p.is_multiple_of(plane_size.width()),
+                x_p.is_multiple_of(tx_sz.width()),
+            )
+        } else {
+            (
+                y_p.is_multiple_of(plane_size.height()),
+                y_p.is_multiple_of(tx_sz.height()),
+            )
+        };
+
+        let apply_filter = is_tx_edge && (is_block_edge || !skip || is_intra);
+        if !apply_filter {
+            return;
+        }
+
+        let filter_size = if pass == 0 {
+            Decoder::filter_size(tx_sz.width(), prev_tx_sz.width(), plane)
+        } else {
+            Decoder::filter_size(tx_sz.height(), prev_tx_sz.height(), plane)
+        };
+
+        let mut strength = self.adaptive_filter_strength(row, col, plane, pass);
+        if strength.lvl == 0 {
+            strength = self.adaptive_filter_strength(prev_row, prev_col, plane, pass);
+        }
+
+        if strength.lvl == 0 {
+            return;
+        }
+
+        for i in 0..Decoder::MI_SIZE as usize {
+            self.sample_filtering(
+                x_p + dy * i,
+                y_p + dx * i,
+                plane,
+                strength,
+                (dx, dy),
+                filter_size,
+            );
+        }
+    }
+
+    /// Luma edges use filters of up to 16 taps, chroma edges up to 8.
+    fn filter_size(tx_sz: usize, prev_tx_sz: usize, plane: usize) -> usize {
+        let base_size = tx_sz.min(prev_tx_sz);
+        if plane == 0 {
+            base_size.min(16)
+        } else {
+            base_size.min(8)
+        }
+    }
+
+    fn adaptive_filter_strength(
+        &self,
+        row: usize,
+        col: usize,
+        plane: usize,
+        pass: usize,
+    ) -> FilterStrength {
+        let segment = self.segment_ids[row][col] as usize;
+        let ref_frame = self.ref_frames[row][col][0];
+        let mode_type = Decoder::mode_type(self.y_modes[row][col]);
+        let delta_lf = self.select_delta_lf(&self.delta_lfs[row][col], plane, pass);
+        let lvl = self.adaptive_filter_strength_selection(
+            segment, ref_frame, mode_type, delta_lf, plane, pass,
+        );
+
+        let sharpness = self.uh.loop_filter_params.loop_filter_sharpness as i64;
+        let shift = if sharpness > 4 {
+            2
+        } else if sharpness > 0 {
+            1
+        } else {
+            0
+        };
+
+        let limit = if sharpness > 0 {
+            (lvl >> shift).clamp(1, 9 - sharpness)
+        } else {
+            (lvl >> shift).max(1)
+        };
+
+        FilterStrength {
+            lvl,
+            limit,
+            blimit: 2 * (lvl + 2) + limit,
+            thresh: lvl >> 4,
+        }
+    }
+
+    /// 1 for inter modes with a motion vector of their own, 0 otherwise.
+    fn mode_type(mode: YMode) -> usize {
+        (mode as u64 >= YMode::NearestMv as u64
+            && mode != YMode::GlobalMv
+            && mode != YMode::GlobalGlobalMv) as usize
+    }
+
+    fn select_delta_lf(&self, delta_lf: &[i64], plane: usize, pass: usize) -> i64 {
+        if self.uh.delta_lf_multi {
+            delta_lf[if plane == 0 { pass } else { plane + 1 }]
+        } else {
+            delta_lf[0]
+        }
+    }
+
+    /// The loop filter levels of the current block: luma vertical edges, luma horizontal
+    /// edges, U and V.
+    pub(super) fn block_filter_levels(&self) -> [i64; Decoder::FRAME_LF_COUNT] {
+        let segment = self.segment_id as usize;
+        let mode_type = Decoder::mode_type(self.y_mode);
+        [(0, 0), (0, 1), (1, 0), (2, 0)].map(|(plane, pass)| {
+            let delta_lf = self.select_delta_lf(&self.delta_lf, plane, pass);
+            self.adaptive_filter_strength_selection(
+                segment,
+                self.ref_frame[0],
+                mode_type,
+                delta_lf,
+                plane,
+                pass,
+            )
+        })
+    }
+
+    fn adaptive_filter_strength_selection(
+        &self,
+        segment: usize,
+        ref_frame: i64,
+        mode_type: usize,
+        delta_lf: i64,
+        plane: usize,
+        pass: usize,
+    ) -> i64 {
+        let i = if plane == 0 { pass } else { plane + 1 };
+        let loop_filter_params = &self.uh.loop_filter_params;
+
+        let base_filter_level = (delta_lf + loop_filter_params.loop_filter_level[i] as i64)
+            .clamp(0, Decoder::MAX_LOOP_FILTER);
+
+        let mut lvl_seg = base_filter_level;
+        let feature = Decoder::SEG_LVL_ALT_LF_Y_V + i;
+        if self.feature_enabled[segment][feature] {
+            lvl_seg =
+                (self.feature_data[segment][feature] + lvl_seg).clamp(0, Decoder::MAX_LOOP_FILTER);
+        }
+
+        if loop_filter_params.loop_filter_delta_enabled {
+            let n_shift = lvl_seg >> 5;
+            if ref_frame == Decoder::INTRA_FRAME {
+                lvl_seg += self.loop_filter_ref_deltas[Decoder::INTRA_FRAME as usize] << n_shift;
+            } else {
+                lvl_seg += (self.loop_filter_ref_deltas[ref_frame as usize] << n_shift)
+                    + (self.loop_filter_mode_deltas[mode_type] << n_shift);
+            }
+
+            lvl_seg = lvl_seg.clamp(0, Decoder::MAX_LOOP_FILTER);
+        }
+
+        lvl_seg
+    }
+
+    fn sample_filtering(
+        &mut self,
+        x: usize,
+        y: usize,
+        plane: usize,
+        strength: FilterStrength,
+        (dx, dy): (usize, usize),
+        filter_size: usize,
+    ) {
+        let masks = self.filter_mask(x, y, plane, strength, (dx, dy), filter_size);
+
+        if !masks.filter_mask {
+            return;
+        }
+
+        if filter_size == 4 || !masks.flat_mask {
+            self.narrow_filter(masks.hev_mask, x, y, plane, (dx, dy));
+        } else if filter_size == 8 || !masks.flat_mask2 {
+            self.wide_filter(x, y, plane, (dx, dy), 3);
+        } else {
+            self.wide_filter(x, y, plane, (dx, dy), 4);
+        }
+    }
+
+    /// The sample `k` positions across the edge, where 0 is q0 and -1 is p0.
+    fn edge_sample(
+        &self,
+        x: usize,
+        y: usize,
+        plane: usize,
+        (dx, dy): (usize, usize),
+        k: i64,
+    ) -> i64 {
+        let sx = (x as i64 + k * dx as i64) as usize;
+        let sy = (y as i64 + k * dy as i64) as usize;
+
+        self.curr_frame[plane][sy][sx] as i64
+    }
+
+    fn filter_mask(
+        &self,
+        x: usize,
+        y: usize,
+        plane: usize,
+        strength: FilterStrength,
+        d: (usize, usize),
+        filter_size: usize,
+    ) -> FilterMasks {
+        let taps = if filter_size == 16 { 7 } else { 4 };
+        let q: Vec<i64> = (0..taps)
+            .map(|i| self.edge_sample(x, y, plane, d, i))
+            .collect();
+        let p: Vec<i64> = (0..taps)
+            .map(|i| self.edge_sample(x, y, plane, d, -i - 1))
+            .collect();
+
+        let shift = self.bit_depth as i64 - 8;
+        let limit_bd = strength.limit << shift;
+        let blimit_bd = strength.blimit << shift;
+        let thresh_bd = strength.thresh << shift;
+
+        let hev_mask = (p[1] - p[0]).abs() > thresh_bd || (q[1] - q[0]).abs() > thresh_bd;
+
+        let filter_len = if filter_size == 4 {
+            4
+        } else if plane != 0 {
+            6
+        } else if filter_size == 8 {
+            8
+        } else {
+            16
+        };
+
+        let blimit_ok = (p[0] - q[0]).abs() * 2 + (p[1] - q[1]).abs() / 2 <= blimit_bd;
+        let filter_mask = match filter_len {
+            4 => (p[1] - p[0]).abs() <= limit_bd && (q[1] - q[0]).abs() <= limit_bd && blimit_ok,
+            6 => {
+                (p[2] - p[1]).abs() <= limit_bd
+                    && (p[1] - p[0]).abs() <= limit_bd
+                    && (q[1] - q[0]).abs() <= limit_bd
+                    && (q[2] - q[1]).abs() <= limit_bd
+                    && blimit_ok
+            }
+            _ => {
+                (p[3] - p[2]).abs() <= limit_bd
+                    && (p[2] - p[1]).abs() <= limit_bd
+                    && (p[1] - p[0]).abs() <= limit_bd
+                    && (q[1] - q[0]).abs() <= limit_bd
+                    && (q[2] - q[1]).abs() <= limit_bd
+                    && (q[3] - q[2]).abs() <= limit_bd
+                    && blimit_ok
+            }
+        };
+
+        let threshold_bd = 1 << shift;
+        let flat_mask = match filter_len {
+            4 => false,
+            6 => {
+                (p[1] - p[0]).abs() <= threshold_bd
+                    && (q[1] - q[0]).abs() <= threshold_bd
+                    && (p[2] - p[0]).abs() <= threshold_bd
+                    && (q[2] - q[0]).abs() <= threshold_bd
+            }
+            _ => (1..4).all(|i| {
+                (p[i] - p[0]).abs() <= threshold_bd && (q[i] - q[0]).abs() <= threshold_bd
+            }),
+        };
+
+        let flat_mask2 = filter_len == 16
+            && (4..7).all(|i| {
+                (p[i] - p[0]).abs() <= threshold_bd && (q[i] - q[0]).abs() <= threshold_bd
+            });
+
+        FilterMasks {
+            hev_mask,
+            filter_mask,
+            flat_mask,
+            flat_mask2,
+        }
+    }
+
+    fn filter4_clamp(&self, x: i64) -> i64 {
+        let bit_depth = self.bit_depth as i64;
+        x.clamp(-(1 << (bit_depth - 1)), (1 << (bit_depth - 1)) - 1)
+    }
+
+    fn set_edge_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        plane: usize,
+        (dx, dy): (usize, usize),
+        k: i64,
+        value: i64,
+    ) {
+        let sx = (x as i64 + k * dx as i64) as usize;
+        let sy = (y as i64 + k * dy as i64) as usize;
+
+        self.curr_frame[plane][sy][sx] = value as u16;
+    }
+
+    /// Modifies up to two samples on each side of the edge.
+    fn narrow_filter(
+        &mut self,
+        hev_mask: bool,
+        x: usize,
+        y: usize,
+        plane: usize,
+        d: (usize, usize),
+    ) {
+        let offset = 0x80 << (self.bit_depth as i64 - 8);
+
+        let ps1 = self.edge_sample(x, y, plane, d, -2) - offset;
+        let ps0 = self.edge_sample(x, y, plane, d, -1) - offset;
+        let qs0 = self.edge_sample(x, y, plane, d, 0) - offset;
+        let qs1 = self.edge_sample(x, y, plane, d, 1) - offset;
+
+        let filter = if hev_mask {
+            self.filter4_clamp(ps1 - qs1)
+        } else {
+            0
+        };
+        let filter = self.filter4_clamp(filter + 3 * (qs0 - ps0));
+        let filter1 = self.filter4_clamp(filter + 4) >> 3;
+        let filter2 = self.filter4_clamp(filter + 3) >> 3;
+
+        let oq0 = self.filter4_clamp(qs0 - filter1) + offset;
+        let op0 = self.filter4_clamp(ps0 + filter2) + offset;
+        self.set_edge_sample(x, y, plane, d, 0, oq0);
+        self.set_edge_sample(x, y, plane, d, -1, op0);
+
+        if !hev_mask {
+            let filter = round2(filter1, 1);
+            let oq1 = self.filter4_clamp(qs1 - filter) + offset;
+            let op1 = self.filter4_clamp(ps1 + filter) + offset;
+            self.set_edge_sample(x, y, plane, d, 1, oq1);
+            self.set_edge_sample(x, y, plane, d, -2, op1);
+        }
+    }
+
+    /// Smooths `n` samples on each side of the edge with a low pass filter of 2^log2_size
+    /// taps.
+    fn wide_filter(&mut self, x: usize, y: usize, plane: usize, d: (usize, usize), log2_size: i64) {
+        let n: i64 = if log2_size == 4 {
+            6
+        } else if plane == 0 {
+            3
+        } else {
+            2
+        };
+        let n2 = if log2_size == 3 && plane == 0 { 0 } else { 1 };
+
+        let filtered: Vec<i64> = (-n..n)
+            .map(|i| {
+                let t: i64 = (-n..=n)
+                    .map(|j| {
+                        let p = (i + j).clamp(-(n + 1), n);
+                        let tap = if j.abs() <= n2 { 2 } else { 1 };
+                        self.edge_sample(x, y, plane, d, p) * tap
+                    })
+                    .sum();
+
+                round2(t, log2_size)
+            })
+            .collect();
+
+        for (i, value) in (-n..n).zip(filtered) {
+            self.set_edge_sample(x, y, plane, d, i, value);
+        }
+    }
+}
@@ -0,0 +1,699 @@
+use super::{cicp::MatrixCoefficients, round2, Decoder, NumPlanes};
+
+// https://aomediacodec.github.io/av1-spec/#film-grain-synthesis-process
+
+/// The state of the film grain synthesis for one frame.
+#[derive(Debug, Default)]
+struct GrainSynthesis {
+    random_register: u64,
+    grain_min: i64,
+    grain_max: i64,
+    luma_grain: Vec<Vec<i64>>,
+    cb_grain: Vec<Vec<i64>>,
+    cr_grain: Vec<Vec<i64>>,
+    /// [plane][256]
+    scaling_lut: Vec<Vec<i64>>,
+}
+
+impl GrainSynthesis {
+    /// A 16 bit linear feedback shift register.
+    fn get_random_number(&mut self, bits: u64) -> u64 {
+        let r = self.random_register;
+        let bit = (r ^ (r >> 1) ^ (r >> 3) ^ (r >> 12)) & 1;
+        let r = (r >> 1) | (bit << 15);
+        self.random_register = r;
+
+        (r >> (16 - bits)) & ((1 << bits) - 1)
+    }
+}
+
+impl Decoder {
+    /// Gaussian_Sequence: 2048 samples of a zero mean Gaussian with a standard deviation of
+    /// about 512.
+    const GAUSSIAN_SEQUENCE: [i64; 2048] = [
+        56, 568, -180, 172, 124, -84, 172, -64, -900, 24, 820, 224, 1248, 996, 272, -8, -916, -388,
+        -732, -104, -188, 800, 112, -652, -320, -376, 140, -252, 492, -168, 44, -788, 588, -584,
+        500, -228, 12, 680, 272, -476, 972, -100, 652, 368, 432, -196, -720, -192, 1000, -332, 652,
+        -136, -552, -604, -4, 192, -220, -136, 1000, -52, 372, -96, -624, 124, -24, 396, 540, -12,
+        -104, 640, 464, 244, -208, -84, 368, -528, -740, 248, -968, -848, 608, 376, -60, -292, -40,
+        -156, 252, -292, 248, 224, -280, 400, -244, 244, -60, 76, -80, 212, 532, 340, 128, -36,
+        824, -352, -60, -264, -96, -612, 416, -704, 220, -204, 640, -160, 1220, -408, 900, 336, 20,
+        -336, -96, -792, 304, 48, -28, -1232, -1172, -448, 104, -292, -520, 244, 60, -948, 0, -708,
+        268, 108, 356, -548, 488, -344, -136, 488, -196, -224, 656, -236, -1128, 60, 4, 140, 276,
+        -676, -376, 168, -108, 464, 8, 564, 64, 240, 308, -300, -400, -456, -136, 56, 120, -408,
+        -116, 436, 504, -232, 328, 844, -164, -84, 784, -168, 232, -224, 348, -376, 128, 568, 96,
+        -1244, -288, 276, 848, 832, -360, 656, 464, -384, -332, -356, 728, -388, 160, -192, 468,
+        296, 224, 140, -776, -100, 280, 4, 196, 44, -36, -648, 932, 16, 1428, 28, 528, 808, 772,
+        20, 268, 88, -332, -284, 124, -384, -448, 208, -228, -1044, -328, 660, 380, -148, -300,
+        588, 240, 540, 28, 136, -88, -436, 256, 296, -1000, 1400, 0, -48, 1056, -136, 264, -528,
+        -1108, 632, -484, -592, -344, 796, 124, -668, -768, 388, 1296, -232, -188, -200, -288, -4,
+        308, 100, -168, 256, -500, 204, -508, 648, -136, 372, -272, -120, -1004, -552, -548, -384,
+        548, -296, 428, -108, -8, -912, -324, -224, -88, -112, -220, -100, 996, -796, 548, 360,
+        -216, 180, 428, -200, -212, 148, 96, 148, 284, 216, -412, -320, 120, -300, -384, -604,
+        -572, -332, -8, -180, -176, 696, 116, -88, 628, 76, 44, -516, 240, -208, -40, 100, -592,
+        344, -308, -452, -228, 20, 916, -1752, -136, -340, -804, 140, 40, 512, 340, 248, 184, -492,
+        896, -156, 932, -628, 328, -688, -448, -616, -752, -100, 560, -1020, 180, -800, -64, 76,
+        576, 1068, 396, 660, 552, -108, -28, 320, -628, 312, -92, -92, -472, 268, 16, 560, 516,
+        -672, -52, 492, -100, 260, 384, 284, 292, 304, -148, 88, -152, 1012, 1064, -228, 164, -376,
+        -684, 592, -392, 156, 196, -524, -64, -884, 160, -176, 636, 648, 404, -396, -436, 864, 424,
+        -728, 988, -604, 904, -592, 296, -224, 536, -176, -920, 436, -48, 1176, -884, 416, -776,
+        -824, -884, 524, -548, -564, -68, -164, -96, 692, 364, -692, -1012, -68, 260, -480, 876,
+        -1116, 452, -332, -352, 892, -1088, 1220, -676, 12, -292, 244, 496, 372, -32, 280, 200,
+        112, -440, -96, 24, -644, -184, 56, -432, 224, -980, 272, -260, 144, -436, 420, 356, 364,
+        -528, 76, 172, -744, -368, 404, -752, -416, 684, -688, 72, 540, 416, 92, 444, 480, -72,
+        -1416, 164, -1172, -68, 24, 424, 264, 1040, 128, -912, -524, -356, 64, 876, -12, 4, -88,
+        532, 272, -524, 320, 276, -508, 940, 24, -400, -120, 756, 60, 236, -412, 100, 376, -484,
+        400, -100, -740, -108, -260, 328, -268, 224, -200, -416, 184, -604, -564, -20, 296, 60,
+        892, -888, 60, 164, 68, -760, 216, -296, 904, -336, -28, 404, -356, -568, -208, -1480,
+        -512, 296, 328, -360, -164, -1560, -776, 1156, -428, 164, -504, -112, 120, -216, -148,
+        -264, 308, 32, 64, -72, 72, 116, 176, -64, -272, 460, -536, -784, -280, 348, 108, -752,
+        -132, 524, -540, -776, 116, -296, -1196, -288, -560, 1040, -472, 116, -848, -1116, 116,
+        636, 696, 284, -176, 1016, 204, -864, -648, -248, 356, 972, -584, -204, 264, 880, 528, -24,
+        -184, 116, 448, -144, 828, 524, 212, -212, 52, 12, 200, 268, -488, -404, -880, 824, -672,
+        -40, 908, -248, 500, 716, -576, 492, -576, 16, 720, -108, 384, 124, 344, 280, 576, -500,
+        252, 104, -308, 196, -188, -8, 1268, 296, 1032, -1196, 436, 316, 372, -432, -200, -660,
+        704, -224, 596, -132, 268, 32, -452, 884, 104, -1008, 424, -1348, -280, 4, -1168, 368, 476,
+        696, 300, -8, 24, 180, -592, -196, 388, 304, 500, 724, -160, 244, -84, 272, -256, -420,
+        320, 208, -144, -156, 156, 364, 452, 28, 540, 316, 220, -644, -248, 464, 72, 360, 32, -388,
+        496, -680, -48, 208, -116, -408, 60, -604, -392, 548, -840, 784, -460, 656, -544, -388,
+        -264, 908, -800, -628, -612, -568, 572, -220, 164, 288, -16, -308, 308, -112, -636, -760,
+        280, -668, 432, 364, 240, -196, 604, 340, 384, 196, 592, -44, -500, 432, -580, -132, 636,
+        -76, 392, 4, -412, 540, 508, 328, -356, -36, 16, -220, -64, -248, -60, 24, -192, 368, 1040,
+        92, -24, -1044, -32, 40, 104, 148, 192, -136, -520, 56, -816, -224, 732, 392, 356, 212,
+        -80, -424, -1008, -324, 588, -1496, 576, 460, -816, -848, 56, -580, -92, -1372, -112, -496,
+        200, 364, 52, -140, 48, -48, -60, 84, 72, 40, 132, -356, -268, -104, -284, -404, 732, -520,
+        164, -304, -540, 120, 328, -76, -460, 756, 388, 588, 236, -436, -72, -176, -404, -316,
+        -148, 716, -604, 404, -72, -88, -888, -68, 944, 88, -220, -344, 960, 472, 460, -232, 704,
+        120, 832, -228, 692, -508, 132, -476, 844, -748, -364, -44, 1116, -1104, -1056, 76, 428,
+        552, -692, 60, 356, 96, -384, -188, -612, -576, 736, 508, 892, 352, -1132, 504, -24, -352,
+        324, 332, -600, -312, 292, 508, -144, -8, 484, 48, 284, -260, -240, 256, -100, -292, -204,
+        -44, 472, -204, 908, -188, -1000, -256, 92, 1164, -392, 564, 356, 652, -28, -884, 256, 484,
+        -192, 760, -176, 376, -524, -452, -436, 860, -736, 212, 124, 504, -476, 468, 76, -472, 552,
+        -692, -944, -620, 740, -240, 400, 132, 20, 192, -196, 264, -668, -1012, -60, 296, -316,
+        -828, 76, -156, 284, -768, -448, -832, 148, 248, 652, 616, 1236, 288, -328, -400, -124,
+        588, 220, 520, -696, 1032, 768, -740, -92, -272, 296, 448, -464, 412, -200, 392, 440, -200,
+        264, -152, -260, 320, 1032, 216, 320, -8, -64, 156, -1016, 1084, 1172, 536, 484, -432, 132,
+        372, -52, -256, 84, 116, -352, 48, 116, 304, -384, 412, 924, -300, 528, 628, 180, 648, 44,
+        -980, -220, 1320, 48, 332, 748, 524, -268, -720, 540, -276, 564, -344, -208, -196, 436,
+        896, 88, -392, 132, 80, -964, -288, 568, 56, -48, -456, 888, 8, 552, -156, -292, 948, 288,
+        128, -716, -292, 1192, -152, 876, 352, -600, -260, -812, -468, -28, -120, -32, -44, 1284,
+        496, 192, 464, 312, -76, -516, -380, -456, -1012, -48, 308, -156, 36, 492, -156, -808, 188,
+        1652, 68, -120, -116, 316, 160, -140, 352, 808, -416, 592, 316, -480, 56, 528, -204, -568,
+        372, -232, 752, -344, 744, -4, 324, -416, -600, 768, 268, -248, -88, -132, -420, -432, 80,
+        -288, 404, -316, -1216, -588, 520, -108, 92, -320, 368, -480, -216, -92, 1688, -300, 180,
+        1020, -176, 820, -68, -228, -260, 436, -904, 20, 40, -508, 440, -736, 312, 332, 204, 760,
+        -372, 728, 96, -20, -632, -520, -560, 336, 1076, -64, -532, 776, 584, 192, 396, -728, -520,
+        276, -188, 80, -52, -612, -252, -48, 648, 212, -688, 228, -52, -260, 428, -412, -272, -404,
+        180, 816, -796, 48, 152, 484, -88, -216, 988, 696, 188, -528, 648, -116, -180, 316, 476,
+        12, -564, 96, 476, -252, -364, -376, -392, 556, -256, -576, 260, -352, 120, -16, -136,
+        -260, -492, 72, 556, 660, 580, 616, 772, 436, 424, -32, -324, -1268, 416, -324, -80, 920,
+        160, 228, 724, 32, -516, 64, 384, 68, -128, 136, 240, 248, -204, -68, 252, -932, -120,
+        -480, -628, -84, 192, 852, -404, -288, -132, 204, 100, 168, -68, -196, -868, 460, 1080,
+        380, -80, 244, 0, 484, -888, 64, 184, 352, 600, 460, 164, 604, -196, 320, -64, 588, -184,
+        228, 12, 372, 48, -848, -344, 224, 208, -200, 484, 128, -20, 272, -468, -840, 384, 256,
+        -720, -520, -464, -580, 112, -120, 644, -356, -208, -608, -528, 704, 560, -424, 392, 828,
+        40, 84, 200, -152, 0, -144, 584, 280, -120, 80, -556, -972, -196, -472, 724, 80, 168, -32,
+        88, 160, -688, 0, 160, 356, 372, -776, 740, -128, 676, -248, -480, 4, -364, 96, 544, 232,
+        -1032, 956, 236, 356, 20, -40, 300, 24, -676, -596, 132, 1120, -104, 532, -1096, 568, 648,
+        444, 508, 380, 188, -376, -604, 1488, 424, 24, 756, -220, -192, 716, 120, 920, 688, 168,
+        44, -460, 568, 284, 1144, 1160, 600, 424, 888, 656, -356, -320, 220, 316, -176, -724, -188,
+        -816, -628, -348, -228, -380, 1012, -452, -660, 736, 928, 404, -696, -72, -268, -892, 128,
+        184, -344, -780, 360, 336, 400, 344, 428, 548, -112, 136, -228, -216, -820, -516, 340, 92,
+        -136, 116, -300, 376, -244, 100, -316, -520, -284, -12, 824, 164, -548, -180, -128, 116,
+        -924, -828, 268, -368, -580, 620, 192, 160, 0, -1676, 1068, 424, -56, -360, 468, -156, 720,
+        288, -528, 556, -364, 548, -148, 504, 316, 152, -648, -620, -684, -24, -376, -384, -108,
+        -920, -1032, 768, 180, -264, -508, -1268, -260, -60, 300, -240, 988, 724, -376, -576, -212,
+        -736, 556, 192, 1092, -620, -880, 376, -56, -4, -216, -32, 836, 268, 396, 1332, 864, -600,
+        100, 56, -412, -92, 356, 180, 884, -468, -436, 292, -388, -804, -704, -840, 368, -348, 140,
+        -724, 1536, 940, 372, 112, -372, 436, -480, 1136, 296, -32, -228, 132, -48, -220, 868,
+        -1016, -60, -1044, -464, 328, 916, 244, 12, -736, -296, 360, 468, -376, -108, -92, 788,
+        368, -56, 544, 400, -672, -420, 728, 16, 320, 44, -284, -380, -796, 488, 132, 204, -596,
+        -372, 88, -152, -908, -636, -572, -624, -116, -692, -200, -56, 276, -88, 484, -324, 948,
+        864, 1000, -456, -184, -276, 292, -296, 156, 676, 320, 160, 908, -84, -1236, -288, -116,
+        260, -372, -644, 732, -756, -96, 84, 344, -520, 348, -688, 240, -84, 216, -1044, -136,
+        -676, -396, -1500, 960, -40, 176, 168, 1516, 420, -504, -344, -364, -360, 1216, -940, -380,
+        -212, 252, -660, -708, 484, -444, -152, 928, -120, 1112, 476, -260, 560, -148, -344, 108,
+        -196, 228, -288, 504, 560, -328, -88, 288, -1008, 460, -228, 468, -836, -196, 76, 388, 232,
+        412, -1168, -716, -644, 756, -172, -356, -504, 116, 432, 528, 48, 476, -168, -608, 448,
+        160, -532, -272, 28, -676, -12, 828, 980, 456, 520, 104, -104, 256, -344, -4, -28, -368,
+        -52, -524, -572, -556, -200, 768, 1124, -208, -512, 176, 232, 248, -148, -888, 604, -600,
+        -304, 804, -156, -212, 488, -192, -804, -256, 368, -360, -916, -328, 228, -240, -448, -472,
+        856, -556, -364, 572, -12, -156, -368, -340, 432, 252, -752, -152, 288, 268, -580, -848,
+        -592, 108, -76, 244, 312, -716, 592, -80, 436, 360, 4, -248, 160, 516, 584, 732, 44, -468,
+        -280, -292, -156, -588, 28, 308, 912, 24, 124, 156, 180, -252, 944, -924, -772, -520, -428,
+        -624, 300, -212, -1144, 32, -724, 800, -1128, -212, -1288, -848, 180, -416, 440, 192, -576,
+        -792, -76, -1080, 80, -532, -352, -132, 380, -820, 148, 1112, 128, 164, 456, 700, -924,
+        144, -668, -384, 648, -832, 508, 552, -52, -100, -656, 208, -568, 748, -88, 680, 232, 300,
+        192, -408, -1012, -152, -252, -268, 272, -876, -664, -648, -332, -136, 16, 12, 1152, -28,
+        332, -536, 320, -672, -460, -316, 532, -260, 228, -40, 1052, -816, 180, 88, -496, -556,
+        -672, -368, 428, 92, 356, 404, -408, 252, 196, -176, -556, 792, 268, 32, 372, 40, 96, -332,
+        328, 120, 372, -900, -40, 472, -264, -592, 952, 128, 656, 112, 664, -232, 420, 4, -344,
+        -464, 556, 244, -416, -32, 252, 0, -412, 188, -696, 508, -476, 324, -1096, 656, -312, 560,
+        264, -136, 304, 160, -64, -580, 248, 336, -720, 560, -348, -288, -276, -196, -500, 852,
+        -544, -236, -1128, -992, -776, 116, 56, 52, 860, 884, 212, -12, 168, 1020, 512, -552, 924,
+        -148, 716, 188, 164, -340, -520, -184, 880, -152, -680, -208, -1156, -300, -528, -472, 364,
+        100, -744, -1056, -32, 540, 280, 144, -676, -32, -232, -280, -224, 96, 568, -76, 172, 148,
+        148, 104, 32, -296, -32, 788, -80, 32, -16, 280, 288, 944, 428, -484,
+    ];
+
+    const LUMA_GRAIN_W: usize = 82;
+    const LUMA_GRAIN_H: usize = 73;
+
+    /// Sets output_frame to the current frame, with film grain added unless it is disabled
+    /// by the stream or by skip_film_grain.
+    pub(super) fn output_process(&mut self) {
+        let params = &self.uh.film_grain_params;
+        if !params.apply_grain || self.skip_film_grain {
+            self.output_frame = self.curr_frame.clone();
+            return;
+        }
+
+        self.output_frame =
+            self.film_grain_synthesis(self.upscaled_width as usize, self.frame_height as usize);
+    }
+
+    fn film_grain_synthesis(&self, w: usize, h: usize) -> Vec<Vec<Vec<u16>>> {
+        let bit_depth = self.bit_depth as i64;
+        let grain_center = 128 << (bit_depth - 8);
+        let mut grain = GrainSynthesis {
+            grain_min: -grain_center,
+            grain_max: (256 << (bit_depth - 8)) - 1 - grain_center,
+            ..Default::default()
+        };
+
+        self.generate_grain(&mut grain);
+        self.scaling_lookup_init(&mut grain);
+        let noise_image = self.add_noise_synthesis(&mut grain, w, h);
+
+        self.add_noise(&grain, &noise_image, w, h)
+    }
+
+    fn generate_grain(&self, grain: &mut GrainSynthesis) {
+        let params = &self.uh.film_grain_params;
+        let bit_depth = self.bit_depth as i64;
+        let color_config = &self.sequence_header.color_config;
+        let sub_x = color_config.subsampling_x as usize;
+        let sub_y = color_config.subsampling_y as usize;
+
+        let shift = 12 - bit_depth + params.grain_scale_shift as i64;
+        grain.random_register = params.grain_seed;
+        grain.luma_grain = vec![vec![0; Decoder::LUMA_GRAIN_W]; Decoder::LUMA_GRAIN_H];
+        for y in 0..Decoder::LUMA_GRAIN_H {
+            for x in 0..Decoder::LUMA_GRAIN_W {
+                let g = if !params.point_y_value.is_empty() {
+                    Decoder::GAUSSIAN_SEQUENCE[grain.get_random_number(11) as usize]
+                } else {
+                    0
+                };
+                grain.luma_grain[y][x] = round2(g, shift);
+            }
+        }
+
+        let lag = params.ar_coeff_lag as i64;
+        let ar_shift = params.ar_coeff_shift_minus_6 as i64 + 6;
+        if !params.point_y_value.is_empty() {
+            for y in 3..Decoder::LUMA_GRAIN_H {
+                for x in 3..Decoder::LUMA_GRAIN_W - 3 {
+                    let mut sum = 0;
+                    let mut pos = 0;
+                    'outer: for delta_row in -lag..=0 {
+                        for delta_col in -lag..=lag {
+                            if delta_row == 0 && delta_col == 0 {
+                                break 'outer;
+                            }
+
+                            let c = params.ar_coeffs_y_plus_128[pos] as i64 - 128;
+                            sum += grain.luma_grain[(y as i64 + delta_row) as usize]
+                                [(x as i64 + delta_col) as usize]
+                                * c;
+                            pos += 1;
+                        }
+                    }
+
+                    grain.luma_grain[y][x] = (grain.luma_grain[y][x] + round2(sum, ar_shift))
+                        .clamp(grain.grain_min, grain.grain_max);
+                }
+            }
+        }
+
+        if matches!(self.num_planes, NumPlanes::One) {
+            return;
+        }
+
+        let chroma_w = if sub_x != 0 { 44 } else { 82 };
+        let chroma_h = if sub_y != 0 { 38 } else { 73 };
+
+        let has_cb = !params.point_cb_value.is_empty() || params.chroma_scaling_from_luma;
+        let has_cr = !params.point_cr_value.is_empty() || params.chroma_scaling_from_luma;
+
+        for (seed_xor, has_grain) in [(0xb524, has_cb), (0x49d8, has_cr)] {
+            grain.random_register = params.grain_seed ^ seed_xor;
+            let plane_grain: Vec<Vec<i64>> = (0..chroma_h)
+                .map(|_| {
+                    (0..chroma_w)
+                        .map(|_| {
+                            let g = if has_grain {
+                                Decoder::GAUSSIAN_SEQUENCE[grain.get_random_number(11) as usize]
+                            } else {
+                                0
+                            };
+                            round2(g, shift)
+                        })
+                        .collect()
+                })
+                .collect();
+
+            if seed_xor == 0xb524 {
+                grain.cb_grain = plane_grain;
+            } else {
+                grain.cr_grain = plane_grain;
+            }
+        }
+
+        for y in 3..chroma_h {
+            for x in 3..chroma_w - 3 {
+                let mut sum0 = 0;
+                let mut sum1 = 0;
+                let mut pos = 0;
+                'outer: for delta_row in -lag..=0 {
+                    for delta_col in -lag..=lag {
+                        let c0 = params
+                            .ar_coeffs_cb_plus_128
+                            .get(pos)
+                            .copied()
+                            .unwrap_or(128) as i64
+                            - 128;
+                        let c1 = params
+                            .ar_coeffs_cr_plus_128
+                            .get(pos)
+                            .copied()
+                            .unwrap_or(128) as i64
+                            - 128;
+
+                        if delta_row == 0 && delta_col == 0 {
+                            if !params.point_y_value.is_empty() {
+                                let luma_x = ((x - 3) << sub_x) + 3;
+                                let luma_y = ((y - 3) << sub_y) + 3;
+                                let mut luma = 0;
+                                for i in 0..=sub_y {
+                                    for j in 0..=sub_x {
+                                        luma += grain.luma_grain[luma_y + i][luma_x + j];
+                                    }
+                                }
+                                let luma = round2(luma, (sub_x + sub_y) as i64);
+
+                                sum0 += luma * c0;
+                                sum1 += luma * c1;
+                            }
+                            break 'outer;
+                        }
+
+                        let gy = (y as i64 + delta_row) as usize;
+                        let gx = (x as i64 + delta_col) as usize;
+                        sum0 += c0 * grain.cb_grain[gy][gx];
+                        sum1 += c1 * grain.cr_grain[gy][gx];
+                        pos += 1;
+                    }
+                }
+
+                if has_cb {
+                    grain.cb_grain[y][x] = (grain.cb_grain[y][x] + round2(sum0, ar_shift))
+                        .clamp(grain.grain_min, grain.grain_max);
+                }
+                if has_cr {
+                    grain.cr_grain[y][x] = (grain.cr_grain[y][x] + round2(sum1, ar_shift))
+                        .clamp(grain.grain_min, grain.grain_max);
+                }
+            }
+        }
+    }
+
+    /// Builds the 256 entry piecewise linear scaling function of every plane.
+    fn scaling_lookup_init(&self, grain: &mut GrainSynthesis) {
+        let params = &self.uh.film_grain_params;
+
+        grain.scaling_lut = (0..self.num_planes as usize)
+            .map(|plane| {
+                let (value, scaling) = if plane == 0 || params.chroma_scaling_from_luma {
+                    (&params.point_y_value, &params.point_y_scaling)
+                } else if plane == 1 {
+                    (&params.point_cb_value, &params.point_cb_scaling)
+                } else {
+                    (&params.point_cr_value, &params.point_cr_scaling)
+                };
+
+                let mut lut = vec![0; 256];
+                let num_points = value.len();
+                if num_points == 0 {
+                    return lut;
+                }
+
+                for entry in lut.iter_mut().take(value[0] as usize) {
+                    *entry = scaling[0] as i64;
+                }
+
+                for i in 0..num_points - 1 {
+                    let delta_y = scaling[i + 1] as i64 - scaling[i] as i64;
+                    let delta_x = value[i + 1] as i64 - value[i] as i64;
+                    let delta = delta_y * ((65536 + (delta_x >> 1)) / delta_x);
+                    for x in 0..delta_x {
+                        lut[(value[i] as i64 + x) as usize] =
+                            scaling[i] as i64 + ((x * delta + 32768) >> 16);
+                    }
+                }
+
+                for entry in lut.iter_mut().skip(value[num_points - 1] as usize) {
+                    *entry = scaling[num_points - 1] as i64;
+                }
+
+                lut
+            })
+            .collect();
+    }
+
+    fn scale_lut(&self, grain: &GrainSynthesis, plane: usize, index: i64) -> i64 {
+        let shift = self.bit_depth as i64 - 8;
+        let x = index >> shift;
+        let rem = index - (x << shift);
+        let lut = &grain.scaling_lut[plane];
+
+        if shift == 0 || x == 255 {
+            lut[x as usize]
+        } else {
+            let start = lut[x as usize];
+            let end = lut[x as usize + 1];
+            start + round2((end - start) * rem, shift)
+        }
+    }
+
+    /// Tiles the grain templates over the frame in 32x32 blocks (in luma samples) at random
+    /// offsets, blending the overlapping edges when overlap_flag is set.
+    fn add_noise_synthesis(
+        &self,
+        grain: &mut GrainSynthesis,
+        w: usize,
+        h: usize,
+    ) -> Vec<Vec<Vec<i64>>> {
+        let params = &self.uh.film_grain_params;
+        let num_planes = self.num_planes as usize;
+        let color_config = &self.sequence_header.color_config;
+
+        let stripe_count = h.div_ceil(2).div_ceil(16);
+        let stripe_w = w + 34;
+        // [luma_num][plane][i][x]
+        let mut noise_stripe: Vec<Vec<Vec<Vec<i64>>>> = vec![];
+
+        for luma_num in 0..stripe_count {
+            grain.random_register = params.grain_seed;
+            grain.random_register ^= ((luma_num as u64 * 37 + 178) & 255) << 8;
+            grain.random_register ^= (luma_num as u64 * 173 + 105) & 255;
+
+            noise_stripe.push(vec![vec![vec![0; stripe_w]; 34]; num_planes]);
+            let stripe = &mut noise_stripe[luma_num];
+
+            for x in (0..w.div_ceil(2)).step_by(16) {
+                let rand = grain.get_random_number(8) as usize;
+                let offset_x = rand >> 4;
+                let offset_y = rand & 15;
+
+                for (plane, plane_stripe) in stripe.iter_mut().enumerate() {
+                    let (plane_sub_x, plane_sub_y) = if plane == 0 {
+                        (0, 0)
+                    } else {
+                        (
+                            color_config.subsampling_x as usize,
+                            color_config.subsampling_y as usize,
+                        )
+                    };
+                    let plane_offset_x = if plane_sub_x != 0 {
+                        6 + offset_x
+                    } else {
+                        9 + offset_x * 2
+                    };
+                    let plane_offset_y = if plane_sub_y != 0 {
+                        6 + offset_y
+                    } else {
+                        9 + offset_y * 2
+                    };
+
+                    let template = match plane {
+                        0 => &grain.luma_grain,
+                        1 => &grain.cb_grain,
+                        _ => &grain.cr_grain,
+                    };
+
+                    for i in 0..(34 >> plane_sub_y) {
+                        for j in 0..(34 >> plane_sub_x) {
+                            let mut g = template[plane_offset_y + i][plane_offset_x + j];
+
+                            if plane_sub_x == 0 {
+                                let old = plane_stripe[i][x * 2 + j];
+                                if j < 2 && params.overlap_flag && x > 0 {
+                                    g = if j == 0 {
+                                        old * 27 + g * 17
+                                    } else {
+                                        old * 17 + g * 27
+                                    };
+                                    g = round2(g, 5).clamp(grain.grain_min, grain.grain_max);
+                                }
+                                plane_stripe[i][x * 2 + j] = g;
+                            } else {
+                                let old = plane_stripe[i][x + j];
+                                if j == 0 && params.overlap_flag && x > 0 {
+                                    g = round2(old * 23 + g * 22, 5)
+                                        .clamp(grain.grain_min, grain.grain_max);
+                                }
+                                plane_stripe[i][x + j] = g;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        (0..num_planes)
+            .map(|plane| {
+                let (plane_sub_x, plane_sub_y) = if plane == 0 {
+                    (0, 0)
+                } else {
+                    (
+                        color_config.subsampling_x as usize,
+                        color_config.subsampling_y as usize,
+                    )
+                };
+
+                (0..(h + plane_sub_y) >> plane_sub_y)
+                    .map(|y| {
+                        let luma_num = y >> (5 - plane_sub_y);
+                        let i = y - (luma_num << (5 - plane_sub_y));
+
+                        (0..(w + plane_sub_x) >> plane_sub_x)
+                            .map(|x| {
+                                let mut g = noise_stripe[luma_num][plane][i][x];
+                                if !params.overlap_flag || luma_num == 0 {
+                                    return g;
+                                }
+
+                                if plane_sub_y == 0 {
+                                    if i < 2 {
+                                        let old = noise_stripe[luma_num - 1][plane][i + 32][x];
+                                        g = if i == 0 {
+                                            old * 27 + g * 17
+                                        } else {
+                                            old * 17 + g * 27
+                                        };
+                                        g = round2(g, 5).clamp(grain.grain_min, grain.grain_max);
+                                    }
+                                } else if i < 1 {
+                                    let old = noise_stripe[luma_num - 1][plane][i + 16][x];
+                                    g = round2(old * 23 + g * 22, 5)
+                                        .clamp(grain.grain_min, grain.grain_max);
+                                }
+
+                                g
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Adds the scaled noise to a copy of the current frame. Chroma is done first since
+    /// it is scaled by the luma values before noise.
+    fn add_noise(
+        &self,
+        grain: &GrainSynthesis,
+        noise_image: &[Vec<Vec<i64>>],
+        w: usize,
+        h: usize,
+    ) -> Vec<Vec<Vec<u16>>> {
+        let params = &self.uh.film_grain_params;
+        let color_config = &self.sequence_header.color_config;
+        let bit_depth = self.bit_depth as i64;
+        let mut out = self.curr_frame.clone();
+
+        let (min_value, max_luma, max_chroma) = if params.clip_to_restricted_range {
+            let max_luma = 235 << (bit_depth - 8);
+            let max_chroma = if color_config.matrix_coefficients == MatrixCoefficients::Identity {
+                max_luma
+            } else {
+                240 << (bit_depth - 8)
+            };
+            (16 << (bit_depth - 8), max_luma, max_chroma)
+        } else {
+            let max = (256 << (bit_depth - 8)) - 1;
+            (0, max, max)
+        };
+
+        let scaling_shift = params.grain_scaling_minus_8 as i64 + 8;
+
+        if matches!(self.num_planes, NumPlanes::Three) {
+            let sub_x = color_config.subsampling_x as usize;
+            let sub_y = color_config.subsampling_y as usize;
+
+            let chroma = [
+                (
+                    1,
+                    !params.point_cb_value.is_empty(),
+                    params.cb_mult,
+                    params.cb_luma_mult,
+                    params.cb_offset,
+                ),
+                (
+                    2,
+                    !params.point_cr_value.is_empty(),
+                    params.cr_mult,
+                    params.cr_luma_mult,
+                    params.cr_offset,
+                ),
+            ];
+
+            for (plane, has_points, mult, luma_mult, offset) in chroma {
+                if !has_points && !params.chroma_scaling_from_luma {
+                    continue;
+                }
+
+                for y in 0..(h + sub_y) >> sub_y {
+                    for x in 0..(w + sub_x) >> sub_x {
+                        let luma_x = x << sub_x;
+                        let luma_y = y << sub_y;
+                        let luma_next_x = (luma_x + 1).min(w - 1);
+                        let average_luma = if sub_x != 0 {
+                            round2(
+                                out[0][luma_y][luma_x] as i64 + out[0][luma_y][luma_next_x] as i64,
+                                1,
+                            )
+                        } else {
+                            out[0][luma_y][luma_x] as i64
+                        };
+
+                        let orig = out[plane][y][x] as i64;
+                        let merged = if params.chroma_scaling_from_luma {
+                            average_luma
+                        } else {
+                            let combined = average_luma * (luma_mult as i64 - 128)
+                                + orig * (mult as i64 - 128);
+                            self.clip1(
+                                ((combined >> 6) + ((offset as i64 - 256) << (bit_depth - 8)))
+                                    .max(0) as u64,
+                            ) as i64
+                        };
+
+                        let noise = round2(
+                            self.scale_lut(grain, plane, merged) * noise_image[plane][y][x],
+                            scaling_shift,
+                        );
+                        out[plane][y][x] = (orig + noise).clamp(min_value, max_chroma) as u16;
+                    }
+                }
+            }
+        }
+
+        if !params.point_y_value.is_empty() {
+            for y in 0..h {
+                for x in 0..w {
+                    let orig = out[0][y][x] as i64;
+                    let noise = round2(
+                        self.scale_lut(grain, 0, orig) * noise_image[0][y][x],
+                        scaling_shift,
+                    );
+                    out[0][y][x] = (orig + noise).clamp(min_value, max_luma) as u16;
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::av1::{obu::FilmGrainParams, BitDepth};
+
+    fn decoder(params: FilmGrainParams) -> Decoder {
+        let mut decoder = Decoder {
+            bit_depth: BitDepth::Eight,
+            num_planes: NumPlanes::Three,
+            ..Default::default()
+        };
+        decoder.sequence_header.color_config.subsampling_x = true;
+        decoder.sequence_header.color_config.subsampling_y = true;
+        decoder.uh.film_grain_params = params;
+        decoder
+    }
+
+    fn grain(decoder: &Decoder) -> GrainSynthesis {
+        let mut grain = GrainSynthesis {
+            grain_min: -128,
+            grain_max: 127,
+            ..Default::default()
+        };
+        decoder.generate_grain(&mut grain);
+        grain
+    }
+
+    #[test]
+    fn white_noise_follows_the_seed() {
+        let decoder = decoder(FilmGrainParams {
+            apply_grain: true,
+            grain_seed: 0x2f5c,
+            point_y_value: vec![0, 255],
+            point_y_scaling: vec![64, 64],
+            point_cb_value: vec![0, 255],
+            point_cb_scaling: vec![32, 32],
+            point_cr_value: vec![0, 255],
+            point_cr_scaling: vec![32, 32],
+            ..Default::default()
+        });
+        let grain = grain(&decoder);
+
+        assert_eq!(grain.luma_grain[0][..8], [14, -43, 22, 27, 20, -8, -63, 5]);
+        assert_eq!(
+            grain.luma_grain[72][74..],
+            [-8, 11, -29, -45, -72, 36, 28, 17]
+        );
+        assert_eq!(grain.cb_grain[0][..8], [-11, -1, 7, 6, -8, -32, 4, -32]);
+        assert_eq!(grain.cr_grain[37][36..], [29, 21, -11, 1, -17, -53, 42, 64]);
+    }
+
+    #[test]
+    fn auto_regressive_filter_is_applied_to_luma() {
+        let decoder = decoder(FilmGrainParams {
+            apply_grain: true,
+            grain_seed: 0x2f5c,
+            point_y_value: vec![0, 255],
+            point_y_scaling: vec![64, 64],
+            grain_scale_shift: 0,
+            ar_coeff_lag: 1,
+            ar_coeffs_y_plus_128: vec![138, 108, 158, 168],
+            ar_coeff_shift_minus_6: 1,
+            ..Default::default()
+        });
+        let grain = grain(&decoder);
+
+        assert_eq!(grain.luma_grain[3][3..9], [28, 45, -24, -54, -36, -40]);
+        assert_eq!(grain.luma_grain[72][73..79], [33, 7, 2, -32, -48, -99]);
+    }
+}
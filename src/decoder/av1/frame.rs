@@ -0,0 +1,356 @@
+use std::borrow::Cow;
+
+use tracing::info;
+
+use super::{
+    block::{BlockInfo, TxSize, TxType},
+    obu::ColorConfig,
+    symbol::SyntaxCategory,
+    BitDepth, Decoder,
+};
+
+/// One plane of a decoded frame. Samples are stored row by row, `stride` samples apart, of
+/// which the first `width` are part of the picture.
+#[derive(Debug, Clone, Default)]
+pub struct Plane {
+    pub width: usize,
+    pub height: usize,
+    pub stride: usize,
+    pub data: Vec<u16>,
+}
+
+impl Plane {
+    /// The visible samples of row y.
+    pub fn row(&self, y: usize) -> &[u16] {
+        let start = y * self.stride;
+        &self.data[start..start + self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u16]> {
+        (0..self.height).map(|y| self.row(y))
+    }
+}
+
+/// A motion vector of a reference frame projected onto the current frame.
+#[derive(Debug, Clone, Copy)]
+pub struct ProjectedMv {
+    /// The reference frame whose motion field it came from, LAST_FRAME to ALTREF_FRAME.
+    pub source: usize,
+    /// The projection towards LAST_FRAME, in 1/8 pixels, [row, col].
+    pub mv: [i64; 2],
+}
+
+/// The coding structure of a frame, for drawing on top of it.
+#[derive(Debug, Clone, Default)]
+pub struct FrameInfo {
+    /// The coded size, before superres upscaling.
+    pub frame_width: usize,
+    pub frame_height: usize,
+    pub mi_rows: usize,
+    pub mi_cols: usize,
+    /// 64 or 128.
+    pub superblock_size: usize,
+    /// Every block of the frame in decoding order.
+    pub blocks: Vec<BlockInfo>,
+    /// The luma transform size at every mode info position, [row][col].
+    pub tx_sizes: Vec<Vec<TxSize>>,
+    /// The luma transform type at every mode info position, [row][col].
+    pub tx_types: Vec<Vec<TxType>>,
+    /// The temporal motion field at 8x8 granularity, [row >> 1][col >> 1]. Empty unless the
+    /// frame uses reference frame motion vectors.
+    pub motion_field: Vec<Vec<Option<ProjectedMv>>>,
+    /// The CDEF direction of every 8x8 luma block, [row >> 1][col >> 1]. None where CDEF
+    /// is off or the block is skipped.
+    pub cdef_directions: Vec<Vec<Option<usize>>>,
+    /// The directional variance behind each CDEF direction, [row >> 1][col >> 1].
+    pub cdef_variances: Vec<Vec<i64>>,
+}
+
+impl FrameInfo {
+    /// The bits spent on each syntax category over the whole frame, indexed by
+    /// SyntaxCategory.
+    pub fn bits(&self) -> [f64; SyntaxCategory::COUNT] {
+        bits_by_category(&self.blocks)
+    }
+}
+
+fn bits_by_category(blocks: &[BlockInfo]) -> [f64; SyntaxCategory::COUNT] {
+    let mut bits = [0.0; SyntaxCategory::COUNT];
+    for block in blocks {
+        for (total, block_bits) in bits.iter_mut().zip(block.bits) {
+            *total += block_bits;
+        }
+    }
+
+    bits
+}
+
+/// The padded samples of one plane, [y][x].
+type PlaneSamples = Vec<Vec<u16>>;
+
+/// A point in the reconstruction pipeline a frame can be captured at, in pipeline order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// The prediction alone, without the residual.
+    Prediction,
+    /// The residual alone, centred on mid grey.
+    Residual,
+    /// Prediction plus residual, before any filtering.
+    Reconstruction,
+    /// After deblocking the vertical edges, before the horizontal ones.
+    DeblockedVertical,
+    /// After deblocking both directions.
+    Deblocked,
+    Cdef,
+    /// After superres upscaling, the same as Cdef for frames that don't use superres.
+    Superres,
+    /// After loop restoration, what is stored as a reference frame.
+    Restored,
+    /// What is shown, with film grain.
+    Output,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 9] = [
+        Stage::Prediction,
+        Stage::Residual,
+        Stage::Reconstruction,
+        Stage::DeblockedVertical,
+        Stage::Deblocked,
+        Stage::Cdef,
+        Stage::Superres,
+        Stage::Restored,
+        Stage::Output,
+    ];
+
+    pub fn new(name: &str) -> Option<Stage> {
+        Stage::ALL.into_iter().find(|stage| stage.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Prediction => "prediction",
+            Stage::Residual => "residual",
+            Stage::Reconstruction => "reconstruction",
+            Stage::DeblockedVertical => "deblocked-vertical",
+            Stage::Deblocked => "deblocked",
+            Stage::Cdef => "cdef",
+            Stage::Superres => "superres",
+            Stage::Restored => "restored",
+            Stage::Output => "output",
+        }
+    }
+
+    /// Whether the stage comes before superres, so its frame has the coded width.
+    fn is_coded_size(self) -> bool {
+        matches!(
+            self,
+            Stage::Prediction
+                | Stage::Residual
+                | Stage::Reconstruction
+                | Stage::DeblockedVertical
+                | Stage::Deblocked
+                | Stage::Cdef
+        )
+    }
+}
+
+/// A shown frame, cropped to its upscaled size.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Presentation time in container time base units.
+    pub timestamp: u64,
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: BitDepth,
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+    /// How the samples map to colours.
+    pub color_config: ColorConfig,
+    /// Y, U and V, or only Y for monochrome streams.
+    pub planes: Vec<Plane>,
+    pub info: FrameInfo,
+    /// The frame at the earlier stages the sink asked for. Their info is left empty.
+    pub stages: Vec<(Stage, Frame)>,
+}
+
+impl Frame {
+    pub fn is_monochrome(&self) -> bool {
+        self.planes.len() == 1
+    }
+
+    /// The frame as it was at `stage`, if it was captured. Output is the frame itself.
+    pub fn stage(&self, stage: Stage) -> Option<&Frame> {
+        if stage == Stage::Output {
+            return Some(self);
+        }
+
+        self.stages
+            .iter()
+            .find(|(captured, _)| *captured == stage)
+            .map(|(_, frame)| frame)
+    }
+}
+
+impl Decoder {
+    /// Logs how the bits of the frame just decoded split over the syntax categories.
+    pub(super) fn log_frame_bits(&self) {
+        let bits = bits_by_category(&self.block_infos);
+        let breakdown = SyntaxCategory::ALL
+            .iter()
+            .zip(bits)
+            .map(|(category, bits)| format!("{} {bits:.0}", category.name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            "frame {}: {:.0} bits ({breakdown})",
+            self.order_hint,
+            bits.iter().sum::<f64>()
+        );
+    }
+
+    /// Adds the frame at `stage` to stage_frames if the sink asked for it. `samples` picks
+    /// or computes the frame from the decoder state.
+    pub(super) fn capture_stage(
+        &mut self,
+        stage: Stage,
+        samples: fn(&Decoder) -> Cow<'_, [PlaneSamples]>,
+    ) {
+        if !self.stages.contains(&stage) {
+            return;
+        }
+
+        let width = if stage.is_coded_size() {
+            self.frame_width
+        } else {
+            self.upscaled_width
+        };
+        let frame = self.crop_frame(&samples(self), width as usize);
+        self.stage_frames.push((stage, frame));
+    }
+
+    /// CurrFrame with the residual taken out again.
+    pub(super) fn prediction_samples(&self) -> Cow<'_, [PlaneSamples]> {
+        let max = (1 << self.bit_depth as i32) - 1;
+        self.map_residual(|sample, residual| (sample as i32 - residual).clamp(0, max) as u16)
+    }
+
+    pub(super) fn residual_samples(&self) -> Cow<'_, [PlaneSamples]> {
+        let max = (1 << self.bit_depth as i32) - 1;
+        let mid = 1 << (self.bit_depth as i32 - 1);
+        self.map_residual(|_, residual| (mid + residual).clamp(0, max) as u16)
+    }
+
+    fn map_residual(&self, f: impl Fn(u16, i32) -> u16) -> Cow<'_, [PlaneSamples]> {
+        let samples = self
+            .curr_frame
+            .iter()
+            .zip(&self.residual_frame)
+            .map(|(plane, residuals)| {
+                plane
+                    .iter()
+                    .zip(residuals)
+                    .map(|(row, residuals)| {
+                        row.iter()
+                            .zip(residuals)
+                            .map(|(&sample, &residual)| f(sample, residual))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+
+        Cow::Owned(samples)
+    }
+
+    /// Wraps the decoded samples of a frame, which are padded to whole superblocks, in a
+    /// Frame covering only the visible picture.
+    pub(super) fn to_frame(&self, samples: &[PlaneSamples]) -> Frame {
+        let mut frame = self.crop_frame(samples, self.upscaled_width as usize);
+        frame.info = FrameInfo {
+            frame_width: self.frame_width as usize,
+            frame_height: self.frame_height as usize,
+            mi_rows: self.mi_rows as usize,
+            mi_cols: self.mi_cols as usize,
+            superblock_size: if self.sequence_header.use_128x128_superblock {
+                128
+            } else {
+                64
+            },
+            blocks: self.block_infos.clone(),
+            tx_sizes: self.loopfilter_tx_sizes[0].clone(),
+            tx_types: self.tx_types.clone(),
+            motion_field: self.projected_motion_field(),
+            cdef_directions: self.cdef_directions.clone(),
+            cdef_variances: self.cdef_variances.clone(),
+        };
+
+        frame
+    }
+
+    /// A frame holding the first `width` columns of padded samples.
+    fn crop_frame(&self, samples: &[PlaneSamples], width: usize) -> Frame {
+        let color_config = &self.sequence_header.color_config;
+        let height = self.frame_height as usize;
+
+        let planes = samples
+            .iter()
+            .enumerate()
+            .map(|(plane, rows)| {
+                let (sub_x, sub_y) = if plane == 0 {
+                    (0, 0)
+                } else {
+                    (
+                        color_config.subsampling_x as usize,
+                        color_config.subsampling_y as usize,
+                    )
+                };
+
+                let stride = rows.first().map_or(0, |row| row.len());
+                Plane {
+                    width: (width + sub_x) >> sub_x,
+                    height: (height + sub_y) >> sub_y,
+                    stride,
+                    data: rows.concat(),
+                }
+            })
+            .collect();
+
+        Frame {
+            timestamp: self.timestamp,
+            width,
+            height,
+            bit_depth: self.bit_depth,
+            subsampling_x: color_config.subsampling_x,
+            subsampling_y: color_config.subsampling_y,
+            color_config: color_config.clone(),
+            planes,
+            info: FrameInfo::default(),
+            stages: Vec::new(),
+        }
+    }
+
+    fn projected_motion_field(&self) -> Vec<Vec<Option<ProjectedMv>>> {
+        if !self.uh.use_ref_frame_mvs {
+            return Vec::new();
+        }
+
+        let mvs = &self.motion_field_mvs[Decoder::LAST_FRAME];
+        self.motion_field_sources
+            .iter()
+            .zip(mvs)
+            .map(|(sources, mvs)| {
+                sources
+                    .iter()
+                    .zip(mvs)
+                    .map(|(&source, &mv)| {
+                        (source > Decoder::INTRA_FRAME).then_some(ProjectedMv {
+                            source: source as usize,
+                            mv,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,726 @@
+use super::{
+    block::{CompoundType, InterIntraMode, InterpFilter, MotionMode, SubSize, YMode},
+    obu::WarpModel,
+    round2, round2signed, BitStream, Decoder,
+};
+
+// https://aomediacodec.github.io/av1-spec/#inter-prediction-process
+
+impl Decoder {
+    const SUBPEL_BITS: i64 = 4;
+    const SUBPEL_MASK: i64 = 15;
+    const SCALE_SUBPEL_BITS: i64 = 10;
+    const REF_SCALE_SHIFT: i64 = 14;
+    pub const FILTER_BITS: i64 = 7;
+
+    /// Subpel_Filters[filter_idx][position]. The first four are indexed by InterpFilter, the
+    /// last two are the 4-tap variants used for small blocks.
+    const SUBPEL_FILTERS: [[[i64; 8]; 16]; 6] = [
+        // EIGHTTAP (regular)
+        [
+            [0, 0, 0, 128, 0, 0, 0, 0],
+            [0, 2, -6, 126, 8, -2, 0, 0],
+            [0, 2, -10, 122, 18, -4, 0, 0],
+            [0, 2, -12, 116, 28, -8, 2, 0],
+            [0, 2, -14, 110, 38, -10, 2, 0],
+            [0, 2, -14, 102, 48, -12, 2, 0],
+            [0, 2, -16, 94, 58, -12, 2, 0],
+            [0, 2, -14, 84, 66, -12, 2, 0],
+            [0, 2, -14, 76, 76, -14, 2, 0],
+            [0, 2, -12, 66, 84, -14, 2, 0],
+            [0, 2, -12, 58, 94, -16, 2, 0],
+            [0, 2, -12, 48, 102, -14, 2, 0],
+            [0, 2, -10, 38, 110, -14, 2, 0],
+            [0, 2, -8, 28, 116, -12, 2, 0],
+            [0, 0, -4, 18, 122, -10, 2, 0],
+            [0, 0, -2, 8, 126, -6, 2, 0],
+        ],
+        // EIGHTTAP_SMOOTH
+        [
+            [0, 0, 0, 128, 0, 0, 0, 0],
+            [0, 2, 28, 62, 34, 2, 0, 0],
+            [0, 0, 26, 62, 36, 4, 0, 0],
+            [0, 0, 22, 62, 40, 4, 0, 0],
+            [0, 0, 20, 60, 42, 6, 0, 0],
+            [0, 0, 18, 58, 44, 8, 0, 0],
+            [0, 0, 16, 56, 46, 10, 0, 0],
+            [0, -2, 16, 54, 48, 12, 0, 0],
+            [0, -2, 14, 52, 52, 14, -2, 0],
+            [0, 0, 12, 48, 54, 16, -2, 0],
+            [0, 0, 10, 46, 56, 16, 0, 0],
+            [0, 0, 8, 44, 58, 18, 0, 0],
+            [0, 0, 6, 42, 60, 20, 0, 0],
+            [0, 0, 4, 40, 62, 22, 0, 0],
+            [0, 0, 4, 36, 62, 26, 0, 0],
+            [0, 0, 2, 34, 62, 28, 2, 0],
+        ],
+        // EIGHTTAP_SHARP
+        [
+            [0, 0, 0, 128, 0, 0, 0, 0],
+            [-2, 2, -6, 126, 8, -2, 2, 0],
+            [-2, 6, -12, 124, 16, -6, 4, -2],
+            [-2, 8, -18, 120, 26, -10, 6, -2],
+            [-4, 10, -22, 116, 38, -14, 6, -2],
+            [-4, 10, -22, 108, 48, -18, 8, -2],
+            [-4, 10, -24, 100, 60, -20, 8, -2],
+            [-4, 10, -24, 90, 70, -22, 10, -2],
+            [-4, 12, -24, 80, 80, -24, 12, -4],
+            [-2, 10, -22, 70, 90, -24, 10, -4],
+            [-2, 8, -20, 60, 100, -24, 10, -4],
+            [-2, 8, -18, 48, 108, -22, 10, -4],
+            [-2, 6, -14, 38, 116, -22, 10, -4],
+            [-2, 6, -10, 26, 120, -18, 8, -2],
+            [-2, 4, -6, 16, 124, -12, 6, -2],
+            [0, 2, -2, 8, 126, -6, 2, -2],
+        ],
+        // BILINEAR
+        [
+            [0, 0, 0, 128, 0, 0, 0, 0],
+            [0, 0, 0, 120, 8, 0, 0, 0],
+            [0, 0, 0, 112, 16, 0, 0, 0],
+            [0, 0, 0, 104, 24, 0, 0, 0],
+            [0, 0, 0, 96, 32, 0, 0, 0],
+            [0, 0, 0, 88, 40, 0, 0, 0],
+            [0, 0, 0, 80, 48, 0, 0, 0],
+            [0, 0, 0, 72, 56, 0, 0, 0],
+            [0, 0, 0, 64, 64, 0, 0, 0],
+            [0, 0, 0, 56, 72, 0, 0, 0],
+            [0, 0, 0, 48, 80, 0, 0, 0],
+            [0, 0, 0, 40, 88, 0, 0, 0],
+            [0, 0, 0, 32, 96, 0, 0, 0],
+            [0, 0, 0, 24, 104, 0, 0, 0],
+            [0, 0, 0, 16, 112, 0, 0, 0],
+            [0, 0, 0, 8, 120, 0, 0, 0],
+        ],
+        // 4-tap regular, used for blocks of width or height <= 4
+        [
+            [0, 0, 0, 128, 0, 0, 0, 0],
+            [0, 0, -4, 126, 8, -2, 0, 0],
+            [0, 0, -8, 122, 18, -4, 0, 0],
+            [0, 0, -10, 116, 28, -6, 0, 0],
+            [0, 0, -12, 110, 38, -8, 0, 0],
+            [0, 0, -12, 102, 48, -10, 0, 0],
+            [0, 0, -14, 94, 58, -10, 0, 0],
+            [0, 0, -12, 84, 66, -10, 0, 0],
+            [0, 0, -12, 76, 76, -12, 0, 0],
+            [0, 0, -10, 66, 84, -12, 0, 0],
+            [0, 0, -10, 58, 94, -14, 0, 0],
+            [0, 0, -10, 48, 102, -12, 0, 0],
+            [0, 0, -8, 38, 110, -12, 0, 0],
+            [0, 0, -6, 28, 116, -10, 0, 0],
+            [0, 0, -4, 18, 122, -8, 0, 0],
+            [0, 0, -2, 8, 126, -4, 0, 0],
+        ],
+        // 4-tap smooth, used for blocks of width or height <= 4
+        [
+            [0, 0, 0, 128, 0, 0, 0, 0],
+            [0, 0, 30, 62, 34, 2, 0, 0],
+            [0, 0, 26, 62, 36, 4, 0, 0],
+            [0, 0, 22, 62, 40, 4, 0, 0],
+            [0, 0, 20, 60, 42, 6, 0, 0],
+            [0, 0, 18, 58, 44, 8, 0, 0],
+            [0, 0, 16, 56, 46, 10, 0, 0],
+            [0, 0, 14, 54, 48, 12, 0, 0],
+            [0, 0, 12, 52, 52, 12, 0, 0],
+            [0, 0, 12, 48, 54, 14, 0, 0],
+            [0, 0, 10, 46, 56, 16, 0, 0],
+            [0, 0, 8, 44, 58, 18, 0, 0],
+            [0, 0, 6, 42, 60, 20, 0, 0],
+            [0, 0, 4, 40, 62, 22, 0, 0],
+            [0, 0, 4, 36, 62, 26, 0, 0],
+            [0, 0, 2, 34, 62, 30, 0, 0],
+        ],
+    ];
+
+    /// The inter part of compute_prediction(). Chroma of blocks smaller than 8x8 is predicted
+    /// with the motion vectors of the luma blocks it covers, unless one of them is intra.
+    pub(super) fn predict_inter_block(&mut self) {
+        let color_config = &self.sequence_header.color_config;
+        let (subsampling_x, subsampling_y) = (
+            color_config.subsampling_x as usize,
+            color_config.subsampling_y as usize,
+        );
+        let num_planes = if self.has_chroma {
+            self.num_planes as usize
+        } else {
+            1
+        };
+
+        if self.motion_mode == MotionMode::LocalWarp {
+            self.warp_estimation();
+            if self.local_valid {
+                self.local_valid = Decoder::setup_shear(&self.local_warp_params).0;
+            }
+        }
+
+        for plane in 0..num_planes {
+            let plane_sz = self.get_plane_residual_size(self.mi_size, plane);
+            let num4x4_w = Decoder::NUM_4X4_BLOCKS_WIDE[plane_sz as usize];
+            let num4x4_h = Decoder::NUM_4X4_BLOCKS_HIGH[plane_sz as usize];
+            let (sub_x, sub_y) = if plane > 0 {
+                (subsampling_x, subsampling_y)
+            } else {
+                (0, 0)
+            };
+
+            let mi_row = self.mi_row as usize;
+            let mi_col = self.mi_col as usize;
+            let base_x = (mi_col >> sub_x) * Decoder::MI_SIZE as usize;
+            let base_y = (mi_row >> sub_y) * Decoder::MI_SIZE as usize;
+            let mut cand_row = (mi_row >> sub_y) << sub_y;
+            let mut cand_col = (mi_col >> sub_x) << sub_x;
+
+            // The intra prediction goes into CurrFrame, where mask_blend() mixes it with the
+            // inter prediction.
+            if self.interintra {
+                let mode = match self.interintra_mode {
+                    InterIntraMode::Dc => YMode::Dc,
+                    InterIntraMode::V => YMode::V,
+                    InterIntraMode::H => YMode::H,
+                    InterIntraMode::Smooth => YMode::Smooth,
+                };
+                let (avail_l, avail_u) = if plane == 0 {
+                    (self.avail_l, self.avail_u)
+                } else {
+                    (self.avail_l_chroma, self.avail_u_chroma)
+                };
+                let sb_mask = if self.sequence_header.use_128x128_superblock {
+                    31
+                } else {
+                    15
+                };
+                // block_decoded is offset by one in both directions.
+                let decoded_row = ((mi_row & sb_mask) >> sub_y) + 1;
+                let decoded_col = ((mi_col & sb_mask) >> sub_x) + 1;
+                let have_above_right =
+                    self.block_decoded[plane][decoded_row - 1][decoded_col + num4x4_w];
+                let have_below_left =
+                    self.block_decoded[plane][decoded_row + num4x4_h][decoded_col - 1];
+
+                self.predict_intra(
+                    plane,
+                    base_x,
+                    base_y,
+                    avail_l,
+                    avail_u,
+                    have_above_right,
+                    have_below_left,
+                    mode,
+                    (num4x4_w * 4).trailing_zeros() as usize,
+                    (num4x4_h * 4).trailing_zeros() as usize,
+                );
+            }
+
+            let mut pred_w = self.mi_size.width() >> sub_x;
+            let mut pred_h = self.mi_size.height() >> sub_y;
+            let mut some_use_intra = false;
+            for r in 0..(num4x4_h << sub_y) {
+                for c in 0..(num4x4_w << sub_x) {
+                    let (row, col) = (cand_row + r, cand_col + c);
+                    if row < self.mi_rows as usize
+                        && col < self.mi_cols as usize
+                        && self.ref_frames[row][col][0] == Decoder::INTRA_FRAME
+                    {
+                        some_use_intra = true;
+                    }
+                }
+            }
+
+            if some_use_intra {
+                pred_w = num4x4_w * 4;
+                pred_h = num4x4_h * 4;
+                cand_row = mi_row;
+                cand_col = mi_col;
+            }
+
+            for (r, y) in (0..num4x4_h * 4).step_by(pred_h).enumerate() {
+                for (c, x) in (0..num4x4_w * 4).step_by(pred_w).enumerate() {
+                    self.predict_inter(
+                        plane,
+                        base_x + x,
+                        base_y + y,
+                        pred_w,
+                        pred_h,
+                        cand_row + r,
+                        cand_col + c,
+                    );
+                }
+            }
+        }
+    }
+
+    /// The interp_filter part of inter_block_mode_info(). Without enable_dual_filter the
+    /// vertical filter is also used horizontally.
+    pub(super) fn read_interp_filter(&mut self, b: &mut BitStream) {
+        if self.uh.interpolation_filter != InterpFilter::Switchable {
+            self.interp_filter = [self.uh.interpolation_filter; 2];
+            return;
+        }
+
+        let dirs = if self.sequence_header.enable_dual_filter {
+            2
+        } else {
+            1
+        };
+
+        for dir in 0..dirs {
+            self.interp_filter[dir] = if self.needs_interp_filter() {
+                let ctx = self.interp_filter_ctx(dir);
+                InterpFilter::new(
+                    self.symbol.read_symbol(b, &mut self.cdf.interp_filter[ctx]) as u64
+                )
+            } else {
+                InterpFilter::EightTap
+            };
+        }
+
+        if !self.sequence_header.enable_dual_filter {
+            self.interp_filter[1] = self.interp_filter[0];
+        }
+    }
+
+    /// The motion_mode part of inter_block_mode_info(). Local warp needs warp samples and
+    /// an unscaled reference, otherwise only OBMC can be signalled.
+    pub(super) fn read_motion_mode(&mut self, b: &mut BitStream, is_compound: bool) {
+        self.motion_mode = MotionMode::Simple;
+
+        if self.skip_mode
+            || !self.uh.is_motion_mode_switchable
+            || self.mi_size.width().min(self.mi_size.height()) < 8
+        {
+            return;
+        }
+
+        if self.uh.force_integer_mv == 0
+            && matches!(self.y_mode, YMode::GlobalMv | YMode::GlobalGlobalMv)
+            && self.gm_type[self.ref_frame[0] as usize] > WarpModel::Translation
+        {
+            return;
+        }
+
+        if is_compound
+            || self.ref_frame[1] == Decoder::INTRA_FRAME
+            || !self.has_overlappable_candidates()
+        {
+            return;
+        }
+
+        self.find_warp_samples();
+        let mi_size = self.mi_size as usize;
+        if self.uh.force_integer_mv != 0
+            || self.num_samples == 0
+            || !self.uh.allow_warped_motion
+            || self.is_scaled(self.ref_frame[0] as usize)
+        {
+            let use_obmc = self.symbol.read_symbol(b, &mut self.cdf.use_obmc[mi_size]) != 0;
+            if use_obmc {
+                self.motion_mode = MotionMode::Obmc;
+            }
+        } else {
+            self.motion_mode = MotionMode::new(
+                self.symbol
+                    .read_symbol(b, &mut self.cdf.motion_mode[mi_size]) as u64,
+            );
+        }
+    }
+
+    fn needs_interp_filter(&self) -> bool {
+        let large = self.mi_size.width().min(self.mi_size.height()) >= 8;
+        let is_translation =
+            |i: usize| self.gm_type[self.ref_frame[i] as usize] == WarpModel::Translation;
+
+        if self.skip_mode || self.motion_mode == MotionMode::LocalWarp {
+            false
+        } else if large && self.y_mode == YMode::GlobalMv {
+            is_translation(0)
+        } else if large && self.y_mode == YMode::GlobalGlobalMv {
+            is_translation(0) || is_translation(1)
+        } else {
+            true
+        }
+    }
+
+    /// Neighbours only contribute their filter if they use the same first reference frame.
+    fn interp_filter_ctx(&self, dir: usize) -> usize {
+        let mut ctx = ((dir & 1) * 2 + (self.ref_frame[1] > Decoder::INTRA_FRAME) as usize) * 4;
+        let mi_row = self.mi_row as usize;
+        let mi_col = self.mi_col as usize;
+
+        let neighbour_type = |row: usize, col: usize| {
+            if self.ref_frames[row][col].contains(&self.ref_frame[0]) {
+                self.interp_filters[row][col][dir] as usize
+            } else {
+                3
+            }
+        };
+
+        let left_type = if self.avail_l {
+            neighbour_type(mi_row, mi_col - 1)
+        } else {
+            3
+        };
+        let above_type = if self.avail_u {
+            neighbour_type(mi_row - 1, mi_col)
+        } else {
+            3
+        };
+
+        ctx += if left_type == above_type {
+            left_type
+        } else if left_type == 3 {
+            above_type
+        } else if above_type == 3 {
+            left_type
+        } else {
+            3
+        };
+
+        ctx
+    }
+
+    pub(super) fn get_plane_residual_size(&self, subsize: SubSize, plane: usize) -> SubSize {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as usize,
+                color_config.subsampling_y as usize,
+            )
+        } else {
+            (0, 0)
+        };
+
+        let w4 = (Decoder::NUM_4X4_BLOCKS_WIDE[subsize as usize] >> sub_x).max(1);
+        let h4 = (Decoder::NUM_4X4_BLOCKS_HIGH[subsize as usize] >> sub_y).max(1);
+
+        (0..Decoder::BLOCK_SIZES)
+            .find(|&i| {
+                Decoder::NUM_4X4_BLOCKS_WIDE[i] == w4 && Decoder::NUM_4X4_BLOCKS_HIGH[i] == h4
+            })
+            .map(|i| SubSize::new(i as u64))
+            .unwrap_or_else(|| panic!("invalid residual size for {subsize:?} in plane {plane}"))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn predict_inter(
+        &mut self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        cand_row: usize,
+        cand_col: usize,
+    ) {
+        let is_compound = self.ref_frames[cand_row][cand_col][1] > Decoder::INTRA_FRAME;
+        self.rounding_variables_derivation(is_compound);
+
+        let preds: Vec<Vec<Vec<i64>>> = (0..1 + is_compound as usize)
+            .map(|ref_list| {
+                self.predict_from_reference(plane, x, y, w, h, cand_row, cand_col, ref_list)
+            })
+            .collect();
+
+        let is_inter_intra = self.is_inter && self.ref_frame[1] == Decoder::INTRA_FRAME;
+        if is_compound || is_inter_intra {
+            self.compound_mask(plane, &preds, w, h);
+        }
+
+        if is_compound && self.compound_type == CompoundType::Distance {
+            self.distance_weights(cand_row, cand_col);
+        }
+
+        if !is_compound && !is_inter_intra {
+            for (i, row) in preds[0].iter().enumerate() {
+                for (j, &p) in row.iter().enumerate() {
+                    self.curr_frame[plane][y + i][x + j] = self.clip1(p.max(0) as u64);
+                }
+            }
+        } else if self.compound_type == CompoundType::Average {
+            for (i, (row0, row1)) in preds[0].iter().zip(&preds[1]).enumerate() {
+                for (j, (p0, p1)) in row0.iter().zip(row1).enumerate() {
+                    let p = round2(p0 + p1, 1 + self.inter_post_round);
+                    self.curr_frame[plane][y + i][x + j] = self.clip1(p.max(0) as u64);
+                }
+            }
+        } else if self.compound_type == CompoundType::Distance {
+            for (i, (row0, row1)) in preds[0].iter().zip(&preds[1]).enumerate() {
+                for (j, (p0, p1)) in row0.iter().zip(row1).enumerate() {
+                    let p = round2(
+                        self.fwd_weight * p0 + self.bck_weight * p1,
+                        4 + self.inter_post_round,
+                    );
+                    self.curr_frame[plane][y + i][x + j] = self.clip1(p.max(0) as u64);
+                }
+            }
+        } else {
+            self.mask_blend(&preds, plane, x, y, w, h);
+        }
+
+        if self.motion_mode == MotionMode::Obmc {
+            self.overlapped_motion_compensation(plane, w, h);
+        }
+    }
+
+    /// Predicts a w x h block from reference ref_list of the block at (cand_row, cand_col),
+    /// with the warp filter if a warp model applies.
+    #[allow(clippy::too_many_arguments)]
+    fn predict_from_reference(
+        &self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        cand_row: usize,
+        cand_col: usize,
+        ref_list: usize,
+    ) -> Vec<Vec<i64>> {
+        let ref_frame = self.ref_frames[cand_row][cand_col][ref_list] as usize;
+        let mv = self.mvs[cand_row][cand_col][ref_list];
+        let interp_filter = self.interp_filters[cand_row][cand_col];
+
+        // The reference of intra block copy is the current frame before any filtering.
+        let (reference, ref_upscaled_width, ref_frame_height) = if self.use_intrabc {
+            (
+                &self.curr_frame[plane],
+                self.upscaled_width,
+                self.frame_height,
+            )
+        } else {
+            let ref_idx = self.uh.ref_frame_idx[ref_frame - Decoder::LAST_FRAME];
+            let store = &self.ref_frame_store[ref_idx];
+            assert!(
+                2 * self.frame_width >= store.upscaled_width
+                    && 2 * self.frame_height >= store.frame_height
+                    && self.frame_width <= 16 * store.upscaled_width
+                    && self.frame_height <= 16 * store.frame_height,
+                "reference frame {ref_idx} ({}x{}) can't be scaled to {}x{}",
+                store.upscaled_width,
+                store.frame_height,
+                self.frame_width,
+                self.frame_height
+            );
+
+            (
+                &store.frame[plane],
+                store.upscaled_width,
+                store.frame_height,
+            )
+        };
+
+        let use_warp = self.use_warp(w, h, ref_frame);
+        if use_warp != 0 {
+            let warp_params = if use_warp == 1 {
+                &self.local_warp_params[..]
+            } else {
+                &self.gm_params[ref_frame][..]
+            };
+
+            let mut pred = vec![vec![0; w]; h];
+            for i8 in 0..=((h - 1) >> 3) {
+                for j8 in 0..=((w - 1) >> 3) {
+                    self.block_warp(
+                        warp_params,
+                        reference,
+                        ref_upscaled_width as i64,
+                        ref_frame_height as i64,
+                        plane,
+                        x as i64,
+                        y as i64,
+                        i8,
+                        j8,
+                        &mut pred,
+                    );
+                }
+            }
+
+            pred
+        } else {
+            let (start_x, start_y, x_step, y_step) = self.motion_vector_scaling(
+                plane,
+                ref_upscaled_width as i64,
+                ref_frame_height as i64,
+                x as i64,
+                y as i64,
+                mv,
+            );
+
+            self.block_inter_prediction(
+                reference,
+                plane,
+                ref_upscaled_width as i64,
+                ref_frame_height as i64,
+                start_x,
+                start_y,
+                x_step,
+                y_step,
+                w,
+                h,
+                interp_filter,
+            )
+        }
+    }
+
+    pub(super) fn is_scaled(&self, ref_frame: usize) -> bool {
+        let store = &self.ref_frame_store[self.uh.ref_frame_idx[ref_frame - Decoder::LAST_FRAME]];
+        let frame_width = self.frame_width as i64;
+        let frame_height = self.frame_height as i64;
+        let x_scale = (((store.upscaled_width as i64) << Decoder::REF_SCALE_SHIFT)
+            + (frame_width / 2))
+            / frame_width;
+        let y_scale = (((store.frame_height as i64) << Decoder::REF_SCALE_SHIFT)
+            + (frame_height / 2))
+            / frame_height;
+        let no_scale = 1 << Decoder::REF_SCALE_SHIFT;
+
+        x_scale != no_scale || y_scale != no_scale
+    }
+
+    pub(super) fn rounding_variables_derivation(&mut self, is_compound: bool) {
+        self.inter_round0 = 3;
+        self.inter_round1 = if is_compound { 7 } else { 11 };
+
+        if self.bit_depth as u64 == 12 {
+            self.inter_round0 = 5;
+            if !is_compound {
+                self.inter_round1 = 9;
+            }
+        }
+
+        self.inter_post_round = 2 * Decoder::FILTER_BITS - (self.inter_round0 + self.inter_round1);
+    }
+
+    /// Returns the position of the top left sample in the reference in units of 1/1024th
+    /// sample, and the step between neighbouring samples.
+    pub(super) fn motion_vector_scaling(
+        &self,
+        plane: usize,
+        ref_upscaled_width: i64,
+        ref_frame_height: i64,
+        x: i64,
+        y: i64,
+        mv: [i64; 2],
+    ) -> (i64, i64, i64, i64) {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as i64,
+                color_config.subsampling_y as i64,
+            )
+        } else {
+            (0, 0)
+        };
+
+        let frame_width = self.frame_width as i64;
+        let frame_height = self.frame_height as i64;
+        let x_scale =
+            ((ref_upscaled_width << Decoder::REF_SCALE_SHIFT) + (frame_width / 2)) / frame_width;
+        let y_scale =
+            ((ref_frame_height << Decoder::REF_SCALE_SHIFT) + (frame_height / 2)) / frame_height;
+
+        let half_sample = 1 << (Decoder::SUBPEL_BITS - 1);
+        let orig_x = (x << Decoder::SUBPEL_BITS) + ((2 * mv[1]) >> sub_x) + half_sample;
+        let orig_y = (y << Decoder::SUBPEL_BITS) + ((2 * mv[0]) >> sub_y) + half_sample;
+        let base_x = orig_x * x_scale - (half_sample << Decoder::REF_SCALE_SHIFT);
+        let base_y = orig_y * y_scale - (half_sample << Decoder::REF_SCALE_SHIFT);
+        let off = (1 << (Decoder::SCALE_SUBPEL_BITS - Decoder::SUBPEL_BITS)) / 2;
+        let shift = Decoder::REF_SCALE_SHIFT + Decoder::SUBPEL_BITS - Decoder::SCALE_SUBPEL_BITS;
+
+        (
+            round2signed(base_x, shift) + off,
+            round2signed(base_y, shift) + off,
+            round2signed(
+                x_scale,
+                Decoder::REF_SCALE_SHIFT - Decoder::SCALE_SUBPEL_BITS,
+            ),
+            round2signed(
+                y_scale,
+                Decoder::REF_SCALE_SHIFT - Decoder::SCALE_SUBPEL_BITS,
+            ),
+        )
+    }
+
+    /// Separable 8-tap interpolation of a w x h block, horizontal first with interp_filter[1],
+    /// then vertical with interp_filter[0]. The intermediate result keeps InterRound0 bits,
+    /// the output InterRound1 bits of extra precision.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn block_inter_prediction(
+        &self,
+        reference: &[Vec<u16>],
+        plane: usize,
+        ref_upscaled_width: i64,
+        ref_frame_height: i64,
+        start_x: i64,
+        start_y: i64,
+        x_step: i64,
+        y_step: i64,
+        w: usize,
+        h: usize,
+        interp_filter: [InterpFilter; 2],
+    ) -> Vec<Vec<i64>> {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as i64,
+                color_config.subsampling_y as i64,
+            )
+        } else {
+            (0, 0)
+        };
+
+        let filter_idx = |filter: InterpFilter, size: usize| match filter {
+            InterpFilter::EightTap | InterpFilter::EightTapSharp if size <= 4 => 4,
+            InterpFilter::EightTapSmooth if size <= 4 => 5,
+            InterpFilter::Switchable => panic!("interpolation filter has not been resolved"),
+            _ => filter as usize,
+        };
+        let filters_x = &Decoder::SUBPEL_FILTERS[filter_idx(interp_filter[1], w)];
+        let filters_y = &Decoder::SUBPEL_FILTERS[filter_idx(interp_filter[0], h)];
+
+        let last_x = ((ref_upscaled_width + sub_x) >> sub_x) - 1;
+        let last_y = ((ref_frame_height + sub_y) >> sub_y) - 1;
+
+        let intermediate_height = ((((h as i64 - 1) * y_step + (1 << Decoder::SCALE_SUBPEL_BITS)
+            - 1)
+            >> Decoder::SCALE_SUBPEL_BITS)
+            + 8) as usize;
+
+        let mut intermediate = vec![vec![0i64; w]; intermediate_height];
+        for (r, row) in intermediate.iter_mut().enumerate() {
+            let ref_row = &reference[((start_y >> Decoder::SCALE_SUBPEL_BITS) + r as i64 - 3)
+                .clamp(0, last_y) as usize];
+
+            for (c, v) in row.iter_mut().enumerate() {
+                let p = start_x + x_step * c as i64;
+                let filter = &filters_x[((p >> 6) & Decoder::SUBPEL_MASK) as usize];
+
+                let mut s = 0;
+                for (t, f) in filter.iter().enumerate() {
+                    let sample_x =
+                        ((p >> Decoder::SCALE_SUBPEL_BITS) + t as i64 - 3).clamp(0, last_x);
+                    s += f * ref_row[sample_x as usize] as i64;
+                }
+
+                *v = round2(s, self.inter_round0);
+            }
+        }
+
+        let mut pred = vec![vec![0i64; w]; h];
+        for (r, row) in pred.iter_mut().enumerate() {
+            let p = (start_y & 1023) + y_step * r as i64;
+            let filter = &filters_y[((p >> 6) & Decoder::SUBPEL_MASK) as usize];
+
+            for (c, v) in row.iter_mut().enumerate() {
+                let mut s = 0;
+                for (t, f) in filter.iter().enumerate() {
+                    s += f * intermediate[((p >> Decoder::SCALE_SUBPEL_BITS) + t as i64) as usize]
+                        [c];
+                }
+
+                *v = round2(s, self.inter_round1);
+            }
+        }
+
+        pred
+    }
+}
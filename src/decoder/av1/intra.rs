@@ -0,0 +1,688 @@
+use super::{
+    block::{TxSize, UvMode, YMode},
+    round2, round2signed, Decoder,
+};
+
+// https://aomediacodec.github.io/av1-spec/#intra-prediction-process
+
+/// AboveRow and LeftCol are indexed from -1 (and from -2 once upsampled), so they are kept
+/// in buffers starting this many entries earlier.
+const EDGE_OFFSET: usize = 16;
+
+impl Decoder {
+    const ANGLE_STEP: i64 = 3;
+    const INTRA_FILTER_SCALE_BITS: i64 = 4;
+    const MODE_TO_ANGLE: [i64; 9] = [0, 90, 180, 45, 135, 113, 157, 203, 67];
+    const INTRA_EDGE_KERNEL: [[i64; 5]; 3] = [[0, 4, 8, 4, 0], [0, 5, 6, 5, 0], [2, 4, 4, 4, 2]];
+    const SM_WEIGHTS_TX_4X4: [i64; 4] = [255, 149, 85, 64];
+    const SM_WEIGHTS_TX_8X8: [i64; 8] = [255, 197, 146, 105, 73, 50, 37, 32];
+    const SM_WEIGHTS_TX_16X16: [i64; 16] = [
+        255, 225, 196, 170, 145, 123, 102, 84, 68, 54, 43, 33, 26, 20, 17, 16,
+    ];
+    const SM_WEIGHTS_TX_32X32: [i64; 32] = [
+        255, 240, 225, 210, 196, 182, 169, 157, 145, 133, 122, 111, 101, 92, 83, 74, 66, 59, 52,
+        45, 39, 34, 29, 25, 21, 17, 14, 12, 10, 9, 8, 8,
+    ];
+    const SM_WEIGHTS_TX_64X64: [i64; 64] = [
+        255, 248, 240, 233, 225, 218, 210, 203, 196, 189, 182, 176, 169, 163, 156, 150, 144, 138,
+        133, 127, 121, 116, 111, 106, 101, 96, 91, 86, 82, 77, 73, 69, 65, 61, 57, 54, 50, 47, 44,
+        41, 38, 35, 32, 29, 27, 25, 22, 20, 18, 16, 15, 13, 12, 10, 9, 8, 7, 6, 6, 5, 5, 4, 4, 4,
+    ];
+    /// Indexed by filter_intra_mode, then by the position in the 4x2 patch.
+    const INTRA_FILTER_TAPS: [[[i64; 7]; 8]; 5] = [
+        [
+            [-6, 10, 0, 0, 0, 12, 0],
+            [-5, 2, 10, 0, 0, 9, 0],
+            [-3, 1, 1, 10, 0, 7, 0],
+            [-3, 1, 1, 2, 10, 5, 0],
+            [-4, 6, 0, 0, 0, 2, 12],
+            [-3, 2, 6, 0, 0, 2, 9],
+            [-3, 2, 2, 6, 0, 2, 7],
+            [-3, 1, 2, 2, 6, 3, 5],
+        ],
+        [
+            [-10, 16, 0, 0, 0, 10, 0],
+            [-6, 0, 16, 0, 0, 6, 0],
+            [-4, 0, 0, 16, 0, 4, 0],
+            [-2, 0, 0, 0, 16, 2, 0],
+            [-10, 16, 0, 0, 0, 0, 10],
+            [-6, 0, 16, 0, 0, 0, 6],
+            [-4, 0, 0, 16, 0, 0, 4],
+            [-2, 0, 0, 0, 16, 0, 2],
+        ],
+        [
+            [-8, 8, 0, 0, 0, 16, 0],
+            [-8, 0, 8, 0, 0, 16, 0],
+            [-8, 0, 0, 8, 0, 16, 0],
+            [-8, 0, 0, 0, 8, 16, 0],
+            [-4, 4, 0, 0, 0, 0, 16],
+            [-4, 0, 4, 0, 0, 0, 16],
+            [-4, 0, 0, 4, 0, 0, 16],
+            [-4, 0, 0, 0, 4, 0, 16],
+        ],
+        [
+            [-2, 8, 0, 0, 0, 10, 0],
+            [-1, 3, 8, 0, 0, 6, 0],
+            [-1, 2, 3, 8, 0, 4, 0],
+            [0, 1, 2, 3, 8, 2, 0],
+            [-1, 4, 0, 0, 0, 3, 10],
+            [-1, 3, 4, 0, 0, 4, 6],
+            [-1, 2, 3, 4, 0, 4, 4],
+            [-1, 2, 2, 3, 4, 3, 3],
+        ],
+        [
+            [-12, 14, 0, 0, 0, 14, 0],
+            [-10, 0, 14, 0, 0, 12, 0],
+            [-9, 0, 0, 14, 0, 11, 0],
+            [-8, 0, 0, 0, 14, 10, 0],
+            [-10, 12, 0, 0, 0, 0, 14],
+            [-9, 1, 12, 0, 0, 0, 12],
+            [-8, 0, 0, 12, 0, 1, 11],
+            [-7, 0, 0, 1, 12, 1, 9],
+        ],
+    ];
+
+    /// Predicts a block of 1 << log2_w by 1 << log2_h samples at (x, y) of the plane from
+    /// the already decoded samples above and to the left of it.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn predict_intra(
+        &mut self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        have_left: bool,
+        have_above: bool,
+        have_above_right: bool,
+        have_below_left: bool,
+        mode: YMode,
+        log2_w: usize,
+        log2_h: usize,
+    ) {
+        let w = 1 << log2_w;
+        let h = 1 << log2_h;
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as usize,
+                color_config.subsampling_y as usize,
+            )
+        } else {
+            (0, 0)
+        };
+        let max_x = ((self.mi_cols as usize * Decoder::MI_SIZE as usize) >> sub_x) - 1;
+        let max_y = ((self.mi_rows as usize * Decoder::MI_SIZE as usize) >> sub_y) - 1;
+        let base = 1i64 << (self.bit_depth as i64 - 1);
+        let frame = &self.curr_frame[plane];
+
+        let mut above_row = vec![0i64; 2 * EDGE_OFFSET + 2 * (w + h)];
+        let mut left_col = vec![0i64; 2 * EDGE_OFFSET + 2 * (w + h)];
+        for i in 0..w + h {
+            above_row[EDGE_OFFSET + i] = if !have_above && have_left {
+                frame[y][x - 1] as i64
+            } else if !have_above {
+                base - 1
+            } else {
+                let above_limit = max_x.min(x + if have_above_right { 2 * w } else { w } - 1);
+                frame[y - 1][above_limit.min(x + i)] as i64
+            };
+
+            left_col[EDGE_OFFSET + i] = if !have_left && have_above {
+                frame[y - 1][x] as i64
+            } else if !have_left {
+                base + 1
+            } else {
+                let left_limit = max_y.min(y + if have_below_left { 2 * h } else { h } - 1);
+                frame[left_limit.min(y + i)][x - 1] as i64
+            };
+        }
+
+        let corner = match (have_above, have_left) {
+            (true, true) => frame[y - 1][x - 1] as i64,
+            (true, false) => frame[y - 1][x] as i64,
+            (false, true) => frame[y][x - 1] as i64,
+            (false, false) => base,
+        };
+        above_row[EDGE_OFFSET - 1] = corner;
+        left_col[EDGE_OFFSET - 1] = corner;
+
+        let pred = if plane == 0 && self.use_filter_intra {
+            self.recursive_intra_prediction(&above_row, &left_col, w, h)
+        } else if matches!(
+            mode,
+            YMode::V
+                | YMode::H
+                | YMode::D45
+                | YMode::D135
+                | YMode::D113
+                | YMode::D157
+                | YMode::D203
+                | YMode::D67
+        ) {
+            self.directional_intra_prediction(
+                plane,
+                x,
+                y,
+                have_left,
+                have_above,
+                mode,
+                w,
+                h,
+                max_x,
+                max_y,
+                &mut above_row,
+                &mut left_col,
+            )
+        } else if matches!(mode, YMode::Smooth | YMode::SmoothV | YMode::SmoothH) {
+            Decoder::smooth_intra_prediction(mode, &above_row, &left_col, log2_w, log2_h)
+        } else if mode == YMode::Dc {
+            let avg = self
+                .dc_intra_prediction(have_left, have_above, &above_row, &left_col, log2_w, log2_h);
+            vec![vec![avg; w]; h]
+        } else {
+            Decoder::paeth_intra_prediction(&above_row, &left_col, w, h)
+        };
+
+        for (i, row) in pred.iter().enumerate() {
+            for (j, &p) in row.iter().enumerate() {
+                self.curr_frame[plane][y + i][x + j] = p as u16;
+            }
+        }
+    }
+
+    fn intra_clip1(&self, x: i64) -> i64 {
+        x.clamp(0, (1 << self.bit_depth as i64) - 1)
+    }
+
+    /// The filter intra modes predict 4x2 patches from the 7 samples above and to the
+    /// left of them, which may themselves be predicted.
+    fn recursive_intra_prediction(
+        &self,
+        above_row: &[i64],
+        left_col: &[i64],
+        w: usize,
+        h: usize,
+    ) -> Vec<Vec<i64>> {
+        let mut pred = vec![vec![0; w]; h];
+        let taps = &Decoder::INTRA_FILTER_TAPS[self.filter_intra_mode];
+
+        for i2 in 0..h >> 1 {
+            for j4 in 0..w >> 2 {
+                let mut p = [0; 7];
+                for (i, p) in p.iter_mut().enumerate() {
+                    *p = if i < 5 {
+                        if i2 == 0 {
+                            above_row[EDGE_OFFSET + (j4 << 2) + i - 1]
+                        } else if j4 == 0 && i == 0 {
+                            left_col[EDGE_OFFSET + (i2 << 1) - 1]
+                        } else {
+                            pred[(i2 << 1) - 1][(j4 << 2) + i - 1]
+                        }
+                    } else if j4 == 0 {
+                        left_col[EDGE_OFFSET + (i2 << 1) + i - 5]
+                    } else {
+                        pred[(i2 << 1) + i - 5][(j4 << 2) - 1]
+                    };
+                }
+
+                for i in 0..2 {
+                    for j in 0..4 {
+                        let pr: i64 = taps[(i << 2) + j].iter().zip(p).map(|(t, p)| t * p).sum();
+                        pred[(i2 << 1) + i][(j4 << 2) + j] =
+                            self.intra_clip1(round2signed(pr, Decoder::INTRA_FILTER_SCALE_BITS));
+                    }
+                }
+            }
+        }
+
+        pred
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn directional_intra_prediction(
+        &self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        have_left: bool,
+        have_above: bool,
+        mode: YMode,
+        w: usize,
+        h: usize,
+        max_x: usize,
+        max_y: usize,
+        above_row: &mut [i64],
+        left_col: &mut [i64],
+    ) -> Vec<Vec<i64>> {
+        let angle_delta = if plane == 0 {
+            self.angle_delta_y
+        } else {
+            self.angle_delta_uv
+        };
+        let p_angle = Decoder::MODE_TO_ANGLE[mode as usize] + angle_delta * Decoder::ANGLE_STEP;
+        let mut upsample_above = 0;
+        let mut upsample_left = 0;
+
+        if self.sequence_header.enable_intra_edge_filter {
+            let filter_type = self.get_filter_type(plane);
+
+            if p_angle != 90 && p_angle != 180 {
+                if p_angle > 90 && p_angle < 180 && w + h >= 24 {
+                    let corner = round2(
+                        left_col[EDGE_OFFSET] * 5
+                            + above_row[EDGE_OFFSET - 1] * 6
+                            + above_row[EDGE_OFFSET] * 5,
+                        4,
+                    );
+                    left_col[EDGE_OFFSET - 1] = corner;
+                    above_row[EDGE_OFFSET - 1] = corner;
+                }
+
+                if have_above {
+                    let strength =
+                        Decoder::intra_edge_filter_strength(w, h, filter_type, p_angle - 90);
+                    let num_px = w.min(max_x - x + 1) + if p_angle < 90 { h } else { 0 } + 1;
+                    Decoder::intra_edge_filter(above_row, num_px, strength);
+                }
+
+                if have_left {
+                    let strength =
+                        Decoder::intra_edge_filter_strength(w, h, filter_type, p_angle - 180);
+                    let num_px = h.min(max_y - y + 1) + if p_angle > 180 { w } else { 0 } + 1;
+                    Decoder::intra_edge_filter(left_col, num_px, strength);
+                }
+            }
+
+            if Decoder::intra_edge_upsample(w, h, filter_type, p_angle - 90) {
+                upsample_above = 1;
+                let num_px = w + if p_angle < 90 { h } else { 0 };
+                self.intra_edge_upsample_edge(above_row, num_px);
+            }
+
+            if Decoder::intra_edge_upsample(w, h, filter_type, p_angle - 180) {
+                upsample_left = 1;
+                let num_px = h + if p_angle > 180 { w } else { 0 };
+                self.intra_edge_upsample_edge(left_col, num_px);
+            }
+        }
+
+        let dx = if p_angle < 90 {
+            Decoder::dr_intra_derivative(p_angle)
+        } else if p_angle > 90 && p_angle < 180 {
+            Decoder::dr_intra_derivative(180 - p_angle)
+        } else {
+            0
+        };
+        let dy = if p_angle > 90 && p_angle < 180 {
+            Decoder::dr_intra_derivative(p_angle - 90)
+        } else if p_angle > 180 {
+            Decoder::dr_intra_derivative(270 - p_angle)
+        } else {
+            0
+        };
+
+        let above = |i: i64| above_row[(EDGE_OFFSET as i64 + i) as usize];
+        let left = |i: i64| left_col[(EDGE_OFFSET as i64 + i) as usize];
+        let interpolate = |edge: &dyn Fn(i64) -> i64, base: i64, shift: i64| {
+            round2(edge(base) * (32 - shift) + edge(base + 1) * shift, 5)
+        };
+
+        let mut pred = vec![vec![0; w]; h];
+        for (i, row) in pred.iter_mut().enumerate() {
+            let i = i as i64;
+            for (j, p) in row.iter_mut().enumerate() {
+                let j = j as i64;
+                *p = if p_angle < 90 {
+                    let idx = (i + 1) * dx;
+                    let base = (idx >> (6 - upsample_above)) + (j << upsample_above);
+                    let shift = ((idx << upsample_above) >> 1) & 0x1F;
+                    let max_base_x = (w as i64 + h as i64 - 1) << upsample_above;
+                    if base < max_base_x {
+                        interpolate(&above, base, shift)
+                    } else {
+                        above(max_base_x)
+                    }
+                } else if p_angle > 90 && p_angle < 180 {
+                    let idx = (j << 6) - (i + 1) * dx;
+                    let base = idx >> (6 - upsample_above);
+                    if base >= -(1 << upsample_above) {
+                        let shift = ((idx << upsample_above) >> 1) & 0x1F;
+                        interpolate(&above, base, shift)
+                    } else {
+                        let idx = (i << 6) - (j + 1) * dy;
+                        let base = idx >> (6 - upsample_left);
+                        let shift = ((idx << upsample_left) >> 1) & 0x1F;
+                        interpolate(&left, base, shift)
+                    }
+                } else if p_angle > 180 {
+                    let idx = (j + 1) * dy;
+                    let base = (idx >> (6 - upsample_left)) + (i << upsample_left);
+                    let shift = ((idx << upsample_left) >> 1) & 0x1F;
+                    interpolate(&left, base, shift)
+                } else if p_angle == 90 {
+                    above(j)
+                } else {
+                    left(i)
+                };
+            }
+        }
+
+        pred
+    }
+
+    /// Dr_Intra_Derivative, the step per row or column of a prediction angle.
+    fn dr_intra_derivative(angle: i64) -> i64 {
+        match angle {
+            3 => 1023,
+            6 => 547,
+            9 => 372,
+            14 => 273,
+            17 => 215,
+            20 => 178,
+            23 => 151,
+            26 => 132,
+            29 => 116,
+            32 => 102,
+            36 => 90,
+            39 => 80,
+            42 => 71,
+            45 => 64,
+            48 => 57,
+            51 => 51,
+            54 => 45,
+            58 => 40,
+            61 => 35,
+            64 => 31,
+            67 => 27,
+            70 => 23,
+            73 => 19,
+            76 => 15,
+            81 => 11,
+            84 => 7,
+            87 => 3,
+            _ => panic!("invalid angle for Dr_Intra_Derivative: {angle}"),
+        }
+    }
+
+    /// Whether the above or left neighbour uses one of the smooth modes, which selects
+    /// the gentler edge filters.
+    fn get_filter_type(&self, plane: usize) -> bool {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = (color_config.subsampling_x, color_config.subsampling_y);
+        let (avail_u, avail_l) = if plane == 0 {
+            (self.avail_u, self.avail_l)
+        } else {
+            (self.avail_u_chroma, self.avail_l_chroma)
+        };
+        let max_row = self.mi_rows as usize - 1;
+        let max_col = self.mi_cols as usize - 1;
+
+        let above_smooth = avail_u && {
+            let mut r = self.mi_row as usize - 1;
+            let mut c = self.mi_col as usize;
+            if plane > 0 {
+                if sub_x && self.mi_col & 1 == 0 {
+                    c += 1;
+                }
+                if sub_y && self.mi_row & 1 == 1 {
+                    r -= 1;
+                }
+            }
+            self.is_smooth(r, c.min(max_col), plane)
+        };
+
+        let left_smooth = avail_l && {
+            let mut r = self.mi_row as usize;
+            let mut c = self.mi_col as usize - 1;
+            if plane > 0 {
+                if sub_x && self.mi_col & 1 == 1 {
+                    c -= 1;
+                }
+                if sub_y && self.mi_row & 1 == 0 {
+                    r += 1;
+                }
+            }
+            self.is_smooth(r.min(max_row), c, plane)
+        };
+
+        above_smooth || left_smooth
+    }
+
+    fn is_smooth(&self, row: usize, col: usize, plane: usize) -> bool {
+        if plane == 0 {
+            matches!(
+                self.y_modes[row][col],
+                YMode::Smooth | YMode::SmoothV | YMode::SmoothH
+            )
+        } else {
+            self.ref_frames[row][col][0] <= Decoder::INTRA_FRAME
+                && matches!(
+                    self.uv_modes[row][col],
+                    UvMode::Smooth | UvMode::SmoothV | UvMode::SmoothH
+                )
+        }
+    }
+
+    fn intra_edge_filter_strength(w: usize, h: usize, filter_type: bool, delta: i64) -> usize {
+        let d = delta.abs();
+        let blk_wh = w + h;
+
+        if !filter_type {
+            match blk_wh {
+                0..=8 => (d >= 56) as usize,
+                9..=16 => (d >= 40) as usize,
+                17..=24 if d >= 32 => 3,
+                17..=24 if d >= 16 => 2,
+                17..=24 => (d >= 8) as usize,
+                25..=32 if d >= 32 => 3,
+                25..=32 if d >= 4 => 2,
+                25..=32 => 1,
+                _ => 3,
+            }
+        } else {
+            match blk_wh {
+                0..=8 if d >= 64 => 2,
+                0..=8 => (d >= 40) as usize,
+                9..=16 if d >= 48 => 2,
+                9..=16 => (d >= 20) as usize,
+                17..=24 if d >= 4 => 3,
+                17..=24 => 0,
+                _ => 3,
+            }
+        }
+    }
+
+    /// Smooths the first num_px entries of an edge, starting at index -1.
+    fn intra_edge_filter(edge: &mut [i64], num_px: usize, strength: usize) {
+        if strength == 0 {
+            return;
+        }
+
+        let unfiltered = edge[EDGE_OFFSET - 1..EDGE_OFFSET - 1 + num_px].to_vec();
+        for i in 1..num_px {
+            let s: i64 = Decoder::INTRA_EDGE_KERNEL[strength - 1]
+                .iter()
+                .enumerate()
+                .map(|(j, tap)| {
+                    let k = (i + j).saturating_sub(2).min(num_px - 1);
+                    tap * unfiltered[k]
+                })
+                .sum();
+            edge[EDGE_OFFSET - 1 + i] = (s + 8) >> 4;
+        }
+    }
+
+    fn intra_edge_upsample(w: usize, h: usize, filter_type: bool, delta: i64) -> bool {
+        let d = delta.abs();
+        let blk_wh = w + h;
+
+        if d <= 0 || d >= 40 {
+            false
+        } else if !filter_type {
+            blk_wh <= 16
+        } else {
+            blk_wh <= 8
+        }
+    }
+
+    /// Doubles the resolution of the first num_px entries of an edge, which then start
+    /// at index -2.
+    fn intra_edge_upsample_edge(&self, edge: &mut [i64], num_px: usize) {
+        let mut dup = vec![0; num_px + 3];
+        dup[0] = edge[EDGE_OFFSET - 1];
+        dup[1..num_px + 2].copy_from_slice(&edge[EDGE_OFFSET - 1..EDGE_OFFSET + num_px]);
+        dup[num_px + 2] = edge[EDGE_OFFSET + num_px - 1];
+
+        edge[EDGE_OFFSET - 2] = dup[0];
+        for i in 0..num_px {
+            let s = -dup[i] + 9 * dup[i + 1] + 9 * dup[i + 2] - dup[i + 3];
+            edge[EDGE_OFFSET + 2 * i - 1] = self.intra_clip1(round2(s, 4));
+            edge[EDGE_OFFSET + 2 * i] = dup[i + 2];
+        }
+    }
+
+    fn sm_weights(log2_size: usize) -> &'static [i64] {
+        match log2_size {
+            2 => &Decoder::SM_WEIGHTS_TX_4X4,
+            3 => &Decoder::SM_WEIGHTS_TX_8X8,
+            4 => &Decoder::SM_WEIGHTS_TX_16X16,
+            5 => &Decoder::SM_WEIGHTS_TX_32X32,
+            _ => &Decoder::SM_WEIGHTS_TX_64X64,
+        }
+    }
+
+    fn smooth_intra_prediction(
+        mode: YMode,
+        above_row: &[i64],
+        left_col: &[i64],
+        log2_w: usize,
+        log2_h: usize,
+    ) -> Vec<Vec<i64>> {
+        let w = 1 << log2_w;
+        let h = 1 << log2_h;
+        let above = &above_row[EDGE_OFFSET..];
+        let left = &left_col[EDGE_OFFSET..];
+        let weights_x = Decoder::sm_weights(log2_w);
+        let weights_y = Decoder::sm_weights(log2_h);
+
+        let mut pred = vec![vec![0; w]; h];
+        for (i, row) in pred.iter_mut().enumerate() {
+            for (j, p) in row.iter_mut().enumerate() {
+                let vertical = weights_y[i] * above[j] + (256 - weights_y[i]) * left[h - 1];
+                let horizontal = weights_x[j] * left[i] + (256 - weights_x[j]) * above[w - 1];
+                *p = match mode {
+                    YMode::Smooth => round2(vertical + horizontal, 9),
+                    YMode::SmoothV => round2(vertical, 8),
+                    _ => round2(horizontal, 8),
+                };
+            }
+        }
+
+        pred
+    }
+
+    fn dc_intra_prediction(
+        &self,
+        have_left: bool,
+        have_above: bool,
+        above_row: &[i64],
+        left_col: &[i64],
+        log2_w: usize,
+        log2_h: usize,
+    ) -> i64 {
+        let w = 1 << log2_w;
+        let h = 1 << log2_h;
+        let above_sum: i64 = above_row[EDGE_OFFSET..EDGE_OFFSET + w].iter().sum();
+        let left_sum: i64 = left_col[EDGE_OFFSET..EDGE_OFFSET + h].iter().sum();
+
+        match (have_left, have_above) {
+            (true, true) => {
+                let sum = above_sum + left_sum + ((w + h) as i64 >> 1);
+                sum / (w + h) as i64
+            }
+            (true, false) => self.intra_clip1((left_sum + (h as i64 >> 1)) >> log2_h),
+            (false, true) => self.intra_clip1((above_sum + (w as i64 >> 1)) >> log2_w),
+            (false, false) => 1 << (self.bit_depth as i64 - 1),
+        }
+    }
+
+    fn paeth_intra_prediction(
+        above_row: &[i64],
+        left_col: &[i64],
+        w: usize,
+        h: usize,
+    ) -> Vec<Vec<i64>> {
+        let top_left = above_row[EDGE_OFFSET - 1];
+
+        let mut pred = vec![vec![0; w]; h];
+        for (i, row) in pred.iter_mut().enumerate() {
+            for (j, p) in row.iter_mut().enumerate() {
+                let above = above_row[EDGE_OFFSET + j];
+                let left = left_col[EDGE_OFFSET + i];
+                let base = above + left - top_left;
+                let p_left = (base - left).abs();
+                let p_top = (base - above).abs();
+                let p_top_left = (base - top_left).abs();
+
+                *p = if p_left <= p_top && p_left <= p_top_left {
+                    left
+                } else if p_top <= p_top_left {
+                    above
+                } else {
+                    top_left
+                };
+            }
+        }
+
+        pred
+    }
+
+    /// Adds the scaled AC contribution of the co-located luma samples to the DC predicted
+    /// chroma block.
+    pub(super) fn predict_chroma_from_luma(
+        &mut self,
+        plane: usize,
+        start_x: usize,
+        start_y: usize,
+        tx_sz: TxSize,
+    ) {
+        let w = tx_sz.width();
+        let h = tx_sz.height();
+        let color_config = &self.sequence_header.color_config;
+        let sub_x = color_config.subsampling_x as usize;
+        let sub_y = color_config.subsampling_y as usize;
+        let alpha = if plane == 1 {
+            self.cfl_alpha_u
+        } else {
+            self.cfl_alpha_v
+        };
+
+        let mut luma = vec![vec![0i64; w]; h];
+        let mut luma_avg = 0;
+        for (i, row) in luma.iter_mut().enumerate() {
+            let luma_y = (start_y + i).min((self.max_luma_h >> sub_y) - 1) << sub_y;
+            for (j, l) in row.iter_mut().enumerate() {
+                let luma_x = (start_x + j).min((self.max_luma_w >> sub_x) - 1) << sub_x;
+
+                let mut t = 0;
+                for dy in 0..=sub_y {
+                    for dx in 0..=sub_x {
+                        t += self.curr_frame[0][luma_y + dy][luma_x + dx] as i64;
+                    }
+                }
+
+                *l = t << (3 - sub_x - sub_y);
+                luma_avg += *l;
+            }
+        }
+        let luma_avg = round2(luma_avg, (w.trailing_zeros() + h.trailing_zeros()) as i64);
+
+        for (i, row) in luma.iter().enumerate() {
+            for (j, &l) in row.iter().enumerate() {
+                let dc = self.curr_frame[plane][start_y + i][start_x + j] as i64;
+                let scaled_luma = round2signed(alpha * (l - luma_avg), 6);
+                self.curr_frame[plane][start_y + i][start_x + j] =
+                    self.intra_clip1(dc + scaled_luma) as u16;
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use super::{
+    block::{CompoundType, InterpFilter, MotionMode, SubSize, UvMode, YMode},
+    BitStream, Decoder,
+};
+
+impl Decoder {
+    pub const INTRABC_DELAY_PIXELS: i64 = 256;
+    const INTRABC_DELAY_SB64: i64 = 4;
+
+    /// The use_intrabc part of intra_frame_mode_info(). Returns true if the block is
+    /// predicted from the already decoded part of the current frame.
+    pub(super) fn intrabc_mode_info(&mut self, b: &mut BitStream) -> bool {
+        self.ref_frame = [Decoder::INTRA_FRAME, Decoder::NONE];
+
+        self.use_intrabc = if self.uh.allow_intrabc {
+            self.symbol.read_symbol(b, &mut self.cdf.intrabc) != 0
+        } else {
+            false
+        };
+
+        if !self.use_intrabc {
+            return false;
+        }
+
+        self.is_inter = true;
+        self.motion_mode = MotionMode::Simple;
+        self.compound_type = CompoundType::Average;
+        self.interintra = false;
+        self.y_mode = YMode::Dc;
+        self.uv_mode = UvMode::Dc;
+        self.palette_size_y = 0;
+        self.palette_size_uv = 0;
+        self.interp_filter = [InterpFilter::Bilinear, InterpFilter::Bilinear];
+        self.find_mv_stack(false);
+        self.assign_mv(b, false);
+
+        true
+    }
+
+    /// Motion vectors have to stay within 2^14. Displacement vectors additionally have to be
+    /// whole pixels and point into the part of the tile that has already been decoded, at
+    /// least 256 pixels behind the current superblock in wavefront order.
+    pub(super) fn is_mv_valid(&self, is_compound: bool) -> bool {
+        for i in 0..1 + is_compound as usize {
+            for comp in 0..2 {
+                if self.mv[i][comp].abs() >= (1 << 14) {
+                    return false;
+                }
+            }
+        }
+
+        if !self.use_intrabc {
+            return true;
+        }
+
+        let bw = self.mi_size.width() as i64;
+        let bh = self.mi_size.height() as i64;
+        if (self.mv[0][0] & 7) != 0 || (self.mv[0][1] & 7) != 0 {
+            return false;
+        }
+
+        let mi_size = Decoder::MI_SIZE as i64;
+        let delta_row = self.mv[0][0] >> 3;
+        let delta_col = self.mv[0][1] >> 3;
+        let mut src_top_edge = self.mi_row as i64 * mi_size + delta_row;
+        let mut src_left_edge = self.mi_col as i64 * mi_size + delta_col;
+        let src_bottom_edge = src_top_edge + bh;
+        let src_right_edge = src_left_edge + bw;
+
+        if self.has_chroma {
+            let color_config = &self.sequence_header.color_config;
+            if bw < 8 && color_config.subsampling_x {
+                src_left_edge -= 4;
+            }
+
+            if bh < 8 && color_config.subsampling_y {
+                src_top_edge -= 4;
+            }
+        }
+
+        if src_top_edge < self.mi_row_start as i64 * mi_size
+            || src_left_edge < self.mi_col_start as i64 * mi_size
+            || src_bottom_edge > self.mi_row_end as i64 * mi_size
+            || src_right_edge > self.mi_col_end as i64 * mi_size
+        {
+            return false;
+        }
+
+        let sb_size = if self.sequence_header.use_128x128_superblock {
+            SubSize::Block128x128
+        } else {
+            SubSize::Block64x64
+        };
+        let sb_h = sb_size.height() as i64;
+        let active_sb_row = (self.mi_row as i64 * mi_size) / sb_h;
+        let active_sb64_col = (self.mi_col as i64 * mi_size) >> 6;
+        let src_sb_row = (src_bottom_edge - 1) / sb_h;
+        let src_sb64_col = (src_right_edge - 1) >> 6;
+        let total_sb64_per_row = ((self.mi_col_end as i64 - self.mi_col_start as i64 - 1) >> 4) + 1;
+        let active_sb64 = active_sb_row * total_sb64_per_row + active_sb64_col;
+        let src_sb64 = src_sb_row * total_sb64_per_row + src_sb64_col;
+        if src_sb64 >= active_sb64 - Decoder::INTRABC_DELAY_SB64 {
+            return false;
+        }
+
+        let gradient = 1 + Decoder::INTRABC_DELAY_SB64 + (sb_size == SubSize::Block128x128) as i64;
+        let wf_offset = gradient * (active_sb_row - src_sb_row);
+        if src_sb_row > active_sb_row
+            || src_sb64_col >= active_sb64_col - Decoder::INTRABC_DELAY_SB64 + wf_offset
+        {
+            return false;
+        }
+
+        true
+    }
+}
@@ -0,0 +1,547 @@
+use super::{
+    block::{TxSize, TxType},
+    round2, Decoder,
+};
+
+// https://aomediacodec.github.io/av1-spec/#inverse-transform-process
+
+/// The 1D transform applied to the rows or to the columns of a transform block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transform1d {
+    Dct,
+    Adst,
+    FlipAdst,
+    Identity,
+}
+
+impl TxType {
+    /// The transforms applied to the columns and to the rows.
+    fn transforms_1d(self) -> (Transform1d, Transform1d) {
+        use Transform1d::*;
+        match self {
+            TxType::DctDct => (Dct, Dct),
+            TxType::AdstDct => (Adst, Dct),
+            TxType::DctAdst => (Dct, Adst),
+            TxType::AdstAdst => (Adst, Adst),
+            TxType::FlipadstDct => (FlipAdst, Dct),
+            TxType::DctFlipadst => (Dct, FlipAdst),
+            TxType::FlipadstFlipadst => (FlipAdst, FlipAdst),
+            TxType::AdstFlipadst => (Adst, FlipAdst),
+            TxType::FlipadstAdst => (FlipAdst, Adst),
+            TxType::Idtx => (Identity, Identity),
+            TxType::VDct => (Dct, Identity),
+            TxType::HDct => (Identity, Dct),
+            TxType::VAdst => (Adst, Identity),
+            TxType::HAdst => (Identity, Adst),
+            TxType::VFlipadst => (FlipAdst, Identity),
+            TxType::HFlipadst => (Identity, FlipAdst),
+        }
+    }
+}
+
+impl Decoder {
+    /// Round(4096 * cos(i * PI / 128)) for i = 0..64.
+    const COS128_LOOKUP: [i64; 65] = [
+        4096, 4095, 4091, 4085, 4076, 4065, 4052, 4036, 4017, 3996, 3973, 3948, 3920, 3889, 3857,
+        3822, 3784, 3745, 3703, 3659, 3612, 3564, 3513, 3461, 3406, 3349, 3290, 3229, 3166, 3102,
+        3035, 2967, 2896, 2824, 2751, 2675, 2598, 2520, 2440, 2359, 2276, 2191, 2106, 2019, 1931,
+        1842, 1751, 1660, 1567, 1474, 1380, 1285, 1189, 1092, 995, 897, 799, 700, 601, 501, 401,
+        301, 201, 101, 0,
+    ];
+    const SINPI_1_9: i64 = 1321;
+    const SINPI_2_9: i64 = 2482;
+    const SINPI_3_9: i64 = 3344;
+    const SINPI_4_9: i64 = 3803;
+    const TRANSFORM_ROW_SHIFT: [i64; 19] =
+        [0, 1, 2, 2, 2, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2];
+
+    /// Reverses the order of the lowest num_bits bits of x.
+    fn brev(num_bits: usize, x: usize) -> usize {
+        (0..num_bits).fold(0, |result, i| {
+            result | (((x >> i) & 1) << (num_bits - 1 - i))
+        })
+    }
+
+    fn cos128(angle: i64) -> i64 {
+        let angle2 = (angle & 255) as usize;
+        match angle2 {
+            0..=64 => Decoder::COS128_LOOKUP[angle2],
+            65..=128 => -Decoder::COS128_LOOKUP[128 - angle2],
+            129..=192 => -Decoder::COS128_LOOKUP[angle2 - 128],
+            _ => Decoder::COS128_LOOKUP[256 - angle2],
+        }
+    }
+
+    fn sin128(angle: i64) -> i64 {
+        Decoder::cos128(angle - 64)
+    }
+
+    /// The butterfly rotation B(a, b, angle, flip).
+    fn butterfly(t: &mut [i64], a: usize, b: usize, angle: i64, flip: bool) {
+        let x = t[a] * Decoder::cos128(angle) - t[b] * Decoder::sin128(angle);
+        let y = t[a] * Decoder::sin128(angle) + t[b] * Decoder::cos128(angle);
+        t[a] = round2(x, 12);
+        t[b] = round2(y, 12);
+
+        if flip {
+            t.swap(a, b);
+        }
+    }
+
+    /// The Hadamard rotation H(a, b, flip, r). Results are clamped to r bits, as they have to
+    /// fit in conformant streams.
+    fn hadamard(t: &mut [i64], a: usize, b: usize, flip: bool, r: usize) {
+        let (a, b) = if flip { (b, a) } else { (a, b) };
+        let max = (1 << (r - 1)) - 1;
+        let min = -(1 << (r - 1));
+        let (x, y) = (t[a], t[b]);
+        t[a] = (x + y).clamp(min, max);
+        t[b] = (x - y).clamp(min, max);
+    }
+
+    /// The inverse DCT of size 1 << n.
+    fn inverse_dct(t: &mut [i64], n: usize, r: usize) {
+        let n0 = 1 << n;
+        let copy = t[..n0].to_vec();
+        for (i, v) in t.iter_mut().enumerate().take(n0) {
+            *v = copy[Decoder::brev(n, i)];
+        }
+
+        let b = Decoder::butterfly;
+        let h = |t: &mut [i64], a, b, flip| Decoder::hadamard(t, a, b, flip, r);
+        let brev = |num_bits, x| Decoder::brev(num_bits, x) as i64;
+
+        if n == 6 {
+            for i in 0..16 {
+                b(t, 32 + i, 63 - i, 63 - 4 * brev(4, i), false);
+            }
+        }
+        if n >= 5 {
+            for i in 0..8 {
+                b(t, 16 + i, 31 - i, 6 + (brev(3, 7 - i) << 3), false);
+            }
+        }
+        if n == 6 {
+            for i in 0..16 {
+                h(t, 32 + i * 2, 33 + i * 2, i & 1 != 0);
+            }
+        }
+        if n >= 4 {
+            for i in 0..4 {
+                b(t, 8 + i, 15 - i, 12 + (brev(2, 3 - i) << 4), false);
+            }
+        }
+        if n >= 5 {
+            for i in 0..8 {
+                h(t, 16 + 2 * i, 17 + 2 * i, i & 1 != 0);
+            }
+        }
+        if n == 6 {
+            for i in 0..4 {
+                for j in 0..2 {
+                    let angle = 60 - 16 * brev(2, i) + 64 * j as i64;
+                    b(t, 62 - i * 4 - j, 33 + i * 4 + j, angle, true);
+                }
+            }
+        }
+        if n >= 3 {
+            for i in 0..2 {
+                b(t, 4 + i, 7 - i, 56 - 32 * i as i64, false);
+            }
+        }
+        if n >= 4 {
+            for i in 0..4 {
+                h(t, 8 + 2 * i, 9 + 2 * i, i & 1 != 0);
+            }
+        }
+        if n >= 5 {
+            for i in 0..2 {
+                for j in 0..2 {
+                    let angle = 24 + ((j as i64) << 6) + ((1 - i as i64) << 5);
+                    b(t, 30 - 4 * i - j, 17 + 4 * i + j, angle, true);
+                }
+            }
+        }
+        if n == 6 {
+            for i in 0..8 {
+                for j in 0..2 {
+                    h(t, 32 + i * 4 + j, 35 + i * 4 - j, i & 1 != 0);
+                }
+            }
+        }
+        for i in 0..2 {
+            b(t, 2 * i, 2 * i + 1, 32 + 16 * i as i64, i == 0);
+        }
+        if n >= 3 {
+            for i in 0..2 {
+                h(t, 4 + 2 * i, 5 + 2 * i, i != 0);
+            }
+        }
+        if n >= 4 {
+            for i in 0..2 {
+                b(t, 14 - i, 9 + i, 48 + 64 * i as i64, true);
+            }
+        }
+        if n >= 5 {
+            for i in 0..4 {
+                for j in 0..2 {
+                    h(t, 16 + 4 * i + j, 19 + 4 * i - j, i & 1 != 0);
+                }
+            }
+        }
+        if n == 6 {
+            for i in 0..2 {
+                for j in 0..4 {
+                    let angle = 56 - 32 * i as i64 + ((j >> 1) as i64) * 64;
+                    b(t, 61 - i * 8 - j, 34 + i * 8 + j, angle, true);
+                }
+            }
+        }
+        for i in 0..2 {
+            h(t, i, 3 - i, false);
+        }
+        if n >= 3 {
+            b(t, 6, 5, 32, true);
+        }
+        if n >= 4 {
+            for i in 0..2 {
+                for j in 0..2 {
+                    h(t, 8 + 4 * i + j, 11 + 4 * i - j, i != 0);
+                }
+            }
+        }
+        if n >= 5 {
+            for i in 0..2 {
+                for j in 0..2 {
+                    b(
+                        t,
+                        29 - i * 2 - j,
+                        18 + i * 2 + j,
+                        48 + ((i as i64) << 6),
+                        true,
+                    );
+                }
+            }
+        }
+        if n == 6 {
+            for i in 0..4 {
+                for j in 0..4 {
+                    h(t, 32 + 8 * i + j, 39 + 8 * i - j, i & 1 != 0);
+                }
+            }
+        }
+        if n >= 3 {
+            for i in 0..4 {
+                h(t, i, 7 - i, false);
+            }
+        }
+        if n >= 4 {
+            for i in 0..2 {
+                b(t, 13 - i, 10 + i, 32, true);
+            }
+        }
+        if n >= 5 {
+            for i in 0..2 {
+                for j in 0..4 {
+                    h(t, 16 + i * 8 + j, 23 + i * 8 - j, i != 0);
+                }
+            }
+        }
+        if n == 6 {
+            for i in 0..2 {
+                for j in 0..4 {
+                    b(
+                        t,
+                        59 - i * 4 - j,
+                        36 + i * 4 + j,
+                        48 + ((i as i64) << 6),
+                        true,
+                    );
+                }
+            }
+        }
+        if n >= 4 {
+            for i in 0..8 {
+                h(t, i, 15 - i, false);
+            }
+        }
+        if n >= 5 {
+            for i in 0..4 {
+                b(t, 27 - i, 20 + i, 32, true);
+            }
+        }
+        if n == 6 {
+            for i in 0..2 {
+                for j in 0..8 {
+                    h(t, 32 + i * 16 + j, 47 + i * 16 - j, i != 0);
+                }
+            }
+        }
+        if n >= 5 {
+            for i in 0..16 {
+                h(t, i, 31 - i, false);
+            }
+        }
+        if n == 6 {
+            for i in 0..8 {
+                b(t, 55 - i, 40 + i, 32, true);
+            }
+            for i in 0..32 {
+                h(t, i, 63 - i, false);
+            }
+        }
+    }
+
+    fn inverse_adst4(t: &mut [i64]) {
+        let mut s0 = Decoder::SINPI_1_9 * t[0];
+        let mut s1 = Decoder::SINPI_2_9 * t[0];
+        let mut s2 = Decoder::SINPI_3_9 * t[1];
+        let mut s3 = Decoder::SINPI_4_9 * t[2];
+        let s4 = Decoder::SINPI_1_9 * t[2];
+        let s5 = Decoder::SINPI_2_9 * t[3];
+        let s6 = Decoder::SINPI_4_9 * t[3];
+        let a7 = t[0] - t[2];
+        let b7 = a7 + t[3];
+
+        s0 += s3;
+        s1 -= s4;
+        s3 = s2;
+        s2 = Decoder::SINPI_3_9 * b7;
+        s0 += s5;
+        s1 -= s6;
+
+        let x0 = s0 + s3;
+        let x1 = s1 + s3;
+        let x2 = s2;
+        let x3 = s0 + s1 - s3;
+
+        for (v, x) in t.iter_mut().zip([x0, x1, x2, x3]) {
+            *v = round2(x, 12);
+        }
+    }
+
+    fn inverse_adst_input_permutation(t: &mut [i64], n: usize) {
+        let n0 = 1 << n;
+        let copy = t[..n0].to_vec();
+        for (i, v) in t.iter_mut().enumerate().take(n0) {
+            *v = copy[if i & 1 != 0 { i - 1 } else { n0 - i - 1 }];
+        }
+    }
+
+    fn inverse_adst_output_permutation(t: &mut [i64], n: usize) {
+        let n0 = 1 << n;
+        let copy = t[..n0].to_vec();
+        for (i, v) in t.iter_mut().enumerate().take(n0) {
+            let a = (i >> 3) & 1;
+            let b = ((i >> 2) & 1) ^ ((i >> 3) & 1);
+            let c = ((i >> 1) & 1) ^ ((i >> 2) & 1);
+            let d = (i & 1) ^ ((i >> 1) & 1);
+            let idx = ((d << 3) | (c << 2) | (b << 1) | a) >> (4 - n);
+            *v = if i & 1 != 0 { -copy[idx] } else { copy[idx] };
+        }
+    }
+
+    fn inverse_adst8(t: &mut [i64], r: usize) {
+        let b = Decoder::butterfly;
+        let h = |t: &mut [i64], a, b| Decoder::hadamard(t, a, b, false, r);
+
+        Decoder::inverse_adst_input_permutation(t, 3);
+        for i in 0..4 {
+            b(t, 2 * i, 2 * i + 1, 60 - 16 * i as i64, true);
+        }
+        for i in 0..4 {
+            h(t, i, 4 + i);
+        }
+        for i in 0..2 {
+            b(t, 4 + 3 * i, 5 + i, 48 - 32 * i as i64, true);
+        }
+        for i in 0..2 {
+            for j in 0..2 {
+                h(t, 4 * j + i, 4 * j + 2 + i);
+            }
+        }
+        for i in 0..2 {
+            b(t, 2 + 4 * i, 3 + 4 * i, 32, true);
+        }
+        Decoder::inverse_adst_output_permutation(t, 3);
+    }
+
+    fn inverse_adst16(t: &mut [i64], r: usize) {
+        let b = Decoder::butterfly;
+        let h = |t: &mut [i64], a, b| Decoder::hadamard(t, a, b, false, r);
+
+        Decoder::inverse_adst_input_permutation(t, 4);
+        for i in 0..8 {
+            b(t, 2 * i, 2 * i + 1, 62 - 8 * i as i64, true);
+        }
+        for i in 0..8 {
+            h(t, i, 8 + i);
+        }
+        for i in 0..2 {
+            b(t, 8 + 2 * i, 9 + 2 * i, 56 - 32 * i as i64, true);
+            b(t, 13 + 2 * i, 12 + 2 * i, 8 + 32 * i as i64, true);
+        }
+        for i in 0..4 {
+            for j in 0..2 {
+                h(t, 8 * j + i, 8 * j + 4 + i);
+            }
+        }
+        for i in 0..2 {
+            for j in 0..2 {
+                b(
+                    t,
+                    4 + 8 * j + 3 * i,
+                    5 + 8 * j + i,
+                    48 - 32 * i as i64,
+                    true,
+                );
+            }
+        }
+        for i in 0..2 {
+            for j in 0..4 {
+                h(t, 4 * j + i, 4 * j + 2 + i);
+            }
+        }
+        for j in 0..4 {
+            b(t, 2 + 4 * j, 3 + 4 * j, 32, true);
+        }
+        Decoder::inverse_adst_output_permutation(t, 4);
+    }
+
+    fn inverse_adst(t: &mut [i64], n: usize, r: usize) {
+        match n {
+            2 => Decoder::inverse_adst4(t),
+            3 => Decoder::inverse_adst8(t, r),
+            4 => Decoder::inverse_adst16(t, r),
+            _ => panic!("invalid size for an inverse ADST: {}", 1 << n),
+        }
+    }
+
+    fn inverse_identity(t: &mut [i64], n: usize) {
+        for v in t.iter_mut().take(1 << n) {
+            *v = match n {
+                2 => round2(*v * 5793, 12),
+                3 => *v * 2,
+                4 => round2(*v * 11586, 12),
+                5 => *v * 4,
+                _ => panic!("invalid size for an inverse identity transform: {}", 1 << n),
+            };
+        }
+    }
+
+    /// The inverse Walsh-Hadamard transform used by lossless blocks.
+    fn inverse_wht(t: &mut [i64], shift: i64) {
+        let mut a = t[0] >> shift;
+        let mut c = t[1] >> shift;
+        let mut d = t[2] >> shift;
+        let mut b = t[3] >> shift;
+        a += c;
+        d -= b;
+        let e = (a - d) >> 1;
+        b = e - b;
+        c = e - c;
+        a -= b;
+        d += c;
+        t[..4].copy_from_slice(&[a, b, c, d]);
+    }
+
+    fn inverse_transform_1d(t: &mut [i64], transform: Transform1d, n: usize, r: usize) {
+        match transform {
+            Transform1d::Dct => Decoder::inverse_dct(t, n, r),
+            Transform1d::Adst | Transform1d::FlipAdst => Decoder::inverse_adst(t, n, r),
+            Transform1d::Identity => Decoder::inverse_identity(t, n),
+        }
+    }
+
+    /// The 2D inverse transform process. Takes the dequantized coefficients, of which only
+    /// the top left 32x32 can be non-zero, and returns the residual.
+    pub(super) fn inverse_transform_2d(
+        &self,
+        dequant: &[Vec<i64>],
+        tx_sz: TxSize,
+        tx_type: TxType,
+        lossless: bool,
+    ) -> Vec<Vec<i32>> {
+        let w = tx_sz.width();
+        let h = tx_sz.height();
+        let log2w = w.trailing_zeros() as usize;
+        let log2h = h.trailing_zeros() as usize;
+        let (col_type, row_type) = tx_type.transforms_1d();
+        let bit_depth = self.bit_depth as usize;
+        let row_shift = if lossless {
+            0
+        } else {
+            Decoder::TRANSFORM_ROW_SHIFT[tx_sz as usize]
+        };
+        let col_shift = if lossless { 0 } else { 4 };
+        let row_clamp_range = bit_depth + 8;
+        let col_clamp_range = (bit_depth + 6).max(16);
+
+        let mut residual = vec![vec![0i64; w]; h];
+        for (i, residual_row) in residual.iter_mut().enumerate().take(h.min(32)) {
+            let mut t = vec![0i64; w];
+            for (j, v) in t.iter_mut().enumerate().take(w.min(32)) {
+                *v = dequant[i][j];
+            }
+
+            if lossless {
+                Decoder::inverse_wht(&mut t, 2);
+            } else {
+                let max = (1 << (row_clamp_range - 1)) - 1;
+                let min = -(1 << (row_clamp_range - 1));
+                for v in t.iter_mut() {
+                    if log2w.abs_diff(log2h) == 1 {
+                        *v = round2(*v * 2896, 12);
+                    }
+                    *v = (*v).clamp(min, max);
+                }
+
+                Decoder::inverse_transform_1d(&mut t, row_type, log2w, row_clamp_range);
+            }
+
+            for (j, r) in residual_row.iter_mut().enumerate() {
+                let v = if row_type == Transform1d::FlipAdst {
+                    t[w - 1 - j]
+                } else {
+                    t[j]
+                };
+                *r = round2(v, row_shift);
+            }
+        }
+
+        if !lossless {
+            let max = (1 << (col_clamp_range - 1)) - 1;
+            let min = -(1 << (col_clamp_range - 1));
+            for v in residual.iter_mut().flatten() {
+                *v = (*v).clamp(min, max);
+            }
+        }
+
+        let mut t = vec![0i64; h];
+        for j in 0..w {
+            for (i, v) in t.iter_mut().enumerate() {
+                *v = residual[i][j];
+            }
+
+            if lossless {
+                Decoder::inverse_wht(&mut t, 0);
+            } else {
+                Decoder::inverse_transform_1d(&mut t, col_type, log2h, col_clamp_range);
+            }
+
+            for (i, row) in residual.iter_mut().enumerate() {
+                let v = if col_type == Transform1d::FlipAdst {
+                    t[h - 1 - i]
+                } else {
+                    t[i]
+                };
+                row[j] = round2(v, col_shift);
+            }
+        }
+
+        residual
+            .into_iter()
+            .map(|row| row.into_iter().map(|v| v as i32).collect())
+            .collect()
+    }
+}
@@ -1,25 +1,60 @@
 use anyhow::Result;
+pub use block::{
+    BlockInfo, CompoundType, InterIntraMode, InterpFilter, MotionMode, SubSize, TxSize, TxType,
+    UvMode, YMode,
+};
+use cdf::Cdfs;
+use frame::{Frame, Stage};
 use obu::{SequenceHeader, TxMode, UncompressedHeader, WarpModel};
+use refs::RefFrame;
+use symbol::{floor_log2, SymbolDecoder};
 
-use super::ivf::Ivf;
+pub use obu::{ChromaSamplePosition, ColorConfig};
+pub use symbol::SyntaxCategory;
 
+use super::{ivf::Ivf, FrameSink};
+
+mod block;
+mod cdef;
+mod cdf;
+pub mod cicp;
+mod coeffs;
+mod compound;
+mod deblock;
+mod default_cdfs;
+mod film_grain;
+pub mod frame;
+mod inter;
+mod intra;
+mod intrabc;
+mod inverse_transform;
+mod mode_info;
+mod motion_field;
+mod mv;
+mod mvpred;
+mod obmc;
 mod obu;
+mod palette;
+mod partition;
+mod quant;
+mod quantizer_matrix;
+mod ref_frames;
+mod refs;
+mod residual;
+mod restoration;
+mod superres;
+mod symbol;
+mod warp;
 
 #[derive(Debug)]
 pub struct BitStream {
     pos: usize,
     data: Vec<u8>,
-
-    seen_frame_header: bool,
 }
 
 impl BitStream {
     pub fn new(data: Vec<u8>) -> BitStream {
-        BitStream {
-            pos: 0,
-            data,
-            seen_frame_header: false,
-        }
+        BitStream { pos: 0, data }
     }
 
     fn read_bit(&mut self) -> u8 {
@@ -62,6 +97,19 @@ impl BitStream {
         }
     }
 
+    /// ns(n)
+    fn ns(&mut self, n: u64) -> u64 {
+        let w = floor_log2(n) + 1;
+        let m = (1 << w) - n;
+        let v = self.f(w - 1);
+        if v < m {
+            return v;
+        }
+
+        let extra_bit = self.f(1);
+        (v << 1) - m + extra_bit
+    }
+
     fn alignment(&mut self) {
         while (self.pos & 7) != 0 {
             self.f(1);
@@ -78,7 +126,7 @@ impl BitStream {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BitDepth {
     Invalid = -1,
     Eight = 8,
@@ -113,11 +161,13 @@ pub struct Decoder {
     pub sequence_header: SequenceHeader,
     pub frame_is_intra: bool,
     pub ref_valid: [bool; 8],
-    pub ref_order_hint: [bool; 8],
+    pub ref_order_hint: [u64; 8],
+    pub ref_frame_store: [RefFrame; 8],
     pub order_hint: u64,
     pub frame_width: u64,
     pub frame_height: u64,
     pub superres_denom: u64,
+    pub use_superres: bool,
     pub upscaled_width: u64,
     pub mi_cols: u64,
     pub mi_rows: u64,
@@ -130,25 +180,40 @@ pub struct Decoder {
     pub mi_row_starts: Vec<u64>,
     pub tile_rows: u64,
     pub tile_size_bytes: u64,
+    /// The tile whose final CDFs are kept for the next frame.
+    pub context_update_tile_id: u64,
     pub deltaq_ydc: i64,
     pub deltaq_udc: i64,
     pub deltaq_uac: i64,
     pub deltaq_vdc: i64,
     pub deltaq_vac: i64,
     pub feature_enabled: Vec<Vec<bool>>,
-    pub feature_data: Vec<Vec<u64>>,
+    pub feature_data: Vec<Vec<i64>>,
     pub seg_id_pre_skip: bool,
     pub last_active_seg_id: u64,
     pub coded_lossless: bool,
     pub current_q_index: u64,
     pub lossless_array: Vec<bool>,
+    pub seg_qm_level: Vec<Vec<u64>>,
     pub all_lossless: bool,
     pub cdef_damping: u64,
     pub frame_restoration_type: Vec<u64>,
     pub uses_lr: bool,
+    pub loop_restoration_size: [u64; 3],
+    /// [plane][unit_row][unit_col]
+    pub lr_type: Vec<Vec<Vec<u64>>>,
+    /// [plane][unit_row][unit_col][pass][i]
+    pub lr_wiener: Vec<Vec<Vec<[[i64; 3]; 2]>>>,
+    /// [plane][unit_row][unit_col]
+    pub lr_sgr_set: Vec<Vec<Vec<usize>>>,
+    /// [plane][unit_row][unit_col][i]
+    pub lr_sgr_xqd: Vec<Vec<Vec<[i64; 2]>>>,
     pub tx_mode: TxMode,
     pub gm_type: [WarpModel; 8],
-    pub order_hints: [bool; Decoder::REFS_PER_FRAME as usize + Decoder::LAST_FRAME],
+    pub gm_params: Vec<Vec<i64>>,
+    /// The global motion params the current frame's are coded relative to, [ref][i].
+    pub prev_gm_params: Vec<Vec<i64>>,
+    pub order_hints: [u64; Decoder::REFS_PER_FRAME as usize + Decoder::LAST_FRAME],
     pub tile_num: u64,
     pub uh: UncompressedHeader,
     pub num_tiles: u64,
@@ -156,32 +221,224 @@ pub struct Decoder {
     pub mi_row_end: u64,
     pub mi_col_start: u64,
     pub mi_col_end: u64,
-    pub symbol_range: u64,
-    pub symbol_max_bits: i64,
+    pub symbol: SymbolDecoder,
+    /// The CDFs of the tile being decoded.
+    pub cdf: Cdfs,
+    /// The CDFs every tile of the frame starts out with.
+    pub frame_cdf: Cdfs,
+    /// The CDFs of the context update tile, with the counters cleared.
+    pub saved_cdf: Cdfs,
     pub above_level_context: Vec<Vec<u64>>,
     pub above_dc_context: Vec<Vec<u64>>,
     pub above_seg_pred_context: Vec<u64>,
-    pub delta_lf: Vec<u64>,
+    pub delta_lf: Vec<i64>,
     pub ref_sgr_xqd: Vec<Vec<i64>>,
     pub ref_lr_wiener: Vec<Vec<Vec<i64>>>,
     pub left_level_context: Vec<Vec<u64>>,
     pub left_dc_context: Vec<Vec<u64>>,
     pub left_seg_pred_context: Vec<u64>,
+    pub mi_row: u64,
+    pub mi_col: u64,
+    pub mi_size: SubSize,
+    pub has_chroma: bool,
+    pub avail_u: bool,
+    pub avail_l: bool,
+    pub avail_u_chroma: bool,
+    pub avail_l_chroma: bool,
+    pub y_mode: YMode,
+    pub uv_mode: UvMode,
+    pub angle_delta_y: i64,
+    pub angle_delta_uv: i64,
+    pub cfl_alpha_u: i64,
+    pub cfl_alpha_v: i64,
+    pub use_filter_intra: bool,
+    pub filter_intra_mode: usize,
+    pub palette_size_y: u64,
+    pub palette_size_uv: u64,
+    pub palette_colors_y: Vec<u16>,
+    pub palette_colors_u: Vec<u16>,
+    pub palette_colors_v: Vec<u16>,
+    pub color_map_y: Vec<Vec<u8>>,
+    pub color_map_uv: Vec<Vec<u8>>,
+    pub palette_sizes: Vec<Vec<Vec<u64>>>,
+    pub palette_colors: Vec<Vec<Vec<Vec<u16>>>>,
+    pub curr_frame: Vec<Vec<Vec<u16>>>,
+    pub block_infos: Vec<BlockInfo>,
+    pub use_intrabc: bool,
+    pub is_inter: bool,
+    pub ref_frame: [i64; 2],
+    pub interp_filter: [InterpFilter; 2],
+    pub skip_mode: bool,
+    pub skip_mode_frame: [i64; 2],
+    pub motion_mode: MotionMode,
+    pub num_samples: usize,
+    pub num_samples_scanned: usize,
+    pub cand_list: [[i64; 4]; 8],
+    pub local_warp_params: [i64; 6],
+    pub local_valid: bool,
+    pub interintra: bool,
+    pub interintra_mode: InterIntraMode,
+    pub wedge_interintra: bool,
+    pub wedge_index: usize,
+    pub wedge_sign: usize,
+    pub mask_type: bool,
+    pub comp_group_idx: u64,
+    pub compound_idx: u64,
+    pub compound_type: CompoundType,
+    pub comp_group_idxs: Vec<Vec<u64>>,
+    pub compound_idxs: Vec<Vec<u64>>,
+    /// [bsize][flip_sign][wedge_index][i][j], built on first use.
+    pub wedge_masks: Vec<Vec<Vec<Vec<Vec<i64>>>>>,
+    pub mask: Vec<Vec<i64>>,
+    pub fwd_weight: i64,
+    pub bck_weight: i64,
+    pub skip: bool,
+    pub segment_id: u64,
+    pub lossless: bool,
+    /// Whether the delta q and delta lf of the superblock are still to be read.
+    pub read_deltas: bool,
+    pub tx_size: TxSize,
+    pub skips: Vec<Vec<bool>>,
+    pub skip_modes: Vec<Vec<bool>>,
+    /// The transform size of inter blocks at every mode info position, [row][col].
+    pub inter_tx_sizes: Vec<Vec<TxSize>>,
+    /// The references of the above and left blocks, for the contexts of the reference
+    /// frame syntax.
+    pub above_ref_frame: [i64; 2],
+    pub left_ref_frame: [i64; 2],
+    pub above_intra: bool,
+    pub left_intra: bool,
+    pub above_single: bool,
+    pub left_single: bool,
+    /// Which 4x4 blocks of the current superblock have been decoded, [plane][row + 1][col + 1].
+    pub block_decoded: Vec<Vec<Vec<bool>>>,
+    /// The luma area decoded so far of the current block, which CfL may use.
+    pub max_luma_w: usize,
+    pub max_luma_h: usize,
+    /// The coefficients of the current transform block.
+    pub quant: Vec<i64>,
+    pub plane_tx_type: TxType,
+    pub segment_ids: Vec<Vec<u64>>,
+    /// The segment ids of the primary reference frame, [row][col].
+    pub prev_segment_ids: Vec<Vec<u64>>,
+    pub delta_lfs: Vec<Vec<[i64; 4]>>,
+    /// [plane][row >> subsampling_y][col >> subsampling_x]
+    pub loopfilter_tx_sizes: Vec<Vec<Vec<TxSize>>>,
+    /// The luma transform type at every mode info position, set by the transform blocks
+    /// covering it.
+    pub tx_types: Vec<Vec<TxType>>,
+    pub loop_filter_ref_deltas: [i64; 8],
+    pub loop_filter_mode_deltas: [i64; 2],
+    pub cdef_idx: Vec<Vec<i64>>,
+    /// The direction found by the CDEF direction search, [row >> 1][col >> 1]. None for
+    /// 8x8 blocks CDEF doesn't filter.
+    pub cdef_directions: Vec<Vec<Option<usize>>>,
+    /// The directional variance found by the CDEF direction search, [row >> 1][col >> 1].
+    pub cdef_variances: Vec<Vec<i64>>,
+    pub cdef_frame: Vec<Vec<Vec<u16>>>,
+    pub lr_frame: Vec<Vec<Vec<u16>>>,
+    /// Output frames without film grain, so they can be compared with the grained ones.
+    pub skip_film_grain: bool,
+    /// The last shown frame, with film grain applied.
+    pub output_frame: Vec<Vec<Vec<u16>>>,
+    /// Presentation time of the temporal unit being decoded, in container time base units.
+    pub timestamp: u64,
+    /// Shown frames that haven't been handed to the sink yet.
+    pub output_frames: Vec<Frame>,
+    /// The stages to capture for every shown frame, as asked for by the sink.
+    pub stages: Vec<Stage>,
+    /// The stages captured so far for the current frame.
+    pub stage_frames: Vec<(Stage, Frame)>,
+    /// The residual added to every sample of CurrFrame, [plane][y][x]. Only kept when the
+    /// prediction or residual stage is captured.
+    pub residual_frame: Vec<Vec<Vec<i32>>>,
+    pub mv: [[i64; 2]; 2],
+    pub pred_mv: [[i64; 2]; 2],
+    pub ref_mv_idx: usize,
+    pub ref_frame_sign_bias: [bool; 8],
+    pub num_mv_found: usize,
+    pub new_mv_count: u64,
+    pub ref_stack_mv: [[[i64; 2]; 2]; 8],
+    pub weight_stack: [u64; 8],
+    pub global_mvs: [[i64; 2]; 2],
+    pub found_match: bool,
+    pub close_matches: u64,
+    pub total_matches: u64,
+    pub new_mv_context: u64,
+    pub ref_mv_context: u64,
+    pub zero_mv_context: u64,
+    pub mi_sizes: Vec<Vec<SubSize>>,
+    pub y_modes: Vec<Vec<YMode>>,
+    pub uv_modes: Vec<Vec<UvMode>>,
+    pub ref_frames: Vec<Vec<[i64; 2]>>,
+    pub ref_frames_written: Vec<Vec<bool>>,
+    pub mvs: Vec<Vec<[[i64; 2]; 2]>>,
+    pub is_inters: Vec<Vec<bool>>,
+    pub interp_filters: Vec<Vec<[InterpFilter; 2]>>,
+    /// [row >> 1][col >> 1]
+    pub mf_ref_frames: Vec<Vec<i64>>,
+    /// [row >> 1][col >> 1]
+    pub mf_mvs: Vec<Vec<[i64; 2]>>,
+    /// The motion field projected from the reference frames, [ref_frame][row >> 1][col >> 1].
+    /// Entries no projection landed on hold INVALID_MV.
+    pub motion_field_mvs: Vec<Vec<Vec<[i64; 2]>>>,
+    /// The reference frame whose motion field was projected onto each entry, or NONE,
+    /// [row >> 1][col >> 1].
+    pub motion_field_sources: Vec<Vec<i64>>,
+    pub inter_round0: i64,
+    pub inter_round1: i64,
+    pub inter_post_round: i64,
 }
 
 impl Decoder {
-    pub fn decode(&mut self, ivf: Ivf) -> Result<()> {
+    pub fn decode(&mut self, ivf: Ivf, mut sink: Option<&mut dyn FrameSink>) -> Result<()> {
         for block in &ivf.blocks {
-            let mut b = BitStream::new(block.framedata.clone());
-            self.decode_frame(&mut b);
+            self.timestamp = block.timestamp;
+            let sink = sink.as_deref_mut().map(|sink| sink as &mut dyn FrameSink);
+            self.decode_obus(block.framedata.clone(), sink)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes a sequence of OBUs in the low overhead bitstream format and hands the frames
+    /// they show to the sink.
+    pub fn decode_obus(
+        &mut self,
+        data: Vec<u8>,
+        mut sink: Option<&mut dyn FrameSink>,
+    ) -> Result<()> {
+        let mut b = BitStream::new(data);
+        self.decode_frame(&mut b);
+
+        for frame in self.output_frames.drain(..) {
+            if let Some(sink) = sink.as_deref_mut() {
+                sink.write_frame(&frame)?;
+            }
         }
 
         Ok(())
     }
 
     fn decode_frame(&mut self, b: &mut BitStream) {
-        loop {
+        while b.pos < b.data.len() * 8 {
             self.obu(b);
         }
     }
 }
+
+pub fn round2(x: i64, n: i64) -> i64 {
+    if n == 0 {
+        x
+    } else {
+        (x + (1 << (n - 1))) >> n
+    }
+}
+
+pub fn round2signed(x: i64, n: i64) -> i64 {
+    if x >= 0 {
+        round2(x, n)
+    } else {
+        -round2(-x, n)
+    }
+}
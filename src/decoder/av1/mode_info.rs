@@ -0,0 +1,634 @@
+use super::{
+    block::{SubSize, UvMode, YMode},
+    BitStream, Decoder, NumPlanes,
+};
+
+// https://aomediacodec.github.io/av1-spec/#mode-info-syntax
+
+impl Decoder {
+    const INTRA_MODE_CONTEXT: [usize; 13] = [0, 1, 2, 3, 4, 4, 4, 4, 3, 0, 1, 2, 0];
+    const COMPOUND_MODE_CTX_MAP: [[usize; 5]; 3] =
+        [[0, 1, 1, 1, 1], [1, 2, 3, 4, 4], [4, 4, 5, 6, 7]];
+    const MAX_ANGLE_DELTA: i64 = 3;
+    const DELTA_Q_SMALL: usize = 3;
+    const DELTA_LF_SMALL: usize = 3;
+    const CFL_SIGN_ZERO: usize = 0;
+    const CFL_SIGN_NEG: usize = 1;
+
+    pub(super) fn mode_info(&mut self, b: &mut BitStream) {
+        self.use_filter_intra = false;
+
+        if self.frame_is_intra {
+            self.intra_frame_mode_info(b);
+        } else {
+            self.inter_frame_mode_info(b);
+        }
+    }
+
+    fn intra_frame_mode_info(&mut self, b: &mut BitStream) {
+        self.skip = false;
+        if self.seg_id_pre_skip {
+            self.intra_segment_id(b);
+        }
+
+        self.skip_mode = false;
+        self.read_skip(b);
+        if !self.seg_id_pre_skip {
+            self.intra_segment_id(b);
+        }
+
+        self.read_cdef(b);
+        self.read_delta_qindex(b);
+        self.read_delta_lf(b);
+        self.read_deltas = false;
+
+        if self.intrabc_mode_info(b) {
+            return;
+        }
+
+        self.is_inter = false;
+        let above_mode = if self.avail_u {
+            self.y_modes[self.mi_row as usize - 1][self.mi_col as usize]
+        } else {
+            YMode::Dc
+        };
+        let left_mode = if self.avail_l {
+            self.y_modes[self.mi_row as usize][self.mi_col as usize - 1]
+        } else {
+            YMode::Dc
+        };
+        let above_ctx = Decoder::INTRA_MODE_CONTEXT[above_mode as usize];
+        let left_ctx = Decoder::INTRA_MODE_CONTEXT[left_mode as usize];
+        self.y_mode = YMode::new(
+            self.symbol
+                .read_symbol(b, &mut self.cdf.intra_frame_y_mode[above_ctx][left_ctx])
+                as u64,
+        );
+
+        self.intra_modes_and_palette(b);
+    }
+
+    /// The part of intra_frame_mode_info() and intra_block_mode_info() after the luma mode.
+    fn intra_modes_and_palette(&mut self, b: &mut BitStream) {
+        self.angle_delta_y = self.intra_angle_info(b, self.y_mode as usize);
+
+        if self.has_chroma {
+            let cdf = if self.cfl_allowed() {
+                &mut self.cdf.uv_mode_cfl_allowed[self.y_mode as usize]
+            } else {
+                &mut self.cdf.uv_mode_cfl_not_allowed[self.y_mode as usize]
+            };
+            self.uv_mode = UvMode::new(self.symbol.read_symbol(b, cdf) as u64);
+
+            if self.uv_mode == UvMode::Cfl {
+                self.read_cfl_alphas(b);
+            }
+
+            self.angle_delta_uv = self.intra_angle_info(b, self.uv_mode as usize);
+        }
+
+        self.palette_size_y = 0;
+        self.palette_size_uv = 0;
+        if self.palette_allowed() {
+            self.palette_mode_info(b);
+        }
+
+        self.filter_intra_mode_info(b);
+    }
+
+    fn intra_segment_id(&mut self, b: &mut BitStream) {
+        if self.uh.segmentation_enabled {
+            self.read_segment_id(b);
+        } else {
+            self.segment_id = 0;
+        }
+
+        self.lossless = self.lossless_array[self.segment_id as usize];
+    }
+
+    /// The segment id is coded relative to a prediction from the above, left and above left
+    /// neighbours.
+    fn read_segment_id(&mut self, b: &mut BitStream) {
+        let row = self.mi_row as usize;
+        let col = self.mi_col as usize;
+        let prev_ul = if self.avail_u && self.avail_l {
+            self.segment_ids[row - 1][col - 1] as i64
+        } else {
+            -1
+        };
+        let prev_u = if self.avail_u {
+            self.segment_ids[row - 1][col] as i64
+        } else {
+            -1
+        };
+        let prev_l = if self.avail_l {
+            self.segment_ids[row][col - 1] as i64
+        } else {
+            -1
+        };
+
+        let pred = if prev_u == -1 {
+            prev_l.max(0)
+        } else if prev_l == -1 || prev_ul == prev_u {
+            prev_u
+        } else {
+            prev_l
+        };
+
+        if self.skip {
+            self.segment_id = pred as u64;
+            return;
+        }
+
+        let ctx = if prev_ul < 0 {
+            0
+        } else if prev_ul == prev_u && prev_ul == prev_l {
+            2
+        } else if prev_ul == prev_u || prev_ul == prev_l || prev_u == prev_l {
+            1
+        } else {
+            0
+        };
+
+        let segment_id = self.symbol.read_symbol(b, &mut self.cdf.segment_id[ctx]) as i64;
+        let max = self.last_active_seg_id as i64 + 1;
+        self.segment_id = Decoder::neg_deinterleave(segment_id, pred, max).clamp(0, max - 1) as u64;
+    }
+
+    fn neg_deinterleave(diff: i64, r: i64, max: i64) -> i64 {
+        if r == 0 {
+            return diff;
+        }
+
+        if r >= max - 1 {
+            return max - diff - 1;
+        }
+
+        if 2 * r < max {
+            if diff <= 2 * r {
+                if diff & 1 != 0 {
+                    return r + ((diff + 1) >> 1);
+                } else {
+                    return r - (diff >> 1);
+                }
+            }
+
+            diff
+        } else {
+            if diff <= 2 * (max - r - 1) {
+                if diff & 1 != 0 {
+                    return r + ((diff + 1) >> 1);
+                } else {
+                    return r - (diff >> 1);
+                }
+            }
+
+            max - (diff + 1)
+        }
+    }
+
+    fn inter_segment_id(&mut self, b: &mut BitStream, pre_skip: bool) {
+        if !self.uh.segmentation_enabled {
+            self.segment_id = 0;
+            return;
+        }
+
+        if !self.uh.segmentation_update_map {
+            self.segment_id = self.get_segment_id();
+            return;
+        }
+
+        if pre_skip && !self.seg_id_pre_skip {
+            self.segment_id = 0;
+            return;
+        }
+
+        if !pre_skip && self.skip {
+            self.set_seg_pred_context(false);
+            self.read_segment_id(b);
+            return;
+        }
+
+        if self.uh.segmentation_temporal_update {
+            let ctx = (self.left_seg_pred_context[self.mi_row as usize]
+                + self.above_seg_pred_context[self.mi_col as usize]) as usize;
+            let seg_id_predicted = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.segment_id_predicted[ctx])
+                != 0;
+
+            if seg_id_predicted {
+                self.segment_id = self.get_segment_id();
+            } else {
+                self.read_segment_id(b);
+            }
+
+            self.set_seg_pred_context(seg_id_predicted);
+        } else {
+            self.read_segment_id(b);
+        }
+    }
+
+    fn set_seg_pred_context(&mut self, seg_id_predicted: bool) {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+        let col = self.mi_col as usize;
+        let row = self.mi_row as usize;
+        self.above_seg_pred_context[col..col + bw4].fill(seg_id_predicted as u64);
+        self.left_seg_pred_context[row..row + bh4].fill(seg_id_predicted as u64);
+    }
+
+    /// The smallest segment id of the primary reference frame in the visible part of the
+    /// block.
+    fn get_segment_id(&self) -> u64 {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as u64;
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as u64;
+        let x_mis = (self.mi_cols - self.mi_col).min(bw4) as usize;
+        let y_mis = (self.mi_rows - self.mi_row).min(bh4) as usize;
+
+        let mut seg = 7;
+        for row in &self.prev_segment_ids[self.mi_row as usize..][..y_mis] {
+            for &id in &row[self.mi_col as usize..][..x_mis] {
+                seg = seg.min(id);
+            }
+        }
+
+        seg
+    }
+
+    pub(super) fn seg_feature_active(&self, feature: usize) -> bool {
+        self.uh.segmentation_enabled && self.feature_enabled[self.segment_id as usize][feature]
+    }
+
+    fn read_skip_mode(&mut self, b: &mut BitStream) {
+        if self.seg_feature_active(Decoder::SEG_LVL_SKIP)
+            || self.seg_feature_active(Decoder::SEG_LVL_REF_FRAME)
+            || self.seg_feature_active(Decoder::SEG_LVL_GLOBALMV)
+            || !self.uh.skip_mode_present
+            || self.mi_size.width() < 8
+            || self.mi_size.height() < 8
+        {
+            self.skip_mode = false;
+            return;
+        }
+
+        let row = self.mi_row as usize;
+        let col = self.mi_col as usize;
+        let mut ctx = 0;
+        if self.avail_u && self.skip_modes[row - 1][col] {
+            ctx += 1;
+        }
+        if self.avail_l && self.skip_modes[row][col - 1] {
+            ctx += 1;
+        }
+
+        self.skip_mode = self.symbol.read_symbol(b, &mut self.cdf.skip_mode[ctx]) != 0;
+    }
+
+    fn read_skip(&mut self, b: &mut BitStream) {
+        if self.seg_id_pre_skip && self.seg_feature_active(Decoder::SEG_LVL_SKIP) {
+            self.skip = true;
+            return;
+        }
+
+        let row = self.mi_row as usize;
+        let col = self.mi_col as usize;
+        let mut ctx = 0;
+        if self.avail_u && self.skips[row - 1][col] {
+            ctx += 1;
+        }
+        if self.avail_l && self.skips[row][col - 1] {
+            ctx += 1;
+        }
+
+        self.skip = self.symbol.read_symbol(b, &mut self.cdf.skip[ctx]) != 0;
+    }
+
+    fn sb_size(&self) -> SubSize {
+        if self.sequence_header.use_128x128_superblock {
+            SubSize::Block128x128
+        } else {
+            SubSize::Block64x64
+        }
+    }
+
+    /// Reads delta_q_abs or delta_lf_abs, which code values from DELTA_Q_SMALL up with an
+    /// explicit number of bits.
+    fn read_delta_abs(&mut self, b: &mut BitStream, delta_abs: usize) -> i64 {
+        if delta_abs == Decoder::DELTA_Q_SMALL {
+            let rem_bits = self.symbol.read_literal(b, 3) + 1;
+            let abs_bits = self.symbol.read_literal(b, rem_bits);
+            (abs_bits + (1 << rem_bits) + 1) as i64
+        } else {
+            delta_abs as i64
+        }
+    }
+
+    fn read_delta_qindex(&mut self, b: &mut BitStream) {
+        if self.mi_size == self.sb_size() && self.skip {
+            return;
+        }
+
+        if self.read_deltas {
+            let delta_q_abs = self.symbol.read_symbol(b, &mut self.cdf.delta_q);
+            let delta_q_abs = self.read_delta_abs(b, delta_q_abs);
+
+            if delta_q_abs != 0 {
+                let delta_q_sign_bit = self.symbol.read_literal(b, 1) != 0;
+                let reduced_delta_q_index = if delta_q_sign_bit {
+                    -delta_q_abs
+                } else {
+                    delta_q_abs
+                };
+                self.current_q_index = (self.current_q_index as i64
+                    + (reduced_delta_q_index << self.uh.delta_q_res))
+                    .clamp(1, 255) as u64;
+            }
+        }
+    }
+
+    fn read_delta_lf(&mut self, b: &mut BitStream) {
+        if self.mi_size == self.sb_size() && self.skip {
+            return;
+        }
+
+        if !self.read_deltas || !self.uh.delta_lf_present {
+            return;
+        }
+
+        let frame_lf_count = if !self.uh.delta_lf_multi {
+            1
+        } else if matches!(self.num_planes, NumPlanes::Three) {
+            Decoder::FRAME_LF_COUNT
+        } else {
+            Decoder::FRAME_LF_COUNT - 2
+        };
+
+        for i in 0..frame_lf_count {
+            let cdf = if self.uh.delta_lf_multi {
+                &mut self.cdf.delta_lf_multi[i]
+            } else {
+                &mut self.cdf.delta_lf
+            };
+            let delta_lf_abs = self.symbol.read_symbol(b, cdf);
+            debug_assert_eq!(Decoder::DELTA_LF_SMALL, Decoder::DELTA_Q_SMALL);
+            let delta_lf_abs = self.read_delta_abs(b, delta_lf_abs);
+
+            if delta_lf_abs != 0 {
+                let delta_lf_sign_bit = self.symbol.read_literal(b, 1) != 0;
+                let reduced_delta_lf_level = if delta_lf_sign_bit {
+                    -delta_lf_abs
+                } else {
+                    delta_lf_abs
+                };
+                self.delta_lf[i] = (self.delta_lf[i]
+                    + (reduced_delta_lf_level << self.uh.delta_lf_res))
+                    .clamp(-Decoder::MAX_LOOP_FILTER, Decoder::MAX_LOOP_FILTER);
+            }
+        }
+    }
+
+    fn cfl_allowed(&self) -> bool {
+        if self.lossless {
+            self.get_plane_residual_size(self.mi_size, 1) == SubSize::Block4x4
+        } else {
+            self.mi_size.width().max(self.mi_size.height()) <= 32
+        }
+    }
+
+    /// Reads angle_delta_y or angle_delta_uv for the directional modes of blocks of at
+    /// least 8x8, and returns the angle delta.
+    fn intra_angle_info(&mut self, b: &mut BitStream, mode: usize) -> i64 {
+        let is_directional = (YMode::V as usize..=YMode::D67 as usize).contains(&mode);
+        if self.mi_size < SubSize::Block8x8 || !is_directional {
+            return 0;
+        }
+
+        let angle_delta = self
+            .symbol
+            .read_symbol(b, &mut self.cdf.angle_delta[mode - YMode::V as usize]);
+        angle_delta as i64 - Decoder::MAX_ANGLE_DELTA
+    }
+
+    fn read_cfl_alphas(&mut self, b: &mut BitStream) {
+        let cfl_alpha_signs = self.symbol.read_symbol(b, &mut self.cdf.cfl_sign);
+        let sign_u = (cfl_alpha_signs + 1) / 3;
+        let sign_v = (cfl_alpha_signs + 1) % 3;
+
+        self.cfl_alpha_u = if sign_u != Decoder::CFL_SIGN_ZERO {
+            let ctx = (sign_u - 1) * 3 + sign_v;
+            let cfl_alpha_u = 1 + self.symbol.read_symbol(b, &mut self.cdf.cfl_alpha[ctx]) as i64;
+            if sign_u == Decoder::CFL_SIGN_NEG {
+                -cfl_alpha_u
+            } else {
+                cfl_alpha_u
+            }
+        } else {
+            0
+        };
+
+        self.cfl_alpha_v = if sign_v != Decoder::CFL_SIGN_ZERO {
+            let ctx = (sign_v - 1) * 3 + sign_u;
+            let cfl_alpha_v = 1 + self.symbol.read_symbol(b, &mut self.cdf.cfl_alpha[ctx]) as i64;
+            if sign_v == Decoder::CFL_SIGN_NEG {
+                -cfl_alpha_v
+            } else {
+                cfl_alpha_v
+            }
+        } else {
+            0
+        };
+    }
+
+    fn filter_intra_mode_info(&mut self, b: &mut BitStream) {
+        self.use_filter_intra = false;
+
+        if self.sequence_header.enable_filter_intra
+            && self.y_mode == YMode::Dc
+            && self.palette_size_y == 0
+            && self.mi_size.width().max(self.mi_size.height()) <= 32
+        {
+            self.use_filter_intra = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.filter_intra[self.mi_size as usize])
+                != 0;
+
+            if self.use_filter_intra {
+                self.filter_intra_mode =
+                    self.symbol.read_symbol(b, &mut self.cdf.filter_intra_mode);
+            }
+        }
+    }
+
+    fn inter_frame_mode_info(&mut self, b: &mut BitStream) {
+        let row = self.mi_row as usize;
+        let col = self.mi_col as usize;
+        self.use_intrabc = false;
+
+        self.left_ref_frame = if self.avail_l {
+            self.ref_frames[row][col - 1]
+        } else {
+            [Decoder::INTRA_FRAME, Decoder::NONE]
+        };
+        self.above_ref_frame = if self.avail_u {
+            self.ref_frames[row - 1][col]
+        } else {
+            [Decoder::INTRA_FRAME, Decoder::NONE]
+        };
+        self.left_intra = self.left_ref_frame[0] <= Decoder::INTRA_FRAME;
+        self.above_intra = self.above_ref_frame[0] <= Decoder::INTRA_FRAME;
+        self.left_single = self.left_ref_frame[1] <= Decoder::INTRA_FRAME;
+        self.above_single = self.above_ref_frame[1] <= Decoder::INTRA_FRAME;
+
+        self.skip = false;
+        self.inter_segment_id(b, true);
+        self.read_skip_mode(b);
+        if self.skip_mode {
+            self.skip = true;
+        } else {
+            self.read_skip(b);
+        }
+
+        if !self.seg_id_pre_skip {
+            self.inter_segment_id(b, false);
+        }
+
+        self.lossless = self.lossless_array[self.segment_id as usize];
+        self.read_cdef(b);
+        self.read_delta_qindex(b);
+        self.read_delta_lf(b);
+        self.read_deltas = false;
+        self.read_is_inter(b);
+
+        if self.is_inter {
+            self.inter_block_mode_info(b);
+        } else {
+            self.intra_block_mode_info(b);
+        }
+    }
+
+    fn read_is_inter(&mut self, b: &mut BitStream) {
+        self.is_inter = if self.skip_mode {
+            true
+        } else if self.seg_feature_active(Decoder::SEG_LVL_REF_FRAME) {
+            self.feature_data[self.segment_id as usize][Decoder::SEG_LVL_REF_FRAME]
+                != Decoder::INTRA_FRAME
+        } else if self.seg_feature_active(Decoder::SEG_LVL_GLOBALMV) {
+            true
+        } else {
+            let ctx = if self.avail_u && self.avail_l {
+                if self.left_intra && self.above_intra {
+                    3
+                } else {
+                    (self.left_intra || self.above_intra) as usize
+                }
+            } else if self.avail_u {
+                2 * self.above_intra as usize
+            } else if self.avail_l {
+                2 * self.left_intra as usize
+            } else {
+                0
+            };
+
+            self.symbol.read_symbol(b, &mut self.cdf.is_inter[ctx]) != 0
+        };
+    }
+
+    fn intra_block_mode_info(&mut self, b: &mut BitStream) {
+        self.ref_frame = [Decoder::INTRA_FRAME, Decoder::NONE];
+
+        let ctx = Decoder::SIZE_GROUP[self.mi_size as usize];
+        self.y_mode = YMode::new(self.symbol.read_symbol(b, &mut self.cdf.y_mode[ctx]) as u64);
+        self.intra_modes_and_palette(b);
+    }
+
+    fn inter_block_mode_info(&mut self, b: &mut BitStream) {
+        self.palette_size_y = 0;
+        self.palette_size_uv = 0;
+        self.read_ref_frames(b);
+
+        let is_compound = self.ref_frame[1] > Decoder::INTRA_FRAME;
+        self.find_mv_stack(is_compound);
+
+        self.y_mode = if self.skip_mode {
+            YMode::NearestNearestMv
+        } else if self.seg_feature_active(Decoder::SEG_LVL_SKIP)
+            || self.seg_feature_active(Decoder::SEG_LVL_GLOBALMV)
+        {
+            YMode::GlobalMv
+        } else if is_compound {
+            let ctx = Decoder::COMPOUND_MODE_CTX_MAP[self.ref_mv_context as usize >> 1]
+                [self.new_mv_context.min(4) as usize];
+            let compound_mode = self.symbol.read_symbol(b, &mut self.cdf.compound_mode[ctx]);
+            YMode::new(YMode::NearestNearestMv as u64 + compound_mode as u64)
+        } else {
+            let new_mv = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.new_mv[self.new_mv_context as usize]);
+            if new_mv == 0 {
+                YMode::NewMv
+            } else {
+                let zero_mv = self
+                    .symbol
+                    .read_symbol(b, &mut self.cdf.zero_mv[self.zero_mv_context as usize]);
+                if zero_mv == 0 {
+                    YMode::GlobalMv
+                } else {
+                    let ref_mv = self
+                        .symbol
+                        .read_symbol(b, &mut self.cdf.ref_mv[self.ref_mv_context as usize]);
+                    if ref_mv == 0 {
+                        YMode::NearestMv
+                    } else {
+                        YMode::NearMv
+                    }
+                }
+            }
+        };
+
+        self.ref_mv_idx = 0;
+        let has_nearmv = matches!(
+            self.y_mode,
+            YMode::NearMv | YMode::NearNearMv | YMode::NearNewMv | YMode::NewNearMv
+        );
+        let drl_range = if matches!(self.y_mode, YMode::NewMv | YMode::NewNewMv) {
+            Some(0..2)
+        } else if has_nearmv {
+            self.ref_mv_idx = 1;
+            Some(1..3)
+        } else {
+            None
+        };
+
+        for idx in drl_range.into_iter().flatten() {
+            if self.num_mv_found > idx + 1 {
+                let ctx = self.drl_ctx(idx);
+                let drl_mode = self.symbol.read_symbol(b, &mut self.cdf.drl_mode[ctx]);
+                if drl_mode == 0 {
+                    self.ref_mv_idx = idx;
+                    break;
+                }
+
+                self.ref_mv_idx = idx + 1;
+            }
+        }
+
+        self.assign_mv(b, is_compound);
+        self.read_inter_intra(b, is_compound);
+        self.read_motion_mode(b, is_compound);
+        self.read_compound_type(b, is_compound);
+        self.read_interp_filter(b);
+    }
+
+    /// Whether the weights of candidates idx and idx + 1 in the ref mv stack come from the
+    /// nearest neighbours.
+    fn drl_ctx(&self, idx: usize) -> usize {
+        let near = |i: usize| self.weight_stack[i] >= Decoder::REF_CAT_LEVEL;
+        match (near(idx), near(idx + 1)) {
+            (true, true) => 0,
+            (true, false) => 1,
+            (false, false) => 2,
+            (false, true) => 0,
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use super::{block::SubSize, obu::FrameType, round2signed, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#motion-field-estimation-process
+
+impl Decoder {
+    pub const LAST2_FRAME: usize = 2;
+    pub const LAST3_FRAME: usize = 3;
+    pub const GOLDEN_FRAME: usize = 4;
+    pub const BWDREF_FRAME: usize = 5;
+    pub const ALTREF2_FRAME: usize = 6;
+
+    /// Marks an entry of motion_field_mvs that no projection landed on.
+    pub const INVALID_MV: i64 = -1 << 15;
+
+    const REFMVS_LIMIT: i64 = (1 << 12) - 1;
+    const MFMV_STACK_SIZE: i64 = 3;
+    const MAX_OFFSET_WIDTH: i64 = 8;
+    const MAX_OFFSET_HEIGHT: i64 = 0;
+    const DIV_MULT: [i64; 32] = [
+        0, 16384, 8192, 5461, 4096, 3276, 2730, 2340, 2048, 1820, 1638, 1489, 1365, 1260, 1170,
+        1092, 1024, 963, 910, 862, 819, 780, 744, 712, 682, 655, 630, 606, 585, 564, 546, 528,
+    ];
+
+    /// Keeps one motion vector per 8x8 block pointing backwards in time, which later frames
+    /// project through. Only the bottom right 4x4 of every 8x8 is ever read, so that is the
+    /// one that is sampled.
+    pub(super) fn motion_field_motion_vector_storage(&mut self) {
+        let h8 = self.mi_rows as usize >> 1;
+        let w8 = self.mi_cols as usize >> 1;
+
+        self.mf_ref_frames = vec![vec![Decoder::NONE; w8]; h8];
+        self.mf_mvs = vec![vec![[0; 2]; w8]; h8];
+
+        for y8 in 0..h8 {
+            for x8 in 0..w8 {
+                let row = 2 * y8 + 1;
+                let col = 2 * x8 + 1;
+
+                for list in 0..2 {
+                    let r = self.ref_frames[row][col][list];
+                    if r <= Decoder::INTRA_FRAME {
+                        continue;
+                    }
+
+                    let dist =
+                        self.get_relative_dist(self.order_hints[r as usize], self.order_hint);
+                    if dist >= 0 {
+                        continue;
+                    }
+
+                    let mv = self.mvs[row][col][list];
+                    if mv[0].abs() <= Decoder::REFMVS_LIMIT && mv[1].abs() <= Decoder::REFMVS_LIMIT
+                    {
+                        self.mf_ref_frames[y8][x8] = r;
+                        self.mf_mvs[y8][x8] = mv;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Projects the motion fields saved with the reference frames onto the current frame,
+    /// filling motion_field_mvs for every reference frame.
+    pub(super) fn motion_field_estimation(&mut self) {
+        let h8 = self.mi_rows as usize >> 1;
+        let w8 = self.mi_cols as usize >> 1;
+
+        self.motion_field_mvs =
+            vec![vec![vec![[Decoder::INVALID_MV; 2]; w8]; h8]; Decoder::ALTREF_FRAME + 1];
+        self.motion_field_sources = vec![vec![Decoder::NONE; w8]; h8];
+
+        let last_idx = self.uh.ref_frame_idx[0];
+        let cur_gold_hint = self.order_hints[Decoder::GOLDEN_FRAME];
+        let last_alt_hint = self.ref_frame_store[last_idx].saved_order_hints[Decoder::ALTREF_FRAME];
+        if last_alt_hint != cur_gold_hint {
+            self.projection(Decoder::LAST_FRAME, -1);
+        }
+
+        let mut ref_stamp = Decoder::MFMV_STACK_SIZE - 2;
+        for src in [
+            Decoder::BWDREF_FRAME,
+            Decoder::ALTREF2_FRAME,
+            Decoder::ALTREF_FRAME,
+        ] {
+            if src == Decoder::ALTREF_FRAME && ref_stamp < 0 {
+                continue;
+            }
+
+            if self.get_relative_dist(self.order_hints[src], self.order_hint) > 0
+                && self.projection(src, 1)
+            {
+                ref_stamp -= 1;
+            }
+        }
+
+        if ref_stamp >= 0 {
+            self.projection(Decoder::LAST2_FRAME, -1);
+        }
+    }
+
+    /// Returns false if the motion field of src can't be used.
+    fn projection(&mut self, src: usize, dst_sign: i64) -> bool {
+        let src_idx = self.uh.ref_frame_idx[src - Decoder::LAST_FRAME];
+        let store = &self.ref_frame_store[src_idx];
+        if store.mi_rows != self.mi_rows
+            || store.mi_cols != self.mi_cols
+            || store.frame_type == FrameType::IntraOnly
+        {
+            return false;
+        }
+
+        let h8 = self.mi_rows as i64 >> 1;
+        let w8 = self.mi_cols as i64 >> 1;
+
+        for y8 in 0..h8 {
+            for x8 in 0..w8 {
+                let store = &self.ref_frame_store[src_idx];
+                let src_ref = store.saved_ref_frames[y8 as usize][x8 as usize];
+                if src_ref <= Decoder::INTRA_FRAME {
+                    continue;
+                }
+
+                let ref_to_cur = self.get_relative_dist(self.order_hints[src], self.order_hint);
+                let ref_offset = self.get_relative_dist(
+                    self.order_hints[src],
+                    store.saved_order_hints[src_ref as usize],
+                );
+                if ref_to_cur.abs() > Decoder::MAX_FRAME_DISTANCE
+                    || ref_offset.abs() > Decoder::MAX_FRAME_DISTANCE
+                    || ref_offset <= 0
+                {
+                    continue;
+                }
+
+                let mv = store.saved_mvs[y8 as usize][x8 as usize];
+                let proj_mv = Decoder::get_mv_projection(mv, ref_to_cur * dst_sign, ref_offset);
+                let pos_y8 =
+                    Decoder::project(y8, proj_mv[0], dst_sign, h8, Decoder::MAX_OFFSET_HEIGHT);
+                let pos_x8 =
+                    Decoder::project(x8, proj_mv[1], dst_sign, w8, Decoder::MAX_OFFSET_WIDTH);
+                let (Some(pos_y8), Some(pos_x8)) = (pos_y8, pos_x8) else {
+                    continue;
+                };
+
+                for dst in Decoder::LAST_FRAME..=Decoder::ALTREF_FRAME {
+                    let ref_to_dst = self.get_relative_dist(self.order_hint, self.order_hints[dst]);
+                    self.motion_field_mvs[dst][pos_y8][pos_x8] =
+                        Decoder::get_mv_projection(mv, ref_to_dst, ref_offset);
+                }
+                self.motion_field_sources[pos_y8][pos_x8] = src as i64;
+            }
+        }
+
+        true
+    }
+
+    /// Scales a motion vector spanning `denominator` frames to span `numerator` frames.
+    pub(super) fn get_mv_projection(mv: [i64; 2], numerator: i64, denominator: i64) -> [i64; 2] {
+        let clipped_denominator = denominator.min(Decoder::MAX_FRAME_DISTANCE);
+        let clipped_numerator =
+            numerator.clamp(-Decoder::MAX_FRAME_DISTANCE, Decoder::MAX_FRAME_DISTANCE);
+
+        mv.map(|v| {
+            let scaled = round2signed(
+                v * clipped_numerator * Decoder::DIV_MULT[clipped_denominator as usize],
+                14,
+            );
+            scaled.clamp(-(1 << 14) + 1, (1 << 14) - 1)
+        })
+    }
+
+    /// Moves an 8x8 position along a projected motion vector, or returns None if it leaves
+    /// the frame or strays too far from its 64x64 block.
+    fn project(v8: i64, delta: i64, dst_sign: i64, max8: i64, max_off8: i64) -> Option<usize> {
+        let base8 = (v8 >> 3) << 3;
+        let offset8 = if delta >= 0 {
+            delta >> (3 + 1 + Decoder::MI_SIZE_LOG2)
+        } else {
+            -((-delta) >> (3 + 1 + Decoder::MI_SIZE_LOG2))
+        };
+
+        let v8 = v8 + dst_sign * offset8;
+        if v8 < 0 || v8 >= max8 || v8 < base8 - max_off8 || v8 >= base8 + 8 + max_off8 {
+            None
+        } else {
+            Some(v8 as usize)
+        }
+    }
+
+    pub(super) fn temporal_scan(&mut self, is_compound: bool) {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as i64;
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as i64;
+        let step_w4 = if bw4 >= 16 { 4 } else { 2 };
+        let step_h4 = if bh4 >= 16 { 4 } else { 2 };
+
+        for delta_row in (0..bh4.min(16)).step_by(step_h4) {
+            for delta_col in (0..bw4.min(16)).step_by(step_w4) {
+                self.add_tpl_ref_mv(delta_row, delta_col, is_compound);
+            }
+        }
+
+        let min4 = Decoder::NUM_4X4_BLOCKS_HIGH[SubSize::Block8x8 as usize] as i64;
+        let max4 = Decoder::NUM_4X4_BLOCKS_HIGH[SubSize::Block64x64 as usize] as i64;
+        let allow_extension = bh4 >= min4 && bh4 < max4 && bw4 >= min4 && bw4 < max4;
+
+        if allow_extension {
+            for (delta_row, delta_col) in [(bh4, -2), (bh4, bw4), (bh4 - 2, bw4)] {
+                if self.check_sb_border(delta_row, delta_col) {
+                    self.add_tpl_ref_mv(delta_row, delta_col, is_compound);
+                }
+            }
+        }
+    }
+
+    fn check_sb_border(&self, delta_row: i64, delta_col: i64) -> bool {
+        let row = (self.mi_row as i64 & 15) + delta_row;
+        let col = (self.mi_col as i64 & 15) + delta_col;
+
+        (0..16).contains(&row) && (0..16).contains(&col)
+    }
+
+    fn add_tpl_ref_mv(&mut self, delta_row: i64, delta_col: i64, is_compound: bool) {
+        let mv_row = (self.mi_row as i64 + delta_row) | 1;
+        let mv_col = (self.mi_col as i64 + delta_col) | 1;
+        if !self.is_inside(mv_row, mv_col) {
+            return;
+        }
+
+        let x8 = (mv_col >> 1) as usize;
+        let y8 = (mv_row >> 1) as usize;
+        let at_origin = delta_row == 0 && delta_col == 0;
+        if at_origin {
+            self.zero_mv_context = 1;
+        }
+
+        let num_lists = if is_compound { 2 } else { 1 };
+        let mut cand_mvs = [[0; 2]; 2];
+        for (list, cand_mv) in cand_mvs.iter_mut().enumerate().take(num_lists) {
+            *cand_mv = self.motion_field_mvs[self.ref_frame[list] as usize][y8][x8];
+            if cand_mv[0] == Decoder::INVALID_MV {
+                return;
+            }
+
+            self.lower_mv_precision(cand_mv);
+        }
+
+        if at_origin {
+            let far_from_global = (0..num_lists).any(|list| {
+                (cand_mvs[list][0] - self.global_mvs[list][0]).abs() >= 16
+                    || (cand_mvs[list][1] - self.global_mvs[list][1]).abs() >= 16
+            });
+            self.zero_mv_context = far_from_global as u64;
+        }
+
+        let found = (0..self.num_mv_found)
+            .find(|&idx| (0..num_lists).all(|list| self.ref_stack_mv[idx][list] == cand_mvs[list]));
+
+        if let Some(idx) = found {
+            self.weight_stack[idx] += 2;
+        } else if self.num_mv_found < Decoder::MAX_REF_MV_STACK_SIZE {
+            for (list, cand_mv) in cand_mvs.iter().enumerate().take(num_lists) {
+                self.ref_stack_mv[self.num_mv_found][list] = *cand_mv;
+            }
+            self.weight_stack[self.num_mv_found] = 2;
+            self.num_mv_found += 1;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use super::{
+    block::{SubSize, YMode},
+    symbol::SyntaxCategory,
+    BitStream, Decoder,
+};
+
+impl Decoder {
+    const MV_INTRABC_CONTEXT: usize = 1;
+    const MV_JOINT_HNZVZ: usize = 1;
+    const MV_JOINT_HZVNZ: usize = 2;
+    const MV_JOINT_HNZVNZ: usize = 3;
+    const MV_CLASS_0: usize = 0;
+    const CLASS0_SIZE: i64 = 2;
+
+    pub(super) fn assign_mv(&mut self, b: &mut BitStream, is_compound: bool) {
+        self.mv = [[0; 2]; 2];
+
+        for i in 0..1 + is_compound as usize {
+            let comp_mode = if self.use_intrabc {
+                YMode::NewMv
+            } else {
+                self.get_mode(i)
+            };
+
+            if self.use_intrabc {
+                self.pred_mv[0] = self.ref_stack_mv[0][0];
+                if self.pred_mv[0] == [0, 0] {
+                    self.pred_mv[0] = self.ref_stack_mv[1][0];
+                }
+
+                if self.pred_mv[0] == [0, 0] {
+                    let sb_size = if self.sequence_header.use_128x128_superblock {
+                        SubSize::Block128x128
+                    } else {
+                        SubSize::Block64x64
+                    };
+                    let sb_size4 = Decoder::NUM_4X4_BLOCKS_HIGH[sb_size as usize] as i64;
+                    let mi_size = Decoder::MI_SIZE as i64;
+
+                    self.pred_mv[0] = if (self.mi_row as i64) - sb_size4 < self.mi_row_start as i64
+                    {
+                        [0, -(sb_size4 * mi_size + Decoder::INTRABC_DELAY_PIXELS) * 8]
+                    } else {
+                        [-(sb_size4 * mi_size * 8), 0]
+                    };
+                }
+            } else if comp_mode == YMode::GlobalMv {
+                self.pred_mv[i] = self.global_mvs[i];
+            } else {
+                let mut pos = if comp_mode == YMode::NearestMv {
+                    0
+                } else {
+                    self.ref_mv_idx
+                };
+
+                if comp_mode == YMode::NewMv && self.num_mv_found <= 1 {
+                    pos = 0;
+                }
+
+                self.pred_mv[i] = self.ref_stack_mv[pos][i];
+            }
+
+            if comp_mode == YMode::NewMv {
+                self.read_mv(b, i);
+            } else {
+                self.mv[i] = self.pred_mv[i];
+            }
+        }
+
+        assert!(
+            self.is_mv_valid(is_compound),
+            "invalid motion vector {:?} at mi_row {} mi_col {}",
+            self.mv,
+            self.mi_row,
+            self.mi_col
+        );
+    }
+
+    /// Returns the single reference mode that `ref_list` of a compound mode uses.
+    fn get_mode(&self, ref_list: usize) -> YMode {
+        if ref_list == 0 {
+            match self.y_mode {
+                mode if (mode as u64) < YMode::NearestNearestMv as u64 => mode,
+                YMode::NewNewMv | YMode::NewNearestMv | YMode::NewNearMv => YMode::NewMv,
+                YMode::NearestNearestMv | YMode::NearestNewMv => YMode::NearestMv,
+                YMode::NearNearMv | YMode::NearNewMv => YMode::NearMv,
+                _ => YMode::GlobalMv,
+            }
+        } else {
+            match self.y_mode {
+                YMode::NewNewMv | YMode::NearestNewMv | YMode::NearNewMv => YMode::NewMv,
+                YMode::NearestNearestMv | YMode::NewNearestMv => YMode::NearestMv,
+                YMode::NearNearMv | YMode::NewNearMv => YMode::NearMv,
+                _ => YMode::GlobalMv,
+            }
+        }
+    }
+
+    fn read_mv(&mut self, b: &mut BitStream, r: usize) {
+        let mut diff_mv = [0i64; 2];
+        let mv_ctx = if self.use_intrabc {
+            Decoder::MV_INTRABC_CONTEXT
+        } else {
+            0
+        };
+
+        let category = self.symbol.set_category(SyntaxCategory::Mv);
+        let mv_joint = self.symbol.read_symbol(b, &mut self.cdf.mv_joint[mv_ctx]);
+        if mv_joint == Decoder::MV_JOINT_HZVNZ || mv_joint == Decoder::MV_JOINT_HNZVNZ {
+            diff_mv[0] = self.read_mv_component(b, mv_ctx, 0);
+        }
+
+        if mv_joint == Decoder::MV_JOINT_HNZVZ || mv_joint == Decoder::MV_JOINT_HNZVNZ {
+            diff_mv[1] = self.read_mv_component(b, mv_ctx, 1);
+        }
+        self.symbol.set_category(category);
+
+        self.mv[r] = [
+            self.pred_mv[r][0] + diff_mv[0],
+            self.pred_mv[r][1] + diff_mv[1],
+        ];
+    }
+
+    fn read_mv_component(&mut self, b: &mut BitStream, mv_ctx: usize, comp: usize) -> i64 {
+        let mv_sign = self
+            .symbol
+            .read_symbol(b, &mut self.cdf.mv_sign[mv_ctx][comp])
+            != 0;
+        let mv_class = self
+            .symbol
+            .read_symbol(b, &mut self.cdf.mv_class[mv_ctx][comp]);
+
+        let mag = if mv_class == Decoder::MV_CLASS_0 {
+            let mv_class0_bit = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.mv_class0_bit[mv_ctx][comp])
+                as i64;
+
+            let mv_class0_fr = if self.uh.force_integer_mv != 0 {
+                3
+            } else {
+                self.symbol.read_symbol(
+                    b,
+                    &mut self.cdf.mv_class0_fr[mv_ctx][comp][mv_class0_bit as usize],
+                ) as i64
+            };
+
+            let mv_class0_hp = if self.uh.allow_high_precision_mv {
+                self.symbol
+                    .read_symbol(b, &mut self.cdf.mv_class0_hp[mv_ctx][comp]) as i64
+            } else {
+                1
+            };
+
+            ((mv_class0_bit << 3) | (mv_class0_fr << 1) | mv_class0_hp) + 1
+        } else {
+            let mut d = 0;
+            for i in 0..mv_class {
+                let mv_bit = self
+                    .symbol
+                    .read_symbol(b, &mut self.cdf.mv_bit[mv_ctx][comp][i])
+                    as i64;
+                d |= mv_bit << i;
+            }
+
+            let mag = Decoder::CLASS0_SIZE << (mv_class + 2);
+
+            let mv_fr = if self.uh.force_integer_mv != 0 {
+                3
+            } else {
+                self.symbol
+                    .read_symbol(b, &mut self.cdf.mv_fr[mv_ctx][comp]) as i64
+            };
+
+            let mv_hp = if self.uh.allow_high_precision_mv {
+                self.symbol
+                    .read_symbol(b, &mut self.cdf.mv_hp[mv_ctx][comp]) as i64
+            } else {
+                1
+            };
+
+            mag + ((d << 3) | (mv_fr << 1) | mv_hp) + 1
+        };
+
+        if mv_sign {
+            -mag
+        } else {
+            mag
+        }
+    }
+}
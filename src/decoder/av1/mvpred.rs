@@ -0,0 +1,547 @@
+use super::{block::YMode, obu::WarpModel, round2signed, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#motion-vector-prediction-processes
+
+impl Decoder {
+    pub const NONE: i64 = -1;
+    pub const INTRA_FRAME: i64 = 0;
+    pub const MAX_REF_MV_STACK_SIZE: usize = 8;
+    pub const REF_CAT_LEVEL: u64 = 640;
+    const MV_BORDER: i64 = 128;
+
+    pub(super) fn find_mv_stack(&mut self, is_compound: bool) {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as i64;
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as i64;
+
+        self.num_mv_found = 0;
+        self.new_mv_count = 0;
+        self.global_mvs[0] = self.setup_global_mv(0);
+        if is_compound {
+            self.global_mvs[1] = self.setup_global_mv(1);
+        }
+
+        self.found_match = false;
+        self.scan_row(-1, is_compound);
+        let mut found_above_match = self.found_match;
+        self.found_match = false;
+
+        self.scan_col(-1, is_compound);
+        let mut found_left_match = self.found_match;
+        self.found_match = false;
+
+        if bw4.max(bh4) <= 16 {
+            self.scan_point(-1, bw4, is_compound);
+        }
+        if self.found_match {
+            found_above_match = true;
+        }
+
+        self.close_matches = found_above_match as u64 + found_left_match as u64;
+        let num_nearest = self.num_mv_found;
+        let num_new = self.new_mv_count;
+        for weight in self.weight_stack.iter_mut().take(num_nearest) {
+            *weight += Decoder::REF_CAT_LEVEL;
+        }
+
+        self.zero_mv_context = 0;
+        if self.uh.use_ref_frame_mvs {
+            self.temporal_scan(is_compound);
+        }
+
+        self.found_match = false;
+        self.scan_point(-1, -1, is_compound);
+        if self.found_match {
+            found_above_match = true;
+        }
+
+        self.found_match = false;
+        self.scan_row(-3, is_compound);
+        if self.found_match {
+            found_above_match = true;
+        }
+
+        self.found_match = false;
+        self.scan_col(-3, is_compound);
+        if self.found_match {
+            found_left_match = true;
+        }
+
+        self.found_match = false;
+        if bh4 > 1 {
+            self.scan_row(-5, is_compound);
+        }
+        if self.found_match {
+            found_above_match = true;
+        }
+
+        self.found_match = false;
+        if bw4 > 1 {
+            self.scan_col(-5, is_compound);
+        }
+        if self.found_match {
+            found_left_match = true;
+        }
+
+        self.total_matches = found_above_match as u64 + found_left_match as u64;
+
+        self.sorting(0, num_nearest);
+        self.sorting(num_nearest, self.num_mv_found);
+
+        if self.num_mv_found < 2 {
+            self.extra_search(is_compound);
+        }
+
+        self.context_and_clamping(is_compound, num_new);
+    }
+
+    fn setup_global_mv(&self, ref_list: usize) -> [i64; 2] {
+        let ref_frame = self.ref_frame[ref_list];
+        let typ = if ref_frame != Decoder::INTRA_FRAME {
+            self.gm_type[ref_frame as usize]
+        } else {
+            WarpModel::Identity
+        };
+
+        let bw = self.mi_size.width() as i64;
+        let bh = self.mi_size.height() as i64;
+        let prec_bits = Decoder::WARPEDMODEL_PREC_BITS as i64;
+
+        let mut mv = match typ {
+            _ if ref_frame == Decoder::INTRA_FRAME => [0, 0],
+            WarpModel::Identity | WarpModel::Invalid => [0, 0],
+            WarpModel::Translation => {
+                let gm = &self.gm_params[ref_frame as usize];
+                [gm[0] >> (prec_bits - 3), gm[1] >> (prec_bits - 3)]
+            }
+            WarpModel::Rotzoom | WarpModel::Affine => {
+                let gm = &self.gm_params[ref_frame as usize];
+                let x = self.mi_col as i64 * Decoder::MI_SIZE as i64 + bw / 2 - 1;
+                let y = self.mi_row as i64 * Decoder::MI_SIZE as i64 + bh / 2 - 1;
+                let xc = (gm[2] - (1 << prec_bits)) * x + gm[3] * y + gm[0];
+                let yc = gm[4] * x + (gm[5] - (1 << prec_bits)) * y + gm[1];
+
+                if self.uh.allow_high_precision_mv {
+                    [
+                        round2signed(yc, prec_bits - 3),
+                        round2signed(xc, prec_bits - 3),
+                    ]
+                } else {
+                    [
+                        round2signed(yc, prec_bits - 2) * 2,
+                        round2signed(xc, prec_bits - 2) * 2,
+                    ]
+                }
+            }
+        };
+
+        self.lower_mv_precision(&mut mv);
+        mv
+    }
+
+    fn scan_row(&mut self, delta_row: i64, is_compound: bool) {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as i64;
+        let end4 = bw4.min(self.mi_cols as i64 - self.mi_col as i64).min(16);
+        let mut delta_row = delta_row;
+        let mut delta_col = 0;
+        let use_step16 = bw4 >= 16;
+
+        if delta_row.abs() > 1 {
+            delta_row += self.mi_row as i64 & 1;
+            delta_col = 1 - (self.mi_col as i64 & 1);
+        }
+
+        let mut i = 0;
+        while i < end4 {
+            let mv_row = self.mi_row as i64 + delta_row;
+            let mv_col = self.mi_col as i64 + delta_col + i;
+            if !self.is_inside(mv_row, mv_col) {
+                break;
+            }
+
+            let cand_size = self.mi_sizes[mv_row as usize][mv_col as usize];
+            let mut len = bw4.min(Decoder::NUM_4X4_BLOCKS_WIDE[cand_size as usize] as i64);
+            if delta_row.abs() > 1 {
+                len = len.max(2);
+            }
+            if use_step16 {
+                len = len.max(4);
+            }
+
+            let weight = len as u64 * 2;
+            self.add_ref_mv_candidate(mv_row as usize, mv_col as usize, is_compound, weight);
+            i += len;
+        }
+    }
+
+    fn scan_col(&mut self, delta_col: i64, is_compound: bool) {
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as i64;
+        let end4 = bh4.min(self.mi_rows as i64 - self.mi_row as i64).min(16);
+        let mut delta_row = 0;
+        let mut delta_col = delta_col;
+        let use_step16 = bh4 >= 16;
+
+        if delta_col.abs() > 1 {
+            delta_row = 1 - (self.mi_row as i64 & 1);
+            delta_col += self.mi_col as i64 & 1;
+        }
+
+        let mut i = 0;
+        while i < end4 {
+            let mv_row = self.mi_row as i64 + delta_row + i;
+            let mv_col = self.mi_col as i64 + delta_col;
+            if !self.is_inside(mv_row, mv_col) {
+                break;
+            }
+
+            let cand_size = self.mi_sizes[mv_row as usize][mv_col as usize];
+            let mut len = bh4.min(Decoder::NUM_4X4_BLOCKS_HIGH[cand_size as usize] as i64);
+            if delta_col.abs() > 1 {
+                len = len.max(2);
+            }
+            if use_step16 {
+                len = len.max(4);
+            }
+
+            let weight = len as u64 * 2;
+            self.add_ref_mv_candidate(mv_row as usize, mv_col as usize, is_compound, weight);
+            i += len;
+        }
+    }
+
+    fn scan_point(&mut self, delta_row: i64, delta_col: i64, is_compound: bool) {
+        let mv_row = self.mi_row as i64 + delta_row;
+        let mv_col = self.mi_col as i64 + delta_col;
+        let weight = 4;
+
+        if self.is_inside(mv_row, mv_col)
+            && self.ref_frames_written[mv_row as usize][mv_col as usize]
+        {
+            self.add_ref_mv_candidate(mv_row as usize, mv_col as usize, is_compound, weight);
+        }
+    }
+
+    pub(super) fn is_inside(&self, cand_r: i64, cand_c: i64) -> bool {
+        cand_c >= self.mi_col_start as i64
+            && cand_c < self.mi_col_end as i64
+            && cand_r >= self.mi_row_start as i64
+            && cand_r < self.mi_row_end as i64
+    }
+
+    fn add_ref_mv_candidate(
+        &mut self,
+        mv_row: usize,
+        mv_col: usize,
+        is_compound: bool,
+        weight: u64,
+    ) {
+        if !self.is_inters[mv_row][mv_col] {
+            return;
+        }
+
+        let cand_ref_frames = self.ref_frames[mv_row][mv_col];
+        if !is_compound {
+            for (cand_list, &cand_ref_frame) in cand_ref_frames.iter().enumerate() {
+                if cand_ref_frame == self.ref_frame[0] {
+                    self.search_stack(mv_row, mv_col, cand_list, weight);
+                }
+            }
+        } else if cand_ref_frames == self.ref_frame {
+            self.compound_search_stack(mv_row, mv_col, weight);
+        }
+    }
+
+    /// Global motion is only used for candidates that are at least 8x8 and coded with one
+    /// of the GLOBALMV modes.
+    fn uses_global_mv(&self, mv_row: usize, mv_col: usize, ref_frame: i64) -> bool {
+        let cand_mode = self.y_modes[mv_row][mv_col];
+        let cand_size = self.mi_sizes[mv_row][mv_col];
+        let large = cand_size.width().min(cand_size.height()) >= 8;
+
+        (cand_mode == YMode::GlobalMv || cand_mode == YMode::GlobalGlobalMv)
+            && self.gm_type[ref_frame as usize] as i64 > WarpModel::Translation as i64
+            && large
+    }
+
+    fn search_stack(&mut self, mv_row: usize, mv_col: usize, cand_list: usize, weight: u64) {
+        let cand_mode = self.y_modes[mv_row][mv_col];
+        let mut cand_mv = if self.uses_global_mv(mv_row, mv_col, self.ref_frame[0]) {
+            self.global_mvs[0]
+        } else {
+            self.mvs[mv_row][mv_col][cand_list]
+        };
+
+        self.lower_mv_precision(&mut cand_mv);
+
+        if has_newmv(cand_mode) {
+            self.new_mv_count += 1;
+        }
+
+        self.found_match = true;
+
+        let idx = self.ref_stack_mv[..self.num_mv_found]
+            .iter()
+            .position(|mvs| mvs[0] == cand_mv);
+
+        if let Some(idx) = idx {
+            self.weight_stack[idx] += weight;
+        } else if self.num_mv_found < Decoder::MAX_REF_MV_STACK_SIZE {
+            self.ref_stack_mv[self.num_mv_found][0] = cand_mv;
+            self.weight_stack[self.num_mv_found] = weight;
+            self.num_mv_found += 1;
+        }
+    }
+
+    fn compound_search_stack(&mut self, mv_row: usize, mv_col: usize, weight: u64) {
+        let cand_mode = self.y_modes[mv_row][mv_col];
+        let mut cand_mvs = self.mvs[mv_row][mv_col];
+
+        for (ref_list, cand_mv) in cand_mvs.iter_mut().enumerate() {
+            if self.uses_global_mv(mv_row, mv_col, self.ref_frame[ref_list]) {
+                *cand_mv = self.global_mvs[ref_list];
+            }
+
+            self.lower_mv_precision(cand_mv);
+        }
+
+        self.found_match = true;
+
+        let idx = self.ref_stack_mv[..self.num_mv_found]
+            .iter()
+            .position(|mvs| *mvs == cand_mvs);
+
+        if let Some(idx) = idx {
+            self.weight_stack[idx] += weight;
+        } else if self.num_mv_found < Decoder::MAX_REF_MV_STACK_SIZE {
+            self.ref_stack_mv[self.num_mv_found] = cand_mvs;
+            self.weight_stack[self.num_mv_found] = weight;
+            self.num_mv_found += 1;
+        }
+
+        if has_newmv(cand_mode) {
+            self.new_mv_count += 1;
+        }
+    }
+
+    pub(super) fn lower_mv_precision(&self, cand_mv: &mut [i64; 2]) {
+        if self.uh.allow_high_precision_mv {
+            return;
+        }
+
+        for mv in cand_mv.iter_mut() {
+            if self.uh.force_integer_mv != 0 {
+                let a = mv.abs();
+                let a_int = (a + 3) >> 3;
+                if *mv > 0 {
+                    *mv = a_int << 3;
+                } else {
+                    *mv = -(a_int << 3);
+                }
+            } else if *mv & 1 != 0 {
+                *mv += if *mv > 0 { -1 } else { 1 };
+            }
+        }
+    }
+
+    /// Bubble sort of the stack entries in [start, end) by descending weight.
+    fn sorting(&mut self, start: usize, end: usize) {
+        let mut end = end;
+        while end > start {
+            let mut new_end = start;
+            for idx in (start + 1)..end {
+                if self.weight_stack[idx - 1] < self.weight_stack[idx] {
+                    self.weight_stack.swap(idx - 1, idx);
+                    self.ref_stack_mv.swap(idx - 1, idx);
+                    new_end = idx;
+                }
+            }
+
+            end = new_end;
+        }
+    }
+
+    fn extra_search(&mut self, is_compound: bool) {
+        let mut ref_id_mvs = [[[0i64; 2]; 2]; 2];
+        let mut ref_diff_mvs = [[[0i64; 2]; 2]; 2];
+        let mut ref_id_count = [0usize; 2];
+        let mut ref_diff_count = [0usize; 2];
+
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as i64;
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as i64;
+        let w4 = bw4.min(16).min(self.mi_cols as i64 - self.mi_col as i64);
+        let h4 = bh4.min(16).min(self.mi_rows as i64 - self.mi_row as i64);
+        let num4x4 = w4.min(h4);
+
+        for pass in 0..2 {
+            if self.num_mv_found >= 2 {
+                break;
+            }
+
+            let mut idx = 0;
+            while idx < num4x4 && self.num_mv_found < 2 {
+                let (mv_row, mv_col) = if pass == 0 {
+                    (self.mi_row as i64 - 1, self.mi_col as i64 + idx)
+                } else {
+                    (self.mi_row as i64 + idx, self.mi_col as i64 - 1)
+                };
+
+                if !self.is_inside(mv_row, mv_col) {
+                    break;
+                }
+
+                let (mv_row, mv_col) = (mv_row as usize, mv_col as usize);
+                if is_compound {
+                    for cand_list in 0..2 {
+                        let cand_ref = self.ref_frames[mv_row][mv_col][cand_list];
+                        if cand_ref <= Decoder::INTRA_FRAME {
+                            continue;
+                        }
+
+                        for list in 0..2 {
+                            let mut cand_mv = self.mvs[mv_row][mv_col][cand_list];
+                            if cand_ref == self.ref_frame[list] && ref_id_count[list] < 2 {
+                                ref_id_mvs[list][ref_id_count[list]] = cand_mv;
+                                ref_id_count[list] += 1;
+                            } else if ref_diff_count[list] < 2 {
+                                if self.ref_frame_sign_bias[cand_ref as usize]
+                                    != self.ref_frame_sign_bias[self.ref_frame[list] as usize]
+                                {
+                                    cand_mv[0] *= -1;
+                                    cand_mv[1] *= -1;
+                                }
+
+                                ref_diff_mvs[list][ref_diff_count[list]] = cand_mv;
+                                ref_diff_count[list] += 1;
+                            }
+                        }
+                    }
+                } else {
+                    for cand_list in 0..2 {
+                        let cand_ref = self.ref_frames[mv_row][mv_col][cand_list];
+                        if cand_ref <= Decoder::INTRA_FRAME {
+                            continue;
+                        }
+
+                        let mut cand_mv = self.mvs[mv_row][mv_col][cand_list];
+                        if self.ref_frame_sign_bias[cand_ref as usize]
+                            != self.ref_frame_sign_bias[self.ref_frame[0] as usize]
+                        {
+                            cand_mv[0] *= -1;
+                            cand_mv[1] *= -1;
+                        }
+
+                        let found = self.ref_stack_mv[..self.num_mv_found]
+                            .iter()
+                            .any(|mvs| mvs[0] == cand_mv);
+
+                        if !found {
+                            self.ref_stack_mv[self.num_mv_found][0] = cand_mv;
+                            self.weight_stack[self.num_mv_found] = 2;
+                            self.num_mv_found += 1;
+                        }
+                    }
+                }
+
+                let cand_size = self.mi_sizes[mv_row][mv_col] as usize;
+                idx += if pass == 0 {
+                    Decoder::NUM_4X4_BLOCKS_WIDE[cand_size] as i64
+                } else {
+                    Decoder::NUM_4X4_BLOCKS_HIGH[cand_size] as i64
+                };
+            }
+        }
+
+        if is_compound {
+            let mut combined_mvs = [[[0i64; 2]; 2]; 2];
+            for list in 0..2 {
+                let mut comp_count = 0;
+                for &mv in ref_id_mvs[list].iter().take(ref_id_count[list]) {
+                    combined_mvs[comp_count][list] = mv;
+                    comp_count += 1;
+                }
+
+                for &mv in ref_diff_mvs[list].iter().take(ref_diff_count[list]) {
+                    if comp_count >= 2 {
+                        break;
+                    }
+
+                    combined_mvs[comp_count][list] = mv;
+                    comp_count += 1;
+                }
+
+                while comp_count < 2 {
+                    combined_mvs[comp_count][list] = self.global_mvs[list];
+                    comp_count += 1;
+                }
+            }
+
+            if self.num_mv_found == 1 {
+                self.ref_stack_mv[1] = if combined_mvs[0] == self.ref_stack_mv[0] {
+                    combined_mvs[1]
+                } else {
+                    combined_mvs[0]
+                };
+                self.weight_stack[1] = 2;
+                self.num_mv_found += 1;
+            } else {
+                for mvs in combined_mvs {
+                    self.ref_stack_mv[self.num_mv_found] = mvs;
+                    self.weight_stack[self.num_mv_found] = 2;
+                    self.num_mv_found += 1;
+                }
+            }
+        } else {
+            for idx in self.num_mv_found..2 {
+                self.ref_stack_mv[idx][0] = self.global_mvs[0];
+            }
+        }
+    }
+
+    fn context_and_clamping(&mut self, is_compound: bool, num_new: u64) {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as i64;
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as i64;
+
+        for idx in 0..self.num_mv_found {
+            for list in 0..1 + is_compound as usize {
+                let ref_mv = self.ref_stack_mv[idx][list];
+                self.ref_stack_mv[idx][list] = [
+                    self.clamp_mv_row(ref_mv[0], Decoder::MV_BORDER + bh4 * 4 * 8),
+                    self.clamp_mv_col(ref_mv[1], Decoder::MV_BORDER + bw4 * 4 * 8),
+                ];
+            }
+        }
+
+        (self.new_mv_context, self.ref_mv_context) = match self.close_matches {
+            0 => (self.total_matches.min(1), self.total_matches),
+            1 => (3 - num_new.min(1), 2 + self.total_matches),
+            _ => (5 - num_new.min(1), 5),
+        };
+    }
+
+    fn clamp_mv_row(&self, mvec: i64, border: i64) -> i64 {
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as i64;
+        let mb_to_top_edge = -((self.mi_row as i64 * Decoder::MI_SIZE as i64) * 8);
+        let mb_to_bottom_edge =
+            ((self.mi_rows as i64 - bh4 - self.mi_row as i64) * Decoder::MI_SIZE as i64) * 8;
+        mvec.clamp(mb_to_top_edge - border, mb_to_bottom_edge + border)
+    }
+
+    fn clamp_mv_col(&self, mvec: i64, border: i64) -> i64 {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as i64;
+        let mb_to_left_edge = -((self.mi_col as i64 * Decoder::MI_SIZE as i64) * 8);
+        let mb_to_right_edge =
+            ((self.mi_cols as i64 - bw4 - self.mi_col as i64) * Decoder::MI_SIZE as i64) * 8;
+        mvec.clamp(mb_to_left_edge - border, mb_to_right_edge + border)
+    }
+}
+
+pub fn has_newmv(mode: YMode) -> bool {
+    matches!(
+        mode,
+        YMode::NewMv
+            | YMode::NewNewMv
+            | YMode::NearNewMv
+            | YMode::NewNearMv
+            | YMode::NearestNewMv
+            | YMode::NewNearestMv
+    )
+}
@@ -0,0 +1,193 @@
+use super::{block::SubSize, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#overlapped-motion-compensation-process
+
+impl Decoder {
+    const OBMC_MASK_2: [i64; 2] = [45, 64];
+    const OBMC_MASK_4: [i64; 4] = [39, 50, 59, 64];
+    const OBMC_MASK_8: [i64; 8] = [36, 42, 48, 53, 57, 61, 64, 64];
+    const OBMC_MASK_16: [i64; 16] = [
+        34, 37, 40, 43, 46, 49, 52, 54, 56, 58, 60, 61, 64, 64, 64, 64,
+    ];
+    const OBMC_MASK_32: [i64; 32] = [
+        33, 35, 36, 38, 40, 41, 43, 44, 45, 47, 48, 50, 51, 52, 53, 55, 56, 57, 58, 59, 60, 60, 61,
+        62, 64, 64, 64, 64, 64, 64, 64, 64,
+    ];
+
+    /// OBMC is only considered if at least one of the neighbours along the top or left edge
+    /// is inter predicted.
+    pub(super) fn has_overlappable_candidates(&self) -> bool {
+        let mi_row = self.mi_row as usize;
+        let mi_col = self.mi_col as usize;
+        let mi_rows = self.mi_rows as usize;
+        let mi_cols = self.mi_cols as usize;
+
+        if self.avail_u {
+            let w4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+            for x4 in (mi_col..mi_cols.min(mi_col + w4)).step_by(2) {
+                let x5 = (x4 | 1).min(mi_cols - 1);
+                if self.ref_frames[mi_row - 1][x5][0] > Decoder::INTRA_FRAME {
+                    return true;
+                }
+            }
+        }
+
+        if self.avail_l {
+            let h4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+            for y4 in (mi_row..mi_rows.min(mi_row + h4)).step_by(2) {
+                let y5 = (y4 | 1).min(mi_rows - 1);
+                if self.ref_frames[y5][mi_col - 1][0] > Decoder::INTRA_FRAME {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Blends the prediction of a w x h block with predictions made from the motion vectors
+    /// of up to four inter neighbours above it, then up to four to the left of it. Chroma
+    /// blocks smaller than 8x8 are only blended with their left neighbours.
+    pub(super) fn overlapped_motion_compensation(&mut self, plane: usize, w: usize, h: usize) {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as usize,
+                color_config.subsampling_y as usize,
+            )
+        } else {
+            (0, 0)
+        };
+
+        let mi_row = self.mi_row as usize;
+        let mi_col = self.mi_col as usize;
+        let mi_size = Decoder::MI_SIZE as usize;
+
+        if self.avail_u && self.get_plane_residual_size(self.mi_size, plane) >= SubSize::Block8x8 {
+            let w4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+            let n_limit = 4.min(Decoder::MI_WIDTH_LOG2[self.mi_size as usize]);
+            let mut n_count = 0;
+            let mut x4 = mi_col;
+
+            while n_count < n_limit && x4 < (self.mi_cols as usize).min(mi_col + w4) {
+                let cand_row = mi_row - 1;
+                let cand_col = x4 | 1;
+                let cand_sz = self.mi_sizes[cand_row][cand_col];
+                let step4 = Decoder::NUM_4X4_BLOCKS_WIDE[cand_sz as usize].clamp(2, 16);
+
+                if self.ref_frames[cand_row][cand_col][0] > Decoder::INTRA_FRAME {
+                    n_count += 1;
+                    let pred_w = w4.min(step4) * (mi_size >> sub_x);
+                    let pred_h = (h >> 1).min(32 >> sub_y);
+                    let mask = Decoder::get_obmc_mask(pred_h);
+                    self.predict_overlap(
+                        plane, cand_row, cand_col, x4, mi_row, pred_w, pred_h, 0, mask,
+                    );
+                }
+
+                x4 += step4;
+            }
+        }
+
+        if self.avail_l {
+            let h4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+            let n_limit = 4.min(Decoder::MI_HEIGHT_LOG2[self.mi_size as usize]);
+            let mut n_count = 0;
+            let mut y4 = mi_row;
+
+            while n_count < n_limit && y4 < (self.mi_rows as usize).min(mi_row + h4) {
+                let cand_col = mi_col - 1;
+                let cand_row = y4 | 1;
+                let cand_sz = self.mi_sizes[cand_row][cand_col];
+                let step4 = Decoder::NUM_4X4_BLOCKS_HIGH[cand_sz as usize].clamp(2, 16);
+
+                if self.ref_frames[cand_row][cand_col][0] > Decoder::INTRA_FRAME {
+                    n_count += 1;
+                    let pred_w = (w >> 1).min(32 >> sub_x);
+                    let pred_h = h4.min(step4) * (mi_size >> sub_y);
+                    let mask = Decoder::get_obmc_mask(pred_w);
+                    self.predict_overlap(
+                        plane, cand_row, cand_col, mi_col, y4, pred_w, pred_h, 1, mask,
+                    );
+                }
+
+                y4 += step4;
+            }
+        }
+    }
+
+    fn get_obmc_mask(length: usize) -> &'static [i64] {
+        match length {
+            2 => &Decoder::OBMC_MASK_2,
+            4 => &Decoder::OBMC_MASK_4,
+            8 => &Decoder::OBMC_MASK_8,
+            16 => &Decoder::OBMC_MASK_16,
+            32 => &Decoder::OBMC_MASK_32,
+            _ => panic!("invalid OBMC mask length: {length}"),
+        }
+    }
+
+    /// Predicts the overlapping area with the neighbour's motion vector and blends it into
+    /// CurrFrame. The mask runs away from the shared edge: down for pass 0, right for pass 1.
+    #[allow(clippy::too_many_arguments)]
+    fn predict_overlap(
+        &mut self,
+        plane: usize,
+        cand_row: usize,
+        cand_col: usize,
+        x4: usize,
+        y4: usize,
+        pred_w: usize,
+        pred_h: usize,
+        pass: usize,
+        mask: &[i64],
+    ) {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as usize,
+                color_config.subsampling_y as usize,
+            )
+        } else {
+            (0, 0)
+        };
+
+        let mv = self.mvs[cand_row][cand_col][0];
+        let ref_frame = self.ref_frames[cand_row][cand_col][0] as usize;
+        let store = &self.ref_frame_store[self.uh.ref_frame_idx[ref_frame - Decoder::LAST_FRAME]];
+        let pred_x = (x4 * 4) >> sub_x;
+        let pred_y = (y4 * 4) >> sub_y;
+
+        let (start_x, start_y, x_step, y_step) = self.motion_vector_scaling(
+            plane,
+            store.upscaled_width as i64,
+            store.frame_height as i64,
+            pred_x as i64,
+            pred_y as i64,
+            mv,
+        );
+        let obmc_pred = self.block_inter_prediction(
+            &store.frame[plane],
+            plane,
+            store.upscaled_width as i64,
+            store.frame_height as i64,
+            start_x,
+            start_y,
+            x_step,
+            y_step,
+            pred_w,
+            pred_h,
+            self.interp_filters[cand_row][cand_col],
+        );
+
+        for (i, row) in obmc_pred.iter().enumerate() {
+            for (j, &p) in row.iter().enumerate() {
+                let obmc_mask = if pass == 0 { mask[i] } else { mask[j] };
+                let obmc_pred = self.clip1(p.max(0) as u64) as i64;
+                let curr = &mut self.curr_frame[plane][pred_y + i][pred_x + j];
+                *curr = super::round2(obmc_mask * *curr as i64 + (64 - obmc_mask) * obmc_pred, 6)
+                    as u16;
+            }
+        }
+    }
+}
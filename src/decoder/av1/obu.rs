@@ -1,15 +1,26 @@
-use tracing::{info, warn};
+use std::borrow::Cow;
 
-use super::{BitDepth, BitStream, Decoder, NumPlanes};
+use tracing::info;
+
+use super::{
+    block::{InterpFilter, SubSize},
+    cicp::{ColorPrimaries, MatrixCoefficients, TransferCharacteristics},
+    frame::Stage,
+    BitDepth, BitStream, Decoder, NumPlanes,
+};
 
 #[derive(Debug, Clone)]
 pub enum ObuType {
     Reserved,
     SequenceHeader,
     TemporalDelimiter,
+    FrameHeader,
     TileGroup,
-    TileList,
+    Metadata,
     Frame,
+    RedundantFrameHeader,
+    TileList,
+    Padding,
 }
 
 impl Default for ObuType {
@@ -21,21 +32,54 @@ impl Default for ObuType {
 impl ObuType {
     fn new(val: u64) -> ObuType {
         match val {
-            0 => ObuType::Reserved,
+            0 | 9..=14 => ObuType::Reserved,
             1 => ObuType::SequenceHeader,
             2 => ObuType::TemporalDelimiter,
+            3 => ObuType::FrameHeader,
             4 => ObuType::TileGroup,
+            5 => ObuType::Metadata,
             6 => ObuType::Frame,
+            7 => ObuType::RedundantFrameHeader,
             8 => ObuType::TileList,
+            15 => ObuType::Padding,
             v => panic!("unknown obu type: {v}"),
         }
     }
 }
 
+// https://aomediacodec.github.io/av1-spec/#metadata-obu-semantics
+#[derive(Debug, Clone, Copy)]
+pub enum MetadataType {
+    Reserved,
+    HdrCll,
+    HdrMdcv,
+    Scalability,
+    ItutT35,
+    Timecode,
+    UnregisteredUserPrivate,
+}
+
+impl MetadataType {
+    fn new(val: u64) -> MetadataType {
+        match val {
+            1 => MetadataType::HdrCll,
+            2 => MetadataType::HdrMdcv,
+            3 => MetadataType::Scalability,
+            4 => MetadataType::ItutT35,
+            5 => MetadataType::Timecode,
+            6..=31 => MetadataType::UnregisteredUserPrivate,
+            _ => MetadataType::Reserved,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ObuHeader {
     pub obu_type: ObuType,
+    pub extension_flag: bool,
     pub has_size: bool,
+    pub temporal_id: u64,
+    pub spatial_id: u64,
 }
 
 impl ObuHeader {
@@ -48,16 +92,30 @@ impl ObuHeader {
         let has_size = b.f(1) != 0;
         let _reserved_bit = b.f(1);
 
-        if extension_flag {
-            todo!("parse extension header");
-        }
+        let (temporal_id, spatial_id) = if extension_flag {
+            let temporal_id = b.f(3);
+            let spatial_id = b.f(2);
+            let _extension_header_reserved_3bits = b.f(3);
+            (temporal_id, spatial_id)
+        } else {
+            (0, 0)
+        };
 
-        ObuHeader { obu_type, has_size }
+        ObuHeader {
+            obu_type,
+            extension_flag,
+            has_size,
+            temporal_id,
+            spatial_id,
+        }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Profile 0 is 8/10-bit 4:2:0 or monochrome, profile 1 adds 4:4:4 and profile 2 adds
+/// 4:2:2 and 12-bit.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
 pub enum SeqProfile {
+    #[default]
     Zero = 0,
     One = 1,
     Two = 2,
@@ -74,14 +132,10 @@ impl SeqProfile {
     }
 }
 
-#[derive(Debug)]
-pub struct TemporalDelimiter {
-    pub header: ObuHeader,
-}
-
 #[derive(Debug, Default)]
 pub struct SequenceHeader {
     pub header: ObuHeader,
+    pub seq_profile: SeqProfile,
     pub still_picture: bool,
     pub timing_info_present: bool,
     pub decoder_model_info_present: bool,
@@ -93,6 +147,8 @@ pub struct SequenceHeader {
     pub decoder_model_present_for_this_op: Vec<bool>,
     pub initial_display_delay_present_for_this_op: Vec<bool>,
     pub initial_display_delay: Vec<u64>,
+    pub frame_width_bits: u64,
+    pub frame_height_bits: u64,
     pub max_frame_width: u64,
     pub max_frame_height: u64,
     pub frame_id_numbers_present: bool,
@@ -119,10 +175,26 @@ pub struct SequenceHeader {
 #[derive(Debug, Default)]
 pub struct UncompressedHeader {
     pub show_existing_frame: bool,
+    pub frame_type: FrameType,
+    pub show_frame: bool,
+    pub showable_frame: bool,
+    pub error_resilient_mode: bool,
+    pub disable_cdf_update: bool,
+    pub allow_screen_content_tools: u64,
     pub force_integer_mv: u64,
     pub current_frame_id: u64,
+    pub frame_size_override: bool,
+    pub primary_ref_frame: u64,
+    pub refresh_frame_flags: u64,
+    pub ref_frame_idx: [usize; Decoder::REFS_PER_FRAME as usize],
     pub allow_high_precision_mv: bool,
+    pub interpolation_filter: InterpFilter,
+    pub is_motion_mode_switchable: bool,
     pub disabled_frame_end_update_cdf: bool,
+    pub segmentation_enabled: bool,
+    pub segmentation_update_map: bool,
+    pub segmentation_temporal_update: bool,
+    pub delta_q_present: bool,
     pub delta_q_res: u64,
     pub delta_lf_present: bool,
     pub delta_lf_res: u64,
@@ -131,9 +203,13 @@ pub struct UncompressedHeader {
     pub cdef_params: CdefParams,
     pub skip_mode_allowed: bool,
     pub skip_mode_present: bool,
+    pub reference_select: bool,
     pub reduced_tx_set: bool,
     pub allow_warped_motion: bool,
     pub quantization_params: QuantizationParams,
+    pub allow_intrabc: bool,
+    pub use_ref_frame_mvs: bool,
+    pub film_grain_params: FilmGrainParams,
 }
 
 impl Decoder {
@@ -151,15 +227,57 @@ impl Decoder {
 
         let obu_type = header.obu_type.clone();
 
+        // Operating point 0 is decoded. OBUs of layers it doesn't include are dropped.
+        let operating_point_idc = self
+            .sequence_header
+            .operating_point_idc
+            .first()
+            .copied()
+            .unwrap_or(0);
+        if !matches!(
+            obu_type,
+            ObuType::SequenceHeader | ObuType::TemporalDelimiter
+        ) && operating_point_idc != 0
+            && header.extension_flag
+        {
+            let in_temporal_layer = (operating_point_idc >> header.temporal_id) & 1 != 0;
+            let in_spatial_layer = (operating_point_idc >> (header.spatial_id + 8)) & 1 != 0;
+            if !in_temporal_layer || !in_spatial_layer {
+                info!(
+                    "dropping {obu_type:?} of temporal layer {} and spatial layer {}",
+                    header.temporal_id, header.spatial_id
+                );
+                b.pos = start_position + obu_size as usize * 8;
+                return;
+            }
+        }
+
         match obu_type {
             ObuType::SequenceHeader => {
                 let sh = self.sequence_header(b, header);
                 info!("{sh:?}");
                 self.sequence_header = sh;
             }
-            ObuType::TemporalDelimiter => b.seen_frame_header = false,
+            ObuType::TemporalDelimiter => self.seen_frame_header = false,
             ObuType::Frame => self.frame(b, obu_size as usize),
-            _ => panic!("obu type not implemented: {obu_type:?}"),
+            ObuType::FrameHeader | ObuType::TileGroup => {
+                panic!("obu type not implemented: {obu_type:?}")
+            }
+            // Nothing in these changes the decoded frames, so they are skipped whole.
+            ObuType::Metadata => {
+                let metadata_type = MetadataType::new(b.leb128());
+                info!("skipping metadata of type {metadata_type:?}");
+                b.pos = start_position + obu_size as usize * 8;
+                return;
+            }
+            ObuType::RedundantFrameHeader
+            | ObuType::TileList
+            | ObuType::Padding
+            | ObuType::Reserved => {
+                info!("skipping {obu_type:?}");
+                b.pos = start_position + obu_size as usize * 8;
+                return;
+            }
         };
 
         let current_position = b.pos;
@@ -167,7 +285,6 @@ impl Decoder {
 
         if obu_size > 0
             && !matches!(obu_type, ObuType::TileGroup)
-            && !matches!(obu_type, ObuType::TileList)
             && !matches!(obu_type, ObuType::Frame)
         {
             let mut nb_bits = obu_size * 8 - payload_bits as u64;
@@ -335,6 +452,8 @@ impl Decoder {
             decoder_model_present_for_this_op,
             initial_display_delay_present_for_this_op,
             initial_display_delay,
+            frame_width_bits,
+            frame_height_bits,
             max_frame_width,
             max_frame_height,
             frame_id_numbers_present,
@@ -354,6 +473,7 @@ impl Decoder {
             enable_cdef: b.f(1) != 0,
             enable_restoration: b.f(1) != 0,
             color_config: self.color_config(b, seq_profile),
+            seq_profile,
             film_grain_params_present: b.f(1) != 0,
             reduced_still_picture_header,
         }
@@ -409,24 +529,62 @@ impl Decoder {
             self.mi_row_start = self.mi_row_starts[tile_row as usize];
             self.mi_row_end = self.mi_row_starts[tile_row as usize + 1];
             self.mi_col_start = self.mi_col_starts[tile_col as usize];
-            self.mi_col_end = self.mi_row_starts[tile_col as usize + 1];
+            self.mi_col_end = self.mi_col_starts[tile_col as usize + 1];
             self.current_q_index = self.uh.quantization_params.base_q_idx;
 
             self.init_symbol(b, tile_size);
-            self.decode_tile(b)
+            self.decode_tile(b);
+            self.exit_symbol(b);
         }
 
-        todo!("tile_group");
+        if tg_end == self.num_tiles - 1 {
+            if !self.uh.disabled_frame_end_update_cdf {
+                self.frame_end_update_cdf();
+            }
+
+            self.decode_frame_wrapup();
+            self.seen_frame_header = false;
+        }
+    }
+
+    fn decode_frame_wrapup(&mut self) {
+        self.stage_frames.clear();
+        self.capture_stage(Stage::Prediction, Decoder::prediction_samples);
+        self.capture_stage(Stage::Residual, Decoder::residual_samples);
+        self.capture_stage(Stage::Reconstruction, |d| Cow::Borrowed(&d.curr_frame));
+
+        self.loop_filter_frame(0);
+        self.capture_stage(Stage::DeblockedVertical, |d| Cow::Borrowed(&d.curr_frame));
+        self.loop_filter_frame(1);
+        self.capture_stage(Stage::Deblocked, |d| Cow::Borrowed(&d.curr_frame));
+        self.cdef_frame();
+        self.capture_stage(Stage::Cdef, |d| Cow::Borrowed(&d.cdef_frame));
+
+        if self.use_superres {
+            self.curr_frame = self.upscaling(&self.curr_frame);
+            self.cdef_frame = self.upscaling(&self.cdef_frame);
+        }
+        self.capture_stage(Stage::Superres, |d| Cow::Borrowed(&d.cdef_frame));
+
+        self.lr_frame();
+        self.curr_frame = self.lr_frame.clone();
+        self.capture_stage(Stage::Restored, |d| Cow::Borrowed(&d.curr_frame));
+        self.motion_field_motion_vector_storage();
+        self.reference_frame_update();
+        self.log_frame_bits();
+
+        if self.uh.show_frame {
+            self.output_process();
+            let mut frame = self.to_frame(&self.output_frame);
+            frame.stages = std::mem::take(&mut self.stage_frames);
+            self.output_frames.push(frame);
+        }
     }
 
-    const FRAME_LF_COUNT: usize = 4;
+    pub const FRAME_LF_COUNT: usize = 4;
     const SGRPROJ_XQD_MID: [i64; 2] = [-32, 31];
     const WIENER_COEFFS: usize = 3;
     const WIENER_TAPS_MID: [i64; 3] = [3, -7, 15];
-    const BLOCK_SIZES: usize = 22;
-    const NUM_4X4_BLOCKS_WIDE: [usize; Decoder::BLOCK_SIZES] = [
-        1, 1, 2, 2, 2, 4, 4, 4, 8, 8, 8, 16, 16, 16, 32, 32, 1, 4, 2, 8, 4, 16,
-    ];
 
     fn decode_tile(&mut self, b: &mut BitStream) {
         self.clear_above_context();
@@ -460,32 +618,51 @@ impl Decoder {
 
         for r in (self.mi_row_start..self.mi_row_end).step_by(sb_size4) {
             self.clear_left_context();
-        }
 
-        todo!("decode_tile");
+            for c in (self.mi_col_start..self.mi_col_end).step_by(sb_size4) {
+                self.read_deltas = self.uh.delta_q_present;
+                self.clear_cdef(r as usize, c as usize);
+                self.clear_block_decoded_flags(r, c, sb_size4);
+                self.read_lr(b, r as usize, c as usize, sb_size);
+                self.decode_partition(b, r, c, sb_size);
+            }
+        }
     }
 
+    /// The contexts are indexed by plane and 4x4 position, with room for the transform
+    /// blocks of a superblock that crosses the right or bottom edge of the frame.
     fn clear_left_context(&mut self) {
-        self.left_level_context = vec![vec![0; 2]; self.mi_rows as usize - 1];
-        self.left_dc_context = vec![vec![0; 2]; self.mi_rows as usize - 1];
-        self.left_seg_pred_context = vec![0; self.mi_rows as usize - 1];
+        let rows = self.mi_rows as usize + 32;
+        self.left_level_context = vec![vec![0; rows]; 3];
+        self.left_dc_context = vec![vec![0; rows]; 3];
+        self.left_seg_pred_context = vec![0; rows];
     }
 
     fn clear_above_context(&mut self) {
-        self.above_level_context = vec![vec![0; 2]; self.mi_cols as usize - 1];
-        self.above_dc_context = vec![vec![0; 2]; self.mi_cols as usize - 1];
-        self.above_seg_pred_context = vec![0; self.mi_cols as usize - 1];
+        let cols = self.mi_cols as usize + 32;
+        self.above_level_context = vec![vec![0; cols]; 3];
+        self.above_dc_context = vec![vec![0; cols]; 3];
+        self.above_seg_pred_context = vec![0; cols];
     }
 
     fn init_symbol(&mut self, b: &mut BitStream, sz: usize) {
-        let num_bits = (sz * 8).min(15) as u64;
-        let buf = b.f(num_bits);
-        let padded_buf = buf << (15 - num_bits);
-        let _symbol_value = ((1 << 15) - 1) ^ padded_buf;
-        self.symbol_range = 1 << 15;
-        self.symbol_max_bits = 8 * sz as i64 - 15;
-
-        warn!("cdf copying not implemented");
+        self.symbol.init(b, sz, self.uh.disable_cdf_update);
+        self.cdf = self.frame_cdf.clone();
+    }
+
+    /// Skips the padding after the tile data and keeps the CDFs of the context update tile
+    /// for the end of the frame.
+    fn exit_symbol(&mut self, b: &mut BitStream) {
+        self.symbol.exit(b);
+
+        if self.tile_num == self.context_update_tile_id {
+            self.saved_cdf = self.cdf.clone();
+            self.saved_cdf.clear_counters();
+        }
+    }
+
+    fn frame_end_update_cdf(&mut self) {
+        self.frame_cdf = self.saved_cdf.clone();
     }
 
     fn frame_header(&mut self, b: &mut BitStream) {
@@ -495,17 +672,26 @@ impl Decoder {
             self.seen_frame_header = true;
             self.uh = self.uncompressed_header(b);
             info!("{:?}", self.uh);
+            info!(
+                "frame size: {}x{} coded, {}x{} upscaled",
+                self.frame_width, self.frame_height, self.upscaled_width, self.frame_height
+            );
 
             if self.uh.show_existing_frame {
                 todo!();
             } else {
                 self.tile_num = 0;
                 self.seen_frame_header = true;
+                self.init_block_info();
+
+                if self.uh.use_ref_frame_mvs {
+                    self.motion_field_estimation();
+                }
             }
         }
     }
 
-    const NUM_REF_FRAMES: u64 = 8;
+    pub const NUM_REF_FRAMES: u64 = 8;
     pub const REFS_PER_FRAME: u64 = 7;
     const PRIMARY_REF_NONE: u64 = 7;
 
@@ -565,11 +751,11 @@ impl Decoder {
         if matches!(frame_type, FrameType::Key) && show_frame {
             for i in 0..Decoder::NUM_REF_FRAMES {
                 self.ref_valid[i as usize] = false;
-                self.ref_order_hint[i as usize] = false;
+                self.ref_order_hint[i as usize] = 0;
             }
 
             for i in 0..Decoder::REFS_PER_FRAME {
-                self.order_hints[Decoder::LAST_FRAME + i as usize] = false;
+                self.order_hints[Decoder::LAST_FRAME + i as usize] = 0;
             }
         }
 
@@ -588,11 +774,14 @@ impl Decoder {
             } else {
                 self.sequence_header.seq_force_integer_mv
             }
-        } else if self.frame_is_intra {
-            1
         } else {
             0
         };
+        let force_integer_mv = if self.frame_is_intra {
+            1
+        } else {
+            force_integer_mv
+        };
 
         let current_frame_id = if self.sequence_header.frame_id_numbers_present {
             todo!("frame_id_numbers_present");
@@ -620,9 +809,12 @@ impl Decoder {
             todo!();
         }
 
-        let allow_high_precision_mv = false;
-        let use_ref_frame_mvs = false;
+        let mut allow_high_precision_mv = false;
+        let mut use_ref_frame_mvs = false;
         let mut allow_intrabc = false;
+        let mut ref_frame_idx = [0; Decoder::REFS_PER_FRAME as usize];
+        let mut interpolation_filter = InterpFilter::default();
+        let mut is_motion_mode_switchable = false;
 
         let refresh_frame_flags = if matches!(frame_type, FrameType::Switch)
             || (matches!(frame_type, FrameType::Key) && show_frame)
@@ -632,8 +824,16 @@ impl Decoder {
             b.f(8)
         };
 
-        if !self.frame_is_intra || refresh_frame_flags != all_frames {
-            todo!();
+        if (!self.frame_is_intra || refresh_frame_flags != all_frames)
+            && error_resilient_mode
+            && self.sequence_header.enable_order_hint
+        {
+            for i in 0..Decoder::NUM_REF_FRAMES as usize {
+                let ref_order_hint = b.f(self.order_hint_bits);
+                if ref_order_hint != self.ref_order_hint[i] || !self.ref_valid[i] {
+                    todo!("set up an invalid reference frame for error resilient mode");
+                }
+            }
         }
 
         if self.frame_is_intra {
@@ -644,7 +844,49 @@ impl Decoder {
                 allow_intrabc = b.f(1) != 0;
             }
         } else {
-            todo!();
+            let frame_refs_short_signaling = if self.sequence_header.enable_order_hint {
+                b.f(1) != 0
+            } else {
+                false
+            };
+
+            if frame_refs_short_signaling {
+                todo!("set_frame_refs()");
+            }
+
+            for idx in ref_frame_idx.iter_mut() {
+                *idx = b.f(3) as usize;
+            }
+
+            if frame_size_override && !error_resilient_mode {
+                self.frame_size_with_refs(b, &ref_frame_idx);
+            } else {
+                self.frame_size(b, frame_size_override);
+                self.render_size(b);
+            }
+
+            allow_high_precision_mv = if force_integer_mv != 0 {
+                false
+            } else {
+                b.f(1) != 0
+            };
+
+            interpolation_filter = Decoder::read_interpolation_filter(b);
+            is_motion_mode_switchable = b.f(1) != 0;
+            use_ref_frame_mvs =
+                if error_resilient_mode || !self.sequence_header.enable_ref_frame_mvs {
+                    false
+                } else {
+                    b.f(1) != 0
+                };
+
+            for (i, &idx) in ref_frame_idx.iter().enumerate() {
+                let ref_frame = Decoder::LAST_FRAME + i;
+                let hint = self.ref_order_hint[idx];
+                self.order_hints[ref_frame] = hint;
+                self.ref_frame_sign_bias[ref_frame] =
+                    self.get_relative_dist(hint, self.order_hint) > 0;
+            }
         }
 
         let disabled_frame_end_update_cdf =
@@ -655,19 +897,18 @@ impl Decoder {
             };
 
         if primary_ref_frame == Decoder::PRIMARY_REF_NONE {
-            warn!("init_non_coeff_cdfs() not implemented yet");
-            warn!("setup_past_independence() not implemented yet");
+            self.frame_cdf.init_non_coeff_cdfs();
+            self.setup_past_independence();
         } else {
-            todo!();
-        }
-
-        if use_ref_frame_mvs {
-            todo!();
+            let prev_frame = ref_frame_idx[primary_ref_frame as usize];
+            self.load_cdfs(prev_frame);
+            self.load_previous(prev_frame);
         }
 
         self.tile_info(b);
         let quantization_params = self.quantization_params(b);
-        let segmentation_enabled = self.segmentation_params(b);
+        let (segmentation_enabled, segmentation_update_map, segmentation_temporal_update) =
+            self.segmentation_params(b, primary_ref_frame);
 
         let delta_q_present = if quantization_params.base_q_idx > 0 {
             b.f(1) != 0
@@ -691,13 +932,16 @@ impl Decoder {
         }
 
         if primary_ref_frame == Decoder::PRIMARY_REF_NONE {
-            warn!("init_coeff_cdfs() not implemented");
+            self.frame_cdf
+                .init_coeff_cdfs(quantization_params.base_q_idx);
         } else {
-            todo!();
+            let prev_frame = ref_frame_idx[primary_ref_frame as usize];
+            self.load_previous_segment_ids(prev_frame, segmentation_enabled);
         }
 
         self.coded_lossless = true;
         self.lossless_array = vec![false; Decoder::MAX_SEGMENTS];
+        self.seg_qm_level = vec![vec![0; Decoder::MAX_SEGMENTS]; 3];
 
         for segment_id in 0..Decoder::MAX_SEGMENTS {
             let qindex = self.get_qindex(
@@ -720,7 +964,18 @@ impl Decoder {
             }
 
             if quantization_params.using_qmatrix {
-                todo!();
+                let qm_levels = if self.lossless_array[segment_id] {
+                    [Decoder::NUM_QM_LEVELS - 1; 3]
+                } else {
+                    [
+                        quantization_params.qm_y,
+                        quantization_params.qm_u,
+                        quantization_params.qm_v,
+                    ]
+                };
+                for (plane, qm_level) in qm_levels.into_iter().enumerate() {
+                    self.seg_qm_level[plane][segment_id] = qm_level;
+                }
             }
         }
 
@@ -735,7 +990,8 @@ impl Decoder {
             b.f(1) != 0
         };
 
-        let (skip_mode_allowed, skip_mode_present) = self.skip_mode_params(b, reference_select);
+        let (skip_mode_allowed, skip_mode_present) =
+            self.skip_mode_params(b, reference_select, &ref_frame_idx);
         let allow_warped_motion = if self.frame_is_intra
             || error_resilient_mode
             || !self.sequence_header.enable_warped_motion
@@ -745,15 +1001,32 @@ impl Decoder {
             b.f(1) != 0
         };
         let reduced_tx_set = b.f(1) != 0;
-        self.global_motion_params();
-        self.film_grain_params(show_frame, showable_frame);
+        self.global_motion_params(b, allow_high_precision_mv);
+        let film_grain_params =
+            self.film_grain_params(b, frame_type, show_frame, showable_frame, &ref_frame_idx);
 
         UncompressedHeader {
             show_existing_frame,
+            frame_type,
+            show_frame,
+            showable_frame,
+            error_resilient_mode,
+            disable_cdf_update,
+            allow_screen_content_tools,
             force_integer_mv,
             current_frame_id,
+            frame_size_override,
+            primary_ref_frame,
+            refresh_frame_flags,
+            ref_frame_idx,
             allow_high_precision_mv,
+            interpolation_filter,
+            is_motion_mode_switchable,
             disabled_frame_end_update_cdf,
+            segmentation_enabled,
+            segmentation_update_map,
+            segmentation_temporal_update,
+            delta_q_present,
             delta_q_res,
             delta_lf_present,
             delta_lf_res,
@@ -762,36 +1035,169 @@ impl Decoder {
             cdef_params,
             skip_mode_allowed,
             skip_mode_present,
+            reference_select,
             reduced_tx_set,
             allow_warped_motion,
             quantization_params,
+            allow_intrabc,
+            use_ref_frame_mvs,
+            film_grain_params,
         }
     }
 
-    fn film_grain_params(&self, show_frame: bool, showable_frame: bool) {
+    /// Resets the state that is otherwise loaded from the primary reference frame.
+    fn setup_past_independence(&mut self) {
+        self.feature_enabled = vec![vec![false; Decoder::SEG_LVL_MAX]; Decoder::MAX_SEGMENTS];
+        self.feature_data = vec![vec![0; Decoder::SEG_LVL_MAX]; Decoder::MAX_SEGMENTS];
+        self.loop_filter_ref_deltas = Decoder::DEFAULT_LOOP_FILTER_REF_DELTAS;
+        self.loop_filter_mode_deltas = [0; 2];
+        let one = 1 << Decoder::WARPEDMODEL_PREC_BITS;
+        self.prev_gm_params = vec![vec![0, 0, one, 0, 0, one]; 8];
+        self.prev_segment_ids = vec![vec![0; self.mi_cols as usize]; self.mi_rows as usize];
+    }
+
+    fn film_grain_params(
+        &self,
+        b: &mut BitStream,
+        frame_type: FrameType,
+        show_frame: bool,
+        showable_frame: bool,
+        ref_frame_idx: &[usize; Decoder::REFS_PER_FRAME as usize],
+    ) -> FilmGrainParams {
         if !self.sequence_header.film_grain_params_present || (!show_frame && !showable_frame) {
-            warn!("reset_grain_params() is not implemented");
-            return;
+            return FilmGrainParams::default();
+        }
+
+        let apply_grain = b.f(1) != 0;
+        if !apply_grain {
+            return FilmGrainParams::default();
         }
 
-        todo!();
+        let grain_seed = b.f(16);
+        let update_grain = if frame_type == FrameType::Inter {
+            b.f(1) != 0
+        } else {
+            true
+        };
+
+        if !update_grain {
+            let film_grain_params_ref_idx = b.f(3) as usize;
+            assert!(
+                ref_frame_idx.contains(&film_grain_params_ref_idx),
+                "film_grain_params_ref_idx {film_grain_params_ref_idx} is not a reference of the frame"
+            );
+            let temp_grain_seed = grain_seed;
+            let mut params = self.load_grain_params(film_grain_params_ref_idx);
+            params.grain_seed = temp_grain_seed;
+            return params;
+        }
+
+        let read_points = |b: &mut BitStream| {
+            let num_points = b.f(4) as usize;
+            (0..num_points).map(|_| (b.f(8), b.f(8))).unzip()
+        };
+
+        let (point_y_value, point_y_scaling): (Vec<u64>, Vec<u64>) = read_points(b);
+
+        let mono_chrome = matches!(self.num_planes, NumPlanes::One);
+        let chroma_scaling_from_luma = if mono_chrome { false } else { b.f(1) != 0 };
+
+        let color_config = &self.sequence_header.color_config;
+        let (point_cb_value, point_cb_scaling, point_cr_value, point_cr_scaling) = if mono_chrome
+            || chroma_scaling_from_luma
+            || (color_config.subsampling_x
+                && color_config.subsampling_y
+                && point_y_value.is_empty())
+        {
+            (vec![], vec![], vec![], vec![])
+        } else {
+            let (cb_value, cb_scaling) = read_points(b);
+            let (cr_value, cr_scaling) = read_points(b);
+            (cb_value, cb_scaling, cr_value, cr_scaling)
+        };
+
+        let grain_scaling_minus_8 = b.f(2);
+        let ar_coeff_lag = b.f(2);
+        let num_pos_luma = 2 * ar_coeff_lag * (ar_coeff_lag + 1);
+        let (ar_coeffs_y_plus_128, num_pos_chroma) = if !point_y_value.is_empty() {
+            (
+                (0..num_pos_luma).map(|_| b.f(8)).collect(),
+                num_pos_luma + 1,
+            )
+        } else {
+            (vec![], num_pos_luma)
+        };
+
+        let ar_coeffs_cb_plus_128 = if chroma_scaling_from_luma || !point_cb_value.is_empty() {
+            (0..num_pos_chroma).map(|_| b.f(8)).collect()
+        } else {
+            vec![]
+        };
+        let ar_coeffs_cr_plus_128 = if chroma_scaling_from_luma || !point_cr_value.is_empty() {
+            (0..num_pos_chroma).map(|_| b.f(8)).collect()
+        } else {
+            vec![]
+        };
+
+        let ar_coeff_shift_minus_6 = b.f(2);
+        let grain_scale_shift = b.f(2);
+
+        let (cb_mult, cb_luma_mult, cb_offset) = if !point_cb_value.is_empty() {
+            (b.f(8), b.f(8), b.f(9))
+        } else {
+            (0, 0, 0)
+        };
+        let (cr_mult, cr_luma_mult, cr_offset) = if !point_cr_value.is_empty() {
+            (b.f(8), b.f(8), b.f(9))
+        } else {
+            (0, 0, 0)
+        };
+
+        FilmGrainParams {
+            apply_grain,
+            grain_seed,
+            point_y_value,
+            point_y_scaling,
+            chroma_scaling_from_luma,
+            point_cb_value,
+            point_cb_scaling,
+            point_cr_value,
+            point_cr_scaling,
+            grain_scaling_minus_8,
+            ar_coeff_lag,
+            ar_coeffs_y_plus_128,
+            ar_coeffs_cb_plus_128,
+            ar_coeffs_cr_plus_128,
+            ar_coeff_shift_minus_6,
+            grain_scale_shift,
+            cb_mult,
+            cb_luma_mult,
+            cb_offset,
+            cr_mult,
+            cr_luma_mult,
+            cr_offset,
+            overlap_flag: b.f(1) != 0,
+            clip_to_restricted_range: b.f(1) != 0,
+        }
     }
 
     pub const LAST_FRAME: usize = 1;
-    const ALTREF_FRAME: usize = 7;
-    const WARPEDMODEL_PREC_BITS: u64 = 16;
-
-    fn global_motion_params(&mut self) {
-        let mut gm_params = vec![vec![0u64; 6]; 8];
-        for (r, param) in gm_params
-            .iter_mut()
-            .enumerate()
-            .take(Decoder::ALTREF_FRAME + 1)
-            .skip(Decoder::LAST_FRAME)
-        {
+    pub const ALTREF_FRAME: usize = 7;
+    pub const WARPEDMODEL_PREC_BITS: u64 = 16;
+
+    const GM_ABS_TRANS_BITS: u64 = 12;
+    const GM_ABS_TRANS_ONLY_BITS: u64 = 9;
+    const GM_ABS_ALPHA_BITS: u64 = 12;
+    const GM_ALPHA_PREC_BITS: u64 = 15;
+    const GM_TRANS_PREC_BITS: u64 = 6;
+    const GM_TRANS_ONLY_PREC_BITS: u64 = 3;
+
+    fn global_motion_params(&mut self, b: &mut BitStream, allow_high_precision_mv: bool) {
+        self.gm_params = vec![vec![0; 6]; 8];
+        for r in Decoder::LAST_FRAME..=Decoder::ALTREF_FRAME {
             self.gm_type[r] = WarpModel::Identity;
 
-            for (i, p) in param.iter_mut().enumerate().take(6) {
+            for (i, p) in self.gm_params[r].iter_mut().enumerate() {
                 *p = if i % 3 == 2 {
                     1 << Decoder::WARPEDMODEL_PREC_BITS
                 } else {
@@ -799,16 +1205,168 @@ impl Decoder {
                 };
             }
         }
+
+        if self.frame_is_intra {
+            return;
+        }
+
+        for r in Decoder::LAST_FRAME..=Decoder::ALTREF_FRAME {
+            let is_global = b.f(1) != 0;
+            let typ = if !is_global {
+                WarpModel::Identity
+            } else if b.f(1) != 0 {
+                WarpModel::Rotzoom
+            } else if b.f(1) != 0 {
+                WarpModel::Translation
+            } else {
+                WarpModel::Affine
+            };
+            self.gm_type[r] = typ;
+
+            if typ >= WarpModel::Rotzoom {
+                self.read_global_param(b, typ, r, 2, allow_high_precision_mv);
+                self.read_global_param(b, typ, r, 3, allow_high_precision_mv);
+                if typ == WarpModel::Affine {
+                    self.read_global_param(b, typ, r, 4, allow_high_precision_mv);
+                    self.read_global_param(b, typ, r, 5, allow_high_precision_mv);
+                } else {
+                    self.gm_params[r][4] = -self.gm_params[r][3];
+                    self.gm_params[r][5] = self.gm_params[r][2];
+                }
+            }
+
+            if typ >= WarpModel::Translation {
+                self.read_global_param(b, typ, r, 0, allow_high_precision_mv);
+                self.read_global_param(b, typ, r, 1, allow_high_precision_mv);
+            }
+        }
+    }
+
+    fn read_global_param(
+        &mut self,
+        b: &mut BitStream,
+        typ: WarpModel,
+        r: usize,
+        idx: usize,
+        allow_high_precision_mv: bool,
+    ) {
+        let (abs_bits, prec_bits) = if idx >= 2 {
+            (Decoder::GM_ABS_ALPHA_BITS, Decoder::GM_ALPHA_PREC_BITS)
+        } else if typ == WarpModel::Translation {
+            let low_precision = !allow_high_precision_mv as u64;
+            (
+                Decoder::GM_ABS_TRANS_ONLY_BITS - low_precision,
+                Decoder::GM_TRANS_ONLY_PREC_BITS - low_precision,
+            )
+        } else {
+            (Decoder::GM_ABS_TRANS_BITS, Decoder::GM_TRANS_PREC_BITS)
+        };
+
+        let prec_diff = Decoder::WARPEDMODEL_PREC_BITS - prec_bits;
+        let (round, sub) = if idx % 3 == 2 {
+            (1 << Decoder::WARPEDMODEL_PREC_BITS, 1 << prec_bits)
+        } else {
+            (0, 0)
+        };
+        let mx = 1 << abs_bits;
+        let r_ref = (self.prev_gm_params[r][idx] >> prec_diff) - sub;
+        self.gm_params[r][idx] =
+            (Decoder::decode_signed_subexp_with_ref(b, -mx, mx + 1, r_ref) << prec_diff) + round;
+    }
+
+    fn decode_signed_subexp_with_ref(b: &mut BitStream, low: i64, high: i64, r: i64) -> i64 {
+        let x = Decoder::decode_unsigned_subexp_with_ref(b, (high - low) as u64, (r - low) as u64);
+        x as i64 + low
+    }
+
+    fn decode_unsigned_subexp_with_ref(b: &mut BitStream, mx: u64, r: u64) -> u64 {
+        let v = Decoder::decode_subexp(b, mx);
+        if (r << 1) <= mx {
+            Decoder::inverse_recenter(r, v)
+        } else {
+            mx - 1 - Decoder::inverse_recenter(mx - 1 - r, v)
+        }
     }
 
-    fn skip_mode_params(&self, b: &mut BitStream, reference_select: bool) -> (bool, bool) {
+    fn decode_subexp(b: &mut BitStream, num_syms: u64) -> u64 {
+        let mut i = 0;
+        let mut mk = 0;
+        let k = 3;
+
+        loop {
+            let b2 = if i != 0 { k + i - 1 } else { k };
+            let a = 1 << b2;
+
+            if num_syms <= mk + 3 * a {
+                return b.ns(num_syms - mk) + mk;
+            }
+
+            if b.f(1) != 0 {
+                i += 1;
+                mk += a;
+            } else {
+                return b.f(b2) + mk;
+            }
+        }
+    }
+
+    /// Returns skip_mode_allowed and skip_mode_present, and sets SkipModeFrame to the two
+    /// closest references.
+    fn skip_mode_params(
+        &mut self,
+        b: &mut BitStream,
+        reference_select: bool,
+        ref_frame_idx: &[usize; Decoder::REFS_PER_FRAME as usize],
+    ) -> (bool, bool) {
         let skip_mode_allowed = if self.frame_is_intra
             || !reference_select
             || !self.sequence_header.enable_order_hint
         {
             false
         } else {
-            todo!();
+            let mut forward: Option<(usize, u64)> = None;
+            let mut backward: Option<(usize, u64)> = None;
+            for (i, &idx) in ref_frame_idx.iter().enumerate() {
+                let ref_hint = self.ref_order_hint[idx];
+                if self.get_relative_dist(ref_hint, self.order_hint) < 0 {
+                    if forward.is_none_or(|(_, hint)| self.get_relative_dist(ref_hint, hint) > 0) {
+                        forward = Some((i, ref_hint));
+                    }
+                } else if self.get_relative_dist(ref_hint, self.order_hint) > 0
+                    && backward.is_none_or(|(_, hint)| self.get_relative_dist(ref_hint, hint) < 0)
+                {
+                    backward = Some((i, ref_hint));
+                }
+            }
+
+            let second = match (forward, backward) {
+                (None, _) => None,
+                (Some(_), Some((backward_idx, _))) => Some(backward_idx),
+                (Some((_, forward_hint)), None) => {
+                    let mut second_forward: Option<(usize, u64)> = None;
+                    for (i, &idx) in ref_frame_idx.iter().enumerate() {
+                        let ref_hint = self.ref_order_hint[idx];
+                        if self.get_relative_dist(ref_hint, forward_hint) < 0
+                            && second_forward
+                                .is_none_or(|(_, hint)| self.get_relative_dist(ref_hint, hint) > 0)
+                        {
+                            second_forward = Some((i, ref_hint));
+                        }
+                    }
+
+                    second_forward.map(|(i, _)| i)
+                }
+            };
+
+            if let (Some((forward_idx, _)), Some(second_idx)) = (forward, second) {
+                self.skip_mode_frame = [
+                    (Decoder::LAST_FRAME + forward_idx.min(second_idx)) as i64,
+                    (Decoder::LAST_FRAME + forward_idx.max(second_idx)) as i64,
+                ];
+                true
+            } else {
+                false
+            }
         };
 
         if skip_mode_allowed {
@@ -828,20 +1386,63 @@ impl Decoder {
         }
     }
 
-    const RESTORE_NONE: u64 = 0;
+    const REMAP_LR_TYPE: [u64; 4] = [
+        Decoder::RESTORE_NONE,
+        Decoder::RESTORE_SWITCHABLE,
+        Decoder::RESTORE_WIENER,
+        Decoder::RESTORE_SGRPROJ,
+    ];
+    const RESTORATION_TILESIZE_MAX: u64 = 256;
 
-    fn lr_params(&mut self, _b: &mut BitStream, allow_intrabc: bool) {
+    fn lr_params(&mut self, b: &mut BitStream, allow_intrabc: bool) {
         if self.all_lossless || allow_intrabc || !self.sequence_header.enable_restoration {
             self.frame_restoration_type = vec![Decoder::RESTORE_NONE; 3];
             self.uses_lr = false;
             return;
         }
 
-        todo!();
+        self.frame_restoration_type = vec![Decoder::RESTORE_NONE; 3];
+        self.uses_lr = false;
+        let mut uses_chroma_lr = false;
+        for i in 0..self.num_planes as usize {
+            let lr_type = b.f(2);
+            self.frame_restoration_type[i] = Decoder::REMAP_LR_TYPE[lr_type as usize];
+            if self.frame_restoration_type[i] != Decoder::RESTORE_NONE {
+                self.uses_lr = true;
+                if i > 0 {
+                    uses_chroma_lr = true;
+                }
+            }
+        }
+
+        if self.uses_lr {
+            let mut lr_unit_shift = b.f(1);
+            if self.sequence_header.use_128x128_superblock {
+                lr_unit_shift += 1;
+            } else if lr_unit_shift != 0 {
+                lr_unit_shift += b.f(1);
+            }
+
+            self.loop_restoration_size[0] =
+                Decoder::RESTORATION_TILESIZE_MAX >> (2 - lr_unit_shift);
+
+            let color_config = &self.sequence_header.color_config;
+            let lr_uv_shift =
+                if color_config.subsampling_x && color_config.subsampling_y && uses_chroma_lr {
+                    b.f(1)
+                } else {
+                    0
+                };
+
+            self.loop_restoration_size[1] = self.loop_restoration_size[0] >> lr_uv_shift;
+            self.loop_restoration_size[2] = self.loop_restoration_size[0] >> lr_uv_shift;
+
+            self.init_restoration_units();
+        }
     }
 
-    fn cdef_params(&mut self, _b: &mut BitStream, allow_intrabc: bool) -> CdefParams {
-        if self.coded_lossless || allow_intrabc || self.sequence_header.enable_cdef {
+    fn cdef_params(&mut self, b: &mut BitStream, allow_intrabc: bool) -> CdefParams {
+        if self.coded_lossless || allow_intrabc || !self.sequence_header.enable_cdef {
             self.cdef_damping = 3;
             return CdefParams {
                 cdef_bits: 0,
@@ -852,14 +1453,51 @@ impl Decoder {
             };
         }
 
-        todo!();
+        self.cdef_damping = b.f(2) + 3;
+        let cdef_bits = b.f(2);
+
+        let count = 1 << cdef_bits;
+        let mut cdef_y_pri_strength = vec![0; count];
+        let mut cdef_y_sec_strength = vec![0; count];
+        let mut cdef_uv_pri_strength = vec![0; count];
+        let mut cdef_uv_sec_strength = vec![0; count];
+
+        for i in 0..count {
+            cdef_y_pri_strength[i] = b.f(4);
+            cdef_y_sec_strength[i] = b.f(2);
+            if cdef_y_sec_strength[i] == 3 {
+                cdef_y_sec_strength[i] += 1;
+            }
+
+            if matches!(self.num_planes, NumPlanes::Three) {
+                cdef_uv_pri_strength[i] = b.f(4);
+                cdef_uv_sec_strength[i] = b.f(2);
+                if cdef_uv_sec_strength[i] == 3 {
+                    cdef_uv_sec_strength[i] += 1;
+                }
+            }
+        }
+
+        CdefParams {
+            cdef_bits,
+            cdef_y_pri_strength,
+            cdef_y_sec_strength,
+            cdef_uv_pri_strength,
+            cdef_uv_sec_strength,
+        }
     }
 
-    fn loop_filter_params(&self, b: &mut BitStream, allow_intrabc: bool) -> LoopFilterParams {
+    /// loop_filter_ref_deltas for INTRA_FRAME, LAST_FRAME ... ALTREF_FRAME.
+    const DEFAULT_LOOP_FILTER_REF_DELTAS: [i64; 8] = [1, 0, 0, 0, -1, 0, -1, -1];
+
+    fn loop_filter_params(&mut self, b: &mut BitStream, allow_intrabc: bool) -> LoopFilterParams {
         let mut loop_filter_level = [0u64; 4];
 
         if self.coded_lossless || allow_intrabc {
-            todo!();
+            self.loop_filter_ref_deltas = Decoder::DEFAULT_LOOP_FILTER_REF_DELTAS;
+            self.loop_filter_mode_deltas = [0; 2];
+
+            return LoopFilterParams::default();
         }
 
         loop_filter_level[0] = b.f(6);
@@ -876,7 +1514,20 @@ impl Decoder {
         let loop_filter_delta_enabled = b.f(1) != 0;
 
         if loop_filter_delta_enabled {
-            todo!();
+            let loop_filter_delta_update = b.f(1) != 0;
+            if loop_filter_delta_update {
+                for delta in self.loop_filter_ref_deltas.iter_mut() {
+                    if b.f(1) != 0 {
+                        *delta = b.su(7);
+                    }
+                }
+
+                for delta in self.loop_filter_mode_deltas.iter_mut() {
+                    if b.f(1) != 0 {
+                        *delta = b.su(7);
+                    }
+                }
+            }
         }
 
         LoopFilterParams {
@@ -886,7 +1537,7 @@ impl Decoder {
         }
     }
 
-    fn get_qindex(
+    pub(super) fn get_qindex(
         &self,
         ignore_delta_q: bool,
         segment_id: usize,
@@ -895,7 +1546,13 @@ impl Decoder {
         base_q_idx: u64,
     ) -> u64 {
         if segmentation_enabled && self.feature_enabled[segment_id][Decoder::SEG_LVL_ALT_Q] {
-            todo!();
+            let data = self.feature_data[segment_id][Decoder::SEG_LVL_ALT_Q];
+            let qindex = if !ignore_delta_q && delta_q_present {
+                self.current_q_index as i64 + data
+            } else {
+                base_q_idx as i64 + data
+            };
+            qindex.clamp(0, 255) as u64
         } else if !ignore_delta_q && delta_q_present {
             self.current_q_index
         } else {
@@ -905,13 +1562,69 @@ impl Decoder {
 
     const MAX_SEGMENTS: usize = 8;
     const SEG_LVL_MAX: usize = 8;
-    const SEG_LVL_REF_FRAME: usize = 5;
+    pub const SEG_LVL_REF_FRAME: usize = 5;
+    pub const SEG_LVL_SKIP: usize = 6;
+    pub const SEG_LVL_GLOBALMV: usize = 7;
     const SEG_LVL_ALT_Q: usize = 0;
 
-    fn segmentation_params(&mut self, b: &mut BitStream) -> bool {
+    const SEGMENTATION_FEATURE_BITS: [u64; Decoder::SEG_LVL_MAX] = [8, 6, 6, 6, 6, 3, 0, 0];
+    const SEGMENTATION_FEATURE_SIGNED: [bool; Decoder::SEG_LVL_MAX] =
+        [true, true, true, true, true, false, false, false];
+    const SEGMENTATION_FEATURE_MAX: [i64; Decoder::SEG_LVL_MAX] = [
+        255,
+        Decoder::MAX_LOOP_FILTER,
+        Decoder::MAX_LOOP_FILTER,
+        Decoder::MAX_LOOP_FILTER,
+        Decoder::MAX_LOOP_FILTER,
+        7,
+        0,
+        0,
+    ];
+
+    /// Returns segmentation_enabled, segmentation_update_map and
+    /// segmentation_temporal_update.
+    fn segmentation_params(
+        &mut self,
+        b: &mut BitStream,
+        primary_ref_frame: u64,
+    ) -> (bool, bool, bool) {
         let segmentation_enabled = b.f(1) != 0;
+        let mut segmentation_update_map = false;
+        let mut segmentation_temporal_update = false;
+
         if segmentation_enabled {
-            todo!();
+            let segmentation_update_data = if primary_ref_frame == Decoder::PRIMARY_REF_NONE {
+                segmentation_update_map = true;
+                true
+            } else {
+                segmentation_update_map = b.f(1) != 0;
+                if segmentation_update_map {
+                    segmentation_temporal_update = b.f(1) != 0;
+                }
+                b.f(1) != 0
+            };
+
+            if segmentation_update_data {
+                for i in 0..Decoder::MAX_SEGMENTS {
+                    for j in 0..Decoder::SEG_LVL_MAX {
+                        let feature_enabled = b.f(1) != 0;
+                        self.feature_enabled[i][j] = feature_enabled;
+
+                        let mut clipped_value = 0;
+                        if feature_enabled {
+                            let bits_to_read = Decoder::SEGMENTATION_FEATURE_BITS[j];
+                            let limit = Decoder::SEGMENTATION_FEATURE_MAX[j];
+                            clipped_value = if Decoder::SEGMENTATION_FEATURE_SIGNED[j] {
+                                b.su(1 + bits_to_read).clamp(-limit, limit)
+                            } else {
+                                (b.f(bits_to_read) as i64).clamp(0, limit)
+                            };
+                        }
+
+                        self.feature_data[i][j] = clipped_value;
+                    }
+                }
+            }
         } else {
             self.feature_enabled = vec![vec![false; Decoder::SEG_LVL_MAX]; Decoder::MAX_SEGMENTS];
             self.feature_data = vec![vec![0; Decoder::SEG_LVL_MAX]; Decoder::MAX_SEGMENTS];
@@ -937,7 +1650,11 @@ impl Decoder {
             }
         }
 
-        segmentation_enabled
+        (
+            segmentation_enabled,
+            segmentation_update_map,
+            segmentation_temporal_update,
+        )
     }
 
     fn quantization_params(&mut self, b: &mut BitStream) -> QuantizationParams {
@@ -957,6 +1674,7 @@ impl Decoder {
             if diff_uv_delta {
                 self.deltaq_vdc = Decoder::read_delta_q(b);
                 self.deltaq_vac = Decoder::read_delta_q(b);
+            } else {
                 self.deltaq_vdc = self.deltaq_udc;
                 self.deltaq_vac = self.deltaq_uac;
             }
@@ -986,7 +1704,7 @@ impl Decoder {
             }
         } else {
             QuantizationParams {
-                base_q_idx: 0,
+                base_q_idx,
                 qm_y: 0,
                 qm_u: 0,
                 qm_v: 0,
@@ -1005,7 +1723,8 @@ impl Decoder {
 
     fn frame_size(&mut self, b: &mut BitStream, frame_size_override: bool) {
         if frame_size_override {
-            todo!();
+            self.frame_width = b.f(self.sequence_header.frame_width_bits) + 1;
+            self.frame_height = b.f(self.sequence_header.frame_height_bits) + 1;
         } else {
             self.frame_width = self.sequence_header.max_frame_width;
             self.frame_height = self.sequence_header.max_frame_height;
@@ -1015,18 +1734,52 @@ impl Decoder {
         self.compute_image_size();
     }
 
+    /// Takes the frame size from the first reference frame that signals found_ref. Frames
+    /// coded at a different size than their references use scaled motion compensation.
+    fn frame_size_with_refs(
+        &mut self,
+        b: &mut BitStream,
+        ref_frame_idx: &[usize; Decoder::REFS_PER_FRAME as usize],
+    ) {
+        let found_ref = ref_frame_idx.iter().find(|_| b.f(1) != 0);
+
+        if let Some(&idx) = found_ref {
+            let ref_frame = &self.ref_frame_store[idx];
+            self.upscaled_width = ref_frame.upscaled_width;
+            self.frame_width = self.upscaled_width;
+            self.frame_height = ref_frame.frame_height;
+            self.render_width = ref_frame.render_width;
+            self.render_height = ref_frame.render_height;
+
+            self.superres_params(b);
+            self.compute_image_size();
+        } else {
+            self.frame_size(b, true);
+            self.render_size(b);
+        }
+    }
+
+    fn read_interpolation_filter(b: &mut BitStream) -> InterpFilter {
+        let is_filter_switchable = b.f(1) != 0;
+        if is_filter_switchable {
+            InterpFilter::Switchable
+        } else {
+            InterpFilter::new(b.f(2))
+        }
+    }
+
     const SUPERRES_DENOM_BITS: u64 = 3;
     const SUPERRES_DENOM_MIN: u64 = 9;
-    const SUPERRES_NUM: u64 = 8;
+    pub const SUPERRES_NUM: u64 = 8;
 
     fn superres_params(&mut self, b: &mut BitStream) {
-        let use_superres = if self.sequence_header.enable_superres {
+        self.use_superres = if self.sequence_header.enable_superres {
             b.f(1) != 0
         } else {
             false
         };
 
-        self.superres_denom = if use_superres {
+        self.superres_denom = if self.use_superres {
             b.f(Decoder::SUPERRES_DENOM_BITS) + Decoder::SUPERRES_DENOM_MIN
         } else {
             Decoder::SUPERRES_NUM
@@ -1078,7 +1831,7 @@ impl Decoder {
         if uniform_tile_spacing {
             self.tile_cols_log2 = min_log2_tile_cols;
 
-            while self.tile_cols_log2 >= max_log2_tile_cols {
+            while self.tile_cols_log2 < max_log2_tile_cols {
                 if b.f(1) != 0 {
                     self.tile_cols_log2 += 1;
                 } else {
@@ -1088,7 +1841,7 @@ impl Decoder {
 
             let tile_width_sb = (sb_cols + (1 << self.tile_cols_log2) - 1) >> self.tile_cols_log2;
             let mut i = 0;
-            self.mi_col_starts = vec![0; sb_cols as usize];
+            self.mi_col_starts = vec![0; sb_cols as usize + 1];
             for start_sb in (0..sb_cols).step_by(tile_width_sb as usize) {
                 self.mi_col_starts[i] = start_sb << sb_shift;
                 i += 1;
@@ -1096,7 +1849,7 @@ impl Decoder {
             self.mi_col_starts[i] = self.mi_cols;
             self.tile_cols = i as u64;
 
-            self.tile_rows_log2 = 0.max(min_log2_tiles - self.tile_cols_log2);
+            self.tile_rows_log2 = min_log2_tiles.saturating_sub(self.tile_cols_log2);
             while self.tile_rows_log2 < max_log2_tile_rows {
                 if b.f(1) != 0 {
                     self.tile_rows_log2 += 1;
@@ -1107,7 +1860,7 @@ impl Decoder {
 
             let tile_height_sb = (sb_rows + (1 << self.tile_rows_log2) - 1) >> self.tile_rows_log2;
             let mut i = 0;
-            self.mi_row_starts = vec![0; sb_rows as usize];
+            self.mi_row_starts = vec![0; sb_rows as usize + 1];
             for start_sb in (0..sb_rows).step_by(tile_height_sb as usize) {
                 self.mi_row_starts[i] = start_sb << sb_shift;
                 i += 1;
@@ -1120,10 +1873,10 @@ impl Decoder {
         }
 
         if self.tile_cols_log2 > 0 || self.tile_rows_log2 > 0 {
-            let _context_update_tile_id = b.f(self.tile_rows_log2 + self.tile_cols_log2);
+            self.context_update_tile_id = b.f(self.tile_rows_log2 + self.tile_cols_log2);
             self.tile_size_bytes = b.f(2) + 1;
         } else {
-            let _context_update_tile_id = 0;
+            self.context_update_tile_id = 0;
         }
     }
 
@@ -1150,8 +1903,9 @@ pub struct QuantizationParams {
     pub using_qmatrix: bool,
 }
 
-#[derive(Debug)]
-enum FrameType {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameType {
+    #[default]
     Key = 0,
     Inter = 1,
     IntraOnly = 2,
@@ -1170,55 +1924,7 @@ impl FrameType {
     }
 }
 
-#[derive(Debug)]
-enum ColorPrimaries {
-    Bt709 = 1,
-    Unspecified = 2,
-}
-
-impl ColorPrimaries {
-    fn new(val: u64) -> ColorPrimaries {
-        match val {
-            1 => ColorPrimaries::Bt709,
-            2 => ColorPrimaries::Unspecified,
-            _ => panic!("invalid value for ColorPrimaries: {val}"),
-        }
-    }
-}
-
-#[derive(Debug)]
-enum TransferCharacteristics {
-    Unspecified = 2,
-    Srgb = 13,
-}
-
-impl TransferCharacteristics {
-    fn new(val: u64) -> TransferCharacteristics {
-        match val {
-            2 => TransferCharacteristics::Unspecified,
-            13 => TransferCharacteristics::Srgb,
-            _ => panic!("invalid value for TransferCharacterstics: {val}"),
-        }
-    }
-}
-
-#[derive(Debug)]
-enum MatrixCoefficients {
-    Unspecified = 0,
-    Identity = 2,
-}
-
-impl MatrixCoefficients {
-    fn new(val: u64) -> MatrixCoefficients {
-        match val {
-            0 => MatrixCoefficients::Identity,
-            2 => MatrixCoefficients::Unspecified,
-            _ => panic!("invalid value for MatrixCoefficients: {val}"),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ChromaSamplePosition {
     Unknown = 0,
     Vertical = 1,
@@ -1239,18 +1945,23 @@ impl ChromaSamplePosition {
             1 => Self::Vertical,
             2 => Self::Colocated,
             3 => Self::Reserved,
-            _ => panic!("invalid seq_profile: {val}"),
+            _ => panic!("invalid value for ChromaSamplePosition: {val}"),
         }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct ColorConfig {
+    pub bit_depth: BitDepth,
+    pub mono_chrome: bool,
     pub separate_uv_delta_q: bool,
     pub color_range: bool,
     pub subsampling_x: bool,
     pub subsampling_y: bool,
     pub chroma_sample_position: ChromaSamplePosition,
+    pub color_primaries: ColorPrimaries,
+    pub transfer_characteristics: TransferCharacteristics,
+    pub matrix_coefficients: MatrixCoefficients,
 }
 
 impl Decoder {
@@ -1295,6 +2006,13 @@ impl Decoder {
             )
         };
 
+        info!(
+            "colour: primaries {color_primaries} ({}), transfer {transfer_characteristics} ({}), matrix {matrix_coefficients} ({})",
+            color_primaries.value(),
+            transfer_characteristics.value(),
+            matrix_coefficients.value()
+        );
+
         let color_range: bool;
         let subsampling_x: bool;
         let subsampling_y: bool;
@@ -1302,16 +2020,32 @@ impl Decoder {
 
         if monochrome {
             return ColorConfig {
+                bit_depth: self.bit_depth,
+                mono_chrome: true,
                 separate_uv_delta_q: false,
                 color_range: b.f(1) != 0,
                 subsampling_x: true,
                 subsampling_y: true,
                 chroma_sample_position,
+                color_primaries,
+                transfer_characteristics,
+                matrix_coefficients,
             };
-        } else if matches!(color_primaries, ColorPrimaries::Bt709)
-            && matches!(transfer_characteristics, TransferCharacteristics::Srgb)
-            && matches!(matrix_coefficients, MatrixCoefficients::Identity)
+        } else if color_primaries == ColorPrimaries::Bt709
+            && transfer_characteristics == TransferCharacteristics::Srgb
+            && matrix_coefficients == MatrixCoefficients::Identity
         {
+            // 4:4:4 is only part of the High profile, and of the Professional profile at
+            // 12-bit.
+            let allows_444 = seq_profile == SeqProfile::One
+                || (seq_profile == SeqProfile::Two && self.bit_depth == BitDepth::Twelve);
+            if !allows_444 {
+                panic!(
+                    "sRGB needs 4:4:4, which {seq_profile:?} doesn't allow at {}-bit",
+                    self.bit_depth as u64
+                );
+            }
+
             color_range = true;
             subsampling_x = false;
             subsampling_y = false;
@@ -1340,12 +2074,21 @@ impl Decoder {
             }
         }
 
+        if matrix_coefficients == MatrixCoefficients::Identity && (subsampling_x || subsampling_y) {
+            panic!("identity matrix coefficients need 4:4:4, got subsampling_x {subsampling_x}, subsampling_y {subsampling_y}");
+        }
+
         ColorConfig {
+            bit_depth: self.bit_depth,
+            mono_chrome: false,
             separate_uv_delta_q: b.f(1) != 0,
             color_range,
             subsampling_x,
             subsampling_y,
             chroma_sample_position,
+            color_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
         }
     }
 }
@@ -1357,6 +2100,36 @@ pub struct LoopFilterParams {
     pub loop_filter_delta_enabled: bool,
 }
 
+/// The film grain parameters of a frame. The default value is the state after
+/// reset_grain_params(), with grain disabled.
+#[derive(Debug, Default, Clone)]
+pub struct FilmGrainParams {
+    pub apply_grain: bool,
+    pub grain_seed: u64,
+    pub point_y_value: Vec<u64>,
+    pub point_y_scaling: Vec<u64>,
+    pub chroma_scaling_from_luma: bool,
+    pub point_cb_value: Vec<u64>,
+    pub point_cb_scaling: Vec<u64>,
+    pub point_cr_value: Vec<u64>,
+    pub point_cr_scaling: Vec<u64>,
+    pub grain_scaling_minus_8: u64,
+    pub ar_coeff_lag: u64,
+    pub ar_coeffs_y_plus_128: Vec<u64>,
+    pub ar_coeffs_cb_plus_128: Vec<u64>,
+    pub ar_coeffs_cr_plus_128: Vec<u64>,
+    pub ar_coeff_shift_minus_6: u64,
+    pub grain_scale_shift: u64,
+    pub cb_mult: u64,
+    pub cb_luma_mult: u64,
+    pub cb_offset: u64,
+    pub cr_mult: u64,
+    pub cr_luma_mult: u64,
+    pub cr_offset: u64,
+    pub overlap_flag: bool,
+    pub clip_to_restricted_range: bool,
+}
+
 #[derive(Debug, Default)]
 pub struct CdefParams {
     pub cdef_bits: u64,
@@ -1366,7 +2139,7 @@ pub struct CdefParams {
     pub cdef_uv_sec_strength: Vec<u64>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum TxMode {
     Invalid = -1,
     Only4x4 = 0,
@@ -1380,13 +2153,13 @@ impl Default for TxMode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum WarpModel {
     Invalid = -1,
     Identity = 0,
-    // Translation = 1,
-    // Rotzoom = 2,
-    // Affine = 3,
+    Translation = 1,
+    Rotzoom = 2,
+    Affine = 3,
 }
 
 impl Default for WarpModel {
@@ -1394,9 +2167,3 @@ impl Default for WarpModel {
         Self::Invalid
     }
 }
-
-#[derive(Debug, Clone, Copy)]
-pub enum SubSize {
-    Block64x64 = 12,
-    Block128x128 = 15,
-}
@@ -0,0 +1,616 @@
+use super::{
+    block::{SubSize, TxSize, UvMode, YMode},
+    symbol::{ceil_log2, SyntaxCategory},
+    BitStream, Decoder,
+};
+
+impl Decoder {
+    const PALETTE_COLORS: usize = 8;
+    const PALETTE_NUM_NEIGHBORS: usize = 3;
+    const PALETTE_COLOR_CONTEXT: [i64; 9] = [-1, -1, 0, -1, -1, 4, 3, 2, 1];
+    const PALETTE_COLOR_HASH_MULTIPLIERS: [u64; Decoder::PALETTE_NUM_NEIGHBORS] = [1, 2, 2];
+
+    /// Palette mode is only signalled for blocks between 8x8 and 64x64 when screen content
+    /// tools are enabled.
+    pub(super) fn palette_allowed(&self) -> bool {
+        self.mi_size >= SubSize::Block8x8
+            && self.mi_size.width() <= 64
+            && self.mi_size.height() <= 64
+            && self.uh.allow_screen_content_tools != 0
+    }
+
+    pub(super) fn palette_mode_info(&mut self, b: &mut BitStream) {
+        let bsize_ctx = (Decoder::MI_WIDTH_LOG2[self.mi_size as usize]
+            + Decoder::MI_HEIGHT_LOG2[self.mi_size as usize]
+            - 2) as usize;
+        let bit_depth = self.bit_depth as u64;
+        let category = self.symbol.set_category(SyntaxCategory::Palette);
+
+        self.palette_size_y = 0;
+        self.palette_size_uv = 0;
+        self.palette_colors_y = Vec::new();
+        self.palette_colors_u = Vec::new();
+        self.palette_colors_v = Vec::new();
+
+        if self.y_mode == YMode::Dc {
+            let mut ctx = 0;
+            if self.avail_u
+                && self.palette_sizes[0][self.mi_row as usize - 1][self.mi_col as usize] > 0
+            {
+                ctx += 1;
+            }
+            if self.avail_l
+                && self.palette_sizes[0][self.mi_row as usize][self.mi_col as usize - 1] > 0
+            {
+                ctx += 1;
+            }
+
+            let has_palette_y = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.palette_y_mode[bsize_ctx][ctx])
+                != 0;
+
+            if has_palette_y {
+                let palette_size_y_minus_2 = self
+                    .symbol
+                    .read_symbol(b, &mut self.cdf.palette_y_size[bsize_ctx]);
+                self.palette_size_y = palette_size_y_minus_2 as u64 + 2;
+
+                let cache = self.get_palette_cache(0);
+                let mut colors = self.read_palette_cache_colors(b, &cache, self.palette_size_y);
+
+                if (colors.len() as u64) < self.palette_size_y {
+                    colors.push(self.symbol.read_literal(b, bit_depth) as u16);
+                }
+
+                let mut palette_bits = 0;
+                if (colors.len() as u64) < self.palette_size_y {
+                    let min_bits = bit_depth - 3;
+                    let palette_num_extra_bits_y = self.symbol.read_literal(b, 2);
+                    palette_bits = min_bits + palette_num_extra_bits_y;
+                }
+
+                while (colors.len() as u64) < self.palette_size_y {
+                    let palette_delta_y = self.symbol.read_literal(b, palette_bits) + 1;
+                    let color = self.clip1(*colors.last().unwrap() as u64 + palette_delta_y);
+                    colors.push(color);
+
+                    let range = (1 << bit_depth) - color as u64 - 1;
+                    palette_bits = palette_bits.min(ceil_log2(range));
+                }
+
+                colors.sort_unstable();
+                self.palette_colors_y = colors;
+            }
+        }
+
+        if self.has_chroma && self.uv_mode == UvMode::Dc {
+            let ctx = (self.palette_size_y > 0) as usize;
+            let has_palette_uv = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.palette_uv_mode[ctx])
+                != 0;
+
+            if has_palette_uv {
+                let palette_size_uv_minus_2 = self
+                    .symbol
+                    .read_symbol(b, &mut self.cdf.palette_uv_size[bsize_ctx]);
+                self.palette_size_uv = palette_size_uv_minus_2 as u64 + 2;
+
+                let cache = self.get_palette_cache(1);
+                let mut colors = self.read_palette_cache_colors(b, &cache, self.palette_size_uv);
+
+                if (colors.len() as u64) < self.palette_size_uv {
+                    colors.push(self.symbol.read_literal(b, bit_depth) as u16);
+                }
+
+                let mut palette_bits = 0;
+                if (colors.len() as u64) < self.palette_size_uv {
+                    let min_bits = bit_depth - 3;
+                    let palette_num_extra_bits_u = self.symbol.read_literal(b, 2);
+                    palette_bits = min_bits + palette_num_extra_bits_u;
+                }
+
+                while (colors.len() as u64) < self.palette_size_uv {
+                    let palette_delta_u = self.symbol.read_literal(b, palette_bits);
+                    let color = self.clip1(*colors.last().unwrap() as u64 + palette_delta_u);
+                    colors.push(color);
+
+                    let range = (1 << bit_depth) - color as u64;
+                    palette_bits = palette_bits.min(ceil_log2(range));
+                }
+
+                colors.sort_unstable();
+                self.palette_colors_u = colors;
+
+                self.palette_colors_v = self.read_palette_colors_v(b);
+            }
+        }
+
+        self.symbol.set_category(category);
+    }
+
+    fn read_palette_cache_colors(
+        &mut self,
+        b: &mut BitStream,
+        cache: &[u16],
+        palette_size: u64,
+    ) -> Vec<u16> {
+        let mut colors = Vec::with_capacity(palette_size as usize);
+        for &color in cache {
+            if colors.len() as u64 >= palette_size {
+                break;
+            }
+
+            let use_palette_color_cache = self.symbol.read_literal(b, 1) != 0;
+            if use_palette_color_cache {
+                colors.push(color);
+            }
+        }
+
+        colors
+    }
+
+    fn read_palette_colors_v(&mut self, b: &mut BitStream) -> Vec<u16> {
+        let bit_depth = self.bit_depth as u64;
+        let mut colors = vec![0u16; self.palette_size_uv as usize];
+
+        let delta_encode_palette_colors_v = self.symbol.read_literal(b, 1) != 0;
+        if delta_encode_palette_colors_v {
+            let min_bits = bit_depth - 4;
+            let max_val = 1i64 << bit_depth;
+            let palette_num_extra_bits_v = self.symbol.read_literal(b, 2);
+            let palette_bits = min_bits + palette_num_extra_bits_v;
+
+            colors[0] = self.symbol.read_literal(b, bit_depth) as u16;
+            for idx in 1..colors.len() {
+                let mut palette_delta_v = self.symbol.read_literal(b, palette_bits) as i64;
+                if palette_delta_v != 0 {
+                    let palette_delta_sign_bit_v = self.symbol.read_literal(b, 1) != 0;
+                    if palette_delta_sign_bit_v {
+                        palette_delta_v = -palette_delta_v;
+                    }
+                }
+
+                let mut val = colors[idx - 1] as i64 + palette_delta_v;
+                if val < 0 {
+                    val += max_val;
+                }
+                if val >= max_val {
+                    val -= max_val;
+                }
+
+                colors[idx] = self.clip1(val as u64);
+            }
+        } else {
+            for color in colors.iter_mut() {
+                *color = self.symbol.read_literal(b, bit_depth) as u16;
+            }
+        }
+
+        colors
+    }
+
+    /// Merges the sorted palettes of the above and left blocks into a sorted list without
+    /// duplicates. The above palette is not used across 64x64 boundaries.
+    fn get_palette_cache(&self, plane: usize) -> Vec<u16> {
+        let row = self.mi_row as usize;
+        let col = self.mi_col as usize;
+
+        let above: &[u16] = if !(self.mi_row * Decoder::MI_SIZE).is_multiple_of(64) && self.avail_u
+        {
+            let n = self.palette_sizes[plane][row - 1][col] as usize;
+            &self.palette_colors[plane][row - 1][col][..n]
+        } else {
+            &[]
+        };
+
+        let left: &[u16] = if self.avail_l {
+            let n = self.palette_sizes[plane][row][col - 1] as usize;
+            &self.palette_colors[plane][row][col - 1][..n]
+        } else {
+            &[]
+        };
+
+        let mut cache: Vec<u16> = Vec::with_capacity(above.len() + left.len());
+        let mut push = |val: u16| {
+            if cache.last() != Some(&val) {
+                cache.push(val);
+            }
+        };
+
+        let mut above_idx = 0;
+        let mut left_idx = 0;
+        while above_idx < above.len() && left_idx < left.len() {
+            let above_c = above[above_idx];
+            let left_c = left[left_idx];
+            if left_c < above_c {
+                push(left_c);
+                left_idx += 1;
+            } else {
+                push(above_c);
+                above_idx += 1;
+                if left_c == above_c {
+                    left_idx += 1;
+                }
+            }
+        }
+
+        for &val in &above[above_idx..] {
+            push(val);
+        }
+
+        for &val in &left[left_idx..] {
+            push(val);
+        }
+
+        cache
+    }
+
+    pub(super) fn palette_tokens(&mut self, b: &mut BitStream) {
+        let category = self.symbol.set_category(SyntaxCategory::Palette);
+        let mut block_height = self.mi_size.height();
+        let mut block_width = self.mi_size.width();
+        let mut onscreen_height =
+            block_height.min(((self.mi_rows - self.mi_row) * Decoder::MI_SIZE) as usize);
+        let mut onscreen_width =
+            block_width.min(((self.mi_cols - self.mi_col) * Decoder::MI_SIZE) as usize);
+
+        if self.palette_size_y != 0 {
+            self.color_map_y = self.read_color_map(
+                b,
+                0,
+                block_width,
+                block_height,
+                onscreen_width,
+                onscreen_height,
+            );
+        }
+
+        if self.palette_size_uv != 0 {
+            let color_config = &self.sequence_header.color_config;
+            block_height >>= color_config.subsampling_y as usize;
+            block_width >>= color_config.subsampling_x as usize;
+            onscreen_height >>= color_config.subsampling_y as usize;
+            onscreen_width >>= color_config.subsampling_x as usize;
+
+            if block_width < 4 {
+                block_width += 2;
+                onscreen_width += 2;
+            }
+
+            if block_height < 4 {
+                block_height += 2;
+                onscreen_height += 2;
+            }
+
+            self.color_map_uv = self.read_color_map(
+                b,
+                1,
+                block_width,
+                block_height,
+                onscreen_width,
+                onscreen_height,
+            );
+        }
+
+        self.symbol.set_category(category);
+    }
+
+    /// Reads the color indices of the visible part of the block in wavefront order and
+    /// replicates the last visible row and column into the rest of the block.
+    fn read_color_map(
+        &mut self,
+        b: &mut BitStream,
+        plane_type: usize,
+        block_width: usize,
+        block_height: usize,
+        onscreen_width: usize,
+        onscreen_height: usize,
+    ) -> Vec<Vec<u8>> {
+        let n = if plane_type == 0 {
+            self.palette_size_y
+        } else {
+            self.palette_size_uv
+        };
+
+        let mut color_map = vec![vec![0u8; block_width]; block_height];
+        color_map[0][0] = self.symbol.read_ns(b, n) as u8;
+
+        for i in 1..(onscreen_height + onscreen_width - 1) {
+            let j_min = (i as i64 - onscreen_height as i64 + 1).max(0) as usize;
+            for j in (j_min..=i.min(onscreen_width - 1)).rev() {
+                let (color_order, color_context_hash) =
+                    Decoder::get_palette_color_context(&color_map, i - j, j, n as usize);
+                let ctx = Decoder::PALETTE_COLOR_CONTEXT[color_context_hash] as usize;
+
+                let cdf = if plane_type == 0 {
+                    &mut self.cdf.palette_y_color[n as usize - 2][ctx]
+                } else {
+                    &mut self.cdf.palette_uv_color[n as usize - 2][ctx]
+                };
+
+                let palette_color_idx = self.symbol.read_symbol(b, cdf);
+                color_map[i - j][j] = color_order[palette_color_idx];
+            }
+        }
+
+        for row in color_map.iter_mut().take(onscreen_height) {
+            let last = row[onscreen_width - 1];
+            for c in row.iter_mut().skip(onscreen_width) {
+                *c = last;
+            }
+        }
+
+        for i in onscreen_height..block_height {
+            color_map[i] = color_map[onscreen_height - 1].clone();
+        }
+
+        color_map
+    }
+
+    /// Ranks the colors of the left, above-left and above neighbours. Returns the color order
+    /// and the hash used to select the cdf of the next color index.
+    fn get_palette_color_context(
+        color_map: &[Vec<u8>],
+        r: usize,
+        c: usize,
+        n: usize,
+    ) -> ([u8; Decoder::PALETTE_COLORS], usize) {
+        let mut scores = [0u64; Decoder::PALETTE_COLORS];
+        let mut color_order = [0u8; Decoder::PALETTE_COLORS];
+        for (i, order) in color_order.iter_mut().enumerate() {
+            *order = i as u8;
+        }
+
+        if c > 0 {
+            scores[color_map[r][c - 1] as usize] += 2;
+        }
+
+        if r > 0 && c > 0 {
+            scores[color_map[r - 1][c - 1] as usize] += 1;
+        }
+
+        if r > 0 {
+            scores[color_map[r - 1][c] as usize] += 2;
+        }
+
+        for i in 0..Decoder::PALETTE_NUM_NEIGHBORS {
+            let mut max_score = scores[i];
+            let mut max_idx = i;
+            for (j, &score) in scores.iter().enumerate().take(n).skip(i + 1) {
+                if score > max_score {
+                    max_score = score;
+                    max_idx = j;
+                }
+            }
+
+            if max_idx != i {
+                let max_color_order = color_order[max_idx];
+                for k in ((i + 1)..=max_idx).rev() {
+                    scores[k] = scores[k - 1];
+                    color_order[k] = color_order[k - 1];
+                }
+
+                scores[i] = max_score;
+                color_order[i] = max_color_order;
+            }
+        }
+
+        let color_context_hash: u64 = scores
+            .iter()
+            .zip(Decoder::PALETTE_COLOR_HASH_MULTIPLIERS)
+            .map(|(score, multiplier)| score * multiplier)
+            .sum();
+
+        (color_order, color_context_hash as usize)
+    }
+
+    /// Palette prediction process. `x` and `y` are in units of 4x4 blocks relative to the
+    /// top left of the current block.
+    pub(super) fn predict_palette(
+        &mut self,
+        plane: usize,
+        start_x: usize,
+        start_y: usize,
+        x: usize,
+        y: usize,
+        tx_sz: TxSize,
+    ) {
+        let (palette, map) = match plane {
+            0 => (&self.palette_colors_y, &self.color_map_y),
+            1 => (&self.palette_colors_u, &self.color_map_uv),
+            _ => (&self.palette_colors_v, &self.color_map_uv),
+        };
+
+        for i in 0..tx_sz.height() {
+            for j in 0..tx_sz.width() {
+                self.curr_frame[plane][start_y + i][start_x + j] =
+                    palette[map[y * 4 + i][x * 4 + j] as usize];
+            }
+        }
+    }
+
+    pub(super) fn clip1(&self, x: u64) -> u16 {
+        x.min((1 << self.bit_depth as u64) - 1) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::av1::{symbol::floor_log2, BitDepth};
+
+    /// The range encoder matching the symbol decoder, as in the libaom reference encoder.
+    struct SymbolEncoder {
+        low: u64,
+        rng: u64,
+        cnt: i64,
+        precarry: Vec<u64>,
+    }
+
+    impl SymbolEncoder {
+        fn new() -> SymbolEncoder {
+            SymbolEncoder {
+                low: 0,
+                rng: 0x8000,
+                cnt: -9,
+                precarry: Vec::new(),
+            }
+        }
+
+        fn write_symbol(&mut self, s: usize, cdf: &[u16]) {
+            let n = (cdf.len() - 1) as u64;
+            let scale = |f: u64, s: u64| (((self.rng >> 8) * (f >> 6)) >> 1) + 4 * (n - s - 1);
+
+            let fl = if s > 0 {
+                32768 - cdf[s - 1] as u64
+            } else {
+                32768
+            };
+            let fh = 32768 - cdf[s] as u64;
+            let v = scale(fh, s as u64);
+            let (low, rng) = if fl < 32768 {
+                let u = scale(fl, s as u64 - 1);
+                (self.low + self.rng - u, u - v)
+            } else {
+                (self.low, self.rng - v)
+            };
+
+            self.normalize(low, rng);
+        }
+
+        fn normalize(&mut self, mut low: u64, rng: u64) {
+            let d = 15 - floor_log2(rng) as i64;
+            let mut c = self.cnt;
+            let mut s = c + d;
+            if s >= 0 {
+                c += 16;
+                let mut m = (1 << c) - 1;
+                if s >= 8 {
+                    self.precarry.push(low >> c);
+                    low &= m;
+                    c -= 8;
+                    m >>= 8;
+                }
+
+                self.precarry.push(low >> c);
+                s = c + d - 24;
+                low &= m;
+            }
+
+            self.low = low << d;
+            self.rng = rng << d;
+            self.cnt = s;
+        }
+
+        fn write_bool(&mut self, bit: bool) {
+            self.write_symbol(bit as usize, &[1 << 14, 1 << 15, 0]);
+        }
+
+        fn write_literal(&mut self, n: u64, x: u64) {
+            for i in (0..n).rev() {
+                self.write_bool((x >> i) & 1 != 0);
+            }
+        }
+
+        fn write_ns(&mut self, n: u64, x: u64) {
+            let w = floor_log2(n) + 1;
+            let m = (1 << w) - n;
+            if x < m {
+                self.write_literal(w - 1, x);
+            } else {
+                self.write_literal(w - 1, (x + m) >> 1);
+                self.write_literal(1, (x + m) & 1);
+            }
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            let mut c = self.cnt;
+            let mut s = c + 10;
+            let m = 0x3fff;
+            let mut e = ((self.low + m) & !m) | (m + 1);
+            if s > 0 {
+                let mut n = (1 << (c + 16)) - 1;
+                while s > 0 {
+                    self.precarry.push(e >> (c + 16));
+                    e &= n;
+                    s -= 8;
+                    c -= 8;
+                    n >>= 8;
+                }
+            }
+
+            let mut out = vec![0; self.precarry.len()];
+            let mut carry = 0;
+            for (byte, &val) in out.iter_mut().zip(&self.precarry).rev() {
+                carry += val;
+                *byte = carry as u8;
+                carry >>= 8;
+            }
+
+            out
+        }
+    }
+
+    fn decoder() -> Decoder {
+        let mut decoder = Decoder {
+            bit_depth: BitDepth::Eight,
+            mi_size: SubSize::Block8x8,
+            mi_rows: 2,
+            mi_cols: 2,
+            y_mode: YMode::Dc,
+            ..Default::default()
+        };
+        decoder.uh.allow_screen_content_tools = 1;
+        decoder.cdf.init_non_coeff_cdfs();
+        decoder
+    }
+
+    #[test]
+    fn decodes_luma_palette_block() {
+        let color_map: Vec<Vec<u8>> = (0..8)
+            .map(|r| (0..8).map(|c| [0, 1, 2, 1][(r / 4) * 2 + c / 4]).collect())
+            .collect();
+        let cdf = decoder().cdf;
+
+        let mut enc = SymbolEncoder::new();
+        enc.write_symbol(1, &cdf.palette_y_mode[0][0]);
+        enc.write_symbol(1, &cdf.palette_y_size[0]);
+        enc.write_literal(8, 20);
+        enc.write_literal(2, 1);
+        enc.write_literal(6, 9);
+        enc.write_literal(6, 39);
+        enc.write_ns(3, color_map[0][0] as u64);
+        for i in 1..15usize {
+            for j in (i.saturating_sub(7)..=i.min(7)).rev() {
+                let (color_order, hash) =
+                    Decoder::get_palette_color_context(&color_map, i - j, j, 3);
+                let ctx = Decoder::PALETTE_COLOR_CONTEXT[hash] as usize;
+                let idx = color_order
+                    .iter()
+                    .position(|&color| color == color_map[i - j][j])
+                    .unwrap();
+                enc.write_symbol(idx, &cdf.palette_y_color[1][ctx]);
+            }
+        }
+        let data = enc.finish();
+
+        let mut decoder = decoder();
+        let mut b = BitStream::new(data.clone());
+        decoder.symbol.init(&mut b, data.len(), true);
+        decoder.palette_mode_info(&mut b);
+        decoder.palette_tokens(&mut b);
+        decoder.symbol.exit(&mut b);
+
+        assert_eq!(decoder.palette_size_y, 3);
+        assert_eq!(decoder.palette_colors_y, [20, 30, 70]);
+        assert_eq!(decoder.palette_size_uv, 0);
+        assert_eq!(decoder.color_map_y, color_map);
+
+        decoder.curr_frame = vec![vec![vec![0; 8]; 8]];
+        decoder.predict_palette(0, 0, 0, 0, 0, TxSize::Tx8x8);
+        assert_eq!(decoder.curr_frame[0][0], [20, 20, 20, 20, 30, 30, 30, 30]);
+        assert_eq!(decoder.curr_frame[0][7], [70, 70, 70, 70, 30, 30, 30, 30]);
+    }
+}
@@ -0,0 +1,223 @@
+use super::{block::SubSize, cdf::Cdfs, symbol::SyntaxCategory, BitStream, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#decode-partition-syntax
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Partition {
+    None = 0,
+    Horz = 1,
+    Vert = 2,
+    Split = 3,
+    HorzA = 4,
+    HorzB = 5,
+    VertA = 6,
+    VertB = 7,
+    Horz4 = 8,
+    Vert4 = 9,
+}
+
+impl Partition {
+    fn new(val: u64) -> Partition {
+        match val {
+            0 => Partition::None,
+            1 => Partition::Horz,
+            2 => Partition::Vert,
+            3 => Partition::Split,
+            4 => Partition::HorzA,
+            5 => Partition::HorzB,
+            6 => Partition::VertA,
+            7 => Partition::VertB,
+            8 => Partition::Horz4,
+            9 => Partition::Vert4,
+            _ => panic!("invalid value for Partition: {val}"),
+        }
+    }
+
+    /// Partition_Subsize[partition][bsize], for the square block sizes that are partitioned.
+    fn subsize(self, bsize: SubSize) -> SubSize {
+        let w4 = Decoder::NUM_4X4_BLOCKS_WIDE[bsize as usize];
+        let h4 = Decoder::NUM_4X4_BLOCKS_HIGH[bsize as usize];
+        let (w4, h4) = match self {
+            Partition::None => (w4, h4),
+            Partition::Horz | Partition::HorzA | Partition::HorzB => (w4, h4 / 2),
+            Partition::Vert | Partition::VertA | Partition::VertB => (w4 / 2, h4),
+            Partition::Split => (w4 / 2, h4 / 2),
+            Partition::Horz4 => (w4, h4 / 4),
+            Partition::Vert4 => (w4 / 4, h4),
+        };
+
+        (0..Decoder::BLOCK_SIZES)
+            .find(|&i| {
+                Decoder::NUM_4X4_BLOCKS_WIDE[i] == w4 && Decoder::NUM_4X4_BLOCKS_HIGH[i] == h4
+            })
+            .map(|i| SubSize::new(i as u64))
+            .unwrap_or_else(|| panic!("invalid partition {self:?} of {bsize:?}"))
+    }
+}
+
+impl Decoder {
+    pub(super) fn decode_partition(&mut self, b: &mut BitStream, r: u64, c: u64, bsize: SubSize) {
+        if r >= self.mi_rows || c >= self.mi_cols {
+            return;
+        }
+
+        self.avail_u = self.is_inside(r as i64 - 1, c as i64);
+        self.avail_l = self.is_inside(r as i64, c as i64 - 1);
+
+        let num4x4 = Decoder::NUM_4X4_BLOCKS_WIDE[bsize as usize] as u64;
+        let half_block4x4 = num4x4 >> 1;
+        let quarter_block4x4 = half_block4x4 >> 1;
+        let has_rows = (r + half_block4x4) < self.mi_rows;
+        let has_cols = (c + half_block4x4) < self.mi_cols;
+
+        let category = self.symbol.set_category(SyntaxCategory::Partition);
+        let partition = if bsize < SubSize::Block8x8 {
+            Partition::None
+        } else if has_rows && has_cols {
+            let ctx = self.partition_ctx(r, c, bsize);
+            let cdf = Decoder::partition_cdf(&mut self.cdf, bsize, ctx);
+            Partition::new(self.symbol.read_symbol(b, cdf) as u64)
+        } else if has_cols {
+            let split_or_horz = self.read_split_or(b, r, c, bsize, true);
+            if split_or_horz {
+                Partition::Split
+            } else {
+                Partition::Horz
+            }
+        } else if has_rows {
+            let split_or_vert = self.read_split_or(b, r, c, bsize, false);
+            if split_or_vert {
+                Partition::Split
+            } else {
+                Partition::Vert
+            }
+        } else {
+            Partition::Split
+        };
+        self.symbol.set_category(category);
+
+        let sub_size = partition.subsize(bsize);
+        // Only the HORZ_A, HORZ_B, VERT_A and VERT_B partitions use it, and a 4x4 block has no
+        // split size.
+        let split_size = if bsize < SubSize::Block8x8 {
+            bsize
+        } else {
+            Partition::Split.subsize(bsize)
+        };
+        match partition {
+            Partition::None => self.decode_block(b, r, c, sub_size),
+            Partition::Horz => {
+                self.decode_block(b, r, c, sub_size);
+                if has_rows {
+                    self.decode_block(b, r + half_block4x4, c, sub_size);
+                }
+            }
+            Partition::Vert => {
+                self.decode_block(b, r, c, sub_size);
+                if has_cols {
+                    self.decode_block(b, r, c + half_block4x4, sub_size);
+                }
+            }
+            Partition::Split => {
+                self.decode_partition(b, r, c, sub_size);
+                self.decode_partition(b, r, c + half_block4x4, sub_size);
+                self.decode_partition(b, r + half_block4x4, c, sub_size);
+                self.decode_partition(b, r + half_block4x4, c + half_block4x4, sub_size);
+            }
+            Partition::HorzA => {
+                self.decode_block(b, r, c, split_size);
+                self.decode_block(b, r, c + half_block4x4, split_size);
+                self.decode_block(b, r + half_block4x4, c, sub_size);
+            }
+            Partition::HorzB => {
+                self.decode_block(b, r, c, sub_size);
+                self.decode_block(b, r + half_block4x4, c, split_size);
+                self.decode_block(b, r + half_block4x4, c + half_block4x4, split_size);
+            }
+            Partition::VertA => {
+                self.decode_block(b, r, c, split_size);
+                self.decode_block(b, r + half_block4x4, c, split_size);
+                self.decode_block(b, r, c + half_block4x4, sub_size);
+            }
+            Partition::VertB => {
+                self.decode_block(b, r, c, sub_size);
+                self.decode_block(b, r, c + half_block4x4, split_size);
+                self.decode_block(b, r + half_block4x4, c + half_block4x4, split_size);
+            }
+            Partition::Horz4 => {
+                for i in 0..4 {
+                    let row = r + quarter_block4x4 * i;
+                    if i < 3 || row < self.mi_rows {
+                        self.decode_block(b, row, c, sub_size);
+                    }
+                }
+            }
+            Partition::Vert4 => {
+                for i in 0..4 {
+                    let col = c + quarter_block4x4 * i;
+                    if i < 3 || col < self.mi_cols {
+                        self.decode_block(b, r, col, sub_size);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The partition cdf for the block size, with a context from whether the neighbours
+    /// are split smaller than the current block.
+    fn partition_ctx(&self, r: u64, c: u64, bsize: SubSize) -> usize {
+        let bsl = Decoder::MI_WIDTH_LOG2[bsize as usize];
+        let (r, c) = (r as usize, c as usize);
+        let above = self.avail_u && Decoder::MI_WIDTH_LOG2[self.mi_sizes[r - 1][c] as usize] < bsl;
+        let left = self.avail_l && Decoder::MI_HEIGHT_LOG2[self.mi_sizes[r][c - 1] as usize] < bsl;
+
+        left as usize * 2 + above as usize
+    }
+
+    fn partition_cdf(cdf: &mut Cdfs, bsize: SubSize, ctx: usize) -> &mut Vec<u16> {
+        match Decoder::MI_WIDTH_LOG2[bsize as usize] {
+            1 => &mut cdf.partition_w8[ctx],
+            2 => &mut cdf.partition_w16[ctx],
+            3 => &mut cdf.partition_w32[ctx],
+            4 => &mut cdf.partition_w64[ctx],
+            _ => &mut cdf.partition_w128[ctx],
+        }
+    }
+
+    /// Reads split_or_horz (or split_or_vert) at the bottom (or right) edge of the frame,
+    /// where only some partitions are possible. The probability of a split is the sum of
+    /// the probabilities of the partitions that split the block the other way.
+    fn read_split_or(
+        &mut self,
+        b: &mut BitStream,
+        r: u64,
+        c: u64,
+        bsize: SubSize,
+        horz: bool,
+    ) -> bool {
+        let ctx = self.partition_ctx(r, c, bsize);
+        let partition_cdf = Decoder::partition_cdf(&mut self.cdf, bsize, ctx);
+        let prob = |partition: Partition| {
+            let p = partition as usize;
+            let prev = if p > 0 { partition_cdf[p - 1] } else { 0 };
+            (partition_cdf[p] - prev) as u32
+        };
+
+        let mut psum = prob(Partition::Split) + prob(Partition::HorzA) + prob(Partition::VertA);
+        if horz {
+            psum += prob(Partition::Vert) + prob(Partition::VertB);
+            if bsize != SubSize::Block128x128 {
+                psum += prob(Partition::Vert4);
+            }
+        } else {
+            psum += prob(Partition::Horz) + prob(Partition::HorzB);
+            if bsize != SubSize::Block128x128 {
+                psum += prob(Partition::Horz4);
+            }
+        }
+
+        // The cdf is only used for this symbol, so it doesn't matter that it adapts.
+        let mut cdf = [(32768 - psum) as u16, 1 << 15, 0];
+        self.symbol.read_symbol(b, &mut cdf) == 1
+    }
+}
@@ -0,0 +1,284 @@
+use super::{BitStream, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#ref-frames-syntax
+
+impl Decoder {
+    pub(super) fn read_ref_frames(&mut self, b: &mut BitStream) {
+        if self.skip_mode {
+            self.ref_frame = self.skip_mode_frame;
+            return;
+        }
+
+        if self.seg_feature_active(Decoder::SEG_LVL_REF_FRAME) {
+            self.ref_frame = [
+                self.feature_data[self.segment_id as usize][Decoder::SEG_LVL_REF_FRAME],
+                Decoder::NONE,
+            ];
+            return;
+        }
+
+        if self.seg_feature_active(Decoder::SEG_LVL_SKIP)
+            || self.seg_feature_active(Decoder::SEG_LVL_GLOBALMV)
+        {
+            self.ref_frame = [Decoder::LAST_FRAME as i64, Decoder::NONE];
+            return;
+        }
+
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+        let comp_mode = if self.uh.reference_select && bw4.min(bh4) >= 2 {
+            let ctx = self.comp_mode_ctx();
+            self.symbol.read_symbol(b, &mut self.cdf.comp_mode[ctx]) != 0
+        } else {
+            false
+        };
+
+        self.ref_frame = if comp_mode {
+            self.read_compound_ref_frames(b)
+        } else {
+            [self.read_single_ref_frame(b) as i64, Decoder::NONE]
+        };
+    }
+
+    fn read_compound_ref_frames(&mut self, b: &mut BitStream) -> [i64; 2] {
+        let ctx = self.comp_ref_type_ctx();
+        let bidir = self.symbol.read_symbol(b, &mut self.cdf.comp_ref_type[ctx]) != 0;
+
+        if !bidir {
+            let [first, second] = if self.read_ref_bit(b, RefCdf::UniComp, 0) {
+                [Decoder::BWDREF_FRAME, Decoder::ALTREF_FRAME]
+            } else if self.read_ref_bit(b, RefCdf::UniComp, 1) {
+                if self.read_ref_bit(b, RefCdf::UniComp, 2) {
+                    [Decoder::LAST_FRAME, Decoder::GOLDEN_FRAME]
+                } else {
+                    [Decoder::LAST_FRAME, Decoder::LAST3_FRAME]
+                }
+            } else {
+                [Decoder::LAST_FRAME, Decoder::LAST2_FRAME]
+            };
+
+            return [first as i64, second as i64];
+        }
+
+        let first = if !self.read_ref_bit(b, RefCdf::Comp, 0) {
+            if self.read_ref_bit(b, RefCdf::Comp, 1) {
+                Decoder::LAST2_FRAME
+            } else {
+                Decoder::LAST_FRAME
+            }
+        } else if self.read_ref_bit(b, RefCdf::Comp, 2) {
+            Decoder::GOLDEN_FRAME
+        } else {
+            Decoder::LAST3_FRAME
+        };
+
+        let second = if !self.read_ref_bit(b, RefCdf::CompBwd, 0) {
+            if self.read_ref_bit(b, RefCdf::CompBwd, 1) {
+                Decoder::ALTREF2_FRAME
+            } else {
+                Decoder::BWDREF_FRAME
+            }
+        } else {
+            Decoder::ALTREF_FRAME
+        };
+
+        [first as i64, second as i64]
+    }
+
+    fn read_single_ref_frame(&mut self, b: &mut BitStream) -> usize {
+        if self.read_ref_bit(b, RefCdf::Single, 0) {
+            if !self.read_ref_bit(b, RefCdf::Single, 1) {
+                if self.read_ref_bit(b, RefCdf::Single, 5) {
+                    Decoder::ALTREF2_FRAME
+                } else {
+                    Decoder::BWDREF_FRAME
+                }
+            } else {
+                Decoder::ALTREF_FRAME
+            }
+        } else if self.read_ref_bit(b, RefCdf::Single, 2) {
+            if self.read_ref_bit(b, RefCdf::Single, 4) {
+                Decoder::GOLDEN_FRAME
+            } else {
+                Decoder::LAST3_FRAME
+            }
+        } else if self.read_ref_bit(b, RefCdf::Single, 3) {
+            Decoder::LAST2_FRAME
+        } else {
+            Decoder::LAST_FRAME
+        }
+    }
+
+    /// Reads one of the binary reference frame decisions, e.g. single_ref_p1 for
+    /// `RefCdf::Single` and n = 0. The context compares how often the neighbours use the
+    /// references on either side of the decision.
+    fn read_ref_bit(&mut self, b: &mut BitStream, cdf: RefCdf, n: usize) -> bool {
+        use RefCdf::*;
+        let (first, second): (&[usize], &[usize]) = match (cdf, n) {
+            (UniComp, 0) | (Single, 0) => (
+                &[
+                    Decoder::LAST_FRAME,
+                    Decoder::LAST2_FRAME,
+                    Decoder::LAST3_FRAME,
+                    Decoder::GOLDEN_FRAME,
+                ],
+                &[
+                    Decoder::BWDREF_FRAME,
+                    Decoder::ALTREF2_FRAME,
+                    Decoder::ALTREF_FRAME,
+                ],
+            ),
+            (UniComp, 1) => (
+                &[Decoder::LAST2_FRAME],
+                &[Decoder::LAST3_FRAME, Decoder::GOLDEN_FRAME],
+            ),
+            (UniComp, _) | (Comp, 2) | (Single, 4) => {
+                (&[Decoder::LAST3_FRAME], &[Decoder::GOLDEN_FRAME])
+            }
+            (Comp, 0) | (Single, 2) => (
+                &[Decoder::LAST_FRAME, Decoder::LAST2_FRAME],
+                &[Decoder::LAST3_FRAME, Decoder::GOLDEN_FRAME],
+            ),
+            (Comp, _) | (Single, 3) => (&[Decoder::LAST_FRAME], &[Decoder::LAST2_FRAME]),
+            (CompBwd, 0) | (Single, 1) => (
+                &[Decoder::BWDREF_FRAME, Decoder::ALTREF2_FRAME],
+                &[Decoder::ALTREF_FRAME],
+            ),
+            (CompBwd, _) | (Single, _) => (&[Decoder::BWDREF_FRAME], &[Decoder::ALTREF2_FRAME]),
+        };
+
+        let count = |refs: &[usize]| -> usize { refs.iter().map(|&r| self.count_refs(r)).sum() };
+        let ctx = match count(first).cmp(&count(second)) {
+            std::cmp::Ordering::Less => 0,
+            std::cmp::Ordering::Equal => 1,
+            std::cmp::Ordering::Greater => 2,
+        };
+
+        let cdf = match cdf {
+            UniComp => &mut self.cdf.uni_comp_ref[ctx][n],
+            Comp => &mut self.cdf.comp_ref[ctx][n],
+            CompBwd => &mut self.cdf.comp_bwd_ref[ctx][n],
+            Single => &mut self.cdf.single_ref[ctx][n],
+        };
+        self.symbol.read_symbol(b, cdf) != 0
+    }
+
+    /// How many of the references of the above and left blocks are frame_type.
+    fn count_refs(&self, frame_type: usize) -> usize {
+        let mut c = 0;
+        if self.avail_u {
+            c += self
+                .above_ref_frame
+                .iter()
+                .filter(|&&r| r == frame_type as i64)
+                .count();
+        }
+        if self.avail_l {
+            c += self
+                .left_ref_frame
+                .iter()
+                .filter(|&&r| r == frame_type as i64)
+                .count();
+        }
+
+        c
+    }
+
+    fn check_backward(ref_frame: i64) -> bool {
+        ref_frame >= Decoder::BWDREF_FRAME as i64 && ref_frame <= Decoder::ALTREF_FRAME as i64
+    }
+
+    fn comp_mode_ctx(&self) -> usize {
+        let above = Decoder::check_backward(self.above_ref_frame[0]);
+        let left = Decoder::check_backward(self.left_ref_frame[0]);
+
+        if self.avail_u && self.avail_l {
+            if self.above_single && self.left_single {
+                (above ^ left) as usize
+            } else if self.above_single {
+                2 + (above || self.above_intra) as usize
+            } else if self.left_single {
+                2 + (left || self.left_intra) as usize
+            } else {
+                4
+            }
+        } else if self.avail_u {
+            if self.above_single {
+                above as usize
+            } else {
+                3
+            }
+        } else if self.avail_l {
+            if self.left_single {
+                left as usize
+            } else {
+                3
+            }
+        } else {
+            1
+        }
+    }
+
+    fn is_samedir_ref_pair(ref0: i64, ref1: i64) -> bool {
+        (ref0 >= Decoder::BWDREF_FRAME as i64) == (ref1 >= Decoder::BWDREF_FRAME as i64)
+    }
+
+    fn comp_ref_type_ctx(&self) -> usize {
+        let [above0, above1] = self.above_ref_frame;
+        let [left0, left1] = self.left_ref_frame;
+        let above_comp_inter = self.avail_u && !self.above_intra && !self.above_single;
+        let left_comp_inter = self.avail_l && !self.left_intra && !self.left_single;
+        let above_uni_comp = above_comp_inter && Decoder::is_samedir_ref_pair(above0, above1);
+        let left_uni_comp = left_comp_inter && Decoder::is_samedir_ref_pair(left0, left1);
+
+        if self.avail_u && !self.above_intra && self.avail_l && !self.left_intra {
+            let samedir = Decoder::is_samedir_ref_pair(above0, left0) as usize;
+
+            if !above_comp_inter && !left_comp_inter {
+                1 + 2 * samedir
+            } else if !above_comp_inter {
+                if !left_uni_comp {
+                    1
+                } else {
+                    3 + samedir
+                }
+            } else if !left_comp_inter {
+                if !above_uni_comp {
+                    1
+                } else {
+                    3 + samedir
+                }
+            } else if !above_uni_comp && !left_uni_comp {
+                0
+            } else if !above_uni_comp || !left_uni_comp {
+                2
+            } else {
+                let bwd = Decoder::BWDREF_FRAME as i64;
+                3 + ((above0 == bwd) == (left0 == bwd)) as usize
+            }
+        } else if self.avail_u && self.avail_l {
+            if above_comp_inter {
+                1 + 2 * above_uni_comp as usize
+            } else if left_comp_inter {
+                1 + 2 * left_uni_comp as usize
+            } else {
+                2
+            }
+        } else if above_comp_inter {
+            4 * above_uni_comp as usize
+        } else if left_comp_inter {
+            4 * left_uni_comp as usize
+        } else {
+            2
+        }
+    }
+}
+
+/// The families of binary reference frame decisions, which share a context derivation.
+#[derive(Debug, Clone, Copy)]
+enum RefCdf {
+    UniComp,
+    Comp,
+    CompBwd,
+    Single,
+}
@@ -0,0 +1,136 @@
+use super::{
+    cdf::Cdfs,
+    obu::{FilmGrainParams, FrameType},
+    BitDepth, Decoder,
+};
+
+// https://aomediacodec.github.io/av1-spec/#reference-frame-update-process
+
+/// The state the reference frame update process saves for one of the NUM_REF_FRAMES slots.
+#[derive(Debug, Default, Clone)]
+pub struct RefFrame {
+    pub frame_id: u64,
+    pub upscaled_width: u64,
+    pub frame_width: u64,
+    pub frame_height: u64,
+    pub render_width: u64,
+    pub render_height: u64,
+    pub mi_cols: u64,
+    pub mi_rows: u64,
+    pub frame_type: FrameType,
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+    pub bit_depth: BitDepth,
+    /// [plane][y][x]
+    pub frame: Vec<Vec<Vec<u16>>>,
+    pub gm_params: Vec<Vec<i64>>,
+    pub film_grain_params: FilmGrainParams,
+    /// [row >> 1][col >> 1]
+    pub saved_ref_frames: Vec<Vec<i64>>,
+    /// [row >> 1][col >> 1]
+    pub saved_mvs: Vec<Vec<[i64; 2]>>,
+    pub saved_order_hints: [u64; 8],
+    pub cdfs: Cdfs,
+    pub loop_filter_ref_deltas: [i64; 8],
+    pub loop_filter_mode_deltas: [i64; 2],
+    pub feature_enabled: Vec<Vec<bool>>,
+    pub feature_data: Vec<Vec<i64>>,
+    /// [row][col]
+    pub saved_segment_ids: Vec<Vec<u64>>,
+}
+
+impl Decoder {
+    /// Saves the current frame into every slot selected by refresh_frame_flags.
+    pub(super) fn reference_frame_update(&mut self) {
+        if self.uh.frame_type == FrameType::IntraOnly {
+            assert_ne!(
+                self.uh.refresh_frame_flags, 0xff,
+                "intra only frames can't refresh all reference frames"
+            );
+        }
+
+        let color_config = &self.sequence_header.color_config;
+        for i in 0..Decoder::NUM_REF_FRAMES as usize {
+            if (self.uh.refresh_frame_flags >> i) & 1 == 0 {
+                continue;
+            }
+
+            self.ref_valid[i] = true;
+            self.ref_order_hint[i] = self.order_hint;
+            self.ref_frame_store[i] = RefFrame {
+                frame_id: self.uh.current_frame_id,
+                upscaled_width: self.upscaled_width,
+                frame_width: self.frame_width,
+                frame_height: self.frame_height,
+                render_width: self.render_width,
+                render_height: self.render_height,
+                mi_cols: self.mi_cols,
+                mi_rows: self.mi_rows,
+                frame_type: self.uh.frame_type,
+                subsampling_x: color_config.subsampling_x,
+                subsampling_y: color_config.subsampling_y,
+                bit_depth: self.bit_depth,
+                frame: self.curr_frame.clone(),
+                gm_params: self.gm_params.clone(),
+                film_grain_params: self.uh.film_grain_params.clone(),
+                saved_ref_frames: self.mf_ref_frames.clone(),
+                saved_mvs: self.mf_mvs.clone(),
+                saved_order_hints: self.order_hints,
+                cdfs: self.frame_cdf.clone(),
+                loop_filter_ref_deltas: self.loop_filter_ref_deltas,
+                loop_filter_mode_deltas: self.loop_filter_mode_deltas,
+                feature_enabled: self.feature_enabled.clone(),
+                feature_data: self.feature_data.clone(),
+                saved_segment_ids: self.segment_ids.clone(),
+            };
+        }
+    }
+
+    /// Loads the CDFs saved with reference frame ctx, as load_cdfs(ctx).
+    pub(super) fn load_cdfs(&mut self, ctx: usize) {
+        self.frame_cdf = self.ref_frame_store[ctx].cdfs.clone();
+    }
+
+    /// Loads the global motion, loop filter and segmentation params saved with reference
+    /// frame prev_frame, as load_previous().
+    pub(super) fn load_previous(&mut self, prev_frame: usize) {
+        let saved = &self.ref_frame_store[prev_frame];
+        self.prev_gm_params = saved.gm_params.clone();
+        self.loop_filter_ref_deltas = saved.loop_filter_ref_deltas;
+        self.loop_filter_mode_deltas = saved.loop_filter_mode_deltas;
+        self.feature_enabled = saved.feature_enabled.clone();
+        self.feature_data = saved.feature_data.clone();
+    }
+
+    /// Loads the segment ids of reference frame prev_frame if it has the same size as the
+    /// current frame, as load_previous_segment_ids().
+    pub(super) fn load_previous_segment_ids(
+        &mut self,
+        prev_frame: usize,
+        segmentation_enabled: bool,
+    ) {
+        let saved = &self.ref_frame_store[prev_frame];
+        if segmentation_enabled && saved.mi_rows == self.mi_rows && saved.mi_cols == self.mi_cols {
+            self.prev_segment_ids = saved.saved_segment_ids.clone();
+        } else {
+            self.prev_segment_ids = vec![vec![0; self.mi_cols as usize]; self.mi_rows as usize];
+        }
+    }
+
+    /// The film grain params saved with reference frame idx, as load_grain_params(idx).
+    pub(super) fn load_grain_params(&self, idx: usize) -> FilmGrainParams {
+        self.ref_frame_store[idx].film_grain_params.clone()
+    }
+
+    /// The signed distance between two order hints, taking wrap around into account.
+    pub(super) fn get_relative_dist(&self, a: u64, b: u64) -> i64 {
+        if !self.sequence_header.enable_order_hint {
+            return 0;
+        }
+
+        let diff = a as i64 - b as i64;
+        let m = 1 << (self.order_hint_bits - 1);
+
+        (diff & (m - 1)) - (diff & m)
+    }
+}
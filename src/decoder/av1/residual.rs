@@ -0,0 +1,428 @@
+use super::{
+    block::{SubSize, TxSize, UvMode, YMode},
+    obu::TxMode,
+    symbol::SyntaxCategory,
+    BitStream, Decoder,
+};
+
+// https://aomediacodec.github.io/av1-spec/#transform-size-syntax
+
+impl Decoder {
+    const MAX_VARTX_DEPTH: usize = 2;
+    const MAX_TX_DEPTH: [usize; Decoder::BLOCK_SIZES] = [
+        0, 1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4, 4, 4, 4, 2, 2, 3, 3, 4, 4,
+    ];
+
+    /// Reads the transform size of the current block. Inter blocks may split their
+    /// transform into a tree of smaller ones, which is stored in `inter_tx_sizes`.
+    pub(super) fn read_block_tx_size(&mut self, b: &mut BitStream) {
+        let bw4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize] as u64;
+        let bh4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize] as u64;
+
+        let category = self.symbol.set_category(SyntaxCategory::Transform);
+        if self.tx_mode == TxMode::Select
+            && self.mi_size > SubSize::Block4x4
+            && self.is_inter
+            && !self.skip
+            && !self.lossless
+        {
+            let max_tx_sz = Decoder::MAX_TX_SIZE_RECT[self.mi_size as usize];
+            let tx_w4 = (max_tx_sz.width() / Decoder::MI_SIZE as usize) as u64;
+            let tx_h4 = (max_tx_sz.height() / Decoder::MI_SIZE as usize) as u64;
+
+            for row in (self.mi_row..self.mi_row + bh4).step_by(tx_h4 as usize) {
+                for col in (self.mi_col..self.mi_col + bw4).step_by(tx_w4 as usize) {
+                    self.read_var_tx_size(b, row, col, max_tx_sz, 0);
+                }
+            }
+        } else {
+            self.read_tx_size(b, !self.skip || !self.is_inter);
+
+            for (row, col) in self.block_positions() {
+                self.inter_tx_sizes[row][col] = self.tx_size;
+            }
+        }
+        self.symbol.set_category(category);
+    }
+
+    fn read_var_tx_size(
+        &mut self,
+        b: &mut BitStream,
+        row: u64,
+        col: u64,
+        tx_sz: TxSize,
+        depth: usize,
+    ) {
+        if row >= self.mi_rows || col >= self.mi_cols {
+            return;
+        }
+
+        let txfm_split = if tx_sz == TxSize::Tx4x4 || depth == Decoder::MAX_VARTX_DEPTH {
+            false
+        } else {
+            let ctx = self.txfm_split_ctx(row as usize, col as usize, tx_sz);
+            self.symbol.read_symbol(b, &mut self.cdf.txfm_split[ctx]) != 0
+        };
+
+        let w4 = (tx_sz.width() / Decoder::MI_SIZE as usize) as u64;
+        let h4 = (tx_sz.height() / Decoder::MI_SIZE as usize) as u64;
+
+        if txfm_split {
+            let sub_tx_sz = tx_sz.split();
+            let step_w = sub_tx_sz.width() / Decoder::MI_SIZE as usize;
+            let step_h = sub_tx_sz.height() / Decoder::MI_SIZE as usize;
+
+            for i in (0..h4).step_by(step_h) {
+                for j in (0..w4).step_by(step_w) {
+                    self.read_var_tx_size(b, row + i, col + j, sub_tx_sz, depth + 1);
+                }
+            }
+        } else {
+            for i in row..(row + h4).min(self.mi_rows) {
+                for j in col..(col + w4).min(self.mi_cols) {
+                    self.inter_tx_sizes[i as usize][j as usize] = tx_sz;
+                }
+            }
+
+            self.tx_size = tx_sz;
+        }
+    }
+
+    fn read_tx_size(&mut self, b: &mut BitStream, allow_select: bool) {
+        if self.lossless {
+            self.tx_size = TxSize::Tx4x4;
+            return;
+        }
+
+        let max_rect_tx_size = Decoder::MAX_TX_SIZE_RECT[self.mi_size as usize];
+        let max_tx_depth = Decoder::MAX_TX_DEPTH[self.mi_size as usize];
+        self.tx_size = max_rect_tx_size;
+
+        if self.mi_size > SubSize::Block4x4 && allow_select && self.tx_mode == TxMode::Select {
+            let ctx = self.tx_depth_ctx(max_rect_tx_size);
+            let tx_depth = self
+                .symbol
+                .read_symbol(b, &mut self.cdf.tx_size[max_tx_depth - 1][ctx]);
+
+            for _ in 0..tx_depth {
+                self.tx_size = self.tx_size.split();
+            }
+        }
+    }
+
+    fn tx_depth_ctx(&self, max_rect_tx_size: TxSize) -> usize {
+        let row = self.mi_row as usize;
+        let col = self.mi_col as usize;
+
+        let above_w = if self.avail_u && self.is_inters[row - 1][col] {
+            self.mi_sizes[row - 1][col].width()
+        } else if self.avail_u {
+            self.get_above_tx_width(row, col)
+        } else {
+            0
+        };
+
+        let left_h = if self.avail_l && self.is_inters[row][col - 1] {
+            self.mi_sizes[row][col - 1].height()
+        } else if self.avail_l {
+            self.get_left_tx_height(row, col)
+        } else {
+            0
+        };
+
+        (above_w >= max_rect_tx_size.width()) as usize
+            + (left_h >= max_rect_tx_size.height()) as usize
+    }
+
+    fn txfm_split_ctx(&self, row: usize, col: usize, tx_sz: TxSize) -> usize {
+        let above = (self.get_above_tx_width(row, col) < tx_sz.width()) as usize;
+        let left = (self.get_left_tx_height(row, col) < tx_sz.height()) as usize;
+        let size = self.mi_size.width().max(self.mi_size.height()).min(64);
+        let max_tx_sz = TxSize::square(size);
+
+        (tx_sz.sqr_up() != max_tx_sz) as usize * 3
+            + (TxSize::Tx64x64 as usize - max_tx_sz as usize) * 6
+            + above
+            + left
+    }
+
+    fn get_above_tx_width(&self, row: usize, col: usize) -> usize {
+        if row as u64 == self.mi_row {
+            if !self.avail_u {
+                return 64;
+            } else if self.skips[row - 1][col] && self.is_inters[row - 1][col] {
+                return self.mi_sizes[row - 1][col].width();
+            }
+        }
+
+        self.inter_tx_sizes[row - 1][col].width()
+    }
+
+    fn get_left_tx_height(&self, row: usize, col: usize) -> usize {
+        if col as u64 == self.mi_col {
+            if !self.avail_l {
+                return 64;
+            } else if self.skips[row][col - 1] && self.is_inters[row][col - 1] {
+                return self.mi_sizes[row][col - 1].height();
+            }
+        }
+
+        self.inter_tx_sizes[row][col - 1].height()
+    }
+
+    /// Decodes the residual of the current block in chunks of at most 64x64 luma samples,
+    /// predicting intra blocks one transform block at a time.
+    pub(super) fn residual(&mut self, b: &mut BitStream) {
+        let width_chunks = (self.mi_size.width() >> 6).max(1);
+        let height_chunks = (self.mi_size.height() >> 6).max(1);
+        let size_chunk = if width_chunks > 1 || height_chunks > 1 {
+            SubSize::Block64x64
+        } else {
+            self.mi_size
+        };
+        let color_config = &self.sequence_header.color_config;
+        let (subsampling_x, subsampling_y) = (
+            color_config.subsampling_x as usize,
+            color_config.subsampling_y as usize,
+        );
+        let num_planes = if self.has_chroma { 3 } else { 1 };
+
+        for chunk_y in 0..height_chunks {
+            for chunk_x in 0..width_chunks {
+                let mi_row_chunk = self.mi_row as usize + (chunk_y << 4);
+                let mi_col_chunk = self.mi_col as usize + (chunk_x << 4);
+
+                for plane in 0..num_planes {
+                    let tx_sz = if self.lossless {
+                        TxSize::Tx4x4
+                    } else {
+                        self.get_tx_size(plane, self.tx_size)
+                    };
+                    let step_x = tx_sz.width() >> 2;
+                    let step_y = tx_sz.height() >> 2;
+                    let plane_sz = self.get_plane_residual_size(size_chunk, plane);
+                    let num4x4_w = Decoder::NUM_4X4_BLOCKS_WIDE[plane_sz as usize];
+                    let num4x4_h = Decoder::NUM_4X4_BLOCKS_HIGH[plane_sz as usize];
+                    let (sub_x, sub_y) = if plane > 0 {
+                        (subsampling_x, subsampling_y)
+                    } else {
+                        (0, 0)
+                    };
+                    let base_x = (mi_col_chunk >> sub_x) * Decoder::MI_SIZE as usize;
+                    let base_y = (mi_row_chunk >> sub_y) * Decoder::MI_SIZE as usize;
+
+                    if self.is_inter && !self.lossless && plane == 0 {
+                        self.transform_tree(b, base_x, base_y, num4x4_w * 4, num4x4_h * 4);
+                    } else {
+                        let base_x_block =
+                            (self.mi_col as usize >> sub_x) * Decoder::MI_SIZE as usize;
+                        let base_y_block =
+                            (self.mi_row as usize >> sub_y) * Decoder::MI_SIZE as usize;
+
+                        for y in (0..num4x4_h).step_by(step_y) {
+                            for x in (0..num4x4_w).step_by(step_x) {
+                                self.transform_block(
+                                    b,
+                                    plane,
+                                    base_x_block,
+                                    base_y_block,
+                                    tx_sz,
+                                    x + ((chunk_x << 4) >> sub_x),
+                                    y + ((chunk_y << 4) >> sub_y),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Splits the luma of an inter block into the transform blocks read by
+    /// read_var_tx_size().
+    fn transform_tree(
+        &mut self,
+        b: &mut BitStream,
+        start_x: usize,
+        start_y: usize,
+        w: usize,
+        h: usize,
+    ) {
+        let max_x = self.mi_cols as usize * Decoder::MI_SIZE as usize;
+        let max_y = self.mi_rows as usize * Decoder::MI_SIZE as usize;
+        if start_x >= max_x || start_y >= max_y {
+            return;
+        }
+
+        let row = start_y >> 2;
+        let col = start_x >> 2;
+        let tx_sz = self.inter_tx_sizes[row][col];
+
+        if w <= tx_sz.width() && h <= tx_sz.height() {
+            let tx_sz = TxSize::find(w, h);
+            self.transform_block(b, 0, start_x, start_y, tx_sz, 0, 0);
+        } else if w > h {
+            self.transform_tree(b, start_x, start_y, w / 2, h);
+            self.transform_tree(b, start_x + w / 2, start_y, w / 2, h);
+        } else if w < h {
+            self.transform_tree(b, start_x, start_y, w, h / 2);
+            self.transform_tree(b, start_x, start_y + h / 2, w, h / 2);
+        } else {
+            self.transform_tree(b, start_x, start_y, w / 2, h / 2);
+            self.transform_tree(b, start_x + w / 2, start_y, w / 2, h / 2);
+            self.transform_tree(b, start_x, start_y + h / 2, w / 2, h / 2);
+            self.transform_tree(b, start_x + w / 2, start_y + h / 2, w / 2, h / 2);
+        }
+    }
+
+    /// Predicts (for intra blocks) and reconstructs one transform block. `x` and `y` are
+    /// in units of 4x4 blocks relative to `base_x` and `base_y`.
+    #[allow(clippy::too_many_arguments)]
+    fn transform_block(
+        &mut self,
+        b: &mut BitStream,
+        plane: usize,
+        base_x: usize,
+        base_y: usize,
+        tx_sz: TxSize,
+        x: usize,
+        y: usize,
+    ) {
+        let start_x = base_x + 4 * x;
+        let start_y = base_y + 4 * y;
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as usize,
+                color_config.subsampling_y as usize,
+            )
+        } else {
+            (0, 0)
+        };
+        let row = (start_y << sub_y) >> 2;
+        let col = (start_x << sub_x) >> 2;
+        let sb_mask = if self.sequence_header.use_128x128_superblock {
+            31
+        } else {
+            15
+        };
+        let sub_block_mi_row = row & sb_mask;
+        let sub_block_mi_col = col & sb_mask;
+        let step_x = tx_sz.width() >> 2;
+        let step_y = tx_sz.height() >> 2;
+        let max_x = (self.mi_cols as usize * Decoder::MI_SIZE as usize) >> sub_x;
+        let max_y = (self.mi_rows as usize * Decoder::MI_SIZE as usize) >> sub_y;
+        if start_x >= max_x || start_y >= max_y {
+            return;
+        }
+
+        if !self.is_inter {
+            if (plane == 0 && self.palette_size_y != 0) || (plane != 0 && self.palette_size_uv != 0)
+            {
+                self.predict_palette(plane, start_x, start_y, x, y, tx_sz);
+            } else {
+                let is_cfl = plane > 0 && self.uv_mode == UvMode::Cfl;
+                let mode = if plane == 0 {
+                    self.y_mode
+                } else if is_cfl {
+                    YMode::Dc
+                } else {
+                    YMode::new(self.uv_mode as u64)
+                };
+                let (avail_l, avail_u) = if plane == 0 {
+                    (self.avail_l, self.avail_u)
+                } else {
+                    (self.avail_l_chroma, self.avail_u_chroma)
+                };
+                // block_decoded is offset by one in both directions.
+                let decoded_row = (sub_block_mi_row >> sub_y) + 1;
+                let decoded_col = (sub_block_mi_col >> sub_x) + 1;
+                let have_above_right =
+                    self.block_decoded[plane][decoded_row - 1][decoded_col + step_x];
+                let have_below_left =
+                    self.block_decoded[plane][decoded_row + step_y][decoded_col - 1];
+
+                self.predict_intra(
+                    plane,
+                    start_x,
+                    start_y,
+                    avail_l || x > 0,
+                    avail_u || y > 0,
+                    have_above_right,
+                    have_below_left,
+                    mode,
+                    tx_sz.width().trailing_zeros() as usize,
+                    tx_sz.height().trailing_zeros() as usize,
+                );
+
+                if is_cfl {
+                    self.predict_chroma_from_luma(plane, start_x, start_y, tx_sz);
+                }
+            }
+
+            if plane == 0 {
+                self.max_luma_w = start_x + step_x * 4;
+                self.max_luma_h = start_y + step_y * 4;
+            }
+        }
+
+        if !self.skip {
+            let eob = self.coeffs(b, start_x, start_y, plane, tx_sz);
+            if eob > 0 {
+                self.reconstruct(plane, start_x, start_y, tx_sz);
+            }
+        }
+
+        for i in 0..step_y {
+            for j in 0..step_x {
+                // Transform blocks may extend past the bottom and right edges of the frame.
+                let (lf_row, lf_col) = ((row >> sub_y) + i, (col >> sub_x) + j);
+                if lf_row < self.mi_rows as usize && lf_col < self.mi_cols as usize {
+                    self.loopfilter_tx_sizes[plane][lf_row][lf_col] = tx_sz;
+                }
+                self.block_decoded[plane][(sub_block_mi_row >> sub_y) + i + 1]
+                    [(sub_block_mi_col >> sub_x) + j + 1] = true;
+            }
+        }
+    }
+
+    /// Dequantizes the coefficients read by coeffs(), inverse transforms them and adds
+    /// the residual to the prediction.
+    fn reconstruct(&mut self, plane: usize, x: usize, y: usize, tx_sz: TxSize) {
+        let dq_denom_shift = match tx_sz {
+            TxSize::Tx64x64 | TxSize::Tx32x64 | TxSize::Tx64x32 => 2,
+            TxSize::Tx32x32
+            | TxSize::Tx16x32
+            | TxSize::Tx32x16
+            | TxSize::Tx16x64
+            | TxSize::Tx64x16 => 1,
+            _ => 0,
+        };
+        let tw = tx_sz.width().min(32);
+        let th = tx_sz.height().min(32);
+        let dc_quant = self.get_dc_quant(plane);
+        let ac_quant = self.get_ac_quant(plane);
+        let max = (1i64 << (7 + self.bit_depth as i64)) - 1;
+        let min = -(1i64 << (7 + self.bit_depth as i64));
+
+        let mut dequant = vec![vec![0i64; tw]; th];
+        for (i, row) in dequant.iter_mut().enumerate() {
+            for (j, dq) in row.iter_mut().enumerate() {
+                let coef = self.quant[i * tw + j];
+                if coef == 0 {
+                    continue;
+                }
+
+                let q = if i == 0 && j == 0 { dc_quant } else { ac_quant };
+                let q = self.apply_qm(plane, tx_sz, i, j, q);
+                let magnitude = ((coef.abs() * q) & 0xFFFFFF) >> dq_denom_shift;
+                let value = if coef < 0 { -magnitude } else { magnitude };
+                *dq = value.clamp(min, max);
+            }
+        }
+
+        let residual =
+            self.inverse_transform_2d(&dequant, tx_sz, self.plane_tx_type, self.lossless);
+        self.add_residual(plane, x, y, &residual);
+    }
+}
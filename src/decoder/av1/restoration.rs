@@ -0,0 +1,578 @@
+use super::{block::SubSize, round2, symbol::SyntaxCategory, BitStream, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#loop-restoration-process
+
+/// The rows a restoration unit may read from the CDEF output. Rows above and below the
+/// current 64 row stripe come from the deblocked frame instead, and only two of them.
+#[derive(Debug, Clone, Copy)]
+struct Stripe {
+    start_y: i64,
+    end_y: i64,
+    plane_end_x: i64,
+    plane_end_y: i64,
+}
+
+impl Decoder {
+    pub const RESTORE_NONE: u64 = 0;
+    pub const RESTORE_WIENER: u64 = 1;
+    pub const RESTORE_SGRPROJ: u64 = 2;
+    pub const RESTORE_SWITCHABLE: u64 = 3;
+
+    const SGRPROJ_PARAMS_BITS: u64 = 4;
+    const SGRPROJ_PRJ_SUBEXP_K: u64 = 4;
+    const SGRPROJ_PRJ_BITS: i64 = 7;
+    const SGRPROJ_RST_BITS: i64 = 4;
+    const SGRPROJ_MTABLE_BITS: i64 = 20;
+    const SGRPROJ_RECIP_BITS: i64 = 12;
+    const SGRPROJ_SGR_BITS: i64 = 8;
+
+    const WIENER_TAPS_MIN: [i64; 3] = [-5, -23, -17];
+    const WIENER_TAPS_MAX: [i64; 3] = [10, 8, 46];
+    const WIENER_TAPS_K: [u64; 3] = [1, 2, 3];
+    const SGRPROJ_XQD_MIN: [i64; 2] = [-96, -32];
+    const SGRPROJ_XQD_MAX: [i64; 2] = [31, 95];
+
+    /// Sgr_Params[set] holds r0, e0, r1, e1: the radius and noise parameter of each pass.
+    const SGR_PARAMS: [[i64; 4]; 16] = [
+        [2, 12, 1, 4],
+        [2, 15, 1, 6],
+        [2, 18, 1, 8],
+        [2, 21, 1, 9],
+        [2, 24, 1, 10],
+        [2, 29, 1, 11],
+        [2, 36, 1, 12],
+        [2, 45, 1, 13],
+        [2, 56, 1, 14],
+        [2, 68, 1, 15],
+        [0, 0, 1, 5],
+        [0, 0, 1, 8],
+        [0, 0, 1, 11],
+        [0, 0, 1, 14],
+        [2, 30, 0, 0],
+        [2, 75, 0, 0],
+    ];
+
+    pub(super) fn count_units_in_frame(unit_size: u64, frame_size: u64) -> u64 {
+        ((frame_size + (unit_size >> 1)) / unit_size).max(1)
+    }
+
+    /// The number of restoration unit rows and columns of a plane.
+    fn restoration_units(&self, plane: usize) -> (usize, usize) {
+        let (sub_x, sub_y) = self.plane_subsampling(plane);
+        let unit_size = self.loop_restoration_size[plane];
+
+        let unit_rows = Decoder::count_units_in_frame(
+            unit_size,
+            round2(self.frame_height as i64, sub_y) as u64,
+        );
+        let unit_cols = Decoder::count_units_in_frame(
+            unit_size,
+            round2(self.upscaled_width as i64, sub_x) as u64,
+        );
+
+        (unit_rows as usize, unit_cols as usize)
+    }
+
+    fn plane_subsampling(&self, plane: usize) -> (i64, i64) {
+        if plane == 0 {
+            (0, 0)
+        } else {
+            let color_config = &self.sequence_header.color_config;
+            (
+                color_config.subsampling_x as i64,
+                color_config.subsampling_y as i64,
+            )
+        }
+    }
+
+    /// Allocates the per unit parameters once the restoration unit sizes are known.
+    pub(super) fn init_restoration_units(&mut self) {
+        let num_planes = self.num_planes as usize;
+        self.lr_type = vec![vec![]; num_planes];
+        self.lr_wiener = vec![vec![]; num_planes];
+        self.lr_sgr_set = vec![vec![]; num_planes];
+        self.lr_sgr_xqd = vec![vec![]; num_planes];
+
+        for plane in 0..num_planes {
+            if self.frame_restoration_type[plane] == Decoder::RESTORE_NONE {
+                continue;
+            }
+
+            let (rows, cols) = self.restoration_units(plane);
+            self.lr_type[plane] = vec![vec![Decoder::RESTORE_NONE; cols]; rows];
+            self.lr_wiener[plane] = vec![vec![[[0; 3]; 2]; cols]; rows];
+            self.lr_sgr_set[plane] = vec![vec![0; cols]; rows];
+            self.lr_sgr_xqd[plane] = vec![vec![[0; 2]; cols]; rows];
+        }
+    }
+
+    /// Reads the parameters of every restoration unit whose top left corner lies in the
+    /// superblock at (r, c).
+    pub(super) fn read_lr(&mut self, b: &mut BitStream, r: usize, c: usize, b_size: SubSize) {
+        if self.uh.allow_intrabc {
+            return;
+        }
+
+        let w = Decoder::NUM_4X4_BLOCKS_WIDE[b_size as usize];
+        let h = Decoder::NUM_4X4_BLOCKS_HIGH[b_size as usize];
+
+        let category = self.symbol.set_category(SyntaxCategory::Filter);
+        for plane in 0..self.num_planes as usize {
+            if self.frame_restoration_type[plane] == Decoder::RESTORE_NONE {
+                continue;
+            }
+
+            let (sub_x, sub_y) = self.plane_subsampling(plane);
+            let unit_size = self.loop_restoration_size[plane] as usize;
+            let (unit_rows, unit_cols) = self.restoration_units(plane);
+
+            let mi_size = Decoder::MI_SIZE as usize;
+            let unit_row_start = (r * (mi_size >> sub_y)).div_ceil(unit_size);
+            let unit_row_end = unit_rows.min(((r + h) * (mi_size >> sub_y)).div_ceil(unit_size));
+
+            let (numerator, denominator) = if self.use_superres {
+                (
+                    (mi_size >> sub_x) * self.superres_denom as usize,
+                    unit_size * Decoder::SUPERRES_NUM as usize,
+                )
+            } else {
+                (mi_size >> sub_x, unit_size)
+            };
+            let unit_col_start = (c * numerator).div_ceil(denominator);
+            let unit_col_end = unit_cols.min(((c + w) * numerator).div_ceil(denominator));
+
+            for unit_row in unit_row_start..unit_row_end {
+                for unit_col in unit_col_start..unit_col_end {
+                    self.read_lr_unit(b, plane, unit_row, unit_col);
+                }
+            }
+        }
+        self.symbol.set_category(category);
+    }
+
+    fn read_lr_unit(&mut self, b: &mut BitStream, plane: usize, unit_row: usize, unit_col: usize) {
+        let restoration_type = match self.frame_restoration_type[plane] {
+            Decoder::RESTORE_WIENER => {
+                if self.symbol.read_symbol(b, &mut self.cdf.use_wiener) == 1 {
+                    Decoder::RESTORE_WIENER
+                } else {
+                    Decoder::RESTORE_NONE
+                }
+            }
+            Decoder::RESTORE_SGRPROJ => {
+                if self.symbol.read_symbol(b, &mut self.cdf.use_sgrproj) == 1 {
+                    Decoder::RESTORE_SGRPROJ
+                } else {
+                    Decoder::RESTORE_NONE
+                }
+            }
+            _ => self.symbol.read_symbol(b, &mut self.cdf.restoration_type) as u64,
+        };
+        self.lr_type[plane][unit_row][unit_col] = restoration_type;
+
+        if restoration_type == Decoder::RESTORE_WIENER {
+            for pass in 0..2 {
+                let first_coeff = if plane != 0 {
+                    self.lr_wiener[plane][unit_row][unit_col][pass][0] = 0;
+                    1
+                } else {
+                    0
+                };
+
+                for j in first_coeff..3 {
+                    let v = self.decode_signed_subexp_with_ref_bool(
+                        b,
+                        Decoder::WIENER_TAPS_MIN[j],
+                        Decoder::WIENER_TAPS_MAX[j] + 1,
+                        Decoder::WIENER_TAPS_K[j],
+                        self.ref_lr_wiener[plane][pass][j],
+                    );
+                    self.lr_wiener[plane][unit_row][unit_col][pass][j] = v;
+                    self.ref_lr_wiener[plane][pass][j] = v;
+                }
+            }
+        } else if restoration_type == Decoder::RESTORE_SGRPROJ {
+            let set = self.symbol.read_literal(b, Decoder::SGRPROJ_PARAMS_BITS) as usize;
+            self.lr_sgr_set[plane][unit_row][unit_col] = set;
+
+            for i in 0..2 {
+                let radius = Decoder::SGR_PARAMS[set][i * 2];
+                let min = Decoder::SGRPROJ_XQD_MIN[i];
+                let max = Decoder::SGRPROJ_XQD_MAX[i];
+
+                let v = if radius != 0 {
+                    self.decode_signed_subexp_with_ref_bool(
+                        b,
+                        min,
+                        max + 1,
+                        Decoder::SGRPROJ_PRJ_SUBEXP_K,
+                        self.ref_sgr_xqd[plane][i],
+                    )
+                } else if i == 1 {
+                    ((1 << Decoder::SGRPROJ_PRJ_BITS) - self.ref_sgr_xqd[plane][0]).clamp(min, max)
+                } else {
+                    0
+                };
+
+                self.lr_sgr_xqd[plane][unit_row][unit_col][i] = v;
+                self.ref_sgr_xqd[plane][i] = v;
+            }
+        }
+    }
+
+    fn decode_signed_subexp_with_ref_bool(
+        &mut self,
+        b: &mut BitStream,
+        low: i64,
+        high: i64,
+        k: u64,
+        r: i64,
+    ) -> i64 {
+        let x =
+            self.decode_unsigned_subexp_with_ref_bool(b, (high - low) as u64, k, (r - low) as u64);
+        x as i64 + low
+    }
+
+    fn decode_unsigned_subexp_with_ref_bool(
+        &mut self,
+        b: &mut BitStream,
+        mx: u64,
+        k: u64,
+        r: u64,
+    ) -> u64 {
+        let v = self.decode_subexp_bool(b, mx, k);
+        if (r << 1) <= mx {
+            Decoder::inverse_recenter(r, v)
+        } else {
+            mx - 1 - Decoder::inverse_recenter(mx - 1 - r, v)
+        }
+    }
+
+    fn decode_subexp_bool(&mut self, b: &mut BitStream, num_syms: u64, k: u64) -> u64 {
+        let mut i = 0;
+        let mut mk = 0;
+
+        loop {
+            let b2 = if i != 0 { k + i - 1 } else { k };
+            let a = 1 << b2;
+
+            if num_syms <= mk + 3 * a {
+                return self.symbol.read_ns(b, num_syms - mk) + mk;
+            }
+
+            if self.symbol.read_literal(b, 1) != 0 {
+                i += 1;
+                mk += a;
+            } else {
+                return self.symbol.read_literal(b, b2) + mk;
+            }
+        }
+    }
+
+    pub(super) fn inverse_recenter(r: u64, v: u64) -> u64 {
+        if v > 2 * r {
+            v
+        } else if v & 1 != 0 {
+            r - ((v + 1) >> 1)
+        } else {
+            r + (v >> 1)
+        }
+    }
+
+    /// Filters the CDEF output into lr_frame, one 4x4 block (in luma samples) at a time.
+    pub(super) fn lr_frame(&mut self) {
+        self.lr_frame = self.cdef_frame.clone();
+
+        if !self.uses_lr {
+            return;
+        }
+
+        let mi_size = Decoder::MI_SIZE as usize;
+        for y in (0..self.frame_height as usize).step_by(mi_size) {
+            for x in (0..self.upscaled_width as usize).step_by(mi_size) {
+                for plane in 0..self.num_planes as usize {
+                    if self.frame_restoration_type[plane] != Decoder::RESTORE_NONE {
+                        self.loop_restore_block(plane, y / mi_size, x / mi_size);
+                    }
+                }
+            }
+        }
+    }
+
+    fn loop_restore_block(&mut self, plane: usize, row: usize, col: usize) {
+        let mi_size = Decoder::MI_SIZE as i64;
+        let luma_y = row as i64 * mi_size;
+        let stripe_num = (luma_y + 8) / 64;
+
+        let (sub_x, sub_y) = self.plane_subsampling(plane);
+        let stripe_start_y = (-8 + stripe_num * 64) >> sub_y;
+        let stripe_end_y = stripe_start_y + (64 >> sub_y) - 1;
+
+        let unit_size = self.loop_restoration_size[plane] as i64;
+        let (unit_rows, unit_cols) = self.restoration_units(plane);
+        let unit_row =
+            (unit_rows - 1).min((((row as i64 * mi_size + 8) >> sub_y) / unit_size) as usize);
+        let unit_col =
+            (unit_cols - 1).min((((col as i64 * mi_size) >> sub_x) / unit_size) as usize);
+
+        let stripe = Stripe {
+            start_y: stripe_start_y,
+            end_y: stripe_end_y,
+            plane_end_x: round2(self.upscaled_width as i64, sub_x) - 1,
+            plane_end_y: round2(self.frame_height as i64, sub_y) - 1,
+        };
+
+        let x = (col as i64 * mi_size) >> sub_x;
+        let y = (row as i64 * mi_size) >> sub_y;
+        let w = (mi_size >> sub_x).min(stripe.plane_end_x - x + 1);
+        let h = (mi_size >> sub_y).min(stripe.plane_end_y - y + 1);
+        if w <= 0 || h <= 0 {
+            return;
+        }
+
+        let (x, y, w, h) = (x as usize, y as usize, w as usize, h as usize);
+        match self.lr_type[plane][unit_row][unit_col] {
+            Decoder::RESTORE_WIENER => {
+                self.wiener_filter(plane, unit_row, unit_col, x, y, w, h, stripe)
+            }
+            Decoder::RESTORE_SGRPROJ => {
+                self.self_guided_filter(plane, unit_row, unit_col, x, y, w, h, stripe)
+            }
+            _ => (),
+        }
+    }
+
+    /// Rows outside the current stripe are taken from the deblocked frame, clamped to the
+    /// two rows saved on either side.
+    fn get_source_sample(&self, plane: usize, x: i64, y: i64, stripe: Stripe) -> i64 {
+        let x = x.min(stripe.plane_end_x).max(0) as usize;
+        let y = y.min(stripe.plane_end_y).max(0);
+
+        if y < stripe.start_y {
+            let y = (stripe.start_y - 2).max(y) as usize;
+            self.curr_frame[plane][y][x] as i64
+        } else if y > stripe.end_y {
+            let y = (stripe.end_y + 2).min(y) as usize;
+            self.curr_frame[plane][y][x] as i64
+        } else {
+            self.cdef_frame[plane][y as usize][x] as i64
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn wiener_filter(
+        &mut self,
+        plane: usize,
+        unit_row: usize,
+        unit_col: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        stripe: Stripe,
+    ) {
+        self.rounding_variables_derivation(false);
+
+        let coeffs = self.lr_wiener[plane][unit_row][unit_col];
+        let vfilter = Decoder::wiener_coefficient(coeffs[0]);
+        let hfilter = Decoder::wiener_coefficient(coeffs[1]);
+
+        let bit_depth = self.bit_depth as i64;
+        let offset = 1 << (bit_depth + Decoder::FILTER_BITS - self.inter_round0 - 1);
+        let limit = (1 << (bit_depth + 1 + Decoder::FILTER_BITS - self.inter_round0)) - 1;
+
+        let (x, y) = (x as i64, y as i64);
+        let intermediate: Vec<Vec<i64>> = (0..h as i64 + 6)
+            .map(|r| {
+                (0..w as i64)
+                    .map(|c| {
+                        let s: i64 = hfilter
+                            .iter()
+                            .zip(0..)
+                            .map(|(f, t)| {
+                                f * self.get_source_sample(plane, x + c + t - 3, y + r - 3, stripe)
+                            })
+                            .sum();
+
+                        round2(s, self.inter_round0).clamp(-offset, limit - offset)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for r in 0..h {
+            for c in 0..w {
+                let s: i64 = vfilter
+                    .iter()
+                    .zip(&intermediate[r..r + 7])
+                    .map(|(f, row)| f * row[c])
+                    .sum();
+
+                self.lr_frame[plane][y as usize + r][x as usize + c] =
+                    self.clip1(round2(s, self.inter_round1).max(0) as u64);
+            }
+        }
+    }
+
+    /// Expands the three coded taps into a symmetric 7-tap filter summing to 128.
+    fn wiener_coefficient(coeff: [i64; 3]) -> [i64; 7] {
+        let mut filter = [0; 7];
+        filter[3] = 128;
+
+        for (i, &c) in coeff.iter().enumerate() {
+            filter[i] = c;
+            filter[6 - i] = c;
+            filter[3] -= 2 * c;
+        }
+
+        filter
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn self_guided_filter(
+        &mut self,
+        plane: usize,
+        unit_row: usize,
+        unit_col: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        stripe: Stripe,
+    ) {
+        let set = self.lr_sgr_set[plane][unit_row][unit_col];
+        let r0 = Decoder::SGR_PARAMS[set][0];
+        let r1 = Decoder::SGR_PARAMS[set][2];
+
+        let flt0 = if r0 != 0 {
+            self.box_filter(plane, x, y, w, h, set, 0, stripe)
+        } else {
+            vec![]
+        };
+        let flt1 = if r1 != 0 {
+            self.box_filter(plane, x, y, w, h, set, 1, stripe)
+        } else {
+            vec![]
+        };
+
+        let [w0, w1] = self.lr_sgr_xqd[plane][unit_row][unit_col];
+        let w2 = (1 << Decoder::SGRPROJ_PRJ_BITS) - w0 - w1;
+
+        for i in 0..h {
+            for j in 0..w {
+                let u = (self.cdef_frame[plane][y + i][x + j] as i64) << Decoder::SGRPROJ_RST_BITS;
+                let mut v = w1 * u;
+                v += if r0 != 0 { w0 * flt0[i][j] } else { w0 * u };
+                v += if r1 != 0 { w2 * flt1[i][j] } else { w2 * u };
+
+                let s = round2(v, Decoder::SGRPROJ_RST_BITS + Decoder::SGRPROJ_PRJ_BITS);
+                self.lr_frame[plane][y + i][x + j] = self.clip1(s.max(0) as u64);
+            }
+        }
+    }
+
+    /// Computes the guided filter output of one pass. Pass 0 only uses every other row of
+    /// the box sums.
+    #[allow(clippy::too_many_arguments)]
+    fn box_filter(
+        &self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        set: usize,
+        pass: usize,
+        stripe: Stripe,
+    ) -> Vec<Vec<i64>> {
+        let r = Decoder::SGR_PARAMS[set][pass * 2];
+        let eps = Decoder::SGR_PARAMS[set][pass * 2 + 1];
+        let bit_depth = self.bit_depth as i64;
+
+        let n = (2 * r + 1) * (2 * r + 1);
+        let n2e = n * n * eps;
+        let s = ((1 << Decoder::SGRPROJ_MTABLE_BITS) + n2e / 2) / n2e;
+        let one_over_n = ((1 << Decoder::SGRPROJ_RECIP_BITS) + n / 2) / n;
+
+        // A and B are offset by one so that index 0 is the row/column before the block.
+        let mut a_table = vec![vec![0; w + 2]; h + 2];
+        let mut b_table = vec![vec![0; w + 2]; h + 2];
+        let (x, y) = (x as i64, y as i64);
+
+        for i in -1..h as i64 + 1 {
+            for j in -1..w as i64 + 1 {
+                let mut a = 0;
+                let mut b = 0;
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        let c = self.get_source_sample(plane, x + j + dx, y + i + dy, stripe);
+                        a += c * c;
+                        b += c;
+                    }
+                }
+
+                let a = round2(a, 2 * (bit_depth - 8));
+                let d = round2(b, bit_depth - 8);
+                let p = (a * n - d * d).max(0);
+                let z = round2(p * s, Decoder::SGRPROJ_MTABLE_BITS);
+
+                let a2 = if z >= 255 {
+                    256
+                } else if z == 0 {
+                    1
+                } else {
+                    ((z << Decoder::SGRPROJ_SGR_BITS) + (z / 2)) / (z + 1)
+                };
+                let b2 = ((1 << Decoder::SGRPROJ_SGR_BITS) - a2) * b * one_over_n;
+
+                a_table[(i + 1) as usize][(j + 1) as usize] = a2;
+                b_table[(i + 1) as usize][(j + 1) as usize] =
+                    round2(b2, Decoder::SGRPROJ_RECIP_BITS);
+            }
+        }
+
+        (0..h)
+            .map(|i| {
+                let shift = if pass == 0 && (i & 1) != 0 { 4 } else { 5 };
+
+                (0..w)
+                    .map(|j| {
+                        let mut a = 0;
+                        let mut b = 0;
+                        for dy in -1..=1i64 {
+                            for dx in -1..=1i64 {
+                                let weight = if pass == 0 {
+                                    if ((i as i64 + dy) & 1) != 0 {
+                                        if dx == 0 {
+                                            6
+                                        } else {
+                                            5
+                                        }
+                                    } else {
+                                        0
+                                    }
+                                } else if dx == 0 || dy == 0 {
+                                    4
+                                } else {
+                                    3
+                                };
+
+                                let ti = (i as i64 + 1 + dy) as usize;
+                                let tj = (j as i64 + 1 + dx) as usize;
+                                a += weight * a_table[ti][tj];
+                                b += weight * b_table[ti][tj];
+                            }
+                        }
+
+                        let v =
+                            a * self.cdef_frame[plane][y as usize + i][x as usize + j] as i64 + b;
+                        round2(
+                            v,
+                            Decoder::SGRPROJ_SGR_BITS + shift - Decoder::SGRPROJ_RST_BITS,
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
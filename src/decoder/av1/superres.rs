@@ -0,0 +1,152 @@
+use super::{round2, Decoder};
+
+// https://aomediacodec.github.io/av1-spec/#upscaling-process
+
+impl Decoder {
+    const SUPERRES_SCALE_BITS: i64 = 14;
+    const SUPERRES_EXTRA_BITS: i64 = 8;
+    const SUPERRES_SCALE_MASK: i64 = (1 << 14) - 1;
+    const SUPERRES_FILTER_OFFSET: i64 = 3;
+
+    /// Upscale_Filter[subpel position], 1/64th sample precision.
+    const UPSCALE_FILTER: [[i64; 8]; 64] = [
+        [0, 0, 0, 128, 0, 0, 0, 0],
+        [0, 0, -1, 128, 2, -1, 0, 0],
+        [0, 1, -3, 127, 4, -2, 1, 0],
+        [0, 1, -4, 127, 6, -3, 1, 0],
+        [0, 2, -6, 126, 8, -3, 1, 0],
+        [0, 2, -7, 125, 11, -4, 1, 0],
+        [-1, 2, -8, 125, 13, -5, 2, 0],
+        [-1, 3, -9, 124, 15, -6, 2, 0],
+        [-1, 3, -10, 123, 18, -6, 2, -1],
+        [-1, 3, -11, 122, 20, -7, 3, -1],
+        [-1, 4, -12, 121, 22, -8, 3, -1],
+        [-1, 4, -13, 120, 25, -9, 3, -1],
+        [-1, 4, -14, 118, 28, -9, 3, -1],
+        [-1, 4, -15, 117, 30, -10, 4, -1],
+        [-1, 5, -16, 116, 32, -11, 4, -1],
+        [-1, 5, -16, 114, 35, -12, 4, -1],
+        [-1, 5, -17, 112, 38, -12, 4, -1],
+        [-1, 5, -18, 111, 40, -13, 5, -1],
+        [-1, 5, -18, 109, 43, -14, 5, -1],
+        [-1, 6, -19, 107, 45, -14, 5, -1],
+        [-1, 6, -19, 105, 48, -15, 5, -1],
+        [-1, 6, -19, 103, 51, -16, 5, -1],
+        [-1, 6, -20, 101, 53, -16, 6, -1],
+        [-1, 6, -20, 99, 56, -17, 6, -1],
+        [-1, 6, -20, 97, 58, -17, 6, -1],
+        [-1, 6, -20, 95, 61, -18, 6, -1],
+        [-2, 7, -20, 93, 64, -18, 6, -2],
+        [-2, 7, -20, 91, 66, -19, 6, -1],
+        [-2, 7, -20, 88, 69, -19, 6, -1],
+        [-2, 7, -20, 86, 71, -19, 6, -1],
+        [-2, 7, -20, 84, 74, -20, 7, -2],
+        [-2, 7, -20, 81, 76, -20, 7, -1],
+        [-2, 7, -20, 79, 79, -20, 7, -2],
+        [-1, 7, -20, 76, 81, -20, 7, -2],
+        [-2, 7, -20, 74, 84, -20, 7, -2],
+        [-1, 6, -19, 71, 86, -20, 7, -2],
+        [-1, 6, -19, 69, 88, -20, 7, -2],
+        [-1, 6, -19, 66, 91, -20, 7, -2],
+        [-2, 6, -18, 64, 93, -20, 7, -2],
+        [-1, 6, -18, 61, 95, -20, 6, -1],
+        [-1, 6, -17, 58, 97, -20, 6, -1],
+        [-1, 6, -17, 56, 99, -20, 6, -1],
+        [-1, 6, -16, 53, 101, -20, 6, -1],
+        [-1, 5, -16, 51, 103, -19, 6, -1],
+        [-1, 5, -15, 48, 105, -19, 6, -1],
+        [-1, 5, -14, 45, 107, -19, 6, -1],
+        [-1, 5, -14, 43, 109, -18, 5, -1],
+        [-1, 5, -13, 40, 111, -18, 5, -1],
+        [-1, 4, -12, 38, 112, -17, 5, -1],
+        [-1, 4, -12, 35, 114, -16, 5, -1],
+        [-1, 4, -11, 32, 116, -16, 5, -1],
+        [-1, 4, -10, 30, 117, -15, 4, -1],
+        [-1, 3, -9, 28, 118, -14, 4, -1],
+        [-1, 3, -9, 25, 120, -13, 4, -1],
+        [-1, 3, -8, 22, 121, -12, 4, -1],
+        [-1, 3, -7, 20, 122, -11, 3, -1],
+        [-1, 2, -6, 18, 123, -10, 3, -1],
+        [0, 2, -6, 15, 124, -9, 3, -1],
+        [0, 2, -5, 13, 125, -8, 2, -1],
+        [0, 1, -4, 11, 125, -7, 2, 0],
+        [0, 1, -3, 8, 126, -6, 2, 0],
+        [0, 1, -3, 6, 127, -4, 1, 0],
+        [0, 1, -2, 4, 127, -3, 1, 0],
+        [0, 0, -1, 2, 128, -1, 0, 0],
+    ];
+
+    /// Stretches every row of a frame coded at frame_width to upscaled_width.
+    pub(super) fn upscaling(&self, frame: &[Vec<Vec<u16>>]) -> Vec<Vec<Vec<u16>>> {
+        let color_config = &self.sequence_header.color_config;
+
+        frame
+            .iter()
+            .enumerate()
+            .map(|(plane, input)| {
+                let (sub_x, sub_y) = if plane == 0 {
+                    (0, 0)
+                } else {
+                    (
+                        color_config.subsampling_x as i64,
+                        color_config.subsampling_y as i64,
+                    )
+                };
+
+                let downscaled_plane_w = round2(self.frame_width as i64, sub_x);
+                let upscaled_plane_w = round2(self.upscaled_width as i64, sub_x);
+                let plane_h = round2(self.frame_height as i64, sub_y) as usize;
+
+                let step_x = ((downscaled_plane_w << Decoder::SUPERRES_SCALE_BITS)
+                    + (upscaled_plane_w / 2))
+                    / upscaled_plane_w;
+                let err = upscaled_plane_w * step_x
+                    - (downscaled_plane_w << Decoder::SUPERRES_SCALE_BITS);
+                let initial_subpel_x = ((-((upscaled_plane_w - downscaled_plane_w)
+                    << (Decoder::SUPERRES_SCALE_BITS - 1))
+                    + upscaled_plane_w / 2)
+                    / upscaled_plane_w
+                    + (1 << (Decoder::SUPERRES_EXTRA_BITS - 1))
+                    - err / 2)
+                    & Decoder::SUPERRES_SCALE_MASK;
+
+                let mi_w = self.mi_cols as i64 >> sub_x;
+                let max_x = mi_w * Decoder::MI_SIZE as i64 - 1;
+
+                // Keep the output padded like the decoded frame so the later stages can
+                // address whole blocks.
+                let width = ((self.upscaled_width as usize + 127) & !127) >> sub_x;
+                let mut output = vec![vec![0; width]; input.len()];
+
+                for (out_row, in_row) in output.iter_mut().zip(input).take(plane_h) {
+                    for (x, out) in out_row
+                        .iter_mut()
+                        .enumerate()
+                        .take(upscaled_plane_w as usize)
+                    {
+                        let src_x = -(1 << Decoder::SUPERRES_SCALE_BITS)
+                            + initial_subpel_x
+                            + x as i64 * step_x;
+                        let src_x_px = src_x >> Decoder::SUPERRES_SCALE_BITS;
+                        let src_x_subpel =
+                            (src_x & Decoder::SUPERRES_SCALE_MASK) >> Decoder::SUPERRES_EXTRA_BITS;
+
+                        let sum: i64 = Decoder::UPSCALE_FILTER[src_x_subpel as usize]
+                            .iter()
+                            .zip(0..)
+                            .map(|(f, k)| {
+                                let sample_x = (src_x_px + k - Decoder::SUPERRES_FILTER_OFFSET)
+                                    .clamp(0, max_x);
+                                f * in_row[sample_x as usize] as i64
+                            })
+                            .sum();
+
+                        *out = self.clip1(round2(sum, Decoder::FILTER_BITS).max(0) as u64);
+                    }
+                }
+
+                output
+            })
+            .collect()
+    }
+}
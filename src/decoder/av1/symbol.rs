@@ -0,0 +1,195 @@
+use super::BitStream;
+
+// https://aomediacodec.github.io/av1-spec/#symbol-decoding-process
+
+/// What a symbol codes, for attributing the bits it costs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyntaxCategory {
+    Partition,
+    /// Everything about a block not covered by another category: skip, segment, modes,
+    /// reference frames, filters and so on.
+    #[default]
+    ModeInfo,
+    Mv,
+    Palette,
+    Transform,
+    Coefficients,
+    /// Loop filter, CDEF and loop restoration parameters coded in the tile.
+    Filter,
+}
+
+impl SyntaxCategory {
+    pub const COUNT: usize = 7;
+    pub const ALL: [SyntaxCategory; SyntaxCategory::COUNT] = [
+        SyntaxCategory::Partition,
+        SyntaxCategory::ModeInfo,
+        SyntaxCategory::Mv,
+        SyntaxCategory::Palette,
+        SyntaxCategory::Transform,
+        SyntaxCategory::Coefficients,
+        SyntaxCategory::Filter,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SyntaxCategory::Partition => "partition",
+            SyntaxCategory::ModeInfo => "mode_info",
+            SyntaxCategory::Mv => "mv",
+            SyntaxCategory::Palette => "palette",
+            SyntaxCategory::Transform => "transform",
+            SyntaxCategory::Coefficients => "coefficients",
+            SyntaxCategory::Filter => "filter",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolDecoder {
+    pub symbol_value: u64,
+    pub symbol_range: u64,
+    pub symbol_max_bits: i64,
+    pub disable_cdf_update: bool,
+    /// The category the symbols read next are counted towards.
+    pub category: SyntaxCategory,
+    /// Bits consumed per category since the last take_bits, indexed by SyntaxCategory.
+    pub bits: [f64; SyntaxCategory::COUNT],
+}
+
+impl SymbolDecoder {
+    const EC_PROB_SHIFT: u64 = 6;
+    const EC_MIN_PROB: u64 = 4;
+
+    pub fn init(&mut self, b: &mut BitStream, sz: usize, disable_cdf_update: bool) {
+        let num_bits = (sz * 8).min(15) as u64;
+        let buf = b.f(num_bits);
+        let padded_buf = buf << (15 - num_bits);
+        self.symbol_value = ((1 << 15) - 1) ^ padded_buf;
+        self.symbol_range = 1 << 15;
+        self.symbol_max_bits = 8 * sz as i64 - 15;
+        self.disable_cdf_update = disable_cdf_update;
+        self.category = SyntaxCategory::default();
+        self.bits = [0.0; SyntaxCategory::COUNT];
+    }
+
+    /// Counts the following symbols towards `category` and returns the previous category,
+    /// for restoring it afterwards.
+    pub fn set_category(&mut self, category: SyntaxCategory) -> SyntaxCategory {
+        std::mem::replace(&mut self.category, category)
+    }
+
+    /// Returns the bits consumed per category since the last call and starts counting
+    /// from zero again.
+    pub fn take_bits(&mut self) -> [f64; SyntaxCategory::COUNT] {
+        std::mem::take(&mut self.bits)
+    }
+
+    pub fn exit(&mut self, b: &mut BitStream) {
+        assert!(
+            self.symbol_max_bits >= -14,
+            "symbol decoder read past the end of the tile: {}",
+            self.symbol_max_bits
+        );
+
+        b.pos += self.symbol_max_bits.max(0) as usize;
+    }
+
+    /// Decodes one symbol using `cdf`, which holds N cumulative probabilities followed by
+    /// the adaptation counter.
+    pub fn read_symbol(&mut self, b: &mut BitStream, cdf: &mut [u16]) -> usize {
+        let n = cdf.len() - 1;
+
+        let mut cur = self.symbol_range;
+        let mut prev;
+        let mut symbol = 0;
+        loop {
+            prev = cur;
+            let f = (1 << 15) - cdf[symbol] as u64;
+            cur = (((self.symbol_range >> 8) * (f >> Self::EC_PROB_SHIFT))
+                >> (7 - Self::EC_PROB_SHIFT))
+                + Self::EC_MIN_PROB * (n - symbol - 1) as u64;
+
+            if self.symbol_value >= cur {
+                break;
+            }
+
+            symbol += 1;
+        }
+
+        // The information content of the symbol is how much it narrows the range.
+        self.bits[self.category as usize] +=
+            (self.symbol_range as f64 / (prev - cur) as f64).log2();
+
+        self.symbol_range = prev - cur;
+        self.symbol_value -= cur;
+
+        let bits = 15 - floor_log2(self.symbol_range);
+        self.symbol_range <<= bits;
+        let num_bits = bits.min(self.symbol_max_bits.max(0) as u64);
+        let new_data = b.f(num_bits);
+        let padded_data = new_data << (bits - num_bits);
+        self.symbol_value = padded_data ^ (((self.symbol_value + 1) << bits) - 1);
+        self.symbol_max_bits -= bits as i64;
+
+        if !self.disable_cdf_update {
+            let rate =
+                3 + (cdf[n] > 15) as u64 + (cdf[n] > 31) as u64 + floor_log2(n as u64).min(2);
+
+            let mut tmp = 0;
+            for (i, c) in cdf.iter_mut().enumerate().take(n - 1) {
+                if i == symbol {
+                    tmp = 1 << 15;
+                }
+
+                if tmp < *c {
+                    *c -= (*c - tmp) >> rate;
+                } else {
+                    *c += (tmp - *c) >> rate;
+                }
+            }
+
+            cdf[n] += (cdf[n] < 32) as u16;
+        }
+
+        symbol
+    }
+
+    pub fn read_bool(&mut self, b: &mut BitStream) -> bool {
+        let mut cdf = [1 << 14, 1 << 15, 0];
+        self.read_symbol(b, &mut cdf) == 1
+    }
+
+    /// L(n)
+    pub fn read_literal(&mut self, b: &mut BitStream, n: u64) -> u64 {
+        let mut x = 0;
+        for _ in 0..n {
+            x = 2 * x + self.read_bool(b) as u64;
+        }
+
+        x
+    }
+
+    /// NS(n)
+    pub fn read_ns(&mut self, b: &mut BitStream, n: u64) -> u64 {
+        let w = floor_log2(n) + 1;
+        let m = (1 << w) - n;
+        let v = self.read_literal(b, w - 1);
+        if v < m {
+            return v;
+        }
+
+        let extra_bit = self.read_literal(b, 1);
+        (v << 1) - m + extra_bit
+    }
+}
+
+pub fn floor_log2(x: u64) -> u64 {
+    63 - x.leading_zeros() as u64
+}
+
+pub fn ceil_log2(x: u64) -> u64 {
+    if x < 2 {
+        0
+    } else {
+        floor_log2(x - 1) + 1
+    }
+}
@@ -0,0 +1,583 @@
+use super::{
+    block::{MotionMode, YMode},
+    obu::WarpModel,
+    round2, round2signed, Decoder,
+};
+
+// https://aomediacodec.github.io/av1-spec/#warp-estimation-process
+
+/// Div_Lut[i] = Round(2^14 * 256 / (256 + i))
+const DIV_LUT: [i64; 257] = {
+    let mut lut = [0; 257];
+    let mut i = 0;
+    while i < 257 {
+        let d = 256 + i as i64;
+        lut[i] = ((1 << 14) * 256 + d / 2) / d;
+        i += 1;
+    }
+
+    lut
+};
+
+impl Decoder {
+    const LEAST_SQUARES_SAMPLES_MAX: usize = 8;
+    const LS_MV_MAX: i64 = 256;
+    const WARPEDMODEL_TRANS_CLAMP: i64 = 1 << 23;
+    const WARPEDMODEL_NONDIAGAFFINE_CLAMP: i64 = 1 << 13;
+    const WARPEDPIXEL_PREC_SHIFTS: i64 = 1 << 6;
+    const WARPEDDIFF_PREC_BITS: i64 = 10;
+    const WARP_PARAM_REDUCE_BITS: i64 = 6;
+    const DIV_LUT_BITS: i64 = 8;
+    const DIV_LUT_PREC_BITS: i64 = 14;
+
+    const WARPED_FILTERS: [[i64; 8]; 193] = [
+        // [-1, 0)
+        [0, 0, 127, 1, 0, 0, 0, 0],
+        [0, -1, 127, 2, 0, 0, 0, 0],
+        [1, -3, 127, 4, -1, 0, 0, 0],
+        [1, -4, 126, 6, -2, 1, 0, 0],
+        [1, -5, 126, 8, -3, 1, 0, 0],
+        [1, -6, 125, 11, -4, 1, 0, 0],
+        [1, -7, 124, 13, -4, 1, 0, 0],
+        [2, -8, 123, 15, -5, 1, 0, 0],
+        [2, -9, 122, 18, -6, 1, 0, 0],
+        [2, -10, 121, 20, -6, 1, 0, 0],
+        [2, -11, 120, 22, -7, 2, 0, 0],
+        [2, -12, 119, 25, -8, 2, 0, 0],
+        [3, -13, 117, 27, -8, 2, 0, 0],
+        [3, -13, 116, 29, -9, 2, 0, 0],
+        [3, -14, 114, 32, -10, 3, 0, 0],
+        [3, -15, 113, 35, -10, 2, 0, 0],
+        [3, -15, 111, 37, -11, 3, 0, 0],
+        [3, -16, 109, 40, -11, 3, 0, 0],
+        [3, -16, 108, 42, -12, 3, 0, 0],
+        [4, -17, 106, 45, -13, 3, 0, 0],
+        [4, -17, 104, 47, -13, 3, 0, 0],
+        [4, -17, 102, 50, -14, 3, 0, 0],
+        [4, -17, 100, 52, -14, 3, 0, 0],
+        [4, -18, 98, 55, -15, 4, 0, 0],
+        [4, -18, 96, 58, -15, 3, 0, 0],
+        [4, -18, 94, 60, -16, 4, 0, 0],
+        [4, -18, 91, 63, -16, 4, 0, 0],
+        [4, -18, 89, 65, -16, 4, 0, 0],
+        [4, -18, 87, 68, -17, 4, 0, 0],
+        [4, -18, 85, 70, -17, 4, 0, 0],
+        [4, -18, 82, 73, -17, 4, 0, 0],
+        [4, -18, 80, 75, -17, 4, 0, 0],
+        [4, -18, 78, 78, -18, 4, 0, 0],
+        [4, -17, 75, 80, -18, 4, 0, 0],
+        [4, -17, 73, 82, -18, 4, 0, 0],
+        [4, -17, 70, 85, -18, 4, 0, 0],
+        [4, -17, 68, 87, -18, 4, 0, 0],
+        [4, -16, 65, 89, -18, 4, 0, 0],
+        [4, -16, 63, 91, -18, 4, 0, 0],
+        [4, -16, 60, 94, -18, 4, 0, 0],
+        [3, -15, 58, 96, -18, 4, 0, 0],
+        [4, -15, 55, 98, -18, 4, 0, 0],
+        [3, -14, 52, 100, -17, 4, 0, 0],
+        [3, -14, 50, 102, -17, 4, 0, 0],
+        [3, -13, 47, 104, -17, 4, 0, 0],
+        [3, -13, 45, 106, -17, 4, 0, 0],
+        [3, -12, 42, 108, -16, 3, 0, 0],
+        [3, -11, 40, 109, -16, 3, 0, 0],
+        [3, -11, 37, 111, -15, 3, 0, 0],
+        [2, -10, 35, 113, -15, 3, 0, 0],
+        [3, -10, 32, 114, -14, 3, 0, 0],
+        [2, -9, 29, 116, -13, 3, 0, 0],
+        [2, -8, 27, 117, -13, 3, 0, 0],
+        [2, -8, 25, 119, -12, 2, 0, 0],
+        [2, -7, 22, 120, -11, 2, 0, 0],
+        [1, -6, 20, 121, -10, 2, 0, 0],
+        [1, -6, 18, 122, -9, 2, 0, 0],
+        [1, -5, 15, 123, -8, 2, 0, 0],
+        [1, -4, 13, 124, -7, 1, 0, 0],
+        [1, -4, 11, 125, -6, 1, 0, 0],
+        [1, -3, 8, 126, -5, 1, 0, 0],
+        [1, -2, 6, 126, -4, 1, 0, 0],
+        [0, -1, 4, 127, -3, 1, 0, 0],
+        [0, 0, 2, 127, -1, 0, 0, 0],
+        // [0, 1)
+        [0, 0, 0, 127, 1, 0, 0, 0],
+        [0, 0, -1, 127, 2, 0, 0, 0],
+        [0, 1, -3, 127, 4, -2, 1, 0],
+        [0, 1, -5, 127, 6, -2, 1, 0],
+        [0, 2, -6, 126, 8, -3, 1, 0],
+        [-1, 2, -7, 126, 11, -4, 2, -1],
+        [-1, 3, -8, 125, 13, -5, 2, -1],
+        [-1, 3, -10, 124, 16, -6, 3, -1],
+        [-1, 4, -11, 123, 18, -7, 3, -1],
+        [-1, 4, -12, 122, 20, -7, 3, -1],
+        [-1, 4, -13, 121, 23, -8, 3, -1],
+        [-2, 5, -14, 120, 25, -9, 4, -1],
+        [-1, 5, -15, 119, 27, -10, 4, -1],
+        [-1, 5, -16, 118, 30, -11, 4, -1],
+        [-2, 6, -17, 116, 33, -12, 5, -1],
+        [-2, 6, -17, 114, 35, -12, 5, -1],
+        [-2, 6, -18, 113, 38, -13, 5, -1],
+        [-2, 7, -19, 111, 41, -14, 6, -2],
+        [-2, 7, -19, 110, 43, -15, 6, -2],
+        [-2, 7, -20, 108, 46, -15, 6, -2],
+        [-2, 7, -20, 106, 49, -16, 6, -2],
+        [-2, 7, -21, 104, 51, -16, 7, -2],
+        [-2, 7, -21, 102, 54, -17, 7, -2],
+        [-2, 8, -21, 100, 56, -18, 7, -2],
+        [-2, 8, -22, 98, 59, -18, 7, -2],
+        [-2, 8, -22, 96, 62, -19, 7, -2],
+        [-2, 8, -22, 94, 64, -19, 7, -2],
+        [-2, 8, -22, 91, 67, -20, 8, -2],
+        [-2, 8, -22, 89, 69, -20, 8, -2],
+        [-2, 8, -22, 87, 72, -21, 8, -2],
+        [-2, 8, -21, 84, 74, -21, 8, -2],
+        [-2, 8, -22, 82, 77, -21, 8, -2],
+        [-2, 8, -21, 79, 79, -21, 8, -2],
+        [-2, 8, -21, 77, 82, -22, 8, -2],
+        [-2, 8, -21, 74, 84, -21, 8, -2],
+        [-2, 8, -21, 72, 87, -22, 8, -2],
+        [-2, 8, -20, 69, 89, -22, 8, -2],
+        [-2, 8, -20, 67, 91, -22, 8, -2],
+        [-2, 7, -19, 64, 94, -22, 8, -2],
+        [-2, 7, -19, 62, 96, -22, 8, -2],
+        [-2, 7, -18, 59, 98, -22, 8, -2],
+        [-2, 7, -18, 56, 100, -21, 8, -2],
+        [-2, 7, -17, 54, 102, -21, 7, -2],
+        [-2, 7, -16, 51, 104, -21, 7, -2],
+        [-2, 6, -16, 49, 106, -20, 7, -2],
+        [-2, 6, -15, 46, 108, -20, 7, -2],
+        [-2, 6, -15, 43, 110, -19, 7, -2],
+        [-2, 6, -14, 41, 111, -19, 7, -2],
+        [-1, 5, -13, 38, 113, -18, 6, -2],
+        [-1, 5, -12, 35, 114, -17, 6, -2],
+        [-1, 5, -12, 33, 116, -17, 6, -2],
+        [-1, 4, -11, 30, 118, -16, 5, -1],
+        [-1, 4, -10, 27, 119, -15, 5, -1],
+        [-1, 4, -9, 25, 120, -14, 5, -2],
+        [-1, 3, -8, 23, 121, -13, 4, -1],
+        [-1, 3, -7, 20, 122, -12, 4, -1],
+        [-1, 3, -7, 18, 123, -11, 4, -1],
+        [-1, 3, -6, 16, 124, -10, 3, -1],
+        [-1, 2, -5, 13, 125, -8, 3, -1],
+        [-1, 2, -4, 11, 126, -7, 2, -1],
+        [0, 1, -3, 8, 126, -6, 2, 0],
+        [0, 1, -2, 6, 127, -5, 1, 0],
+        [0, 1, -2, 4, 127, -3, 1, 0],
+        [0, 0, 0, 2, 127, -1, 0, 0],
+        // [1, 2)
+        [0, 0, 0, 1, 127, 0, 0, 0],
+        [0, 0, 0, -1, 127, 2, 0, 0],
+        [0, 0, 1, -3, 127, 4, -1, 0],
+        [0, 0, 1, -4, 126, 6, -2, 1],
+        [0, 0, 1, -5, 126, 8, -3, 1],
+        [0, 0, 1, -6, 125, 11, -4, 1],
+        [0, 0, 1, -7, 124, 13, -4, 1],
+        [0, 0, 2, -8, 123, 15, -5, 1],
+        [0, 0, 2, -9, 122, 18, -6, 1],
+        [0, 0, 2, -10, 121, 20, -6, 1],
+        [0, 0, 2, -11, 120, 22, -7, 2],
+        [0, 0, 2, -12, 119, 25, -8, 2],
+        [0, 0, 3, -13, 117, 27, -8, 2],
+        [0, 0, 3, -13, 116, 29, -9, 2],
+        [0, 0, 3, -14, 114, 32, -10, 3],
+        [0, 0, 3, -15, 113, 35, -10, 2],
+        [0, 0, 3, -15, 111, 37, -11, 3],
+        [0, 0, 3, -16, 109, 40, -11, 3],
+        [0, 0, 3, -16, 108, 42, -12, 3],
+        [0, 0, 4, -17, 106, 45, -13, 3],
+        [0, 0, 4, -17, 104, 47, -13, 3],
+        [0, 0, 4, -17, 102, 50, -14, 3],
+        [0, 0, 4, -17, 100, 52, -14, 3],
+        [0, 0, 4, -18, 98, 55, -15, 4],
+        [0, 0, 4, -18, 96, 58, -15, 3],
+        [0, 0, 4, -18, 94, 60, -16, 4],
+        [0, 0, 4, -18, 91, 63, -16, 4],
+        [0, 0, 4, -18, 89, 65, -16, 4],
+        [0, 0, 4, -18, 87, 68, -17, 4],
+        [0, 0, 4, -18, 85, 70, -17, 4],
+        [0, 0, 4, -18, 82, 73, -17, 4],
+        [0, 0, 4, -18, 80, 75, -17, 4],
+        [0, 0, 4, -18, 78, 78, -18, 4],
+        [0, 0, 4, -17, 75, 80, -18, 4],
+        [0, 0, 4, -17, 73, 82, -18, 4],
+        [0, 0, 4, -17, 70, 85, -18, 4],
+        [0, 0, 4, -17, 68, 87, -18, 4],
+        [0, 0, 4, -16, 65, 89, -18, 4],
+        [0, 0, 4, -16, 63, 91, -18, 4],
+        [0, 0, 4, -16, 60, 94, -18, 4],
+        [0, 0, 3, -15, 58, 96, -18, 4],
+        [0, 0, 4, -15, 55, 98, -18, 4],
+        [0, 0, 3, -14, 52, 100, -17, 4],
+        [0, 0, 3, -14, 50, 102, -17, 4],
+        [0, 0, 3, -13, 47, 104, -17, 4],
+        [0, 0, 3, -13, 45, 106, -17, 4],
+        [0, 0, 3, -12, 42, 108, -16, 3],
+        [0, 0, 3, -11, 40, 109, -16, 3],
+        [0, 0, 3, -11, 37, 111, -15, 3],
+        [0, 0, 2, -10, 35, 113, -15, 3],
+        [0, 0, 3, -10, 32, 114, -14, 3],
+        [0, 0, 2, -9, 29, 116, -13, 3],
+        [0, 0, 2, -8, 27, 117, -13, 3],
+        [0, 0, 2, -8, 25, 119, -12, 2],
+        [0, 0, 2, -7, 22, 120, -11, 2],
+        [0, 0, 1, -6, 20, 121, -10, 2],
+        [0, 0, 1, -6, 18, 122, -9, 2],
+        [0, 0, 1, -5, 15, 123, -8, 2],
+        [0, 0, 1, -4, 13, 124, -7, 1],
+        [0, 0, 1, -4, 11, 125, -6, 1],
+        [0, 0, 1, -3, 8, 126, -5, 1],
+        [0, 0, 1, -2, 6, 126, -4, 1],
+        [0, 0, 0, -1, 4, 127, -3, 1],
+        [0, 0, 0, 0, 2, 127, -1, 0],
+        // dummy entry so that rounding up stays in range
+        [0, 0, 0, 0, 2, 127, -1, 0],
+    ];
+
+    /// Collects up to 8 neighbouring blocks that use the same single reference frame. Each
+    /// candidate stores the centre of the neighbour and where its motion vector moves it to,
+    /// both in 1/8th luma samples.
+    pub(super) fn find_warp_samples(&mut self) {
+        self.num_samples = 0;
+        self.num_samples_scanned = 0;
+
+        let mi_row = self.mi_row as usize;
+        let mi_col = self.mi_col as usize;
+        let w4 = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_size as usize];
+        let h4 = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_size as usize];
+        let mut do_top_left = true;
+        let mut do_top_right = true;
+
+        if self.avail_u {
+            let src_w = Decoder::NUM_4X4_BLOCKS_WIDE[self.mi_sizes[mi_row - 1][mi_col] as usize];
+            if w4 <= src_w {
+                let col_offset = -((mi_col & (src_w - 1)) as i64);
+                if col_offset < 0 {
+                    do_top_left = false;
+                }
+
+                if col_offset + src_w as i64 > w4 as i64 {
+                    do_top_right = false;
+                }
+
+                self.add_sample(-1, 0);
+            } else {
+                let mut i = 0;
+                while i < w4.min(self.mi_cols as usize - mi_col) {
+                    let src_w = Decoder::NUM_4X4_BLOCKS_WIDE
+                        [self.mi_sizes[mi_row - 1][mi_col + i] as usize];
+                    self.add_sample(-1, i as i64);
+                    i += src_w.max(1);
+                }
+            }
+        }
+
+        if self.avail_l {
+            let src_h = Decoder::NUM_4X4_BLOCKS_HIGH[self.mi_sizes[mi_row][mi_col - 1] as usize];
+            if h4 <= src_h {
+                let row_offset = -((mi_row & (src_h - 1)) as i64);
+                if row_offset < 0 {
+                    do_top_left = false;
+                }
+
+                self.add_sample(0, -1);
+            } else {
+                let mut i = 0;
+                while i < h4.min(self.mi_rows as usize - mi_row) {
+                    let src_h = Decoder::NUM_4X4_BLOCKS_HIGH
+                        [self.mi_sizes[mi_row + i][mi_col - 1] as usize];
+                    self.add_sample(i as i64, -1);
+                    i += src_h.max(1);
+                }
+            }
+        }
+
+        if do_top_left {
+            self.add_sample(-1, -1);
+        }
+
+        if do_top_right && w4.max(h4) <= 16 {
+            self.add_sample(-1, w4 as i64);
+        }
+
+        if self.num_samples == 0 && self.num_samples_scanned > 0 {
+            self.num_samples = 1;
+        }
+    }
+
+    /// Candidates whose motion differs too much from the current block are only kept if
+    /// nothing else is found.
+    fn add_sample(&mut self, delta_row: i64, delta_col: i64) {
+        if self.num_samples_scanned >= Decoder::LEAST_SQUARES_SAMPLES_MAX {
+            return;
+        }
+
+        let mv_row = self.mi_row as i64 + delta_row;
+        let mv_col = self.mi_col as i64 + delta_col;
+        if !self.is_inside(mv_row, mv_col) {
+            return;
+        }
+
+        let (mv_row, mv_col) = (mv_row as usize, mv_col as usize);
+        if !self.ref_frames_written[mv_row][mv_col]
+            || self.ref_frames[mv_row][mv_col][0] != self.ref_frame[0]
+            || self.ref_frames[mv_row][mv_col][1] != Decoder::NONE
+        {
+            return;
+        }
+
+        let cand_sz = self.mi_sizes[mv_row][mv_col] as usize;
+        let cand_w4 = Decoder::NUM_4X4_BLOCKS_WIDE[cand_sz];
+        let cand_h4 = Decoder::NUM_4X4_BLOCKS_HIGH[cand_sz];
+        let cand_row = mv_row & !(cand_h4 - 1);
+        let cand_col = mv_col & !(cand_w4 - 1);
+        let mid_y = (cand_row * 4 + cand_h4 * 4 / 2) as i64 - 1;
+        let mid_x = (cand_col * 4 + cand_w4 * 4 / 2) as i64 - 1;
+        let threshold = (self.mi_size.width().max(self.mi_size.height()) as i64).clamp(16, 112);
+        let cand_mv = self.mvs[cand_row][cand_col][0];
+        let mv_diff_row = (cand_mv[0] - self.mv[0][0]).abs();
+        let mv_diff_col = (cand_mv[1] - self.mv[0][1]).abs();
+        let valid = mv_diff_row + mv_diff_col <= threshold;
+
+        self.num_samples_scanned += 1;
+        if !valid && self.num_samples_scanned > 1 {
+            return;
+        }
+
+        self.cand_list[self.num_samples] = [
+            mid_y * 8,
+            mid_x * 8,
+            mid_y * 8 + cand_mv[0],
+            mid_x * 8 + cand_mv[1],
+        ];
+
+        if valid {
+            self.num_samples += 1;
+        }
+    }
+
+    /// Fits an affine model to the warp samples with least squares. Sets LocalValid to false
+    /// if the system can't be solved.
+    pub(super) fn warp_estimation(&mut self) {
+        let mut a = [[0i64; 2]; 2];
+        let mut bx = [0i64; 2];
+        let mut by = [0i64; 2];
+
+        let bw = self.mi_size.width() as i64;
+        let bh = self.mi_size.height() as i64;
+        let mid_y = self.mi_row as i64 * 4 + bh / 2 - 1;
+        let mid_x = self.mi_col as i64 * 4 + bw / 2 - 1;
+        let suy = mid_y * 8;
+        let sux = mid_x * 8;
+        let duy = suy + self.mv[0][0];
+        let dux = sux + self.mv[0][1];
+
+        let ls_product = |a: i64, b: i64| ((a * b) >> 2) + (a + b);
+
+        for cand in &self.cand_list[..self.num_samples] {
+            let sy = cand[0] - suy;
+            let sx = cand[1] - sux;
+            let dy = cand[2] - duy;
+            let dx = cand[3] - dux;
+
+            if (sx - dx).abs() < Decoder::LS_MV_MAX && (sy - dy).abs() < Decoder::LS_MV_MAX {
+                a[0][0] += ls_product(sx, sx) + 8;
+                a[0][1] += ls_product(sx, sy) + 4;
+                a[1][1] += ls_product(sy, sy) + 8;
+                bx[0] += ls_product(sx, dx) + 8;
+                bx[1] += ls_product(sy, dx) + 4;
+                by[0] += ls_product(sx, dy) + 4;
+                by[1] += ls_product(sy, dy) + 8;
+            }
+        }
+
+        let det = a[0][0] * a[1][1] - a[0][1] * a[0][1];
+        self.local_valid = det != 0;
+        if !self.local_valid {
+            return;
+        }
+
+        let (mut div_shift, mut div_factor) = Decoder::resolve_divisor(det);
+        div_shift -= Decoder::WARPEDMODEL_PREC_BITS as i64;
+        if div_shift < 0 {
+            div_factor <<= -div_shift;
+            div_shift = 0;
+        }
+
+        // The products can exceed 64 bits before the division.
+        let divide = |v: i64| {
+            let p = v as i128 * div_factor as i128;
+            let r = if div_shift == 0 {
+                p
+            } else if p >= 0 {
+                (p + (1 << (div_shift - 1))) >> div_shift
+            } else {
+                -((-p + (1 << (div_shift - 1))) >> div_shift)
+            };
+
+            r.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+        };
+        let one = 1 << Decoder::WARPEDMODEL_PREC_BITS;
+        let clamp = Decoder::WARPEDMODEL_NONDIAGAFFINE_CLAMP;
+        let nondiag = |v: i64| divide(v).clamp(-clamp + 1, clamp - 1);
+        let diag = |v: i64| divide(v).clamp(one - clamp + 1, one + clamp - 1);
+
+        let params = &mut self.local_warp_params;
+        params[2] = diag(a[1][1] * bx[0] - a[0][1] * bx[1]);
+        params[3] = nondiag(-a[0][1] * bx[0] + a[0][0] * bx[1]);
+        params[4] = nondiag(a[1][1] * by[0] - a[0][1] * by[1]);
+        params[5] = diag(-a[0][1] * by[0] + a[0][0] * by[1]);
+
+        let vx = self.mv[0][1] * (1 << (Decoder::WARPEDMODEL_PREC_BITS - 3))
+            - (mid_x * (params[2] - one) + mid_y * params[3]);
+        let vy = self.mv[0][0] * (1 << (Decoder::WARPEDMODEL_PREC_BITS - 3))
+            - (mid_x * params[4] + mid_y * (params[5] - one));
+        let trans_clamp = Decoder::WARPEDMODEL_TRANS_CLAMP;
+        params[0] = vx.clamp(-trans_clamp, trans_clamp - 1);
+        params[1] = vy.clamp(-trans_clamp, trans_clamp - 1);
+    }
+
+    /// Returns (divShift, divFactor) so that dividing by d can be replaced by multiplying
+    /// with divFactor and shifting right by divShift.
+    fn resolve_divisor(d: i64) -> (i64, i64) {
+        let n = 63 - d.unsigned_abs().leading_zeros() as i64;
+        let e = d.abs() - (1 << n);
+        let f = if n > Decoder::DIV_LUT_BITS {
+            round2(e, n - Decoder::DIV_LUT_BITS)
+        } else {
+            e << (Decoder::DIV_LUT_BITS - n)
+        };
+
+        let div_shift = n + Decoder::DIV_LUT_PREC_BITS;
+        let div_factor = if d < 0 {
+            -DIV_LUT[f as usize]
+        } else {
+            DIV_LUT[f as usize]
+        };
+
+        (div_shift, div_factor)
+    }
+
+    /// Splits the warp into a horizontal and a vertical shear. Returns whether the model is
+    /// usable by the warp filter, together with alpha, beta, gamma and delta.
+    pub(super) fn setup_shear(warp_params: &[i64]) -> (bool, [i64; 4]) {
+        let one = 1 << Decoder::WARPEDMODEL_PREC_BITS;
+        let alpha0 = (warp_params[2] - one).clamp(-32768, 32767);
+        let beta0 = warp_params[3].clamp(-32768, 32767);
+        let (div_shift, div_factor) = Decoder::resolve_divisor(warp_params[2]);
+        let v = warp_params[4] << Decoder::WARPEDMODEL_PREC_BITS;
+        let gamma0 = round2signed(v * div_factor, div_shift).clamp(-32768, 32767);
+        let w = warp_params[3] * warp_params[4];
+        let delta0 =
+            (warp_params[5] - round2signed(w * div_factor, div_shift) - one).clamp(-32768, 32767);
+
+        let reduce = |x: i64| {
+            round2signed(x, Decoder::WARP_PARAM_REDUCE_BITS) << Decoder::WARP_PARAM_REDUCE_BITS
+        };
+        let (alpha, beta, gamma, delta) = (
+            reduce(alpha0),
+            reduce(beta0),
+            reduce(gamma0),
+            reduce(delta0),
+        );
+
+        let warp_valid =
+            4 * alpha.abs() + 7 * beta.abs() < one && 4 * gamma.abs() + 4 * delta.abs() < one;
+
+        (warp_valid, [alpha, beta, gamma, delta])
+    }
+
+    /// Decides whether a block is predicted with the local warp model (1), the global
+    /// motion model of its reference (2), or regular translation (0).
+    pub(super) fn use_warp(&self, w: usize, h: usize, ref_frame: usize) -> u8 {
+        if w < 8 || h < 8 || self.uh.force_integer_mv == 1 {
+            0
+        } else if self.motion_mode == MotionMode::LocalWarp && self.local_valid {
+            1
+        } else if matches!(self.y_mode, YMode::GlobalMv | YMode::GlobalGlobalMv)
+            && self.gm_type[ref_frame] > WarpModel::Translation
+            && !self.is_scaled(ref_frame)
+            && Decoder::setup_shear(&self.gm_params[ref_frame]).0
+        {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// Predicts the 8x8 block at (i8, j8) of a w x h block with the warp filter. The centre
+    /// of the block is projected with the model, the remaining samples follow the shears.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn block_warp(
+        &self,
+        warp_params: &[i64],
+        reference: &[Vec<u16>],
+        ref_upscaled_width: i64,
+        ref_frame_height: i64,
+        plane: usize,
+        x: i64,
+        y: i64,
+        i8: usize,
+        j8: usize,
+        pred: &mut [Vec<i64>],
+    ) {
+        let color_config = &self.sequence_header.color_config;
+        let (sub_x, sub_y) = if plane > 0 {
+            (
+                color_config.subsampling_x as i64,
+                color_config.subsampling_y as i64,
+            )
+        } else {
+            (0, 0)
+        };
+
+        let last_x = ((ref_upscaled_width + sub_x) >> sub_x) - 1;
+        let last_y = ((ref_frame_height + sub_y) >> sub_y) - 1;
+        let src_x = (x + j8 as i64 * 8 + 4) << sub_x;
+        let src_y = (y + i8 as i64 * 8 + 4) << sub_y;
+        let dst_x = warp_params[2] * src_x + warp_params[3] * src_y + warp_params[0];
+        let dst_y = warp_params[4] * src_x + warp_params[5] * src_y + warp_params[1];
+        let (_, [alpha, beta, gamma, delta]) = Decoder::setup_shear(warp_params);
+
+        let prec_bits = Decoder::WARPEDMODEL_PREC_BITS as i64;
+        let x4 = dst_x >> sub_x;
+        let y4 = dst_y >> sub_y;
+        let ix4 = x4 >> prec_bits;
+        let sx4 = x4 & ((1 << prec_bits) - 1);
+        let iy4 = y4 >> prec_bits;
+        let sy4 = y4 & ((1 << prec_bits) - 1);
+
+        let mut intermediate = [[0i64; 8]; 15];
+        for i1 in -7..8 {
+            let ref_row = &reference[(iy4 + i1).clamp(0, last_y) as usize];
+
+            for i2 in -4..4 {
+                let sx = sx4 + alpha * i2 + beta * i1;
+                let offs =
+                    round2(sx, Decoder::WARPEDDIFF_PREC_BITS) + Decoder::WARPEDPIXEL_PREC_SHIFTS;
+                let filter = &Decoder::WARPED_FILTERS[offs as usize];
+
+                let mut s = 0;
+                for (i3, f) in filter.iter().enumerate() {
+                    let sample_x = (ix4 + i2 - 3 + i3 as i64).clamp(0, last_x);
+                    s += f * ref_row[sample_x as usize] as i64;
+                }
+
+                intermediate[(i1 + 7) as usize][(i2 + 4) as usize] = round2(s, self.inter_round0);
+            }
+        }
+
+        for i1 in -4..4 {
+            for i2 in -4..4 {
+                let sy = sy4 + gamma * i2 + delta * i1;
+                let offs =
+                    round2(sy, Decoder::WARPEDDIFF_PREC_BITS) + Decoder::WARPEDPIXEL_PREC_SHIFTS;
+                let filter = &Decoder::WARPED_FILTERS[offs as usize];
+
+                let mut s = 0;
+                for (i3, f) in filter.iter().enumerate() {
+                    s += f * intermediate[(i1 + i3 as i64 + 4) as usize][(i2 + 4) as usize];
+                }
+
+                pred[i8 * 8 + (i1 + 4) as usize][j8 * 8 + (i2 + 4) as usize] =
+                    round2(s, self.inter_round1);
+            }
+        }
+    }
+}
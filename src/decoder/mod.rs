@@ -8,16 +8,60 @@ use crate::decoder::ivf::Ivf;
 
 use self::mp4::Mp4;
 
+pub use av1::{
+    cicp::{ColorPrimaries, MatrixCoefficients, TransferCharacteristics},
+    frame::{Frame, FrameInfo, Plane, ProjectedMv, Stage},
+    BitDepth, BlockInfo, ChromaSamplePosition, ColorConfig, SubSize, SyntaxCategory, TxSize,
+    TxType, UvMode, YMode,
+};
+
 mod av1;
 mod ivf;
 mod mp4;
 
-#[tracing::instrument]
-pub fn decode(p: PathBuf) -> Result<()> {
+/// Frames per second as a fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        FrameRate {
+            numerator: 30,
+            denominator: 1,
+        }
+    }
+}
+
+/// Choices about how a stream is decoded that aren't up to the sink.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DecodeOptions {
+    /// Output frames without the film grain the stream asks for.
+    pub skip_film_grain: bool,
+}
+
+/// Receives the frames a stream shows, in display order.
+pub trait FrameSink {
+    /// Called once before the first frame, with the frame rate stored in the container.
+    fn set_frame_rate(&mut self, frame_rate: FrameRate);
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()>;
+
+    /// The pipeline stages the sink wants captured in Frame::stages.
+    fn stages(&self) -> Vec<Stage> {
+        Vec::new()
+    }
+}
+
+#[tracing::instrument(skip(sink))]
+pub fn decode(p: PathBuf, options: DecodeOptions, sink: Option<&mut dyn FrameSink>) -> Result<()> {
     match p.extension() {
         Some(ext) => match ext.to_str() {
-            Some("mp4") => decode_mp4(p),
-            Some("ivf") => decode_ivf(p),
+            Some("mp4") => decode_mp4(p, options, sink),
+            Some("ivf") => decode_ivf(p, options, sink),
+            Some("obu") => decode_obu(p, options, sink),
             _ => bail!("file extension {:?} is not supported", ext),
         },
         None => bail!(
@@ -28,7 +72,11 @@ pub fn decode(p: PathBuf) -> Result<()> {
 }
 
 #[tracing::instrument(skip_all)]
-pub fn decode_mp4(p: PathBuf) -> Result<()> {
+pub fn decode_mp4(
+    p: PathBuf,
+    options: DecodeOptions,
+    mut sink: Option<&mut dyn FrameSink>,
+) -> Result<()> {
     let mp4 = Mp4::new(p)?;
     info!("ftyp: {:?}", mp4.ftyp);
     info!("moov: {:?}", mp4.moov);
@@ -36,22 +84,83 @@ pub fn decode_mp4(p: PathBuf) -> Result<()> {
         info!("mdat: {:?} bytes", mdat.len());
     }
 
+    if let Some(frame_rate) = mp4.frame_rate() {
+        info!(
+            "frame rate: {}/{}",
+            frame_rate.numerator, frame_rate.denominator
+        );
+        if let Some(sink) = sink.as_deref_mut() {
+            sink.set_frame_rate(frame_rate);
+        }
+    }
+
+    let mut decoder = Decoder {
+        stages: sink.as_deref().map_or(Vec::new(), |sink| sink.stages()),
+        skip_film_grain: options.skip_film_grain,
+        ..Default::default()
+    };
+    decoder.decode_obus(mp4.config_obus()?.to_vec(), None)?;
+    for sample in mp4.samples()? {
+        decoder.timestamp = sample.timestamp;
+        let sink = sink.as_deref_mut().map(|sink| sink as &mut dyn FrameSink);
+        decoder.decode_obus(sample.data, sink)?;
+    }
+
     Ok(())
 }
 
 #[tracing::instrument(skip_all)]
-pub fn decode_ivf(p: PathBuf) -> Result<()> {
+pub fn decode_ivf(
+    p: PathBuf,
+    options: DecodeOptions,
+    mut sink: Option<&mut dyn FrameSink>,
+) -> Result<()> {
     let ivf = Ivf::new(p)?;
     info!("fourcc: {}", ivf.fourcc);
     info!("width: {}", ivf.width);
     info!("height: {}", ivf.height);
     info!("block 1: {}", ivf.blocks[0]);
 
+    // The header stores the time base, whose inverse is the frame rate when every block
+    // lasts one tick.
+    let frame_rate = FrameRate {
+        numerator: ivf.denominator,
+        denominator: ivf.numerator,
+    };
+    info!(
+        "frame rate: {}/{}",
+        frame_rate.numerator, frame_rate.denominator
+    );
+    if let Some(sink) = sink.as_deref_mut() {
+        sink.set_frame_rate(frame_rate);
+    }
+
     match ivf.fourcc.as_str() {
         "AV01" => {
-            let mut decoder = Decoder::default();
-            decoder.decode(ivf)
+            let mut decoder = Decoder {
+                stages: sink.as_deref().map_or(Vec::new(), |sink| sink.stages()),
+                skip_film_grain: options.skip_film_grain,
+                ..Default::default()
+            };
+            decoder.decode(ivf, sink)
         }
         _ => panic!("unknown ivf fourcc: {}", ivf.fourcc),
     }
 }
+
+#[tracing::instrument(skip_all)]
+pub fn decode_obu(
+    p: PathBuf,
+    options: DecodeOptions,
+    sink: Option<&mut dyn FrameSink>,
+) -> Result<()> {
+    let data = std::fs::read(p)?;
+    info!("loaded {} bytes", data.len());
+
+    let mut decoder = Decoder {
+        stages: sink.as_deref().map_or(Vec::new(), |sink| sink.stages()),
+        skip_film_grain: options.skip_film_grain,
+        ..Default::default()
+    };
+    decoder.decode_obus(data, sink)
+}
@@ -30,7 +30,8 @@ impl Ftyp {
         let minor_version = u32::from_be_bytes(minor_version);
 
         let mut compatible_brands = Vec::new();
-        for _ in 0..size / 8 {
+        // The box header, major brand and minor version take 16 bytes.
+        for _ in 0..(size - 16) / 4 {
             let mut brand = [0u8; 4];
             c.read_exact(&mut brand)?;
             compatible_brands.push(String::from_utf8(brand.to_vec())?);
@@ -3,10 +3,12 @@ use std::{
     path::PathBuf,
 };
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use tracing::info;
 
-use self::{ftyp::Ftyp, moov::Moov};
+use self::{ftyp::Ftyp, mdia::Mdia, moov::Moov, stsd::SampleEntry};
+
+use super::FrameRate;
 
 mod av01;
 mod av1c;
@@ -39,6 +41,16 @@ pub struct Mp4 {
     pub ftyp: Ftyp,
     pub moov: Moov,
     pub mdat: Option<Vec<u8>>,
+    /// Where the mdat payload starts in the file, which chunk offsets are relative to.
+    pub mdat_offset: u64,
+}
+
+/// One temporal unit of the video track.
+#[derive(Clone, Debug, Default)]
+pub struct Sample {
+    /// The decoding time in units of the media timescale.
+    pub timestamp: u64,
+    pub data: Vec<u8>,
 }
 
 impl Mp4 {
@@ -72,6 +84,7 @@ impl Mp4 {
                 "moov" => self.moov = Moov::new(&mut c, box_start, box_size)?,
                 "free" => c.set_position(c.position() + box_size as u64 - 8),
                 "mdat" => {
+                    self.mdat_offset = c.position();
                     let mut data = vec![0u8; box_size as usize - 8];
                     c.read_exact(&mut data)?;
                     self.mdat = Some(data.to_vec())
@@ -86,6 +99,94 @@ impl Mp4 {
 
         Ok(())
     }
+
+    fn video_track(&self) -> Option<&Mdia> {
+        self.moov
+            .traks
+            .iter()
+            .find(|trak| trak.mdia.hdlr.handler_type == "vide")
+            .map(|trak| &trak.mdia)
+    }
+
+    /// The frame rate of the first video track, from the media timescale and the duration
+    /// of its first samples.
+    pub fn frame_rate(&self) -> Option<FrameRate> {
+        let mdia = self.video_track()?;
+        let &(_, sample_delta) = mdia.minf.stbl.stts.entries.first()?;
+        if sample_delta == 0 {
+            return None;
+        }
+
+        Some(FrameRate {
+            numerator: mdia.mdhd.timescale,
+            denominator: sample_delta,
+        })
+    }
+
+    /// The configuration OBUs from the av1C box of the first video track, usually its
+    /// sequence header.
+    pub fn config_obus(&self) -> Result<&[u8]> {
+        let mdia = self.video_track().context("no video track")?;
+        match mdia.minf.stbl.stsd.sample_entries.first() {
+            Some(SampleEntry::Av01(_, _, av01)) => Ok(&av01.av1c.config_obus),
+            entry => bail!("video track sample entry {entry:?} is not av01"),
+        }
+    }
+
+    /// The samples of the first video track in decoding order, located through the
+    /// sample to chunk, sample size and chunk offset tables.
+    pub fn samples(&self) -> Result<Vec<Sample>> {
+        let stbl = &self.video_track().context("no video track")?.minf.stbl;
+        let mdat = self.mdat.as_ref().context("no mdat found")?;
+
+        let mut sizes = (0..stbl.stsz.sample_count as usize).map(|i| {
+            if stbl.stsz.sample_size != 0 {
+                stbl.stsz.sample_size
+            } else {
+                stbl.stsz.entries[i]
+            }
+        });
+        let mut deltas = stbl
+            .stts
+            .entries
+            .iter()
+            .flat_map(|&(count, delta)| std::iter::repeat_n(delta, count as usize));
+
+        let mut samples = Vec::new();
+        let mut timestamp = 0;
+        for (chunk, &chunk_offset) in stbl.stco.chunk_offsets.iter().enumerate() {
+            // stsc lists runs of chunks with the same layout by their first, 1-based, chunk.
+            let samples_per_chunk = stbl
+                .stsc
+                .entries
+                .iter()
+                .take_while(|&&(first_chunk, _, _)| first_chunk as usize <= chunk + 1)
+                .last()
+                .map_or(0, |&(_, samples_per_chunk, _)| samples_per_chunk);
+
+            let mut offset = (chunk_offset as u64)
+                .checked_sub(self.mdat_offset)
+                .with_context(|| format!("chunk {chunk} starts before the mdat"))?
+                as usize;
+            for _ in 0..samples_per_chunk {
+                let Some(size) = sizes.next() else {
+                    break;
+                };
+
+                let data = mdat
+                    .get(offset..offset + size as usize)
+                    .with_context(|| format!("sample {} is outside the mdat", samples.len()))?;
+                samples.push(Sample {
+                    timestamp,
+                    data: data.to_vec(),
+                });
+                offset += size as usize;
+                timestamp += deltas.next().unwrap_or(0) as u64;
+            }
+        }
+
+        Ok(samples)
+    }
 }
 
 fn fixed32(data: [u8; 4]) -> f64 {
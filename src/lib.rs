@@ -0,0 +1,3 @@
+pub mod decoder;
+pub mod output;
+pub mod render;
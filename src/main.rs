@@ -1,16 +1,190 @@
-use std::{env, path::PathBuf};
+use std::{env, fs::File, io::BufWriter, path::PathBuf, process};
 
-use anyhow::Context;
+use anyhow::{bail, Context, Result};
+use av1_visualizer::{
+    decoder::{self, DecodeOptions, FrameSink, Stage},
+    output::{
+        BlockCsvWriter, ExpectedMd5, FrameSelection, Md5Writer, PngWriter, StageSelect, Tee,
+        Y4mWriter, YuvWriter,
+    },
+    render::{RenderOptions, View},
+};
 use tracing::{error, info};
 
-mod decoder;
-
 fn main() {
     tracing_subscriber::fmt().init();
 
-    let path_arg = env::args().nth(1).context("no file path provided").unwrap();
-    match decoder::decode(PathBuf::from(&path_arg)) {
-        Ok(_) => info!("done"),
-        Err(err) => error!("{err:?}"),
+    if let Err(err) = run() {
+        error!("{err:?}");
+        process::exit(1);
+    }
+}
+
+/// av1-visualizer [decode] <input> [-o <output.yuv|output.y4m>] [--md5] [--verify <md5|file>]
+///     [--png <pattern>] [--frames <0,5,10-20>] [--time <seconds,...>] [--tone-map]
+///     [--view <view>] [--grey-canvas] [--csv <blocks.csv>] [--stage <stage>]
+///     [--compare <stage>] [--no-grain]
+///
+/// Views: frame, partition, modes, motion, flow, motion-field, qindex, segments,
+/// filter-level, bits, transform, cdef, side-by-side, difference.
+///
+/// Stages: prediction, residual, reconstruction, deblocked-vertical, deblocked, cdef,
+/// superres, restored, output. --stage picks the stage written to -o and drawn by the
+/// views, --compare the one side-by-side and difference compare it with. --no-grain
+/// leaves out the film grain the stream asks for.
+fn run() -> Result<()> {
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("decode") {
+        args.next();
     }
+
+    let mut input = None;
+    let mut output = None;
+    let mut print_md5 = false;
+    let mut verify = None;
+    let mut png_pattern = None;
+    let mut csv_path = None;
+    let mut selection = FrameSelection::default();
+    let mut view = View::default();
+    let mut options = RenderOptions::default();
+    let mut decode_options = DecodeOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().context("-o needs a file path")?),
+            "--md5" => print_md5 = true,
+            "--verify" => verify = Some(args.next().context("--verify needs an md5")?),
+            "--png" => png_pattern = Some(args.next().context("--png needs a file name pattern")?),
+            "--frames" => {
+                let list = args
+                    .next()
+                    .context("--frames needs a list of frame indices")?;
+                selection.indices = FrameSelection::parse_indices(&list)?;
+            }
+            "--time" => {
+                let list = args
+                    .next()
+                    .context("--time needs a list of times in seconds")?;
+                selection.times = FrameSelection::parse_times(&list)?;
+            }
+            "--tone-map" => options.tone_map = true,
+            "--view" => {
+                let name = args.next().context("--view needs a view name")?;
+                view = View::new(&name).with_context(|| format!("unknown view {name:?}"))?;
+            }
+            "--grey-canvas" => options.grey_canvas = true,
+            "--csv" => csv_path = Some(args.next().context("--csv needs a file path")?),
+            "--stage" => options.stage = Some(parse_stage(args.next())?),
+            "--compare" => options.compare = Some(parse_stage(args.next())?),
+            "--no-grain" => decode_options.skip_film_grain = true,
+            _ if arg.starts_with('-') => bail!("unknown argument: {arg}"),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => bail!("more than one input file given: {arg}"),
+        }
+    }
+    let input = input.context("no file path provided")?;
+
+    let mut file_sink = output.map(|p| open_output(PathBuf::from(p))).transpose()?;
+    let mut md5 = (print_md5 || verify.is_some()).then(|| Md5Writer::new(print_md5));
+    let mut png = png_pattern.map(|pattern| PngWriter::new(pattern, selection, view, options));
+    let mut csv = csv_path
+        .map(|p| -> Result<_> {
+            let file = File::create(&p).with_context(|| format!("creating {p:?}"))?;
+            Ok(BlockCsvWriter::new(BufWriter::new(file)))
+        })
+        .transpose()?;
+
+    let mut staged_file_sink = file_sink.as_deref_mut().map(|sink| StageSelect {
+        stage: options.stage.unwrap_or(Stage::Output),
+        sink,
+    });
+
+    let mut sinks: Vec<&mut dyn FrameSink> = Vec::new();
+    if let Some(sink) = staged_file_sink.as_mut() {
+        sinks.push(sink);
+    }
+    if let Some(md5) = md5.as_mut() {
+        sinks.push(md5);
+    }
+    if let Some(png) = png.as_mut() {
+        sinks.push(png);
+    }
+    if let Some(csv) = csv.as_mut() {
+        sinks.push(csv);
+    }
+
+    decoder::decode(input.clone(), decode_options, Some(&mut Tee(sinks)))?;
+    info!("done");
+
+    if let Some(md5) = md5 {
+        let frames = md5.frames().to_vec();
+        let md5 = md5.finish();
+        if print_md5 {
+            println!("{md5}  {}", input.display());
+        }
+
+        if let Some(expected) = verify {
+            let expected = expected_md5(&expected)?;
+            let mismatch = expected
+                .frames
+                .iter()
+                .zip(&frames)
+                .position(|(expected, md5)| expected != md5);
+            if let Some(frame) = mismatch {
+                bail!(
+                    "md5 mismatch in frame {frame}: expected {}, got {}",
+                    expected.frames[frame],
+                    frames[frame]
+                );
+            }
+            if !expected.frames.is_empty() && expected.frames.len() != frames.len() {
+                bail!(
+                    "expected {} frames, decoded {}",
+                    expected.frames.len(),
+                    frames.len()
+                );
+            }
+            if let Some(stream) = expected.stream {
+                if md5 != stream {
+                    bail!("md5 mismatch: expected {stream}, got {md5}");
+                }
+                info!("md5 matches {stream}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn open_output(p: PathBuf) -> Result<Box<dyn FrameSink>> {
+    let writer = BufWriter::new(File::create(&p).with_context(|| format!("creating {p:?}"))?);
+    match p.extension().and_then(|ext| ext.to_str()) {
+        Some("y4m") => Ok(Box::new(Y4mWriter::new(writer))),
+        Some("yuv") => Ok(Box::new(YuvWriter::new(writer))),
+        _ => bail!("output file {p:?} needs a .yuv or .y4m extension"),
+    }
+}
+
+fn parse_stage(name: Option<String>) -> Result<Stage> {
+    let name = name.context("--stage and --compare need a stage name")?;
+    Stage::new(&name).with_context(|| format!("unknown stage {name:?}"))
+}
+
+/// --verify takes either the hash itself or a file in the format --md5 prints.
+fn expected_md5(arg: &str) -> Result<ExpectedMd5> {
+    let is_hash = arg.len() == 32 && arg.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hash {
+        return Ok(ExpectedMd5 {
+            frames: Vec::new(),
+            stream: Some(arg.to_ascii_lowercase()),
+        });
+    }
+
+    let text =
+        std::fs::read_to_string(arg).with_context(|| format!("reading expected md5 {arg:?}"))?;
+    let expected = ExpectedMd5::parse(&text);
+    if expected.frames.is_empty() && expected.stream.is_none() {
+        bail!("no md5 found in {arg:?}");
+    }
+
+    Ok(expected)
 }
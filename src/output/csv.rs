@@ -0,0 +1,63 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::decoder::{Frame, FrameRate, FrameSink, SyntaxCategory};
+
+/// Writes one CSV row per block of every frame, with the values the overlays show as
+/// colours.
+pub struct BlockCsvWriter<W: Write> {
+    writer: W,
+    frames: usize,
+}
+
+impl<W: Write> BlockCsvWriter<W> {
+    pub fn new(writer: W) -> BlockCsvWriter<W> {
+        BlockCsvWriter { writer, frames: 0 }
+    }
+}
+
+impl<W: Write> FrameSink for BlockCsvWriter<W> {
+    fn set_frame_rate(&mut self, _frame_rate: FrameRate) {}
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        if self.frames == 0 {
+            write!(
+                self.writer,
+                "frame,timestamp,x,y,width,height,is_inter,y_mode,segment_id,qindex,\
+                 filter_level_y_v,filter_level_y_h,filter_level_u,filter_level_v"
+            )?;
+            for category in SyntaxCategory::ALL {
+                write!(self.writer, ",bits_{}", category.name())?;
+            }
+            writeln!(self.writer)?;
+        }
+
+        for block in &frame.info.blocks {
+            let [y_v, y_h, u, v] = block.filter_levels;
+            write!(
+                self.writer,
+                "{},{},{},{},{},{},{},{:?},{},{},{y_v},{y_h},{u},{v}",
+                self.frames,
+                frame.timestamp,
+                block.mi_col * 4,
+                block.mi_row * 4,
+                block.mi_size.width(),
+                block.mi_size.height(),
+                block.is_inter as u8,
+                block.y_mode,
+                block.segment_id,
+                block.qindex,
+            )?;
+            for bits in block.bits {
+                write!(self.writer, ",{bits:.3}")?;
+            }
+            writeln!(self.writer)?;
+        }
+
+        self.frames += 1;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::Result;
+
+use crate::decoder::{Frame, FrameRate, FrameSink};
+
+use super::write_plane;
+
+/// Hashes the visible planes of every frame the way `aomdec --rawvideo --md5` does: the
+/// same bytes the raw YUV writer produces, so little-endian 16-bit samples for high bit
+/// depth streams.
+pub struct Md5Writer {
+    stream: md5::Context,
+    frames: Vec<String>,
+    print_frames: bool,
+}
+
+impl Md5Writer {
+    pub fn new(print_frames: bool) -> Md5Writer {
+        Md5Writer {
+            stream: md5::Context::new(),
+            frames: Vec::new(),
+            print_frames,
+        }
+    }
+
+    /// The MD5 of every frame written so far, as lowercase hex.
+    pub fn frames(&self) -> &[String] {
+        &self.frames
+    }
+
+    /// The MD5 of all frames written so far, as lowercase hex.
+    pub fn finish(self) -> String {
+        format!("{:x}", self.stream.compute())
+    }
+}
+
+/// Hashes a decode is checked against.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExpectedMd5 {
+    /// One MD5 per frame, possibly empty.
+    pub frames: Vec<String>,
+    /// The MD5 of the whole stream.
+    pub stream: Option<String>,
+}
+
+impl ExpectedMd5 {
+    /// Reads hashes in the format `--md5` prints: one `frame N: <md5>` line per frame
+    /// followed by `<md5>  <file>` for the whole stream.
+    pub fn parse(text: &str) -> ExpectedMd5 {
+        let mut expected = ExpectedMd5::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some((_, md5)) = line.strip_prefix("frame ").and_then(|l| l.split_once(':')) {
+                expected.frames.push(md5.trim().to_ascii_lowercase());
+            } else if let Some(md5) = line.split_whitespace().next() {
+                expected.stream = Some(md5.to_ascii_lowercase());
+            }
+        }
+
+        expected
+    }
+}
+
+impl FrameSink for Md5Writer {
+    fn set_frame_rate(&mut self, _frame_rate: FrameRate) {}
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let mut data = Vec::new();
+        for plane in &frame.planes {
+            write_plane(&mut data, plane, frame.bit_depth)?;
+        }
+
+        let md5 = format!("{:x}", md5::compute(&data));
+        if self.print_frames {
+            println!("frame {}: {md5}", self.frames.len());
+        }
+
+        self.stream.consume(&data);
+        self.frames.push(md5);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::{BitDepth, ColorConfig, FrameInfo, Plane};
+
+    use super::*;
+
+    fn frame(bit_depth: BitDepth, plane: Plane) -> Frame {
+        Frame {
+            timestamp: 0,
+            width: plane.width,
+            height: plane.height,
+            bit_depth,
+            subsampling_x: false,
+            subsampling_y: false,
+            color_config: ColorConfig::default(),
+            planes: vec![plane],
+            info: FrameInfo::default(),
+            stages: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hashes_frames_and_stream() {
+        // Two visible samples per row, the rest of the stride is padding.
+        let plane = Plane {
+            width: 2,
+            height: 2,
+            stride: 3,
+            data: vec![1, 1, 9, 1, 1, 9],
+        };
+        let frame = frame(BitDepth::Eight, plane);
+
+        let mut md5 = Md5Writer::new(false);
+        md5.write_frame(&frame).unwrap();
+        md5.write_frame(&frame).unwrap();
+        assert_eq!(md5.frames(), ["3b5b9852567ef7618aac7f5f2d74ef74"; 2]);
+        assert_eq!(md5.finish(), "8afef86de3b0eff1ded7591aa5ff2769");
+    }
+
+    #[test]
+    fn hashes_high_bit_depth_as_little_endian() {
+        let plane = Plane {
+            width: 2,
+            height: 1,
+            stride: 2,
+            data: vec![0x3ff, 2],
+        };
+
+        let mut md5 = Md5Writer::new(false);
+        md5.write_frame(&frame(BitDepth::Ten, plane)).unwrap();
+        assert_eq!(md5.finish(), "f074109d9d7dd7ec32ebb6a05d0aff48");
+    }
+
+    #[test]
+    fn parses_md5_output() {
+        let text = "frame 0: 3B5B9852567EF7618AAC7F5F2D74EF74\n\
+                    frame 1: 3b5b9852567ef7618aac7f5f2d74ef74\n\
+                    \n\
+                    8afef86de3b0eff1ded7591aa5ff2769  stream.ivf\n";
+        assert_eq!(
+            ExpectedMd5::parse(text),
+            ExpectedMd5 {
+                frames: vec!["3b5b9852567ef7618aac7f5f2d74ef74".to_string(); 2],
+                stream: Some("8afef86de3b0eff1ded7591aa5ff2769".to_string()),
+            }
+        );
+    }
+}
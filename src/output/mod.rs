@@ -0,0 +1,147 @@
+use std::{io::Write, ops::RangeInclusive};
+
+use anyhow::{Context, Result};
+
+use crate::decoder::{BitDepth, Frame, FrameRate, FrameSink, Plane, Stage};
+
+pub use self::{
+    csv::BlockCsvWriter,
+    hash::{ExpectedMd5, Md5Writer},
+    png::{format_pattern, save_png, write_png, PngWriter},
+    y4m::Y4mWriter,
+    yuv::YuvWriter,
+};
+
+mod csv;
+mod hash;
+mod png;
+mod y4m;
+mod yuv;
+
+/// Hands every frame to each of the sinks in turn.
+pub struct Tee<'a>(pub Vec<&'a mut dyn FrameSink>);
+
+impl FrameSink for Tee<'_> {
+    fn set_frame_rate(&mut self, frame_rate: FrameRate) {
+        for sink in &mut self.0 {
+            sink.set_frame_rate(frame_rate);
+        }
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        for sink in &mut self.0 {
+            sink.write_frame(frame)?;
+        }
+
+        Ok(())
+    }
+
+    fn stages(&self) -> Vec<Stage> {
+        let mut stages = Vec::new();
+        for stage in self.0.iter().flat_map(|sink| sink.stages()) {
+            if !stages.contains(&stage) {
+                stages.push(stage);
+            }
+        }
+
+        stages
+    }
+}
+
+/// Hands a sink the frame as it was at an earlier stage of the pipeline instead of the
+/// output.
+pub struct StageSelect<'a> {
+    pub stage: Stage,
+    pub sink: &'a mut dyn FrameSink,
+}
+
+impl FrameSink for StageSelect<'_> {
+    fn set_frame_rate(&mut self, frame_rate: FrameRate) {
+        self.sink.set_frame_rate(frame_rate);
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let staged = frame
+            .stage(self.stage)
+            .with_context(|| format!("{} stage was not captured", self.stage.name()))?;
+        self.sink.write_frame(staged)
+    }
+
+    fn stages(&self) -> Vec<Stage> {
+        vec![self.stage]
+    }
+}
+
+/// Which frames to export, by display order index or by presentation time. Selecting
+/// nothing selects every frame.
+#[derive(Debug, Clone, Default)]
+pub struct FrameSelection {
+    pub indices: Vec<RangeInclusive<usize>>,
+    /// In seconds.
+    pub times: Vec<f64>,
+}
+
+impl FrameSelection {
+    /// Parses a list of indices and ranges like `0,5,10-20`.
+    pub fn parse_indices(list: &str) -> Result<Vec<RangeInclusive<usize>>> {
+        list.split(',')
+            .map(|item| {
+                let (start, end) = item.split_once('-').unwrap_or((item, item));
+                let start = start
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("frame index {item:?}"))?;
+                let end = end
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("frame index {item:?}"))?;
+                Ok(start..=end)
+            })
+            .collect()
+    }
+
+    /// Parses a list of times in seconds like `1.5,3`.
+    pub fn parse_times(list: &str) -> Result<Vec<f64>> {
+        list.split(',')
+            .map(|item| {
+                item.trim()
+                    .parse()
+                    .with_context(|| format!("time in seconds {item:?}"))
+            })
+            .collect()
+    }
+
+    /// Whether the frame at `index`, shown at `timestamp` in units of one frame duration,
+    /// is selected. A time selects the frame on screen at that moment.
+    pub fn contains(&self, index: usize, timestamp: u64, frame_rate: FrameRate) -> bool {
+        if self.indices.is_empty() && self.times.is_empty() {
+            return true;
+        }
+
+        let duration = frame_rate.denominator as f64 / frame_rate.numerator as f64;
+        let start = timestamp as f64 * duration;
+
+        self.indices.iter().any(|range| range.contains(&index))
+            || self
+                .times
+                .iter()
+                .any(|&t| t >= start && t < start + duration)
+    }
+}
+
+/// Writes the visible samples of a plane, one byte per sample for 8-bit streams and
+/// little-endian 16-bit words otherwise.
+fn write_plane(w: &mut impl Write, plane: &Plane, bit_depth: BitDepth) -> Result<()> {
+    let mut buf = Vec::with_capacity(plane.width * 2);
+    for row in plane.rows() {
+        buf.clear();
+        if matches!(bit_depth, BitDepth::Eight) {
+            buf.extend(row.iter().map(|&s| s as u8));
+        } else {
+            buf.extend(row.iter().flat_map(|s| s.to_le_bytes()));
+        }
+        w.write_all(&buf)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    decoder::{Frame, FrameRate, FrameSink, Stage},
+    render::{render, RenderOptions, RgbImage, View},
+};
+
+use super::FrameSelection;
+
+// https://www.w3.org/TR/png/
+// https://www.rfc-editor.org/rfc/rfc1950 (zlib)
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// Stored deflate blocks hold at most this many bytes.
+const MAX_STORED_BLOCK: usize = 0xffff;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+
+    table
+}
+
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    let mut c = 0xffffffff;
+    for &byte in chunks.iter().flat_map(|chunk| chunk.iter()) {
+        c = CRC_TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+
+    c ^ 0xffffffff
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+/// Wraps data in a zlib stream made of stored deflate blocks. The images are small enough
+/// that skipping compression keeps this simple without any dependency.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    out.extend([0x78, 0x01]);
+
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let is_final = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(is_final as u8);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(chunk);
+    }
+
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn write_chunk(w: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_all(&crc32(&[kind, data]).to_be_bytes())?;
+    Ok(())
+}
+
+/// Writes 8-bit RGB (3 channels) or RGBA (4 channels) pixels as a PNG.
+pub fn write_png(
+    w: &mut impl Write,
+    width: usize,
+    height: usize,
+    channels: usize,
+    pixels: &[u8],
+) -> Result<()> {
+    assert!(
+        channels == 3 || channels == 4,
+        "unsupported channel count {channels}"
+    );
+    assert_eq!(pixels.len(), width * height * channels);
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend((width as u32).to_be_bytes());
+    ihdr.extend((height as u32).to_be_bytes());
+    // Bit depth 8, colour type 2 (RGB) or 6 (RGBA), deflate, no filter, no interlace.
+    ihdr.extend([8, if channels == 3 { 2 } else { 6 }, 0, 0, 0]);
+
+    // Every scanline starts with its filter type, 0 for none.
+    let mut raw = Vec::with_capacity((width * channels + 1) * height);
+    for row in pixels.chunks(width * channels).take(height) {
+        raw.push(0);
+        raw.extend(row);
+    }
+
+    w.write_all(&SIGNATURE)?;
+    write_chunk(w, b"IHDR", &ihdr)?;
+    write_chunk(w, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(w, b"IEND", &[])?;
+    Ok(())
+}
+
+/// Saves an image as a PNG file.
+pub fn save_png(p: &Path, image: &RgbImage) -> Result<()> {
+    let file = File::create(p).with_context(|| format!("creating {p:?}"))?;
+    let mut w = BufWriter::new(file);
+    write_png(&mut w, image.width, image.height, 3, &image.data)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Fills in the frame number in a pattern like `frame_%04d.png`, the way ffmpeg does.
+pub fn format_pattern(pattern: &str, index: usize) -> String {
+    let Some(start) = pattern.find('%') else {
+        return pattern.to_string();
+    };
+    let Some(len) = pattern[start..].find('d') else {
+        return pattern.to_string();
+    };
+
+    let width = pattern[start + 1..start + len].parse().unwrap_or(0);
+    format!(
+        "{}{index:0width$}{}",
+        &pattern[..start],
+        &pattern[start + len + 1..]
+    )
+}
+
+/// Renders the selected frames and writes them as one PNG each, named after a pattern.
+pub struct PngWriter {
+    pattern: String,
+    selection: FrameSelection,
+    view: View,
+    options: RenderOptions,
+    frame_rate: FrameRate,
+    frame_count: usize,
+}
+
+impl PngWriter {
+    pub fn new(
+        pattern: String,
+        selection: FrameSelection,
+        view: View,
+        options: RenderOptions,
+    ) -> PngWriter {
+        PngWriter {
+            pattern,
+            selection,
+            view,
+            options,
+            frame_rate: FrameRate::default(),
+            frame_count: 0,
+        }
+    }
+}
+
+impl FrameSink for PngWriter {
+    fn set_frame_rate(&mut self, frame_rate: FrameRate) {
+        self.frame_rate = frame_rate;
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let index = self.frame_count;
+        self.frame_count += 1;
+        if !self
+            .selection
+            .contains(index, frame.timestamp, self.frame_rate)
+        {
+            return Ok(());
+        }
+
+        let image = render(self.view, frame, self.options);
+        save_png(Path::new(&format_pattern(&self.pattern, index)), &image)
+    }
+
+    fn stages(&self) -> Vec<Stage> {
+        [self.options.stage, self.options.compare]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_of_iend() {
+        assert_eq!(crc32(&[b"IEND"]), 0xae426082);
+        assert_eq!(crc32(&[b"IE", b"ND"]), 0xae426082);
+    }
+
+    #[test]
+    fn adler32_of_wikipedia() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn zlib_stored_blocks() {
+        assert_eq!(
+            zlib_stored(b"abc"),
+            [0x78, 0x01, 1, 3, 0, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27]
+        );
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]
+        );
+
+        let data = vec![7; MAX_STORED_BLOCK + 1];
+        let out = zlib_stored(&data);
+        assert_eq!(out.len(), 2 + 2 * 5 + data.len() + 4);
+        assert_eq!(out[2], 0);
+        assert_eq!(out[2 + 5 + MAX_STORED_BLOCK..][..5], [1, 1, 0, 0xfe, 0xff]);
+    }
+
+    #[test]
+    fn format_pattern_fills_in_the_index() {
+        assert_eq!(format_pattern("frame_%04d.png", 7), "frame_0007.png");
+        assert_eq!(format_pattern("frame_%d.png", 12), "frame_12.png");
+        assert_eq!(format_pattern("frame.png", 3), "frame.png");
+    }
+}
@@ -0,0 +1,149 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::decoder::{BitDepth, Frame, FrameRate, FrameSink};
+
+use super::write_plane;
+
+// https://wiki.multimedia.cx/index.php/YUV4MPEG2
+
+/// Writes frames as YUV4MPEG2. The stream header is taken from the first frame.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    frame_rate: FrameRate,
+    wrote_header: bool,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(writer: W) -> Y4mWriter<W> {
+        Y4mWriter {
+            writer,
+            frame_rate: FrameRate::default(),
+            wrote_header: false,
+        }
+    }
+
+    fn write_header(&mut self, frame: &Frame) -> Result<()> {
+        writeln!(
+            self.writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A0:0 {}",
+            frame.width,
+            frame.height,
+            self.frame_rate.numerator,
+            self.frame_rate.denominator,
+            Y4mWriter::<W>::colorspace(frame)
+        )?;
+
+        Ok(())
+    }
+
+    /// The C tag, e.g. C420jpeg, C420p10, C444 or Cmono.
+    fn colorspace(frame: &Frame) -> String {
+        let sampling = if frame.is_monochrome() {
+            "mono"
+        } else {
+            match (frame.subsampling_x, frame.subsampling_y) {
+                (true, true) => "420",
+                (true, false) => "422",
+                _ => "444",
+            }
+        };
+
+        match frame.bit_depth {
+            BitDepth::Eight if sampling == "420" => "C420jpeg".to_string(),
+            BitDepth::Eight => format!("C{sampling}"),
+            bit_depth if sampling == "mono" => format!("Cmono{}", bit_depth as u8),
+            bit_depth => format!("C{sampling}p{}", bit_depth as u8),
+        }
+    }
+}
+
+impl<W: Write> FrameSink for Y4mWriter<W> {
+    fn set_frame_rate(&mut self, frame_rate: FrameRate) {
+        self.frame_rate = frame_rate;
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        if !self.wrote_header {
+            self.write_header(frame)?;
+            self.wrote_header = true;
+        }
+
+        writeln!(self.writer, "FRAME")?;
+        for plane in &frame.planes {
+            write_plane(&mut self.writer, plane, frame.bit_depth)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::{ColorConfig, FrameInfo, Plane};
+
+    use super::*;
+
+    fn frame(bit_depth: BitDepth, planes: Vec<Plane>) -> Frame {
+        Frame {
+            timestamp: 0,
+            width: planes[0].width,
+            height: planes[0].height,
+            bit_depth,
+            subsampling_x: true,
+            subsampling_y: true,
+            color_config: ColorConfig::default(),
+            planes,
+            info: FrameInfo::default(),
+            stages: Vec::new(),
+        }
+    }
+
+    fn plane(width: usize, height: usize, stride: usize, sample: u16) -> Plane {
+        Plane {
+            width,
+            height,
+            stride,
+            data: vec![sample; stride * height],
+        }
+    }
+
+    #[test]
+    fn writes_header_once_and_visible_samples() {
+        let frame = frame(
+            BitDepth::Eight,
+            vec![plane(4, 2, 8, 1), plane(2, 1, 4, 2), plane(2, 1, 4, 3)],
+        );
+        let mut y4m = Y4mWriter::new(Vec::new());
+        y4m.set_frame_rate(FrameRate {
+            numerator: 25,
+            denominator: 1,
+        });
+        y4m.write_frame(&frame).unwrap();
+        y4m.write_frame(&frame).unwrap();
+
+        let header = b"YUV4MPEG2 W4 H2 F25:1 Ip A0:0 C420jpeg\n";
+        let body = [&b"FRAME\n"[..], &[1; 8], &[2; 2], &[3; 2]].concat();
+        assert_eq!(y4m.writer, [&header[..], &body, &body].concat());
+    }
+
+    #[test]
+    fn colorspace_tags() {
+        let mut frame = frame(BitDepth::Ten, vec![plane(2, 2, 2, 0); 3]);
+        assert_eq!(Y4mWriter::<Vec<u8>>::colorspace(&frame), "C420p10");
+
+        frame.subsampling_y = false;
+        assert_eq!(Y4mWriter::<Vec<u8>>::colorspace(&frame), "C422p10");
+
+        frame.bit_depth = BitDepth::Eight;
+        frame.subsampling_x = false;
+        assert_eq!(Y4mWriter::<Vec<u8>>::colorspace(&frame), "C444");
+
+        frame.planes.truncate(1);
+        assert_eq!(Y4mWriter::<Vec<u8>>::colorspace(&frame), "Cmono");
+
+        frame.bit_depth = BitDepth::Twelve;
+        assert_eq!(Y4mWriter::<Vec<u8>>::colorspace(&frame), "Cmono12");
+    }
+}
@@ -0,0 +1,30 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::decoder::{Frame, FrameRate, FrameSink};
+
+use super::write_plane;
+
+/// Writes frames as raw planar YUV, the same layout as `aomdec --rawvideo`.
+pub struct YuvWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> YuvWriter<W> {
+    pub fn new(writer: W) -> YuvWriter<W> {
+        YuvWriter { writer }
+    }
+}
+
+impl<W: Write> FrameSink for YuvWriter<W> {
+    fn set_frame_rate(&mut self, _frame_rate: FrameRate) {}
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        for plane in &frame.planes {
+            write_plane(&mut self.writer, plane, frame.bit_depth)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use crate::decoder::{Frame, SyntaxCategory};
+
+use super::{
+    canvas,
+    legend::{with_legend, LegendEntry},
+    maps::{scale_color, text_color},
+    text::{draw_text, line_height, text_width},
+    RenderOptions, RgbImage,
+};
+
+/// Legend colours, indexed by SyntaxCategory.
+const CATEGORY_COLORS: [[u8; 3]; SyntaxCategory::COUNT] = [
+    [255, 220, 0],
+    [0, 200, 255],
+    [255, 64, 64],
+    [240, 50, 230],
+    [60, 180, 75],
+    [245, 130, 48],
+    [180, 180, 180],
+];
+
+const ALPHA: f64 = 0.75;
+const OUTLINE_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Colours every block by the bits it took per pixel, relative to the densest block of the
+/// frame, and writes the bit count into blocks big enough to hold it. The legend splits
+/// the frame's bits over the syntax categories.
+pub fn render_bits(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let mut image = canvas(frame, options);
+    let blocks = &frame.info.blocks;
+
+    let density = |bits: f64, w: usize, h: usize| bits / (w * h) as f64;
+    let max_density = blocks
+        .iter()
+        .map(|block| {
+            density(
+                block.bits.iter().sum(),
+                block.mi_size.width(),
+                block.mi_size.height(),
+            )
+        })
+        .fold(0.0, f64::max);
+
+    for block in blocks {
+        let (w, h) = (block.mi_size.width(), block.mi_size.height());
+        let bits = block.bits.iter().sum::<f64>();
+        let t = if max_density > 0.0 {
+            density(bits, w, h) / max_density
+        } else {
+            0.0
+        };
+
+        let rgb = scale_color(t);
+        let (x, y) = (block.mi_col as i64 * 4, block.mi_row as i64 * 4);
+        image.blend(x, y, w as i64, h as i64, rgb, ALPHA);
+        image.outline(x, y, w as i64, h as i64, OUTLINE_COLOR);
+
+        let label = format!("{bits:.0}");
+        if text_width(&label, 1) + 2 <= w && line_height(1) + 2 <= h {
+            draw_text(&mut image, x + 2, y + 2, &label, 1, text_color(rgb));
+        }
+    }
+
+    let bits = frame.info.bits();
+    let total = bits.iter().sum::<f64>();
+    let mut entries = vec![
+        LegendEntry::heading(format!("{total:.0} BITS")),
+        LegendEntry::heading(format!("MAX {max_density:.2} BPP")),
+    ];
+    for (category, bits) in SyntaxCategory::ALL.iter().zip(bits) {
+        if bits == 0.0 {
+            continue;
+        }
+
+        let share = bits / total;
+        entries.push(LegendEntry {
+            color: Some(CATEGORY_COLORS[*category as usize]),
+            label: format!("{} {:.1}%", category.name(), share * 100.0),
+            share: Some(share),
+        });
+    }
+    with_legend(&image, &entries)
+}
@@ -0,0 +1,91 @@
+use crate::decoder::Frame;
+
+use super::{
+    canvas,
+    legend::{with_legend, LegendEntry},
+    RenderOptions, RgbImage,
+};
+
+/// The angle of every CDEF direction in degrees, counter-clockwise from horizontal.
+/// Direction 0 runs up and to the right, 2 is horizontal and 6 vertical.
+const ANGLES: [&str; 8] = ["45", "22.5", "0", "-22.5", "-45", "-67.5", "90", "67.5"];
+
+/// How bright a block with no variance is, so its hue still shows.
+const MIN_VALUE: f64 = 0.25;
+
+/// Fully saturated colour with hue h in [0, 1) and value v in [0, 1].
+fn hsv(h: f64, v: f64) -> [u8; 3] {
+    let h = h.rem_euclid(1.0) * 6.0;
+    let f = h - h.floor();
+    let (p, q, t) = (0.0, 1.0 - f, f);
+    let rgb = match h.floor() as usize {
+        0 => [1.0, t, p],
+        1 => [q, 1.0, p],
+        2 => [p, 1.0, t],
+        3 => [p, q, 1.0],
+        4 => [t, p, 1.0],
+        _ => [1.0, p, q],
+    };
+    rgb.map(|c| (c * v * 255.0).round() as u8)
+}
+
+/// Hue for the direction and brightness for the variance, on a log scale relative to the
+/// largest variance in the frame.
+fn cdef_color(direction: usize, variance: i64, max_variance: i64) -> [u8; 3] {
+    let t = (variance as f64).ln_1p() / (max_variance as f64).ln_1p().max(f64::MIN_POSITIVE);
+    hsv(direction as f64 / 8.0, MIN_VALUE + (1.0 - MIN_VALUE) * t)
+}
+
+/// Fills every 8x8 luma block CDEF filtered with the colour of the direction its search
+/// found, brighter the stronger the direction. Unfiltered blocks keep the canvas.
+pub fn render_cdef(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let info = &frame.info;
+    let mut image = canvas(frame, options);
+
+    let max_variance = info
+        .cdef_directions
+        .iter()
+        .flatten()
+        .zip(info.cdef_variances.iter().flatten())
+        .filter(|(direction, _)| direction.is_some())
+        .map(|(_, &variance)| variance)
+        .max()
+        .unwrap_or(0);
+
+    let mut counts = [0usize; 8];
+    let mut positions = 0;
+    for (y8, (directions, variances)) in info
+        .cdef_directions
+        .iter()
+        .zip(&info.cdef_variances)
+        .enumerate()
+    {
+        for (x8, (direction, &variance)) in directions.iter().zip(variances).enumerate() {
+            positions += 1;
+            let Some(direction) = *direction else {
+                continue;
+            };
+
+            let rgb = cdef_color(direction, variance, max_variance);
+            image.fill(x8 as i64 * 8, y8 as i64 * 8, 8, 8, rgb);
+            counts[direction] += 1;
+        }
+    }
+
+    let filtered = counts.iter().sum::<usize>();
+    let mut entries = vec![
+        LegendEntry::heading(format!(
+            "FILTERED {:.1}%",
+            filtered as f64 / positions.max(1) as f64 * 100.0
+        )),
+        LegendEntry::heading(format!("MAX VARIANCE {max_variance}")),
+    ];
+    for (direction, &count) in counts.iter().enumerate() {
+        entries.push(LegendEntry {
+            color: Some(hsv(direction as f64 / 8.0, 1.0)),
+            label: format!("{direction} {} DEG {count}", ANGLES[direction]),
+            share: Some(count as f64 / filtered.max(1) as f64),
+        });
+    }
+    with_legend(&image, &entries)
+}
@@ -0,0 +1,292 @@
+use crate::decoder::{
+    ChromaSamplePosition, ColorPrimaries, Frame, MatrixCoefficients, Plane, TransferCharacteristics,
+};
+
+use super::RgbImage;
+
+// https://www.itu.int/rec/T-REC-H.273
+// https://www.itu.int/rec/R-REC-BT.2100
+
+/// Luminance of the SDR reference white in an HDR signal, BT.2408.
+const SDR_WHITE_NITS: f64 = 203.0;
+/// Peak luminance HDR frames are assumed to be mastered for.
+const HDR_PEAK_NITS: f64 = 1000.0;
+
+/// Linear BT.2020 RGB to linear BT.709 RGB.
+const BT2020_TO_BT709: [[f64; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+
+/// Converts a frame to RGB according to its colour config. With `tone_map`, PQ and HLG
+/// frames are mapped to SDR BT.709 instead of showing their coded values directly.
+pub fn frame_to_rgb(frame: &Frame, tone_map: bool) -> RgbImage {
+    let color_config = &frame.color_config;
+    let transfer = color_config.transfer_characteristics;
+    let hdr = tone_map
+        && matches!(
+            transfer,
+            TransferCharacteristics::Smpte2084 | TransferCharacteristics::Hlg
+        );
+
+    let mut image = RgbImage::new(frame.width, frame.height);
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let [luma, cb, cr] = sample_ycbcr(frame, x, y);
+            let mut rgb = ycbcr_to_rgb(color_config.matrix_coefficients, luma, cb, cr);
+
+            if hdr {
+                rgb = hdr_to_sdr(rgb, transfer, color_config.color_primaries);
+            }
+
+            let rgb = rgb.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+            image.put(x as i64, y as i64, rgb);
+        }
+    }
+
+    image
+}
+
+/// The Y, Cb and Cr at a luma position, normalized to [0, 1] for Y and [-0.5, 0.5] for
+/// Cb and Cr. With the identity matrix all three are normalized like Y.
+fn sample_ycbcr(frame: &Frame, x: usize, y: usize) -> [f64; 3] {
+    let color_config = &frame.color_config;
+    let bit_depth = frame.bit_depth as i32;
+    let max = ((1 << bit_depth) - 1) as f64;
+    let scale = (1 << (bit_depth - 8)) as f64;
+
+    let normalize_luma = |v: f64| {
+        if color_config.color_range {
+            v / max
+        } else {
+            (v - 16.0 * scale) / (219.0 * scale)
+        }
+    };
+    let normalize_chroma = |v: f64| {
+        if color_config.color_range {
+            (v - (1 << (bit_depth - 1)) as f64) / max
+        } else {
+            (v - 128.0 * scale) / (224.0 * scale)
+        }
+    };
+
+    let luma = normalize_luma(frame.planes[0].row(y)[x] as f64);
+    if frame.is_monochrome() {
+        return [luma, 0.0, 0.0];
+    }
+
+    // Chroma sample j sits at luma position 2 * j + offset along a subsampled axis.
+    let offset_y = match color_config.chroma_sample_position {
+        ChromaSamplePosition::Colocated => 0.0,
+        _ => 0.5,
+    };
+    let cx = if frame.subsampling_x {
+        x as f64 / 2.0
+    } else {
+        x as f64
+    };
+    let cy = if frame.subsampling_y {
+        (y as f64 - offset_y) / 2.0
+    } else {
+        y as f64
+    };
+
+    let cb = bilinear(&frame.planes[1], cx, cy);
+    let cr = bilinear(&frame.planes[2], cx, cy);
+    if color_config.matrix_coefficients == MatrixCoefficients::Identity {
+        [luma, normalize_luma(cb), normalize_luma(cr)]
+    } else {
+        [luma, normalize_chroma(cb), normalize_chroma(cr)]
+    }
+}
+
+fn bilinear(plane: &Plane, x: f64, y: f64) -> f64 {
+    let x = x.clamp(0.0, (plane.width - 1) as f64);
+    let y = y.clamp(0.0, (plane.height - 1) as f64);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = (
+        (x0 + 1).min(plane.width - 1),
+        (y0 + 1).min(plane.height - 1),
+    );
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+    let top = plane.row(y0)[x0] as f64 * (1.0 - fx) + plane.row(y0)[x1] as f64 * fx;
+    let bottom = plane.row(y1)[x0] as f64 * (1.0 - fx) + plane.row(y1)[x1] as f64 * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Kr and Kb of the matrices defined through them. The constant luminance and ICtCp
+/// matrices aren't modelled and fall back to their non-constant luminance relatives.
+fn luma_coefficients(matrix_coefficients: MatrixCoefficients) -> (f64, f64) {
+    match matrix_coefficients {
+        MatrixCoefficients::Bt709 => (0.2126, 0.0722),
+        MatrixCoefficients::Fcc => (0.30, 0.11),
+        MatrixCoefficients::Smpte240 => (0.212, 0.087),
+        MatrixCoefficients::Bt2020Ncl
+        | MatrixCoefficients::Bt2020Cl
+        | MatrixCoefficients::ChromatNcl
+        | MatrixCoefficients::ChromatCl
+        | MatrixCoefficients::Ictcp
+        | MatrixCoefficients::Smpte2085 => (0.2627, 0.0593),
+        _ => (0.299, 0.114),
+    }
+}
+
+fn ycbcr_to_rgb(matrix_coefficients: MatrixCoefficients, y: f64, cb: f64, cr: f64) -> [f64; 3] {
+    match matrix_coefficients {
+        MatrixCoefficients::Identity => [cr, y, cb],
+        MatrixCoefficients::SmpteYcgco => {
+            let t = y - cb;
+            [t + cr, y + cb, t - cr]
+        }
+        _ => {
+            let (kr, kb) = luma_coefficients(matrix_coefficients);
+            let kg = 1.0 - kr - kb;
+            let r = y + 2.0 * (1.0 - kr) * cr;
+            let b = y + 2.0 * (1.0 - kb) * cb;
+            let g = (y - kr * r - kb * b) / kg;
+            [r, g, b]
+        }
+    }
+}
+
+/// Takes non-linear PQ or HLG RGB to non-linear SDR BT.709 RGB.
+fn hdr_to_sdr(
+    rgb: [f64; 3],
+    transfer: TransferCharacteristics,
+    primaries: ColorPrimaries,
+) -> [f64; 3] {
+    let rgb = rgb.map(|v| v.clamp(0.0, 1.0));
+    let nits = if transfer == TransferCharacteristics::Smpte2084 {
+        rgb.map(pq_eotf)
+    } else {
+        hlg_eotf(rgb)
+    };
+
+    let nits = if primaries == ColorPrimaries::Bt2020 {
+        BT2020_TO_BT709.map(|row| row.iter().zip(nits).map(|(m, v)| m * v).sum::<f64>())
+    } else {
+        nits
+    };
+
+    // Extended Reinhard on the largest component keeps the hue while rolling the
+    // highlights off towards the assumed peak.
+    let linear = nits.map(|v| v.max(0.0) / SDR_WHITE_NITS);
+    let peak = HDR_PEAK_NITS / SDR_WHITE_NITS;
+    let max = linear.iter().copied().fold(0.0, f64::max);
+    let scale = if max > 0.0 {
+        (1.0 + max / (peak * peak)) / (1.0 + max)
+    } else {
+        0.0
+    };
+
+    linear.map(|v| srgb_oetf((v * scale).min(1.0)))
+}
+
+/// SMPTE ST 2084 non-linear value to display luminance in nits.
+fn pq_eotf(e: f64) -> f64 {
+    const M1: f64 = 2610.0 / 16384.0;
+    const M2: f64 = 2523.0 / 4096.0 * 128.0;
+    const C1: f64 = 3424.0 / 4096.0;
+    const C2: f64 = 2413.0 / 4096.0 * 32.0;
+    const C3: f64 = 2392.0 / 4096.0 * 32.0;
+
+    let p = e.powf(1.0 / M2);
+    10000.0 * ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1)
+}
+
+/// HLG non-linear RGB to display luminance in nits, for a display of HDR_PEAK_NITS.
+fn hlg_eotf(rgb: [f64; 3]) -> [f64; 3] {
+    const A: f64 = 0.17883277;
+    const B: f64 = 0.28466892;
+    const C: f64 = 0.55991073;
+    const GAMMA: f64 = 1.2;
+
+    let scene = rgb.map(|e| {
+        if e <= 0.5 {
+            e * e / 3.0
+        } else {
+            (((e - C) / A).exp() + B) / 12.0
+        }
+    });
+
+    let ys = 0.2627 * scene[0] + 0.6780 * scene[1] + 0.0593 * scene[2];
+    scene.map(|v| HDR_PEAK_NITS * ys.powf(GAMMA - 1.0) * v)
+}
+
+fn srgb_oetf(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::{BitDepth, ColorConfig, FrameInfo};
+
+    use super::*;
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn ycbcr_to_rgb_inverts_the_matrices() {
+        assert_close(
+            ycbcr_to_rgb(MatrixCoefficients::Bt709, 0.5, 0.0, 0.0),
+            [0.5; 3],
+        );
+
+        // Pure red through BT.709: Y = Kr, Cr = 0.5, Cb = -Kr / (2 * (1 - Kb)).
+        let cb = -0.2126 / (2.0 * (1.0 - 0.0722));
+        assert_close(
+            ycbcr_to_rgb(MatrixCoefficients::Bt709, 0.2126, cb, 0.5),
+            [1.0, 0.0, 0.0],
+        );
+
+        assert_close(
+            ycbcr_to_rgb(MatrixCoefficients::Identity, 0.1, 0.2, 0.3),
+            [0.3, 0.1, 0.2],
+        );
+    }
+
+    #[test]
+    fn pq_eotf_range() {
+        assert_eq!(pq_eotf(0.0), 0.0);
+        assert!((pq_eotf(1.0) - 10000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn limited_range_frame_to_rgb() {
+        let plane = |width: usize, height: usize, data: Vec<u16>| Plane {
+            width,
+            height,
+            stride: width,
+            data,
+        };
+        let frame = Frame {
+            timestamp: 0,
+            width: 2,
+            height: 2,
+            bit_depth: BitDepth::Eight,
+            subsampling_x: true,
+            subsampling_y: true,
+            color_config: ColorConfig::default(),
+            planes: vec![
+                plane(2, 2, vec![16, 235, 0, 255]),
+                plane(1, 1, vec![128]),
+                plane(1, 1, vec![128]),
+            ],
+            info: FrameInfo::default(),
+            stages: Vec::new(),
+        };
+
+        let image = frame_to_rgb(&frame, false);
+        assert_eq!(image.data, [0, 0, 0, 255, 255, 255, 0, 0, 0, 255, 255, 255]);
+    }
+}
@@ -0,0 +1,76 @@
+use super::{
+    text::{draw_text, line_height, text_width},
+    RgbImage,
+};
+
+const SCALE: usize = 2;
+const MARGIN: usize = 8;
+const SWATCH: usize = 10;
+const BAR_WIDTH: usize = 80;
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+const TEXT_COLOR: [u8; 3] = [230, 230, 230];
+
+/// One line of a legend: a colour swatch, a label and optionally a bar showing a share
+/// between 0 and 1.
+pub struct LegendEntry {
+    pub color: Option<[u8; 3]>,
+    pub label: String,
+    pub share: Option<f64>,
+}
+
+impl LegendEntry {
+    pub fn heading(label: impl Into<String>) -> LegendEntry {
+        LegendEntry {
+            color: None,
+            label: label.into(),
+            share: None,
+        }
+    }
+}
+
+/// Puts a legend panel to the right of an image.
+pub fn with_legend(image: &RgbImage, entries: &[LegendEntry]) -> RgbImage {
+    let row_height = line_height(SCALE).max(SWATCH) + 4;
+    let label_width = entries
+        .iter()
+        .map(|entry| text_width(&entry.label, SCALE))
+        .max()
+        .unwrap_or(0);
+    let has_bars = entries.iter().any(|entry| entry.share.is_some());
+    let panel_width =
+        MARGIN * 3 + SWATCH + label_width + if has_bars { BAR_WIDTH + MARGIN } else { 0 };
+    let panel_height = MARGIN * 2 + entries.len() * row_height;
+
+    let mut out = RgbImage::filled(
+        image.width + panel_width,
+        image.height.max(panel_height),
+        BACKGROUND,
+    );
+    out.draw_image(image, 0, 0);
+
+    let x = (image.width + MARGIN) as i64;
+    for (i, entry) in entries.iter().enumerate() {
+        let y = (MARGIN + i * row_height) as i64;
+        if let Some(color) = entry.color {
+            out.fill(x, y, SWATCH as i64, SWATCH as i64, color);
+        }
+
+        let text_x = x + (SWATCH + MARGIN) as i64;
+        draw_text(&mut out, text_x, y, &entry.label, SCALE, TEXT_COLOR);
+
+        if let Some(share) = entry.share {
+            let bar_x = text_x + (label_width + MARGIN) as i64;
+            let len = (share.clamp(0.0, 1.0) * BAR_WIDTH as f64).round() as i64;
+            out.fill(bar_x, y, BAR_WIDTH as i64, SWATCH as i64, [64; 3]);
+            out.fill(
+                bar_x,
+                y,
+                len,
+                SWATCH as i64,
+                entry.color.unwrap_or(TEXT_COLOR),
+            );
+        }
+    }
+
+    out
+}
@@ -0,0 +1,151 @@
+use crate::decoder::{BlockInfo, Frame};
+
+use super::{
+    canvas,
+    legend::{with_legend, LegendEntry},
+    text::{draw_text, line_height, text_width},
+    RenderOptions, RgbImage,
+};
+
+/// Stops of the colour scale, from low to high values (viridis).
+const SCALE: [[u8; 3]; 5] = [
+    [68, 1, 84],
+    [59, 82, 139],
+    [33, 145, 140],
+    [94, 201, 98],
+    [253, 231, 37],
+];
+
+const SEGMENT_COLORS: [[u8; 3]; 8] = [
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+    [240, 50, 230],
+];
+
+const ALPHA: f64 = 0.7;
+const OUTLINE_COLOR: [u8; 3] = [0, 0, 0];
+const LEGEND_STEPS: usize = 5;
+
+/// Maps t in [0, 1] onto the colour scale.
+pub(super) fn scale_color(t: f64) -> [u8; 3] {
+    let pos = t.clamp(0.0, 1.0) * (SCALE.len() - 1) as f64;
+    let i = (pos.floor() as usize).min(SCALE.len() - 2);
+    let f = pos - i as f64;
+    [0, 1, 2].map(|c| (SCALE[i][c] as f64 * (1.0 - f) + SCALE[i + 1][c] as f64 * f).round() as u8)
+}
+
+/// Black or white, whichever reads better on top of rgb.
+pub(super) fn text_color(rgb: [u8; 3]) -> [u8; 3] {
+    let luma = 0.299 * rgb[0] as f64 + 0.587 * rgb[1] as f64 + 0.114 * rgb[2] as f64;
+    if luma > 128.0 {
+        [0; 3]
+    } else {
+        [255; 3]
+    }
+}
+
+/// Colours every block by a value and writes the value into blocks big enough to hold it.
+fn render_map(
+    frame: &Frame,
+    options: RenderOptions,
+    value: impl Fn(&BlockInfo) -> i64,
+    color: impl Fn(i64) -> [u8; 3],
+) -> RgbImage {
+    let mut image = canvas(frame, options);
+    for block in &frame.info.blocks {
+        let (x, y) = (block.mi_col as i64 * 4, block.mi_row as i64 * 4);
+        let (w, h) = (block.mi_size.width() as i64, block.mi_size.height() as i64);
+        let value = value(block);
+        let rgb = color(value);
+        image.blend(x, y, w, h, rgb, ALPHA);
+        image.outline(x, y, w, h, OUTLINE_COLOR);
+
+        let label = value.to_string();
+        if text_width(&label, 1) as i64 + 2 <= w && line_height(1) as i64 + 2 <= h {
+            draw_text(&mut image, x + 2, y + 2, &label, 1, text_color(rgb));
+        }
+    }
+
+    image
+}
+
+/// A block map on the colour scale, stretched over the range of values in the frame.
+fn render_scalar_map(
+    frame: &Frame,
+    options: RenderOptions,
+    name: &str,
+    value: impl Fn(&BlockInfo) -> i64,
+) -> RgbImage {
+    let blocks = &frame.info.blocks;
+    let min = blocks.iter().map(&value).min().unwrap_or(0);
+    let max = blocks.iter().map(&value).max().unwrap_or(0);
+    let t = |v: i64| {
+        if max > min {
+            (v - min) as f64 / (max - min) as f64
+        } else {
+            0.5
+        }
+    };
+
+    let image = render_map(frame, options, &value, |v| scale_color(t(v)));
+
+    let mean =
+        blocks.iter().map(|block| value(block) as f64).sum::<f64>() / blocks.len().max(1) as f64;
+    let mut entries = vec![
+        LegendEntry::heading(name),
+        LegendEntry::heading(format!("MIN {min} MAX {max}")),
+        LegendEntry::heading(format!("MEAN {mean:.1}")),
+    ];
+    for step in 0..LEGEND_STEPS {
+        let v = min + (max - min) * step as i64 / (LEGEND_STEPS - 1) as i64;
+        entries.push(LegendEntry {
+            color: Some(scale_color(t(v))),
+            label: v.to_string(),
+            share: None,
+        });
+    }
+    with_legend(&image, &entries)
+}
+
+pub fn render_qindex(frame: &Frame, options: RenderOptions) -> RgbImage {
+    render_scalar_map(frame, options, "QINDEX", |block| block.qindex as i64)
+}
+
+/// The luma vertical edge level, the one most streams signal deltas for.
+pub fn render_filter_levels(frame: &Frame, options: RenderOptions) -> RgbImage {
+    render_scalar_map(frame, options, "FILTER LEVEL Y", |block| {
+        block.filter_levels[0]
+    })
+}
+
+pub fn render_segments(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let color = |segment: i64| SEGMENT_COLORS[segment as usize % SEGMENT_COLORS.len()];
+    let image = render_map(frame, options, |block| block.segment_id as i64, color);
+
+    let mut areas = [0usize; 8];
+    for block in &frame.info.blocks {
+        areas[block.segment_id as usize % areas.len()] +=
+            block.mi_size.width() * block.mi_size.height();
+    }
+
+    let total = areas.iter().sum::<usize>().max(1) as f64;
+    let mut entries = vec![LegendEntry::heading("SEGMENT")];
+    for (segment, &area) in areas.iter().enumerate() {
+        if area == 0 {
+            continue;
+        }
+
+        let share = area as f64 / total;
+        entries.push(LegendEntry {
+            color: Some(color(segment as i64)),
+            label: format!("{segment} {:.1}%", share * 100.0),
+            share: Some(share),
+        });
+    }
+    with_legend(&image, &entries)
+}
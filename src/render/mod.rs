@@ -0,0 +1,243 @@
+use crate::decoder::{Frame, Stage};
+
+pub use self::color::frame_to_rgb;
+
+mod bits;
+mod cdef;
+mod color;
+mod legend;
+mod maps;
+mod modes;
+mod motion;
+mod partition;
+mod stages;
+mod text;
+mod transform;
+
+/// An 8-bit RGB image, the common currency of everything that gets drawn.
+#[derive(Debug, Clone)]
+pub struct RgbImage {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major, 3 bytes per pixel.
+    pub data: Vec<u8>,
+}
+
+impl RgbImage {
+    pub fn new(width: usize, height: usize) -> RgbImage {
+        RgbImage {
+            width,
+            height,
+            data: vec![0; width * height * 3],
+        }
+    }
+
+    pub fn filled(width: usize, height: usize, rgb: [u8; 3]) -> RgbImage {
+        RgbImage {
+            width,
+            height,
+            data: rgb.repeat(width * height),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * self.width + x) * 3;
+        [self.data[i], self.data[i + 1], self.data[i + 2]]
+    }
+
+    /// Sets a pixel, ignoring positions outside the image so callers can draw shapes that
+    /// are partly off screen.
+    pub fn put(&mut self, x: i64, y: i64, rgb: [u8; 3]) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+
+        let i = (y as usize * self.width + x as usize) * 3;
+        self.data[i..i + 3].copy_from_slice(&rgb);
+    }
+
+    pub fn fill(&mut self, x: i64, y: i64, w: i64, h: i64, rgb: [u8; 3]) {
+        for j in 0..h {
+            for i in 0..w {
+                self.put(x + i, y + j, rgb);
+            }
+        }
+    }
+
+    /// Mixes a colour into a rectangle, `alpha` being the weight of the new colour.
+    pub fn blend(&mut self, x: i64, y: i64, w: i64, h: i64, rgb: [u8; 3], alpha: f64) {
+        let x_range = x.max(0)..(x + w).min(self.width as i64);
+        for j in y.max(0)..(y + h).min(self.height as i64) {
+            for i in x_range.clone() {
+                let old = self.get(i as usize, j as usize);
+                let mixed = [0, 1, 2]
+                    .map(|c| (old[c] as f64 * (1.0 - alpha) + rgb[c] as f64 * alpha).round() as u8);
+                self.put(i, j, mixed);
+            }
+        }
+    }
+
+    /// Copies another image into this one with its top left corner at (x, y).
+    pub fn draw_image(&mut self, other: &RgbImage, x: i64, y: i64) {
+        for j in 0..other.height {
+            for i in 0..other.width {
+                self.put(x + i as i64, y + j as i64, other.get(i, j));
+            }
+        }
+    }
+
+    /// Draws a one pixel wide line between two points, both included.
+    pub fn line(&mut self, x0: i64, y0: i64, x1: i64, y1: i64, rgb: [u8; 3]) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.put(x, y, rgb);
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Draws the one pixel wide outline of a w x h rectangle.
+    pub fn outline(&mut self, x: i64, y: i64, w: i64, h: i64, rgb: [u8; 3]) {
+        for i in 0..w {
+            self.put(x + i, y, rgb);
+            self.put(x + i, y + h - 1, rgb);
+        }
+        for j in 0..h {
+            self.put(x, y + j, rgb);
+            self.put(x + w - 1, y + j, rgb);
+        }
+    }
+}
+
+/// What to draw for each frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum View {
+    /// The decoded picture.
+    #[default]
+    Frame,
+    /// Superblock, block and transform boundaries.
+    Partition,
+    /// Blocks coloured by prediction mode, with a legend and mode histogram.
+    Modes,
+    /// Motion vectors as arrows, coloured by reference frame.
+    Motion,
+    /// Inter blocks coloured by the direction and length of their first motion vector.
+    Flow,
+    /// The temporal motion field projected from the reference frames.
+    MotionField,
+    /// Blocks coloured by their quantizer index.
+    Qindex,
+    /// Blocks coloured by segment.
+    Segments,
+    /// Blocks coloured by their luma loop filter level.
+    FilterLevel,
+    /// Blocks coloured by the bits they took to code per pixel.
+    Bits,
+    /// Transform blocks coloured by transform type.
+    Transform,
+    /// 8x8 blocks coloured by CDEF direction, brighter for higher directional variance.
+    Cdef,
+    /// Two pipeline stages next to each other.
+    SideBySide,
+    /// The luma difference between two pipeline stages.
+    Difference,
+}
+
+impl View {
+    pub fn new(name: &str) -> Option<View> {
+        match name {
+            "frame" => Some(View::Frame),
+            "partition" => Some(View::Partition),
+            "modes" => Some(View::Modes),
+            "motion" => Some(View::Motion),
+            "flow" => Some(View::Flow),
+            "motion-field" => Some(View::MotionField),
+            "qindex" => Some(View::Qindex),
+            "segments" => Some(View::Segments),
+            "filter-level" => Some(View::FilterLevel),
+            "bits" => Some(View::Bits),
+            "transform" => Some(View::Transform),
+            "cdef" => Some(View::Cdef),
+            "side-by-side" => Some(View::SideBySide),
+            "difference" => Some(View::Difference),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    /// Map PQ and HLG frames to SDR.
+    pub tone_map: bool,
+    /// Draw overlays on a grey canvas instead of the decoded luma, for streams whose
+    /// pixels can't be reconstructed yet.
+    pub grey_canvas: bool,
+    /// The pipeline stage to show instead of the output.
+    pub stage: Option<Stage>,
+    /// The stage the side by side and difference views compare `stage` with, the output
+    /// if unset.
+    pub compare: Option<Stage>,
+}
+
+pub fn render(view: View, frame: &Frame, options: RenderOptions) -> RgbImage {
+    match view {
+        View::Frame => frame_to_rgb(staged(frame, options), options.tone_map),
+        View::Partition => partition::render_partition(frame, options),
+        View::Modes => modes::render_modes(frame, options),
+        View::Motion => motion::render_motion(frame, options),
+        View::Flow => motion::render_flow(frame, options),
+        View::MotionField => motion::render_motion_field(frame, options),
+        View::Qindex => maps::render_qindex(frame, options),
+        View::Segments => maps::render_segments(frame, options),
+        View::FilterLevel => maps::render_filter_levels(frame, options),
+        View::Bits => bits::render_bits(frame, options),
+        View::Transform => transform::render_transform(frame, options),
+        View::Cdef => cdef::render_cdef(frame, options),
+        View::SideBySide => stages::render_side_by_side(frame, options),
+        View::Difference => stages::render_difference(frame, options),
+    }
+}
+
+/// The frame at the stage the options ask for.
+fn staged(frame: &Frame, options: RenderOptions) -> &Frame {
+    options
+        .stage
+        .and_then(|stage| frame.stage(stage))
+        .unwrap_or(frame)
+}
+
+/// The background overlays are drawn on: the luma of the frame at its coded size, or flat
+/// grey.
+fn canvas(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let info = &frame.info;
+    if options.grey_canvas {
+        return RgbImage::filled(info.frame_width, info.frame_height, [128; 3]);
+    }
+
+    // With superres the picture is wider than the coded frame the blocks live in.
+    let luma = &staged(frame, options).planes[0];
+    let shift = frame.bit_depth as u32 - 8;
+    let mut image = RgbImage::new(info.frame_width, info.frame_height);
+    for y in 0..info.frame_height.min(luma.height) {
+        let row = luma.row(y);
+        for x in 0..info.frame_width {
+            let v = (row[x * luma.width / info.frame_width] >> shift) as u8;
+            image.put(x as i64, y as i64, [v; 3]);
+        }
+    }
+
+    image
+}
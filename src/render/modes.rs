@@ -0,0 +1,114 @@
+use tracing::info;
+
+use crate::decoder::{BlockInfo, Frame, UvMode, YMode};
+
+use super::{
+    canvas,
+    legend::{with_legend, LegendEntry},
+    RenderOptions, RgbImage,
+};
+
+/// Label and colour of every mode the view tells apart. The first 13 are the luma intra
+/// modes in YMode order, the last 12 the inter modes in YMode order.
+const MODES: [(&str, [u8; 3]); 28] = [
+    ("DC", [255, 255, 255]),
+    ("V", [230, 25, 75]),
+    ("H", [60, 180, 75]),
+    ("D45", [255, 225, 25]),
+    ("D135", [0, 130, 200]),
+    ("D113", [245, 130, 48]),
+    ("D157", [145, 30, 180]),
+    ("D203", [70, 240, 240]),
+    ("D67", [240, 50, 230]),
+    ("SMOOTH", [210, 245, 60]),
+    ("SMOOTH_V", [250, 190, 212]),
+    ("SMOOTH_H", [0, 128, 128]),
+    ("PAETH", [220, 190, 255]),
+    ("CFL", [170, 110, 40]),
+    ("PALETTE", [255, 250, 200]),
+    ("INTRABC", [128, 0, 0]),
+    ("NEARESTMV", [170, 255, 195]),
+    ("NEARMV", [128, 128, 0]),
+    ("GLOBALMV", [255, 215, 180]),
+    ("NEWMV", [0, 0, 160]),
+    ("NEAREST_NEARESTMV", [128, 128, 128]),
+    ("NEAR_NEARMV", [100, 149, 237]),
+    ("NEAREST_NEWMV", [47, 79, 79]),
+    ("NEW_NEARESTMV", [255, 99, 71]),
+    ("NEAR_NEWMV", [154, 205, 50]),
+    ("NEW_NEARMV", [186, 85, 211]),
+    ("GLOBAL_GLOBALMV", [255, 165, 0]),
+    ("NEW_NEWMV", [30, 30, 200]),
+];
+const CFL: usize = 13;
+const PALETTE: usize = 14;
+const INTRABC: usize = 15;
+const FIRST_INTER: usize = 16;
+
+const ALPHA: f64 = 0.6;
+const OUTLINE_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Index into MODES. Intra block copy, palette and CfL are shown instead of the luma mode
+/// they come with.
+fn mode_index(block: &BlockInfo) -> usize {
+    if block.use_intrabc {
+        INTRABC
+    } else if block.is_inter {
+        block.y_mode as usize - YMode::NearestMv as usize + FIRST_INTER
+    } else if block.palette_size_y > 0 {
+        PALETTE
+    } else if block.uv_mode == UvMode::Cfl {
+        CFL
+    } else {
+        block.y_mode as usize
+    }
+}
+
+pub fn render_modes(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let info = &frame.info;
+    let mut image = canvas(frame, options);
+
+    // Area in 4x4 units per mode, clipped to the frame.
+    let mut histogram = [0usize; MODES.len()];
+    for block in &info.blocks {
+        let mode = mode_index(block);
+        let (x, y) = (block.mi_col as i64 * 4, block.mi_row as i64 * 4);
+        let (w, h) = (block.mi_size.width() as i64, block.mi_size.height() as i64);
+        image.blend(x, y, w, h, MODES[mode].1, ALPHA);
+        image.outline(x, y, w, h, OUTLINE_COLOR);
+
+        let w4 = (block.mi_size.width() / 4).min(info.mi_cols - block.mi_col as usize);
+        let h4 = (block.mi_size.height() / 4).min(info.mi_rows - block.mi_row as usize);
+        histogram[mode] += w4 * h4;
+    }
+
+    let total = histogram.iter().sum::<usize>().max(1) as f64;
+    let intra = histogram[..FIRST_INTER].iter().sum::<usize>() as f64 / total;
+    let inter = histogram[FIRST_INTER..].iter().sum::<usize>() as f64 / total;
+    info!(
+        "frame {}: {} blocks, {:.1}% intra, {:.1}% inter by area",
+        frame.timestamp,
+        info.blocks.len(),
+        intra * 100.0,
+        inter * 100.0
+    );
+
+    let mut entries = vec![
+        LegendEntry::heading(format!("INTRA {:.1}%", intra * 100.0)),
+        LegendEntry::heading(format!("INTER {:.1}%", inter * 100.0)),
+    ];
+    for (mode, &area) in histogram.iter().enumerate() {
+        if area == 0 {
+            continue;
+        }
+
+        let share = area as f64 / total;
+        entries.push(LegendEntry {
+            color: Some(MODES[mode].1),
+            label: format!("{} {:.1}%", MODES[mode].0, share * 100.0),
+            share: Some(share),
+        });
+    }
+
+    with_legend(&image, &entries)
+}
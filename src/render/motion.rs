@@ -0,0 +1,202 @@
+use std::f64::consts::PI;
+
+use crate::decoder::{BlockInfo, Frame};
+
+use super::{
+    canvas,
+    legend::{with_legend, LegendEntry},
+    RenderOptions, RgbImage,
+};
+
+/// Name and colour of each reference frame, indexed by reference frame. Intra block copy
+/// refers to the current frame, INTRA_FRAME.
+const REF_FRAMES: [(&str, [u8; 3]); 8] = [
+    ("INTRABC", [255, 255, 255]),
+    ("LAST", [255, 64, 64]),
+    ("LAST2", [255, 160, 32]),
+    ("LAST3", [255, 240, 32]),
+    ("GOLDEN", [64, 220, 64]),
+    ("BWDREF", [32, 200, 255]),
+    ("ALTREF2", [96, 96, 255]),
+    ("ALTREF", [230, 64, 230]),
+];
+
+const ORIGIN_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Draws an arrow from (x, y) along a motion vector in 1/8 pixels, [row, col].
+fn arrow(image: &mut RgbImage, x: i64, y: i64, mv: [i64; 2], rgb: [u8; 3]) {
+    let (dx, dy) = (mv[1] as f64 / 8.0, mv[0] as f64 / 8.0);
+    let (x1, y1) = (x + dx.round() as i64, y + dy.round() as i64);
+    image.line(x, y, x1, y1, rgb);
+
+    let len = dx.hypot(dy);
+    if len < 2.0 {
+        return;
+    }
+
+    let head = (len / 2.0).min(4.0);
+    let angle = dy.atan2(dx);
+    for side in [-1.0, 1.0] {
+        let a = angle + PI - side * PI / 6.0;
+        let hx = x1 + (head * a.cos()).round() as i64;
+        let hy = y1 + (head * a.sin()).round() as i64;
+        image.line(x1, y1, hx, hy, rgb);
+    }
+}
+
+fn center(block: &BlockInfo) -> (i64, i64) {
+    (
+        block.mi_col as i64 * 4 + block.mi_size.width() as i64 / 2,
+        block.mi_row as i64 * 4 + block.mi_size.height() as i64 / 2,
+    )
+}
+
+/// The reference frames a block predicts from, with their motion vectors.
+fn references(block: &BlockInfo) -> impl Iterator<Item = (usize, [i64; 2])> + '_ {
+    let num_refs = if block.ref_frame[1] > 0 { 2 } else { 1 };
+    (0..num_refs).map(|list| (block.ref_frame[list] as usize, block.mv[list]))
+}
+
+/// Legend lines for how often each reference frame is used.
+fn reference_entries(counts: &[usize; 8]) -> Vec<LegendEntry> {
+    let total = counts.iter().sum::<usize>().max(1) as f64;
+    counts
+        .iter()
+        .enumerate()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(ref_frame, &count)| LegendEntry {
+            color: Some(REF_FRAMES[ref_frame].1),
+            label: format!("{} {count}", REF_FRAMES[ref_frame].0),
+            share: Some(count as f64 / total),
+        })
+        .collect()
+}
+
+/// An arrow from the centre of every inter and intra block copy block along each of its
+/// motion vectors, so compound blocks get two.
+pub fn render_motion(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let mut image = canvas(frame, options);
+
+    let mut counts = [0; 8];
+    let mut compound = 0;
+    for block in &frame.info.blocks {
+        if !block.is_inter && !block.use_intrabc {
+            continue;
+        }
+
+        let (x, y) = center(block);
+        for (ref_frame, mv) in references(block) {
+            arrow(&mut image, x, y, mv, REF_FRAMES[ref_frame].1);
+            counts[ref_frame] += 1;
+        }
+        image.put(x, y, ORIGIN_COLOR);
+        compound += (block.ref_frame[1] > 0) as usize;
+    }
+
+    let mut entries = vec![LegendEntry::heading(format!("COMPOUND {compound}"))];
+    entries.extend(reference_entries(&counts));
+    with_legend(&image, &entries)
+}
+
+/// Hue for the direction and saturation for the length, relative to the longest vector.
+fn flow_color(mv: [i64; 2], max_len: f64) -> [u8; 3] {
+    let (dx, dy) = (mv[1] as f64, mv[0] as f64);
+    let hue = (dy.atan2(dx) / (2.0 * PI)).rem_euclid(1.0) * 6.0;
+    let saturation = (dx.hypot(dy) / max_len).min(1.0);
+
+    let sector = hue.floor();
+    let f = hue - sector;
+    let (p, q, t) = (
+        1.0 - saturation,
+        1.0 - saturation * f,
+        1.0 - saturation * (1.0 - f),
+    );
+    let rgb = match sector as usize {
+        0 => [1.0, t, p],
+        1 => [q, 1.0, p],
+        2 => [p, 1.0, t],
+        3 => [p, q, 1.0],
+        4 => [t, p, 1.0],
+        _ => [1.0, p, q],
+    };
+    rgb.map(|c| (c * 255.0).round() as u8)
+}
+
+/// Dense optical flow style colouring of the first motion vector of every inter block.
+/// Still blocks come out white.
+pub fn render_flow(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let mut image = canvas(frame, options);
+    let blocks = || frame.info.blocks.iter().filter(|block| block.is_inter);
+
+    let max_len = blocks()
+        .map(|block| (block.mv[0][1] as f64).hypot(block.mv[0][0] as f64))
+        .fold(1.0, f64::max);
+    for block in blocks() {
+        image.fill(
+            block.mi_col as i64 * 4,
+            block.mi_row as i64 * 4,
+            block.mi_size.width() as i64,
+            block.mi_size.height() as i64,
+            flow_color(block.mv[0], max_len),
+        );
+    }
+
+    let mut entries = vec![LegendEntry::heading(format!("MAX {:.2} PX", max_len / 8.0))];
+    for (label, angle) in [
+        ("RIGHT", 0.0),
+        ("DOWN", PI / 2.0),
+        ("LEFT", PI),
+        ("UP", -PI / 2.0),
+    ] {
+        let mv = [
+            (angle.sin() * max_len) as i64,
+            (angle.cos() * max_len) as i64,
+        ];
+        entries.push(LegendEntry {
+            color: Some(flow_color(mv, max_len)),
+            label: label.to_string(),
+            share: None,
+        });
+    }
+    with_legend(&image, &entries)
+}
+
+/// An arrow for every 8x8 block the projected motion field covers, coloured by the
+/// reference frame it was projected from.
+pub fn render_motion_field(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let mut image = canvas(frame, options);
+    let motion_field = &frame.info.motion_field;
+    if motion_field.is_empty() {
+        return with_legend(&image, &[LegendEntry::heading("NO MOTION FIELD")]);
+    }
+
+    let mut counts = [0; 8];
+    let mut positions = 0;
+    for (y8, row) in motion_field.iter().enumerate() {
+        for (x8, projected) in row.iter().enumerate() {
+            positions += 1;
+            let Some(projected) = projected else {
+                continue;
+            };
+
+            let (x, y) = (x8 as i64 * 8 + 4, y8 as i64 * 8 + 4);
+            arrow(
+                &mut image,
+                x,
+                y,
+                projected.mv,
+                REF_FRAMES[projected.source].1,
+            );
+            image.put(x, y, ORIGIN_COLOR);
+            counts[projected.source] += 1;
+        }
+    }
+
+    let covered = counts.iter().sum::<usize>() as f64 / positions.max(1) as f64;
+    let mut entries = vec![LegendEntry::heading(format!(
+        "PROJECTED {:.1}%",
+        covered * 100.0
+    ))];
+    entries.extend(reference_entries(&counts));
+    with_legend(&image, &entries)
+}
@@ -0,0 +1,45 @@
+use crate::decoder::Frame;
+
+use super::{canvas, RenderOptions, RgbImage};
+
+const SUPERBLOCK_COLOR: [u8; 3] = [255, 48, 48];
+const BLOCK_COLOR: [u8; 3] = [255, 220, 0];
+const TX_COLOR: [u8; 3] = [0, 200, 255];
+
+/// Outlines transform blocks, then blocks, then superblocks, so the coarser boundaries
+/// stay visible where they coincide.
+pub fn render_partition(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let info = &frame.info;
+    let mut image = canvas(frame, options);
+
+    // Transform blocks tile their block from its origin, so they start at multiples of
+    // their own size.
+    for (row, tx_sizes) in info.tx_sizes.iter().enumerate().take(info.mi_rows) {
+        for (col, tx_size) in tx_sizes.iter().enumerate().take(info.mi_cols) {
+            let (x, y) = (col * 4, row * 4);
+            let (w, h) = (tx_size.width(), tx_size.height());
+            if x.is_multiple_of(w) && y.is_multiple_of(h) {
+                image.outline(x as i64, y as i64, w as i64, h as i64, TX_COLOR);
+            }
+        }
+    }
+
+    for block in &info.blocks {
+        image.outline(
+            block.mi_col as i64 * 4,
+            block.mi_row as i64 * 4,
+            block.mi_size.width() as i64,
+            block.mi_size.height() as i64,
+            BLOCK_COLOR,
+        );
+    }
+
+    let sb = info.superblock_size;
+    for y in (0..info.frame_height).step_by(sb) {
+        for x in (0..info.frame_width).step_by(sb) {
+            image.outline(x as i64, y as i64, sb as i64, sb as i64, SUPERBLOCK_COLOR);
+        }
+    }
+
+    image
+}
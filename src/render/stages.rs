@@ -0,0 +1,115 @@
+use crate::decoder::{Frame, Stage};
+
+use super::{
+    frame_to_rgb,
+    legend::{with_legend, LegendEntry},
+    text::{draw_text, line_height, text_width},
+    RenderOptions, RgbImage,
+};
+
+const SCALE: usize = 2;
+const MARGIN: usize = 8;
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+const TEXT_COLOR: [u8; 3] = [230, 230, 230];
+
+/// How much a difference of one 8-bit step brightens or darkens the difference image.
+const DIFFERENCE_GAIN: f64 = 4.0;
+
+/// The two stages being compared: `stage` against `compare`, either defaulting to the
+/// output.
+fn stages(options: RenderOptions) -> (Stage, Stage) {
+    (
+        options.stage.unwrap_or(Stage::Output),
+        options.compare.unwrap_or(Stage::Output),
+    )
+}
+
+fn staged(frame: &Frame, stage: Stage) -> &Frame {
+    frame.stage(stage).unwrap_or(frame)
+}
+
+/// The two stages next to each other, each with its name above it.
+pub fn render_side_by_side(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let (left, right) = stages(options);
+    let images =
+        [left, right].map(|stage| (stage, frame_to_rgb(staged(frame, stage), options.tone_map)));
+
+    let title_height = line_height(SCALE) + MARGIN;
+    let width = images.iter().map(|(_, image)| image.width).sum::<usize>() + MARGIN;
+    let height = images
+        .iter()
+        .map(|(_, image)| image.height)
+        .max()
+        .unwrap_or(0);
+    let mut out = RgbImage::filled(width, title_height + height, BACKGROUND);
+
+    let mut x = 0;
+    for (stage, image) in &images {
+        let title = stage.name().to_uppercase();
+        let title_x = x + image.width.saturating_sub(text_width(&title, SCALE)) / 2;
+        draw_text(
+            &mut out,
+            title_x as i64,
+            (MARGIN / 2) as i64,
+            &title,
+            SCALE,
+            TEXT_COLOR,
+        );
+        out.draw_image(image, x as i64, title_height as i64);
+        x += image.width + MARGIN;
+    }
+
+    out
+}
+
+/// The luma difference between the two stages around mid grey: brighter where the first
+/// is brighter, amplified to make small changes visible. The legend gives the size of the
+/// difference and the PSNR of the first stage against the second.
+pub fn render_difference(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let (a, b) = stages(options);
+    let (frame_a, frame_b) = (staged(frame, a), staged(frame, b));
+    let (luma_a, luma_b) = (&frame_a.planes[0], &frame_b.planes[0]);
+
+    // Stages before superres are narrower, so columns are matched up proportionally.
+    let width = luma_a.width.max(luma_b.width);
+    let height = luma_a.height.min(luma_b.height);
+    let step = (1u32 << (frame.bit_depth as u32 - 8)) as f64;
+    let max_value = ((1u32 << frame.bit_depth as u32) - 1) as f64;
+
+    let mut image = RgbImage::new(width, height);
+    let (mut max_diff, mut sum_abs, mut sum_sq) = (0.0f64, 0.0, 0.0);
+    for y in 0..height {
+        let (row_a, row_b) = (luma_a.row(y), luma_b.row(y));
+        for x in 0..width {
+            let sample_a = row_a[x * luma_a.width / width] as f64;
+            let sample_b = row_b[x * luma_b.width / width] as f64;
+            let diff = sample_a - sample_b;
+            max_diff = max_diff.max(diff.abs());
+            sum_abs += diff.abs();
+            sum_sq += diff * diff;
+
+            let v = (128.0 + diff / step * DIFFERENCE_GAIN)
+                .round()
+                .clamp(0.0, 255.0) as u8;
+            image.put(x as i64, y as i64, [v; 3]);
+        }
+    }
+
+    let count = (width * height).max(1) as f64;
+    let mse = sum_sq / count;
+    let psnr = if mse > 0.0 {
+        format!("{:.2} DB", 10.0 * (max_value * max_value / mse).log10())
+    } else {
+        "IDENTICAL".to_string()
+    };
+
+    let entries = [
+        LegendEntry::heading(a.name().to_uppercase()),
+        LegendEntry::heading(format!("- {}", b.name().to_uppercase())),
+        LegendEntry::heading(format!("MAX {max_diff}")),
+        LegendEntry::heading(format!("MEAN {:.3}", sum_abs / count)),
+        LegendEntry::heading(format!("PSNR {psnr}")),
+        LegendEntry::heading(format!("GAIN {DIFFERENCE_GAIN}X")),
+    ];
+    with_legend(&image, &entries)
+}
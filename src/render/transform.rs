@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use crate::decoder::{Frame, TxType};
+
+use super::{
+    canvas,
+    legend::{with_legend, LegendEntry},
+    RenderOptions, RgbImage,
+};
+
+/// Colours indexed by TxType. Types that flip one of the directions of another get a
+/// related shade of it.
+const TYPE_COLORS: [[u8; 3]; TxType::COUNT] = [
+    [200, 200, 200],
+    [230, 25, 75],
+    [0, 130, 200],
+    [145, 30, 180],
+    [250, 130, 160],
+    [120, 190, 255],
+    [90, 0, 120],
+    [200, 120, 230],
+    [110, 60, 200],
+    [255, 225, 25],
+    [60, 180, 75],
+    [245, 130, 48],
+    [0, 110, 60],
+    [170, 80, 0],
+    [140, 230, 150],
+    [255, 200, 140],
+];
+
+const ALPHA: f64 = 0.6;
+const OUTLINE_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Fills every luma transform block with the colour of its type and outlines it, so the
+/// var-tx splits of inter blocks show. The legend counts transforms per type and size.
+pub fn render_transform(frame: &Frame, options: RenderOptions) -> RgbImage {
+    let info = &frame.info;
+    let mut image = canvas(frame, options);
+
+    let mut type_counts = [0usize; TxType::COUNT];
+    let mut size_counts = BTreeMap::new();
+    for (row, (tx_sizes, tx_types)) in info.tx_sizes.iter().zip(&info.tx_types).enumerate() {
+        for (col, (tx_size, tx_type)) in tx_sizes.iter().zip(tx_types).enumerate() {
+            let (x, y) = (col * 4, row * 4);
+            let (w, h) = (tx_size.width(), tx_size.height());
+            if !x.is_multiple_of(w) || !y.is_multiple_of(h) {
+                continue;
+            }
+
+            let (x, y, w, h) = (x as i64, y as i64, w as i64, h as i64);
+            image.blend(x, y, w, h, TYPE_COLORS[*tx_type as usize], ALPHA);
+            image.outline(x, y, w, h, OUTLINE_COLOR);
+
+            type_counts[*tx_type as usize] += 1;
+            *size_counts
+                .entry((tx_size.width(), tx_size.height()))
+                .or_insert(0) += 1;
+        }
+    }
+
+    let total = type_counts.iter().sum::<usize>();
+    let mut entries = vec![LegendEntry::heading(format!("{total} TRANSFORMS"))];
+    for (tx_type, &count) in type_counts.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+
+        entries.push(LegendEntry {
+            color: Some(TYPE_COLORS[tx_type]),
+            label: format!("{} {count}", TxType::new(tx_type as u64).name()),
+            share: Some(count as f64 / total as f64),
+        });
+    }
+    for ((w, h), count) in size_counts {
+        entries.push(LegendEntry {
+            color: None,
+            label: format!("{w}X{h} {count}"),
+            share: Some(count as f64 / total as f64),
+        });
+    }
+    with_legend(&image, &entries)
+}
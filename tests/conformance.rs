@@ -0,0 +1,216 @@
+//! Decodes every bitstream under tests/data (or $AV1_TEST_DATA) and compares the output
+//! against the MD5s in the sidecar file next to it, `<stream>.md5`. The sidecar has the
+//! same format `av1-visualizer decode --md5` prints: one `frame N: <md5>` line per frame
+//! followed by `<md5>  <file>` for the whole stream.
+//!
+//! Streams that hit a `todo!` or other unsupported feature are reported as blocked instead
+//! of failing the test, unless AV1_CONFORMANCE_STRICT is set, so the output doubles as a
+//! coverage board for the decoder. Run with `cargo test --test conformance -- --nocapture`
+//! to see it.
+
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use av1_visualizer::{
+    decoder::{self, DecodeOptions},
+    output::{ExpectedMd5, Md5Writer},
+};
+
+const EXTENSIONS: [&str; 3] = ["ivf", "obu", "mp4"];
+
+enum Outcome {
+    Pass {
+        frames: usize,
+    },
+    NoHashes {
+        frames: usize,
+    },
+    Mismatch {
+        frame: Option<usize>,
+        detail: String,
+    },
+    Blocked {
+        frames: usize,
+        reason: String,
+    },
+    Error(String),
+}
+
+fn data_dir() -> PathBuf {
+    env::var_os("AV1_TEST_DATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data"))
+}
+
+fn find_streams(dir: &Path, streams: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            find_streams(&path, streams);
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| EXTENSIONS.contains(&ext))
+        {
+            streams.push(path);
+        }
+    }
+}
+
+fn read_expected(stream: &Path) -> Option<ExpectedMd5> {
+    let mut sidecar = stream.as_os_str().to_owned();
+    sidecar.push(".md5");
+    let text = fs::read_to_string(sidecar).ok()?;
+    Some(ExpectedMd5::parse(&text))
+}
+
+fn check(stream: &Path, panic_message: &Mutex<Option<String>>) -> Outcome {
+    let expected = read_expected(stream);
+    let mut md5 = Md5Writer::new(false);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        decoder::decode(
+            stream.to_path_buf(),
+            DecodeOptions::default(),
+            Some(&mut md5),
+        )
+    }));
+
+    let frames = md5.frames().to_vec();
+    let first_mismatch = expected.as_ref().and_then(|expected| {
+        frames
+            .iter()
+            .zip(&expected.frames)
+            .position(|(got, want)| got != want)
+    });
+    if let (Some(frame), Some(expected)) = (first_mismatch, &expected) {
+        return Outcome::Mismatch {
+            frame: Some(frame),
+            detail: format!("expected {}, got {}", expected.frames[frame], frames[frame]),
+        };
+    }
+
+    match result {
+        Err(_) => {
+            let reason = panic_message
+                .lock()
+                .unwrap()
+                .take()
+                .unwrap_or_else(|| "unknown panic".to_string());
+            return Outcome::Blocked {
+                frames: frames.len(),
+                reason,
+            };
+        }
+        Ok(Err(err)) => return Outcome::Error(format!("{err:?}")),
+        Ok(Ok(())) => {}
+    }
+
+    let Some(expected) = expected else {
+        return Outcome::NoHashes {
+            frames: frames.len(),
+        };
+    };
+
+    if !expected.frames.is_empty() && expected.frames.len() != frames.len() {
+        return Outcome::Mismatch {
+            frame: None,
+            detail: format!(
+                "expected {} frames, got {}",
+                expected.frames.len(),
+                frames.len()
+            ),
+        };
+    }
+
+    let stream_md5 = md5.finish();
+    match expected.stream {
+        Some(want) if want != stream_md5 => Outcome::Mismatch {
+            frame: None,
+            detail: format!("expected stream md5 {want}, got {stream_md5}"),
+        },
+        _ => Outcome::Pass {
+            frames: frames.len(),
+        },
+    }
+}
+
+#[test]
+fn conformance() {
+    let dir = data_dir();
+    let mut streams = Vec::new();
+    find_streams(&dir, &mut streams);
+    streams.sort();
+
+    if streams.is_empty() {
+        println!("no bitstreams found under {}", dir.display());
+        return;
+    }
+
+    // Keep the message of the panic that stopped a stream, so the board can say which
+    // todo! it ran into.
+    let panic_message = Arc::new(Mutex::new(None));
+    let hook_message = panic_message.clone();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        let location = info
+            .location()
+            .map(|l| format!(" ({}:{})", l.file(), l.line()))
+            .unwrap_or_default();
+        *hook_message.lock().unwrap() = Some(format!("{message}{location}"));
+    }));
+
+    let strict = env::var_os("AV1_CONFORMANCE_STRICT").is_some();
+    let mut failures = 0;
+    let mut passed = 0;
+    for stream in &streams {
+        let name = stream.strip_prefix(&dir).unwrap_or(stream).display();
+        match check(stream, &panic_message) {
+            Outcome::Pass { frames } => {
+                passed += 1;
+                println!("PASS     {name} ({frames} frames)");
+            }
+            Outcome::NoHashes { frames } => {
+                println!("NO HASH  {name} ({frames} frames decoded, no .md5 sidecar)");
+            }
+            Outcome::Mismatch { frame, detail } => {
+                failures += 1;
+                match frame {
+                    Some(frame) => println!("FAIL     {name}: frame {frame} differs, {detail}"),
+                    None => println!("FAIL     {name}: {detail}"),
+                }
+            }
+            Outcome::Blocked { frames, reason } => {
+                if strict {
+                    failures += 1;
+                }
+                println!("BLOCKED  {name} after {frames} frames: {reason}");
+            }
+            Outcome::Error(err) => {
+                failures += 1;
+                println!("ERROR    {name}: {err}");
+            }
+        }
+    }
+
+    panic::set_hook(default_hook);
+
+    println!("{passed}/{} streams pass", streams.len());
+    assert_eq!(failures, 0, "{failures} streams failed");
+}